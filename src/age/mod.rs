@@ -0,0 +1,113 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::edit;
+use crate::git::{self, BlameLine};
+use crate::system_manifests::ManifestResource;
+
+/// When a secret resource's manifest was last changed, and by whom.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SecretAge {
+    pub platform_name: String,
+    pub component_name: String,
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// Time of the last commit changing the manifest, `None` if it has uncommitted changes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_changed: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    pub days_unchanged: i64,
+    /// Whether the manifest is unchanged for longer than the maximum age, so that the secret is
+    /// likely due for a rotation.
+    pub rotation_candidate: bool,
+}
+
+/// Returns the most recent change among the lines of a resource's document, or among all the
+/// lines of its file for rendered resources. `None` if any of them is uncommitted.
+fn last_change<'a>(
+    manifest_resource: &ManifestResource,
+    blame: &'a [BlameLine],
+) -> Option<&'a BlameLine> {
+    let lines = match manifest_resource.line {
+        Some(line) => {
+            let contents = std::fs::read_to_string(&manifest_resource.file).unwrap_or_default();
+            let range = edit::document_range(&edit::lines(&contents), line);
+            blame.get(range).unwrap_or(blame)
+        }
+        None => blame,
+    };
+    if lines.iter().any(|line| line.commit.is_none()) {
+        return None;
+    }
+    lines.iter().max_by_key(|line| line.time)
+}
+
+/// Finds when the manifest of each secret resource was last changed, from the git history of
+/// the repository, and flags the ones unchanged for longer than the maximum age as rotation
+/// candidates. Uncommitted and untracked manifests count as changed just now.
+pub fn find_ages(
+    secret_resource_manifests: Vec<ManifestResource>,
+    max_age: Duration,
+) -> Result<Vec<SecretAge>> {
+    let now = Utc::now();
+    let mut blames: HashMap<PathBuf, Option<Vec<BlameLine>>> = HashMap::new();
+    let mut ages = Vec::new();
+    for manifest_resource in secret_resource_manifests {
+        if !blames.contains_key(&manifest_resource.file) {
+            let blame = git::blame(&manifest_resource.file).with_context(|| {
+                format!(
+                    "Failed to read the history of {}",
+                    manifest_resource.file.display()
+                )
+            })?;
+            blames.insert(manifest_resource.file.clone(), blame);
+        }
+        let change = blames[&manifest_resource.file]
+            .as_deref()
+            .and_then(|blame| last_change(&manifest_resource, blame));
+        let changed_at = change
+            .and_then(|change| DateTime::from_timestamp(change.time, 0))
+            .unwrap_or(now);
+        let unchanged = (now - changed_at).to_std().unwrap_or_default();
+
+        let resource = &manifest_resource.resource;
+        ages.push(SecretAge {
+            platform_name: manifest_resource.platform.name.clone(),
+            component_name: manifest_resource.component.name.clone(),
+            kind: resource
+                .types
+                .as_ref()
+                .map(|t| t.kind.clone())
+                .unwrap_or_default(),
+            namespace: resource
+                .metadata
+                .namespace
+                .clone()
+                .unwrap_or_else(|| "default".to_owned()),
+            name: resource.metadata.name.clone().unwrap_or_default(),
+            file: manifest_resource.file.clone(),
+            line: manifest_resource.line,
+            last_changed: change.map(|_| changed_at.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            author: change.map(|change| change.author.clone()),
+            author_email: change.map(|change| change.author_email.clone()),
+            commit: change.and_then(|change| change.commit.clone()),
+            days_unchanged: (now - changed_at).num_days(),
+            rotation_candidate: unchanged > max_age,
+        });
+    }
+    Ok(ages)
+}
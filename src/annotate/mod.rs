@@ -0,0 +1,388 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::edit::{self, indent, is_blank_or_comment, EditedResource};
+use crate::inventory;
+use crate::selector::LabelSelector;
+use crate::sops;
+use crate::system_manifests::{ManifestResource, SystemManifests};
+
+/// Metadata entries to set on a resource, by the metadata field each goes in.
+type PendingChanges<'a> = Vec<(&'static str, &'a str, &'a str)>;
+
+/// The metadata entries to add to resources, or update if they're set already.
+#[derive(Debug, Clone, Default)]
+pub struct MetadataChanges {
+    pub labels: Vec<(String, String)>,
+    pub annotations: Vec<(String, String)>,
+}
+
+impl MetadataChanges {
+    /// Returns the changes that aren't applied to a resource yet.
+    fn pending<'a>(&'a self, manifest_resource: &ManifestResource) -> PendingChanges<'a> {
+        let metadata = &manifest_resource.resource.metadata;
+        let labels = self
+            .labels
+            .iter()
+            .map(|entry| ("labels", entry, &metadata.labels));
+        let annotations = self
+            .annotations
+            .iter()
+            .map(|entry| ("annotations", entry, &metadata.annotations));
+        labels
+            .chain(annotations)
+            .filter(|(_, (key, value), current)| {
+                current.as_ref().and_then(|current| current.get(key)) != Some(value)
+            })
+            .map(|(field, (key, value), _)| (field, key.as_str(), value.as_str()))
+            .collect()
+    }
+}
+
+/// A manifest file with the metadata of some of its resources changed.
+#[derive(Debug, Clone)]
+pub struct AnnotatedFile {
+    pub file: PathBuf,
+    /// The resources changed, in the order of the file.
+    pub resources: Vec<EditedResource>,
+    pub original: String,
+    pub contents: String,
+}
+
+/// Returns the key of a mapping entry starting at the given column, along with the byte offset
+/// right after its colon.
+fn entry_key(line: &str, column: usize) -> Option<(String, usize)> {
+    let text = line.get(column..)?;
+    let (key, length) = match text.chars().next()? {
+        quote @ ('"' | '\'') => {
+            let end = text[1..].find(quote)? + 1;
+            (text[1..end].to_owned(), end + 1)
+        }
+        '-' | '#' => return None,
+        _ => {
+            let end = text
+                .match_indices(':')
+                .map(|(index, _)| index)
+                .find(|&index| {
+                    text[index + 1..].starts_with(char::is_whitespace) || index + 1 == text.len()
+                })?;
+            (text[..end].trim_end().to_owned(), end)
+        }
+    };
+    text[length..]
+        .starts_with(':')
+        .then_some((key, column + length + 1))
+}
+
+/// Returns the index after the last content line of the block nested under the line at `start`,
+/// which is indented by `parent_indent`.
+fn block_end(lines: &[String], start: usize, end: usize, parent_indent: usize) -> usize {
+    let mut block_end = start + 1;
+    for (index, line) in lines.iter().enumerate().take(end).skip(start + 1) {
+        if is_blank_or_comment(line) {
+            continue;
+        }
+        if indent(line) <= parent_indent {
+            break;
+        }
+        block_end = index + 1;
+    }
+    block_end
+}
+
+/// Returns the first entry with the given key and indent among the lines.
+fn find_entry(
+    lines: &[String],
+    range: std::ops::Range<usize>,
+    column: usize,
+    key: &str,
+) -> Option<(usize, usize)> {
+    range.into_iter().find_map(|index| {
+        let line = &lines[index];
+        if is_blank_or_comment(line) || indent(line) != column {
+            return None;
+        }
+        entry_key(line, column)
+            .filter(|(entry, _)| entry == key)
+            .map(|(_, value_start)| (index, value_start))
+    })
+}
+
+/// Returns the indent of the first content line among the lines, if any.
+fn first_indent(lines: &[String], range: std::ops::Range<usize>) -> Option<usize> {
+    lines[range]
+        .iter()
+        .find(|line| !is_blank_or_comment(line))
+        .map(|line| indent(line))
+}
+
+/// Renders a string as a YAML scalar that fits on one line. Values that start with a digit and
+/// contain `-` or `:` are quoted too, as YAML 1.1 parsers read them as timestamps or numbers.
+fn scalar(value: &str) -> Result<String> {
+    let is_ambiguous =
+        value.starts_with(|c: char| c.is_ascii_digit()) && value.contains(['-', ':']);
+    if value.contains('\n') || is_ambiguous {
+        return serde_json::to_string(value).with_context(|| "Failed to quote value");
+    }
+    Ok(serde_yaml::to_string(value)
+        .with_context(|| "Failed to quote value")?
+        .trim_end()
+        .to_owned())
+}
+
+/// Inserts lines after the line at `index`, ending that line first if it's the last of the file.
+fn insert_lines(lines: &mut Vec<String>, index: usize, new_lines: Vec<String>) {
+    let ending = if lines[index].ends_with("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    if !lines[index].ends_with('\n') {
+        lines[index].push('\n');
+    }
+    let new_lines = new_lines
+        .into_iter()
+        .map(|line| format!("{}{}", line, ending));
+    lines.splice(index + 1..index + 1, new_lines);
+}
+
+/// Adds or updates an entry of a metadata field of the document starting at the given line,
+/// editing only the lines it takes. Values are replaced along with the lines of multi-line
+/// scalars, keeping trailing comments.
+fn set_entry(
+    lines: &mut Vec<String>,
+    line: usize,
+    field: &str,
+    key: &str,
+    value: &str,
+) -> Result<()> {
+    let range = edit::document_range(lines, line);
+    let top_indent = first_indent(lines, range.clone()).with_context(|| "Empty document")?;
+    let (metadata, metadata_value) = find_entry(lines, range.clone(), top_indent, "metadata")
+        .with_context(|| "No metadata to edit")?;
+    anyhow::ensure!(
+        is_blank_or_comment(&lines[metadata][metadata_value..]),
+        "Metadata isn't a block mapping, which can't be edited in place"
+    );
+    let metadata_end = block_end(lines, metadata, range.end, top_indent);
+    let field_indent = first_indent(lines, metadata + 1..metadata_end).unwrap_or(top_indent + 2);
+    let step = field_indent - top_indent;
+    let entry = scalar(key)? + ": " + &scalar(value)?;
+
+    let Some((field_line, field_value)) =
+        find_entry(lines, metadata + 1..metadata_end, field_indent, field)
+    else {
+        let padding = " ".repeat(field_indent);
+        let new_lines = vec![
+            format!("{}{}:", padding, field),
+            format!("{}{}{}", padding, " ".repeat(step), entry),
+        ];
+        insert_lines(lines, metadata_end - 1, new_lines);
+        return Ok(());
+    };
+    let field_rest = lines[field_line][field_value..].trim();
+    if field_rest == "{}" {
+        let line = &lines[field_line];
+        lines[field_line] = format!("{}{}", &line[..field_value], &line[line.trim_end().len()..]);
+    } else {
+        anyhow::ensure!(
+            is_blank_or_comment(field_rest),
+            "{} isn't a block mapping, which can't be edited in place",
+            field
+        );
+    }
+    let field_end = block_end(lines, field_line, range.end, field_indent);
+    let entry_indent =
+        first_indent(lines, field_line + 1..field_end).unwrap_or(field_indent + step);
+
+    let Some((entry_line, entry_value)) =
+        find_entry(lines, field_line + 1..field_end, entry_indent, key)
+    else {
+        let new_lines = vec![format!("{}{}", " ".repeat(entry_indent), entry)];
+        insert_lines(lines, field_end - 1, new_lines);
+        return Ok(());
+    };
+    replace_value(
+        lines,
+        entry_line,
+        entry_value,
+        field_end,
+        entry_indent,
+        &scalar(value)?,
+    )
+}
+
+/// Replaces the value of the entry at `entry_line` with YAML written as given, along with the
+/// lines of a multi-line scalar before `end`, keeping its trailing comment.
+fn replace_value(
+    lines: &mut Vec<String>,
+    entry_line: usize,
+    entry_value: usize,
+    end: usize,
+    entry_indent: usize,
+    value: &str,
+) -> Result<()> {
+    let comment_pattern =
+        Regex::new(r#"^\s*(?:"(?:[^"\\]|\\.)*"|'(?:[^']|'')*'|[^#]*?)(\s+#.*)?$"#)?;
+    let current = &lines[entry_line];
+    let content = current.trim_end();
+    let comment = comment_pattern
+        .captures(&content[entry_value..])
+        .and_then(|captures| captures.get(1))
+        .map_or("", |comment| comment.as_str());
+    let ending = &current[content.len()..];
+    let continuation_end = block_end(lines, entry_line, end, entry_indent);
+    lines[entry_line] = format!(
+        "{}: {}{}{}",
+        &content[..entry_value - 1],
+        value,
+        comment,
+        ending
+    );
+    lines.drain(entry_line + 1..continuation_end);
+    Ok(())
+}
+
+/// Sets a top-level field of the document starting at the given line to YAML written as given,
+/// like `true`, or removes it without a value, editing only the lines it takes.
+pub fn set_field(
+    lines: &mut Vec<String>,
+    line: usize,
+    key: &str,
+    value: Option<&str>,
+) -> Result<()> {
+    let range = edit::document_range(lines, line);
+    let top_indent = first_indent(lines, range.clone()).with_context(|| "Empty document")?;
+    let first_line = range
+        .clone()
+        .find(|&index| !is_blank_or_comment(&lines[index]))
+        .with_context(|| "Empty document")?;
+    anyhow::ensure!(
+        entry_key(&lines[first_line], top_indent).is_some(),
+        "Document isn't a block mapping, which can't be edited in place"
+    );
+    match (find_entry(lines, range.clone(), top_indent, key), value) {
+        (Some((entry_line, entry_value)), Some(value)) => {
+            replace_value(lines, entry_line, entry_value, range.end, top_indent, value)
+        }
+        (Some((entry_line, _)), None) => {
+            let entry_end = block_end(lines, entry_line, range.end, top_indent);
+            lines.drain(entry_line..entry_end);
+            Ok(())
+        }
+        (None, Some(value)) => {
+            let last_line = range
+                .rev()
+                .find(|&index| !is_blank_or_comment(&lines[index]))
+                .unwrap_or(first_line);
+            let entry = format!("{}{}: {}", " ".repeat(top_indent), key, value);
+            insert_lines(lines, last_line, vec![entry]);
+            Ok(())
+        }
+        (None, None) => Ok(()),
+    }
+}
+
+/// Adds or updates labels and annotations on the secret resources in the given namespaces that
+/// match the selector and the `matches` predicate, editing the lines of their manifest files so
+/// that the formatting and comments are kept. SOPS-encrypted resources, resources rendered from
+/// kustomizations or Helm charts and resources with flow style metadata are skipped with a
+/// warning, as they can't be edited in place.
+///
+/// Returns the files to change, without writing them.
+pub fn annotate(
+    system_manifests: &SystemManifests,
+    namespaces: &[String],
+    selector: Option<&LabelSelector>,
+    matches: impl Fn(&ManifestResource) -> bool,
+    changes: &MetadataChanges,
+) -> Result<Vec<AnnotatedFile>> {
+    let mut documents: BTreeMap<PathBuf, BTreeMap<usize, (PendingChanges, EditedResource)>> =
+        BTreeMap::new();
+    for manifest_resource in inventory::secret_resource_iter(system_manifests, namespaces) {
+        let manifest_resource = manifest_resource?;
+        let labels = manifest_resource.resource.metadata.labels.as_ref();
+        if !selector.is_none_or(|selector| selector.matches(labels)) || !matches(&manifest_resource)
+        {
+            continue;
+        }
+        let pending = changes.pending(&manifest_resource);
+        if pending.is_empty() {
+            continue;
+        }
+        let name = manifest_resource
+            .resource
+            .metadata
+            .name
+            .as_deref()
+            .unwrap_or_default();
+        let Some(line) = manifest_resource.line else {
+            eprintln!(
+                "Skipping {} rendered from {}, edit its source instead",
+                name,
+                manifest_resource.file.display()
+            );
+            continue;
+        };
+        if sops::is_encrypted(&manifest_resource.resource) {
+            eprintln!(
+                "Skipping {} in {}:{}, editing it would break its SOPS MAC",
+                name,
+                manifest_resource.file.display(),
+                line
+            );
+            continue;
+        }
+        let edited = EditedResource::from(&manifest_resource);
+        documents
+            .entry(manifest_resource.file)
+            .or_default()
+            .insert(line, (pending, edited));
+    }
+
+    edit_documents(documents, |lines, line, pending| {
+        pending
+            .into_iter()
+            .try_for_each(|(field, key, value)| set_entry(lines, line, field, key, value))
+    })
+}
+
+/// Edits documents of manifest files, given per file by the line they start at along with the
+/// change to make. Documents that can't be edited are skipped with a warning.
+///
+/// Returns the files to change, without writing them.
+pub fn edit_documents<T>(
+    documents: BTreeMap<PathBuf, BTreeMap<usize, (T, EditedResource)>>,
+    edit: impl Fn(&mut Vec<String>, usize, T) -> Result<()>,
+) -> Result<Vec<AnnotatedFile>> {
+    let mut annotated = Vec::new();
+    for (file, documents) in documents {
+        let original = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let mut lines = edit::lines(&original);
+        let mut resources = Vec::new();
+        // From the last document up, so that inserted lines don't move the ones to edit next.
+        for (line, (change, resource)) in documents.into_iter().rev() {
+            let mut edited = lines.clone();
+            match edit(&mut edited, line, change) {
+                Ok(()) => {
+                    lines = edited;
+                    resources.insert(0, resource);
+                }
+                Err(error) => eprintln!("Skipping {}:{}: {}", file.display(), line, error),
+            }
+        }
+        if resources.is_empty() {
+            continue;
+        }
+        annotated.push(AnnotatedFile {
+            file,
+            resources,
+            original,
+            contents: lines.concat(),
+        });
+    }
+    Ok(annotated)
+}
@@ -0,0 +1,77 @@
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+/// System manifests extracted from an archive into a temporary directory, removed when dropped.
+pub struct Extracted {
+    _temporary_directory: TempDir,
+    pub directory: PathBuf,
+}
+
+impl Extracted {
+    /// Takes the system manifests to be in a temporary directory, or in the single directory
+    /// other than `manifests` in it, as made by archiving the repository's directory.
+    pub fn new(temporary_directory: TempDir) -> Result<Self> {
+        let entries = std::fs::read_dir(temporary_directory.path())
+            .with_context(|| format!("Failed to read {}", temporary_directory.path().display()))?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        let directory = match entries.as_slice() {
+            [entry] if entry.path().is_dir() && entry.file_name() != "manifests" => entry.path(),
+            _ => temporary_directory.path().to_owned(),
+        };
+        Ok(Extracted {
+            _temporary_directory: temporary_directory,
+            directory,
+        })
+    }
+}
+
+enum Format {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+fn format(path: &Path) -> Option<Format> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Some(Format::TarGz)
+    } else if name.ends_with(".tar") {
+        Some(Format::Tar)
+    } else if name.ends_with(".zip") {
+        Some(Format::Zip)
+    } else {
+        None
+    }
+}
+
+/// Returns whether a system manifests location is an archive file rather than a directory.
+pub fn is_archive(path: &Path) -> bool {
+    path.is_file() && format(path).is_some()
+}
+
+/// Extracts a tarball or zip archive of the system manifests into a temporary directory.
+pub fn extract(archive: &Path) -> Result<Extracted> {
+    let format = format(archive)
+        .with_context(|| format!("Unsupported archive format: {}", archive.display()))?;
+    let temporary_directory =
+        TempDir::new().with_context(|| "Failed to create a temporary directory")?;
+    let file =
+        File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+    let result = match format {
+        Format::Tar => tar::Archive::new(file)
+            .unpack(temporary_directory.path())
+            .map_err(anyhow::Error::from),
+        Format::TarGz => tar::Archive::new(GzDecoder::new(file))
+            .unpack(temporary_directory.path())
+            .map_err(anyhow::Error::from),
+        Format::Zip => zip::ZipArchive::new(file)
+            .and_then(|mut zip| zip.extract(temporary_directory.path()))
+            .map_err(anyhow::Error::from),
+    };
+    result.with_context(|| format!("Failed to extract {}", archive.display()))?;
+
+    Extracted::new(temporary_directory)
+}
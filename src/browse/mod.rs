@@ -0,0 +1,325 @@
+use anyhow::{Context, Result};
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+use kube::api::DynamicObject;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Text};
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::redact::redacted;
+use crate::system_manifests::ManifestResource;
+
+/// Placeholder for resources that have no namespace set.
+const UNSET: &str = "<none>";
+
+/// The platforms and components the secrets list can be narrowed down to.
+enum Scope {
+    All,
+    Platform(String),
+    Component(String, String),
+}
+
+impl Scope {
+    fn contains(&self, manifest_resource: &ManifestResource) -> bool {
+        let platform = &manifest_resource.platform.name;
+        match self {
+            Scope::All => true,
+            Scope::Platform(name) => platform == name,
+            Scope::Component(platform_name, name) => {
+                platform == platform_name && &manifest_resource.component.name == name
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq)]
+enum Focus {
+    Scopes,
+    Resources,
+}
+
+fn kind(resource: &DynamicObject) -> &str {
+    resource
+        .types
+        .as_ref()
+        .map(|t| t.kind.as_str())
+        .unwrap_or_default()
+}
+
+/// The line a secret resource is listed and searched by.
+fn label(resource: &DynamicObject) -> String {
+    format!(
+        "{} {}/{}",
+        kind(resource),
+        resource.metadata.namespace.as_deref().unwrap_or(UNSET),
+        resource.metadata.name.as_deref().unwrap_or_default()
+    )
+}
+
+struct Browser<'a> {
+    manifest_resources: &'a [ManifestResource],
+    directory: &'a Path,
+    scopes: Vec<(Scope, String)>,
+    scope_state: ListState,
+    query: String,
+    searching: bool,
+    matches: Vec<usize>,
+    resource_state: ListState,
+    focus: Focus,
+    detail_scroll: u16,
+}
+
+impl<'a> Browser<'a> {
+    fn new(manifest_resources: &'a [ManifestResource], directory: &'a Path) -> Self {
+        let mut counts: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+        for manifest_resource in manifest_resources {
+            *counts
+                .entry(&manifest_resource.platform.name)
+                .or_default()
+                .entry(&manifest_resource.component.name)
+                .or_default() += 1;
+        }
+        let mut scopes = vec![(
+            Scope::All,
+            format!("All platforms ({})", manifest_resources.len()),
+        )];
+        for (platform, components) in counts {
+            scopes.push((
+                Scope::Platform(platform.to_owned()),
+                format!("{} ({})", platform, components.values().sum::<usize>()),
+            ));
+            for (component, count) in components {
+                scopes.push((
+                    Scope::Component(platform.to_owned(), component.to_owned()),
+                    format!("  {} ({})", component, count),
+                ));
+            }
+        }
+
+        let mut browser = Browser {
+            manifest_resources,
+            directory,
+            scopes,
+            scope_state: ListState::default().with_selected(Some(0)),
+            query: String::new(),
+            searching: false,
+            matches: Vec::new(),
+            resource_state: ListState::default(),
+            focus: Focus::Scopes,
+            detail_scroll: 0,
+        };
+        browser.update_matches();
+        browser
+    }
+
+    /// Lists the secret resources in the selected scope that match the search query, best
+    /// matches first.
+    fn update_matches(&mut self) {
+        let scope = &self.scopes[self.scope_state.selected().unwrap_or_default()].0;
+        let matcher = SkimMatcherV2::default();
+        let mut matches: Vec<(i64, usize)> = self
+            .manifest_resources
+            .iter()
+            .enumerate()
+            .filter(|(_, manifest_resource)| scope.contains(manifest_resource))
+            .filter_map(|(index, manifest_resource)| {
+                let score = if self.query.is_empty() {
+                    0
+                } else {
+                    matcher.fuzzy_match(&label(&manifest_resource.resource), &self.query)?
+                };
+                Some((score, index))
+            })
+            .collect();
+        matches.sort_by_key(|(score, _)| -score);
+        self.matches = matches.into_iter().map(|(_, index)| index).collect();
+        self.resource_state
+            .select((!self.matches.is_empty()).then_some(0));
+        self.detail_scroll = 0;
+    }
+
+    fn selected_resource(&self) -> Option<&ManifestResource> {
+        let index = self.matches.get(self.resource_state.selected()?)?;
+        Some(&self.manifest_resources[*index])
+    }
+
+    fn move_selection(&mut self, offset: isize) {
+        let (state, len) = match self.focus {
+            Focus::Scopes => (&mut self.scope_state, self.scopes.len()),
+            Focus::Resources => (&mut self.resource_state, self.matches.len()),
+        };
+        if len == 0 {
+            return;
+        }
+        let selected = state.selected().unwrap_or_default() as isize + offset;
+        state.select(Some(selected.clamp(0, len as isize - 1) as usize));
+        match self.focus {
+            Focus::Scopes => self.update_matches(),
+            Focus::Resources => self.detail_scroll = 0,
+        }
+    }
+
+    /// Handles a key press, returning whether to quit.
+    fn handle_key(&mut self, code: KeyCode) -> bool {
+        if self.searching {
+            match code {
+                KeyCode::Char(c) => self.query.push(c),
+                KeyCode::Backspace => {
+                    self.query.pop();
+                }
+                KeyCode::Esc => {
+                    self.query.clear();
+                    self.searching = false;
+                }
+                KeyCode::Enter => {
+                    self.searching = false;
+                    self.focus = Focus::Resources;
+                    return false;
+                }
+                KeyCode::Up => self.move_selection(-1),
+                KeyCode::Down => self.move_selection(1),
+                _ => return false,
+            }
+            if !matches!(code, KeyCode::Up | KeyCode::Down) {
+                self.update_matches();
+            }
+            return false;
+        }
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Left | KeyCode::Right => {
+                self.focus = match self.focus {
+                    Focus::Scopes => Focus::Resources,
+                    Focus::Resources => Focus::Scopes,
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Home | KeyCode::Char('g') => self.move_selection(isize::MIN / 2),
+            KeyCode::End | KeyCode::Char('G') => self.move_selection(isize::MAX / 2),
+            KeyCode::PageDown => self.detail_scroll = self.detail_scroll.saturating_add(10),
+            KeyCode::PageUp => self.detail_scroll = self.detail_scroll.saturating_sub(10),
+            _ => {}
+        }
+        false
+    }
+
+    fn block(&self, title: &str, focused: bool) -> Block<'static> {
+        let block = Block::bordered().title(format!(" {} ", title));
+        if focused {
+            block.border_style(Style::new().fg(Color::Cyan))
+        } else {
+            block
+        }
+    }
+
+    fn detail(&self) -> Result<Text<'static>> {
+        let Some(manifest_resource) = self.selected_resource() else {
+            return Ok(Text::from("No secret resources match."));
+        };
+        let file = manifest_resource
+            .file
+            .strip_prefix(self.directory)
+            .unwrap_or(&manifest_resource.file)
+            .display()
+            .to_string();
+        let location = match manifest_resource.line {
+            Some(line) => format!("{}:{}", file, line),
+            None => file,
+        };
+        let yaml = serde_yaml::to_string(&redacted(&manifest_resource.resource))
+            .with_context(|| "Failed to serialize resource")?;
+        let mut lines = vec![
+            Line::styled(location, Style::new().add_modifier(Modifier::BOLD)),
+            Line::from(format!(
+                "platform {}, component {}",
+                manifest_resource.platform.name, manifest_resource.component.name
+            )),
+            Line::default(),
+        ];
+        lines.extend(yaml.lines().map(|line| Line::from(line.to_owned())));
+        Ok(Text::from(lines))
+    }
+
+    fn draw(&mut self, frame: &mut Frame) -> Result<()> {
+        let [main, status] =
+            Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+        let [search, resources, detail] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Percentage(40),
+            Constraint::Fill(1),
+        ])
+        .areas(right);
+        let highlight = Style::new().add_modifier(Modifier::REVERSED);
+
+        let scopes = List::new(self.scopes.iter().map(|(_, label)| label.as_str()))
+            .block(self.block("Platforms", self.focus == Focus::Scopes))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(scopes, left, &mut self.scope_state);
+
+        let cursor = if self.searching { "_" } else { "" };
+        let search_input = Paragraph::new(format!("{}{}", self.query, cursor))
+            .block(self.block("Search (/)", self.searching));
+        frame.render_widget(search_input, search);
+
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|index| ListItem::new(label(&self.manifest_resources[*index].resource)))
+            .collect();
+        let title = format!("Secrets ({})", self.matches.len());
+        let resource_list = List::new(items)
+            .block(self.block(&title, self.focus == Focus::Resources))
+            .highlight_style(highlight);
+        frame.render_stateful_widget(resource_list, resources, &mut self.resource_state);
+
+        let detail_text = Paragraph::new(self.detail()?)
+            .block(self.block("Resource", false))
+            .wrap(Wrap { trim: false })
+            .scroll((self.detail_scroll, 0));
+        frame.render_widget(detail_text, detail);
+
+        let help = if self.searching {
+            "type to search  enter: done  esc: clear"
+        } else {
+            "tab: switch pane  ↑↓/jk: move  /: search  pgup/pgdn: scroll resource  q: quit"
+        };
+        frame.render_widget(
+            Paragraph::new(help).style(Style::new().fg(Color::DarkGray)),
+            status,
+        );
+        Ok(())
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            let mut result = Ok(());
+            terminal
+                .draw(|frame| result = self.draw(frame))
+                .with_context(|| "Failed to draw the terminal")?;
+            result?;
+            if let Event::Key(key) = event::read().with_context(|| "Failed to read key press")? {
+                if key.kind == KeyEventKind::Press && self.handle_key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+/// Opens an interactive browser for secret resources in the terminal, with the platforms and
+/// components to narrow them down to on the left, and fuzzy search through the secret resources
+/// and their redacted YAML on the right.
+pub fn browse(manifest_resources: &[ManifestResource], directory: &Path) -> Result<()> {
+    let mut browser = Browser::new(manifest_resources, directory);
+    ratatui::run(|terminal| browser.run(terminal))
+}
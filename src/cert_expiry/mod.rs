@@ -0,0 +1,212 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use k8s_openapi::api::core::v1::Secret;
+use kube::api::{Api, DynamicObject};
+use kube::Client;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+use x509_parser::pem::parse_x509_pem;
+
+use crate::cluster::{api_resource_for, ClusterContexts};
+use crate::references::produced_secret;
+use crate::system_manifests::{ManifestResource, Platform, SystemManifests};
+
+const TLS_SECRET_TYPE: &str = "kubernetes.io/tls";
+
+/// A TLS certificate in a cluster that expires soon, or has expired already.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CertificateExpiry {
+    pub platform_name: String,
+    pub component_name: String,
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub namespace: String,
+    pub secret_name: String,
+    /// What the expiry was read from, the status of the Certificate or the live Secret.
+    pub source: String,
+    pub not_after: String,
+    /// Days until the certificate expires, negative if it has expired.
+    pub days_left: i64,
+}
+
+/// Returns the name of the TLS Secret a resource declares or results in: a Secret of type
+/// `kubernetes.io/tls`, the Secret of a cert-manager Certificate or the target of an
+/// ExternalSecret templating a TLS Secret.
+fn tls_secret_name(resource: &DynamicObject) -> Option<String> {
+    let type_pointer = match resource.types.as_ref()?.kind.as_str() {
+        "Certificate" => None,
+        "Secret" => Some("/type"),
+        "ExternalSecret" => Some("/spec/target/template/type"),
+        _ => return None,
+    };
+    if let Some(pointer) = type_pointer {
+        let secret_type = resource.data.pointer(pointer)?.as_str()?;
+        if secret_type != TLS_SECRET_TYPE {
+            return None;
+        }
+    }
+    Some(produced_secret(resource)?.name)
+}
+
+/// Returns when the first certificate in a PEM bundle expires.
+fn pem_not_after(pem: &[u8]) -> Result<DateTime<Utc>> {
+    let (_, pem) = parse_x509_pem(pem).with_context(|| "tls.crt isn't PEM encoded")?;
+    let certificate = pem
+        .parse_x509()
+        .with_context(|| "tls.crt isn't an X.509 certificate")?;
+    let timestamp = certificate.validity().not_after.timestamp();
+    DateTime::from_timestamp(timestamp, 0).with_context(|| "tls.crt has an invalid expiry date")
+}
+
+/// Reads when the certificate of a TLS Secret expires, from the status of its Certificate if
+/// there is one, or else from the live Secret. Returns None if neither is in the cluster.
+async fn live_not_after(
+    client: &Client,
+    manifest_resource: &ManifestResource,
+    namespace: &str,
+    secret_name: &str,
+) -> Result<Option<(&'static str, DateTime<Utc>)>> {
+    let resource = &manifest_resource.resource;
+    if resource
+        .types
+        .as_ref()
+        .is_some_and(|t| t.kind == "Certificate")
+    {
+        let api_resource = api_resource_for(manifest_resource)?;
+        let api: Api<DynamicObject> =
+            Api::namespaced_with(client.clone(), namespace, &api_resource);
+        let name = resource.metadata.name.as_deref().unwrap_or_default();
+        let live = api
+            .get_opt(name)
+            .await
+            .with_context(|| format!("Failed to get Certificate {}/{}", namespace, name))?;
+        let not_after = live
+            .as_ref()
+            .and_then(|live| live.data.pointer("/status/notAfter"))
+            .and_then(|not_after| not_after.as_str());
+        if let Some(not_after) = not_after {
+            let not_after: DateTime<Utc> = not_after
+                .parse()
+                .with_context(|| format!("Invalid notAfter: {}", not_after))?;
+            return Ok(Some(("Certificate", not_after)));
+        }
+    }
+
+    let api: Api<Secret> = Api::namespaced(client.clone(), namespace);
+    let secret = api
+        .get_opt(secret_name)
+        .await
+        .with_context(|| format!("Failed to get Secret {}/{}", namespace, secret_name))?;
+    let certificate = secret
+        .and_then(|secret| secret.data)
+        .and_then(|mut data| data.remove("tls.crt"));
+    let Some(certificate) = certificate else {
+        return Ok(None);
+    };
+    let not_after = pem_not_after(&certificate.0)
+        .with_context(|| format!("Failed to read Secret {}/{}", namespace, secret_name))?;
+    Ok(Some(("Secret", not_after)))
+}
+
+async fn platform_expiries(
+    platform: &Platform,
+    tls_secrets: Vec<(String, String, ManifestResource)>,
+    contexts: &ClusterContexts,
+    deadline: DateTime<Utc>,
+) -> Result<Vec<(DateTime<Utc>, CertificateExpiry)>> {
+    if tls_secrets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let client = contexts.client_for(platform).await?;
+    let mut expiries = Vec::new();
+    for (namespace, secret_name, manifest_resource) in tls_secrets {
+        let not_after = live_not_after(&client, &manifest_resource, &namespace, &secret_name)
+            .await
+            .with_context(|| format!("Failed to read certificate on platform {}", platform.name))?;
+        let Some((source, not_after)) = not_after.filter(|(_, not_after)| *not_after <= deadline)
+        else {
+            continue;
+        };
+        let expiry = CertificateExpiry {
+            platform_name: platform.name.clone(),
+            component_name: manifest_resource.component.name.clone(),
+            file: manifest_resource.file.clone(),
+            line: manifest_resource.line,
+            namespace,
+            secret_name,
+            source: source.to_owned(),
+            not_after: not_after.to_rfc3339(),
+            days_left: (not_after - Utc::now()).num_days(),
+        };
+        expiries.push((not_after, expiry));
+    }
+    Ok(expiries)
+}
+
+/// Reads the live certificate of every TLS Secret declared in the manifests and returns the ones
+/// expiring within the given time, soonest first.
+///
+/// A Secret a Certificate is issued into is checked through the Certificate, so a Secret that is
+/// both declared and issued is only reported once. Clusters are queried concurrently, and the
+/// names of the platforms whose cluster couldn't be queried are returned along with the expiring
+/// certificates found on the others.
+pub fn find_expiring(
+    system_manifests: &SystemManifests,
+    contexts: &ClusterContexts,
+    within: Duration,
+) -> Result<(Vec<CertificateExpiry>, Vec<String>)> {
+    let mut declared: HashMap<String, BTreeMap<(String, String), ManifestResource>> =
+        HashMap::new();
+    for manifest_resource_result in system_manifests.resource_iter() {
+        let manifest_resource = manifest_resource_result?;
+        let Some(secret_name) = tls_secret_name(&manifest_resource.resource) else {
+            continue;
+        };
+        let namespace = manifest_resource
+            .resource
+            .metadata
+            .namespace
+            .clone()
+            .unwrap_or_else(|| "default".to_owned());
+        let is_certificate = manifest_resource
+            .resource
+            .types
+            .as_ref()
+            .is_some_and(|t| t.kind == "Certificate");
+        let tls_secrets = declared
+            .entry(manifest_resource.platform.name.clone())
+            .or_default();
+        let key = (namespace, secret_name);
+        if is_certificate || !tls_secrets.contains_key(&key) {
+            tls_secrets.insert(key, manifest_resource);
+        }
+    }
+
+    let deadline =
+        Utc::now() + chrono::Duration::from_std(within).with_context(|| "--within is too long")?;
+    let queries = system_manifests.platforms.iter().map(|platform| {
+        let tls_secrets = declared
+            .remove(&platform.name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|((namespace, name), manifest_resource)| (namespace, name, manifest_resource))
+            .collect();
+        (
+            platform.as_ref(),
+            platform_expiries(platform, tls_secrets, contexts, deadline).boxed_local(),
+        )
+    });
+    let (expiries, failed_platforms) = contexts.query_all(queries)?;
+    let mut expiries: Vec<_> = expiries.into_iter().flatten().collect();
+    expiries.sort_by_key(|(not_after, _)| *not_after);
+    Ok((
+        expiries.into_iter().map(|(_, expiry)| expiry).collect(),
+        failed_platforms,
+    ))
+}
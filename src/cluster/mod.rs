@@ -0,0 +1,267 @@
+use anyhow::{Context, Result};
+use futures::future::{join_all, LocalBoxFuture};
+use kube::api::{ApiResource, GroupVersionKind};
+use kube::config::{KubeConfigOptions, Kubeconfig};
+use kube::{Client, Config};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use crate::system_manifests::{ManifestResource, Platform};
+
+/// How to reach the cluster of a platform: a kubeconfig context, in a kubeconfig file of its own
+/// if given. Written in the config as the name of the context, or a table with `context` and
+/// `kubeconfig`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(from = "ClusterEntry")]
+pub struct Cluster {
+    pub context: Option<String>,
+    pub kubeconfig: Option<PathBuf>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClusterEntry {
+    Context(String),
+    Table(ClusterTable),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ClusterTable {
+    context: Option<String>,
+    kubeconfig: Option<PathBuf>,
+}
+
+impl From<ClusterEntry> for Cluster {
+    fn from(entry: ClusterEntry) -> Self {
+        match entry {
+            ClusterEntry::Context(context) => Cluster {
+                context: Some(context),
+                kubeconfig: None,
+            },
+            ClusterEntry::Table(ClusterTable {
+                context,
+                kubeconfig,
+            }) => Cluster {
+                context,
+                kubeconfig,
+            },
+        }
+    }
+}
+
+/// Expands a leading `~` of a path to the home directory.
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_owned(),
+    }
+}
+
+/// How long to wait for the cluster of a platform to answer all queries, unless told otherwise.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Maps platforms to the kubeconfig context used to reach their cluster.
+///
+/// Platforms without an explicit mapping use the context carrying the platform name, and
+/// platforms with a kubeconfig file of their own but no context its current context.
+#[derive(Debug, Clone)]
+pub struct ClusterContexts {
+    clusters: HashMap<String, Cluster>,
+    /// How long to wait for the cluster of a platform to answer all queries.
+    pub timeout: Duration,
+}
+
+impl ClusterContexts {
+    /// Maps platforms to the clusters of the config, then overrides their contexts with the
+    /// given ones, in order.
+    pub fn new(
+        clusters: impl IntoIterator<Item = (String, Cluster)>,
+        contexts: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        let mut clusters: HashMap<String, Cluster> = clusters.into_iter().collect();
+        for (platform, context) in contexts {
+            clusters.entry(platform).or_default().context = Some(context);
+        }
+        ClusterContexts {
+            clusters,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Returns the context for a platform, `None` for the current context of its kubeconfig.
+    pub fn context_for(&self, platform: &Platform) -> Option<String> {
+        match self.clusters.get(&platform.name) {
+            Some(Cluster {
+                context: Some(context),
+                ..
+            }) => Some(context.clone()),
+            Some(Cluster {
+                kubeconfig: Some(_),
+                ..
+            }) => None,
+            _ => Some(platform.name.clone()),
+        }
+    }
+
+    /// Returns the platform's own kubeconfig file, if it has one.
+    fn kubeconfig_path_for(&self, platform: &Platform) -> Option<PathBuf> {
+        self.clusters
+            .get(&platform.name)
+            .and_then(|cluster| cluster.kubeconfig.as_ref())
+            .map(|path| expand_home(path))
+    }
+
+    fn read_kubeconfig(kubeconfig_path: Option<&Path>) -> Result<Kubeconfig> {
+        match kubeconfig_path {
+            Some(path) => Kubeconfig::read_from(path)
+                .with_context(|| format!("Failed to read kubeconfig {}", path.display())),
+            None => Kubeconfig::read().with_context(|| "Failed to read kubeconfig"),
+        }
+    }
+
+    /// Returns the name of the kubeconfig context a platform's cluster is reached with, failing
+    /// if the kubeconfig doesn't have it.
+    pub fn resolve_context(&self, platform: &Platform) -> Result<String> {
+        let kubeconfig_path = self.kubeconfig_path_for(platform);
+        let kubeconfig = Self::read_kubeconfig(kubeconfig_path.as_deref())?;
+        let kubeconfig_name = match &kubeconfig_path {
+            Some(path) => format!("Kubeconfig {}", path.display()),
+            None => "The kubeconfig".to_owned(),
+        };
+        let context = match self.context_for(platform) {
+            Some(context) => context,
+            None => kubeconfig.current_context.clone().with_context(|| {
+                format!(
+                    "{} of platform {} has no current context",
+                    kubeconfig_name, platform.name
+                )
+            })?,
+        };
+        anyhow::ensure!(
+            kubeconfig
+                .contexts
+                .iter()
+                .any(|named_context| named_context.name == context),
+            "{} has no context {} for platform {}",
+            kubeconfig_name,
+            context,
+            platform.name
+        );
+        Ok(context)
+    }
+
+    pub async fn client_for(&self, platform: &Platform) -> Result<Client> {
+        let context = self.context_for(platform);
+        let kubeconfig_path = self.kubeconfig_path_for(platform);
+        debug!(
+            platform = %platform.name,
+            context = context.as_deref(),
+            kubeconfig = kubeconfig_path.as_ref().map(|path| path.display().to_string()),
+            "Connecting to cluster"
+        );
+        let kubeconfig = Self::read_kubeconfig(kubeconfig_path.as_deref())?;
+        let config = Config::from_custom_kubeconfig(
+            kubeconfig,
+            &KubeConfigOptions {
+                context: context.clone(),
+                ..Default::default()
+            },
+        )
+        .await
+        .with_context(|| {
+            let context = match &context {
+                Some(context) => format!("context {}", context),
+                None => "the current context".to_owned(),
+            };
+            match &kubeconfig_path {
+                Some(path) => format!(
+                    "Failed to load {} of kubeconfig {} for platform {}",
+                    context,
+                    path.display(),
+                    platform.name
+                ),
+                None => format!(
+                    "Failed to load kubeconfig {} for platform {}",
+                    context, platform.name
+                ),
+            }
+        })?;
+        Client::try_from(config).with_context(|| {
+            format!(
+                "Failed to create cluster client for platform {}",
+                platform.name
+            )
+        })
+    }
+
+    /// Runs the queries of several platforms' clusters concurrently, giving up on a cluster once
+    /// the timeout passes. Returns the results of the platforms whose queries succeeded, in
+    /// order, and the names of the others, reporting why they failed on stderr.
+    pub fn query_all<'a, T>(
+        &self,
+        queries: impl IntoIterator<Item = (&'a Platform, LocalBoxFuture<'a, Result<T>>)>,
+    ) -> Result<(Vec<T>, Vec<String>)> {
+        let timeout = self.timeout;
+        let queries = queries.into_iter().map(|(platform, query)| async move {
+            let started = Instant::now();
+            let result = match tokio::time::timeout(timeout, query).await {
+                Ok(result) => result,
+                Err(_) => Err(anyhow::anyhow!(
+                    "Timed out after {}s waiting for the cluster",
+                    timeout.as_secs_f64()
+                )),
+            };
+            debug!(
+                platform = %platform.name,
+                succeeded = result.is_ok(),
+                elapsed = ?started.elapsed(),
+                "Queried cluster"
+            );
+            (platform, result)
+        });
+        let results = block_on(join_all(queries))?;
+
+        let mut succeeded = Vec::new();
+        let mut failed = Vec::new();
+        for (platform, result) in results {
+            match result {
+                Ok(result) => succeeded.push(result),
+                Err(error) => {
+                    eprintln!("Failed to query platform {}: {:#}", platform.name, error);
+                    failed.push(platform.name.clone());
+                }
+            }
+        }
+        Ok((succeeded, failed))
+    }
+}
+
+/// Returns the API resource to query the cluster for objects like the given manifest resource.
+pub fn api_resource_for(manifest_resource: &ManifestResource) -> Result<ApiResource> {
+    let types = manifest_resource
+        .resource
+        .types
+        .as_ref()
+        .with_context(|| "Declared resource has no apiVersion or kind")?;
+    let gvk = GroupVersionKind::try_from(types).with_context(|| {
+        format!(
+            "Failed to parse apiVersion of {} in {}",
+            types.kind,
+            manifest_resource.file.display()
+        )
+    })?;
+    Ok(ApiResource::from_gvk(&gvk))
+}
+
+/// Runs a future to completion on a single threaded runtime.
+pub fn block_on<F: std::future::Future>(future: F) -> Result<F::Output> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .with_context(|| "Failed to start async runtime")?;
+    Ok(runtime.block_on(future))
+}
@@ -0,0 +1,82 @@
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::system_manifests::{ManifestResource, SystemManifests};
+
+/// A secret resource that some of the compared platforms declare and others don't.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PlatformSkew {
+    pub component_name: String,
+    pub kind: String,
+    pub name: String,
+    pub present_on: Vec<String>,
+    pub missing_on: Vec<String>,
+}
+
+/// Compares the secret resources of platforms by component, kind and name, returning the ones
+/// missing on some of them. Compares all platforms if none are given.
+pub fn compare_platforms(
+    system_manifests: &SystemManifests,
+    manifest_resources: &[ManifestResource],
+    platform_names: &[String],
+) -> Result<Vec<PlatformSkew>> {
+    let known: Vec<&str> = system_manifests
+        .platforms
+        .iter()
+        .map(|platform| platform.name.as_str())
+        .collect();
+    for name in platform_names {
+        if !known.contains(&name.as_str()) {
+            anyhow::bail!(
+                "Unknown platform {}, expected one of: {}",
+                name,
+                known.join(", ")
+            );
+        }
+    }
+    let compared: BTreeSet<&str> = if platform_names.is_empty() {
+        known.into_iter().collect()
+    } else {
+        platform_names.iter().map(String::as_str).collect()
+    };
+    anyhow::ensure!(
+        compared.len() >= 2,
+        "Comparing platforms requires at least two platforms"
+    );
+
+    let mut present: BTreeMap<(String, String, String), BTreeSet<&str>> = BTreeMap::new();
+    for manifest_resource in manifest_resources {
+        let platform_name = manifest_resource.platform.name.as_str();
+        let Some(platform_name) = compared.get(platform_name) else {
+            continue;
+        };
+        let resource = &manifest_resource.resource;
+        let key = (
+            manifest_resource.component.name.clone(),
+            resource
+                .types
+                .as_ref()
+                .map(|t| t.kind.clone())
+                .unwrap_or_default(),
+            resource.metadata.name.clone().unwrap_or_default(),
+        );
+        present.entry(key).or_default().insert(platform_name);
+    }
+
+    Ok(present
+        .into_iter()
+        .filter(|(_, platforms)| platforms.len() < compared.len())
+        .map(|((component_name, kind, name), platforms)| PlatformSkew {
+            component_name,
+            kind,
+            name,
+            present_on: platforms.iter().map(|name| name.to_string()).collect(),
+            missing_on: compared
+                .difference(&platforms)
+                .map(|name| name.to_string())
+                .collect(),
+        })
+        .collect())
+}
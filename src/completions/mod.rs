@@ -0,0 +1,96 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use clap_complete::env::{Bash, EnvCompleter, Fish, Powershell, Zsh};
+use clap_complete::CompletionCandidate;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+
+use crate::config::Config;
+use crate::system_manifests::{self, SystemManifests};
+
+/// Environment variable the completion scripts set when asking the binary for completions.
+pub const COMPLETE_VAR: &str = "COMPLETE";
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+    Powershell,
+}
+
+/// Writes the script that registers completions for a shell. The script asks the binary itself
+/// for completions, so platform and component names are always current.
+pub fn write_completions(shell: CompletionShell, command: &clap::Command) -> Result<()> {
+    let completer: &dyn EnvCompleter = match shell {
+        CompletionShell::Bash => &Bash,
+        CompletionShell::Zsh => &Zsh,
+        CompletionShell::Fish => &Fish,
+        CompletionShell::Powershell => &Powershell,
+    };
+    let name = command.get_name();
+    completer
+        .write_registration(COMPLETE_VAR, name, name, name, &mut std::io::stdout())
+        .with_context(|| "Failed to write completion script")
+}
+
+/// Reads the system manifests the `SYSTEM_MANIFESTS` environment variable points at, if any,
+/// finding components as the config file of the first says.
+fn probe_system_manifests() -> Option<SystemManifests> {
+    let locations = std::env::var("SYSTEM_MANIFESTS").ok()?;
+    let mut locations = locations.split(',');
+    let directory = PathBuf::from(locations.next()?);
+    let config = Config::load(&directory).ok()?;
+    let discovery = config.discover.unwrap_or_default();
+    let strict = config.strict.unwrap_or_default();
+    let mut system_manifests =
+        SystemManifests::from_directory(directory, discovery, strict).ok()?;
+    for location in locations {
+        let name = system_manifests::repository_name(location);
+        let other =
+            SystemManifests::from_directory(PathBuf::from(location), discovery, strict).ok()?;
+        system_manifests.add_repository(name, other);
+    }
+    Some(system_manifests)
+}
+
+pub fn platform_candidates() -> Vec<CompletionCandidate> {
+    probe_system_manifests()
+        .map(|system_manifests| {
+            system_manifests
+                .platforms
+                .iter()
+                .map(|platform| platform.name.clone())
+                .collect::<BTreeSet<_>>()
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
+
+/// Completes the platform in `PLATFORM=CONTEXT` values.
+pub fn platform_context_candidates() -> Vec<CompletionCandidate> {
+    platform_candidates()
+        .into_iter()
+        .map(|candidate| {
+            CompletionCandidate::new(format!("{}=", candidate.get_value().to_string_lossy()))
+        })
+        .collect()
+}
+
+pub fn component_candidates() -> Vec<CompletionCandidate> {
+    probe_system_manifests()
+        .map(|system_manifests| {
+            system_manifests
+                .platforms
+                .iter()
+                .flat_map(|platform| platform.components(true))
+                .map(|component| component.name.clone())
+                .collect::<BTreeSet<_>>()
+        })
+        .unwrap_or_default()
+        .into_iter()
+        .map(CompletionCandidate::new)
+        .collect()
+}
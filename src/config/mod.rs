@@ -0,0 +1,176 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::cluster::Cluster;
+use crate::findings::{Checks, FailOn, Finding, Severity};
+use crate::lint::LintConfig;
+use crate::render::Render;
+use crate::system_manifests::{Discovery, ManifestResource};
+
+/// Name of the config file in the system manifests repository and the user's config directory.
+pub const CONFIG_FILE_NAME: &str = "dp-secrets-helper.toml";
+
+/// How findings of a rule are reported.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleLevel {
+    Off,
+    Warning,
+    Error,
+}
+
+/// Defaults for the command line, read from `dp-secrets-helper.toml` files. Flags given on the
+/// command line take precedence.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Kinds of resources that make up the secrets inventory, replacing the built-in ones.
+    pub kinds: Option<Vec<String>>,
+    /// Glob patterns of manifest files to leave out, relative to the system manifests directory.
+    pub exclude: Vec<String>,
+    /// Kubeconfig context to use per platform.
+    pub contexts: BTreeMap<String, String>,
+    /// Kubeconfig context, or kubeconfig file and context, to reach the cluster of each
+    /// platform with, taking precedence over `contexts`.
+    pub clusters: BTreeMap<String, Cluster>,
+    /// Settings of the lint rules, and the levels to report findings of any rule at.
+    pub lint: LintConfig,
+    pub max_depth: Option<usize>,
+    pub include_bootstrap: Option<bool>,
+    pub skip_invalid: Option<bool>,
+    pub strict: Option<bool>,
+    pub fail_on: Option<FailOn>,
+    pub render: Option<Vec<Render>>,
+    pub discover: Option<Discovery>,
+    pub cache: Option<bool>,
+    /// Annotation, or else label, naming the team owning a secret.
+    pub owner_key: Option<String>,
+}
+
+/// Compiles exclusion patterns, where `*` stays within a directory and `**` doesn't.
+pub fn exclude_set<'a>(patterns: impl IntoIterator<Item = &'a String>) -> Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(
+            GlobBuilder::new(pattern)
+                .literal_separator(true)
+                .build()
+                .with_context(|| format!("Invalid exclude pattern: {}", pattern))?,
+        );
+    }
+    builder
+        .build()
+        .with_context(|| "Failed to compile exclude patterns")
+}
+
+/// Returns the path of the user's config file, in `$XDG_CONFIG_HOME` or else `~/.config`.
+fn user_config_path() -> Option<PathBuf> {
+    let config_directory = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_directory.join(CONFIG_FILE_NAME))
+}
+
+impl Config {
+    fn read(path: &Path) -> Result<Option<Self>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file: {}", path.display()))?;
+        toml::from_str(&contents)
+            .map(Some)
+            .with_context(|| format!("Failed to parse config file: {}", path.display()))
+    }
+
+    /// Loads the user's config file and the one in the system manifests repository, with the
+    /// repository's settings taking precedence.
+    pub fn load(system_manifests_directory: &Path) -> Result<Self> {
+        let mut config = Config::default();
+        let paths = user_config_path()
+            .into_iter()
+            .chain([system_manifests_directory.join(CONFIG_FILE_NAME)]);
+        for path in paths {
+            if let Some(file_config) = Config::read(&path)? {
+                config.merge(file_config);
+            }
+        }
+        Ok(config)
+    }
+
+    /// Overrides settings with the ones from `other`, adding up exclusions, mappings and lint
+    /// rules.
+    fn merge(&mut self, other: Config) {
+        self.kinds = other.kinds.or(self.kinds.take());
+        self.exclude.extend(other.exclude);
+        self.contexts.extend(other.contexts);
+        self.clusters.extend(other.clusters);
+        self.lint.merge(other.lint);
+        self.max_depth = other.max_depth.or(self.max_depth);
+        self.include_bootstrap = other.include_bootstrap.or(self.include_bootstrap);
+        self.skip_invalid = other.skip_invalid.or(self.skip_invalid);
+        self.strict = other.strict.or(self.strict);
+        self.fail_on = other.fail_on.or(self.fail_on);
+        self.render = other.render.or(self.render.take());
+        self.discover = other.discover.or(self.discover);
+        self.cache = other.cache.or(self.cache);
+        self.owner_key = other.owner_key.or(self.owner_key.take());
+    }
+
+    /// Returns how to reach the cluster of each platform configured, from `contexts` and
+    /// `clusters`.
+    pub fn clusters(&self) -> BTreeMap<String, Cluster> {
+        let mut clusters: BTreeMap<String, Cluster> = self
+            .contexts
+            .iter()
+            .map(|(platform, context)| {
+                (
+                    platform.clone(),
+                    Cluster {
+                        context: Some(context.clone()),
+                        kubeconfig: None,
+                    },
+                )
+            })
+            .collect();
+        clusters.extend(self.clusters.clone());
+        clusters
+    }
+
+    /// Returns the checks of the given rules on the given resources, leaving out rules that are
+    /// off.
+    pub fn checks<'a>(
+        &self,
+        resources: &'a [ManifestResource],
+        rules: impl IntoIterator<Item = &'a str>,
+    ) -> Checks<'a> {
+        Checks {
+            resources,
+            rules: rules
+                .into_iter()
+                .filter(|rule| !matches!(self.lint.rules.get(*rule), Some(RuleLevel::Off)))
+                .map(str::to_owned)
+                .collect(),
+        }
+    }
+
+    /// Applies the configured rule levels, dropping the findings of rules that are off.
+    pub fn apply_lint(&self, findings: Vec<Finding>) -> Vec<Finding> {
+        findings
+            .into_iter()
+            .filter_map(|mut finding| {
+                match self.lint.rules.get(&finding.rule) {
+                    Some(RuleLevel::Off) => return None,
+                    Some(RuleLevel::Warning) => finding.severity = Severity::Warning,
+                    Some(RuleLevel::Error) => finding.severity = Severity::Error,
+                    None => {}
+                }
+                Some(finding)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,234 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use serde_json::{json, Map, Value};
+use std::path::Path;
+use tracing::debug;
+
+use crate::edit::EditedResource;
+use crate::eso::{
+    ExternalSecretData, ExternalSecretSpec, ExternalSecretTarget, RemoteReference, SecretStoreRef,
+    SecretTemplate,
+};
+use crate::inventory::StoreRef;
+use crate::providers::{Registry, Store};
+use crate::stores::{self, SecretStores};
+use crate::system_manifests::SystemManifests;
+
+/// How to convert the Secrets of a manifest file.
+pub struct ConvertOptions<'a> {
+    /// The store the ExternalSecrets read from.
+    pub store: StoreRef,
+    /// Remote key each Secret is stored at, with `{namespace}` and `{name}` replaced.
+    pub key: &'a str,
+    pub refresh_interval: &'a str,
+    /// Whether to write the values of the Secrets to the store's backend.
+    pub write: bool,
+    /// Whether to only resolve the backends to write to, without writing.
+    pub dry_run: bool,
+}
+
+/// Splits the contents of a manifest file into its YAML documents, without the `---` lines
+/// between them.
+fn split_documents(contents: &str) -> Vec<String> {
+    let mut documents = vec![String::new()];
+    for line in contents.split_inclusive('\n') {
+        let marker = line.trim_end();
+        if marker == "---" || marker.starts_with("--- ") {
+            documents.push(String::new());
+        } else if let Some(document) = documents.last_mut() {
+            document.push_str(line);
+        }
+    }
+    documents
+}
+
+/// Returns the plain values of a Secret, decoding the base64 encoded `data`.
+fn secret_values(secret: &Value) -> Result<Map<String, Value>> {
+    let mut values = Map::new();
+    let data = secret.get("data").and_then(Value::as_object).into_iter();
+    for (key, value) in data.flatten() {
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(value.as_str().unwrap_or_default())
+            .with_context(|| format!("Value of {} isn't valid base64", key))?;
+        let decoded = String::from_utf8(decoded)
+            .with_context(|| format!("Value of {} isn't text, which secret stores need", key))?;
+        values.insert(key.clone(), Value::String(decoded));
+    }
+    let string_data = secret.get("stringData").and_then(Value::as_object);
+    for (key, value) in string_data.into_iter().flatten() {
+        values.insert(key.clone(), value.clone());
+    }
+    values.sort_keys();
+    Ok(values)
+}
+
+/// Returns the ExternalSecret that recreates a Secret from a remote key holding its values as
+/// properties, keeping its labels, annotations and type.
+fn external_secret(
+    secret: &Value,
+    values: &Map<String, Value>,
+    options: &ConvertOptions,
+    remote_key: &str,
+) -> Value {
+    let metadata = secret.get("metadata").cloned().unwrap_or_default();
+    let mut external_metadata = Map::new();
+    let mut template_metadata = Map::new();
+    for field in ["name", "namespace", "labels", "annotations"] {
+        if let Some(value) = metadata.get(field) {
+            external_metadata.insert(field.to_owned(), value.clone());
+            if matches!(field, "labels" | "annotations") {
+                template_metadata.insert(field.to_owned(), value.clone());
+            }
+        }
+    }
+    let template = SecretTemplate {
+        metadata: Some(Value::Object(template_metadata)),
+        secret_type: secret
+            .get("type")
+            .and_then(Value::as_str)
+            .filter(|secret_type| *secret_type != "Opaque")
+            .map(str::to_owned),
+        ..Default::default()
+    };
+    let data = values
+        .keys()
+        .map(|key| ExternalSecretData {
+            secret_key: Some(key.clone()),
+            remote_ref: Some(RemoteReference {
+                key: Some(remote_key.to_owned()),
+                property: Some(key.clone()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        })
+        .collect();
+    let spec = ExternalSecretSpec {
+        refresh_interval: Some(options.refresh_interval.to_owned()),
+        secret_store_ref: Some(SecretStoreRef {
+            name: Some(options.store.name.clone()),
+            kind: Some(options.store.kind.clone()),
+            ..Default::default()
+        }),
+        target: Some(ExternalSecretTarget {
+            name: metadata
+                .get("name")
+                .and_then(Value::as_str)
+                .map(str::to_owned),
+            creation_policy: Some("Owner".to_owned()),
+            template: Some(template),
+            ..Default::default()
+        }),
+        data,
+        ..Default::default()
+    };
+    json!({
+        "apiVersion": "external-secrets.io/v1beta1",
+        "kind": "ExternalSecret",
+        "metadata": external_metadata,
+        "spec": spec,
+    })
+}
+
+/// Rewrites the Secrets with inline data in a manifest file into ExternalSecrets, writing their
+/// values to the store's backend first if asked to. Other documents are kept as they are.
+///
+/// Returns the rewritten contents and the Secrets converted.
+pub fn convert(
+    system_manifests: &SystemManifests,
+    file: &Path,
+    options: &ConvertOptions,
+    registry: &mut Registry,
+) -> Result<(String, Vec<EditedResource>)> {
+    let file = std::fs::canonicalize(file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let mut secret_stores = SecretStores::default();
+    let mut platform = None;
+    for manifest_resource_result in system_manifests.resource_iter() {
+        let manifest_resource = manifest_resource_result?;
+        if platform.is_none()
+            && manifest_resource.file.file_name() == file.file_name()
+            && std::fs::canonicalize(&manifest_resource.file).is_ok_and(|path| path == file)
+        {
+            platform = Some(manifest_resource.platform.clone());
+        }
+        secret_stores.insert(manifest_resource);
+    }
+    let platform = platform
+        .with_context(|| format!("{} isn't a manifest file of any component", file.display()))?;
+
+    let contents = std::fs::read_to_string(&file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let mut documents = split_documents(&contents);
+    let mut converted = Vec::new();
+    for document in &mut documents {
+        let Ok(secret) = serde_yaml::from_str::<Value>(document) else {
+            continue;
+        };
+        // SOPS-encrypted Secrets only hold encrypted values.
+        if secret.get("kind").and_then(Value::as_str) != Some("Secret")
+            || secret.get("sops").is_some()
+        {
+            continue;
+        }
+        let name = secret
+            .pointer("/metadata/name")
+            .and_then(Value::as_str)
+            .with_context(|| format!("Secret without a name in {}", file.display()))?;
+        let namespace = secret
+            .pointer("/metadata/namespace")
+            .and_then(Value::as_str)
+            .unwrap_or("default");
+        let values = secret_values(&secret)
+            .with_context(|| format!("Failed to read Secret {}/{}", namespace, name))?;
+        if values.is_empty() {
+            continue;
+        }
+        let remote_key = options
+            .key
+            .replace("{namespace}", namespace)
+            .replace("{name}", name);
+
+        if options.write {
+            let store_resource = secret_stores
+                .resolve(&platform.name, &options.store, Some(namespace))
+                .map_err(anyhow::Error::msg)?;
+            let (provider, provider_spec) = stores::provider(store_resource)
+                .with_context(|| format!("{} has no provider configured", options.store))?;
+            let backend = registry.backend(provider).with_context(|| {
+                format!(
+                    "Writing to {} isn't supported, its provider {} has no backend",
+                    options.store, provider
+                )
+            })?;
+            let store = Store {
+                platform_name: &platform.name,
+                provider: provider_spec,
+            };
+            debug!(
+                provider,
+                platform = %platform.name,
+                store = %options.store,
+                remote_key = %remote_key,
+                dry_run = options.dry_run,
+                "Writing remote key"
+            );
+            if !options.dry_run {
+                backend
+                    .write(&store, &remote_key, &values)
+                    .with_context(|| format!("Failed to write Secret {}/{}", namespace, name))?;
+            }
+        }
+
+        let external_secret = external_secret(&secret, &values, options, &remote_key);
+        *document = serde_yaml::to_string(&external_secret)
+            .with_context(|| "Failed to serialize ExternalSecret")?;
+        converted.push(EditedResource {
+            platform_name: platform.name.clone(),
+            kind: "Secret".to_owned(),
+            namespace: namespace.to_owned(),
+            name: name.to_owned(),
+            file: file.clone(),
+        });
+    }
+    Ok((documents.join("---\n"), converted))
+}
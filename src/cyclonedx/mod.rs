@@ -0,0 +1,128 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::git::GitInfo;
+use crate::inventory::{remote_refs, store_refs};
+use crate::references::produced_secret;
+use crate::system_manifests::ManifestResource;
+
+const CYCLONEDX_SCHEMA: &str = "http://cyclonedx.org/schema/bom-1.5.schema.json";
+
+/// Namespace of the properties of the components, as CycloneDX expects custom properties to be
+/// prefixed with the name of the tool.
+const PROPERTY_PREFIX: &str = concat!(env!("CARGO_PKG_NAME"), ":");
+
+fn property(name: &str, value: impl Into<String>) -> Value {
+    json!({ "name": format!("{}{}", PROPERTY_PREFIX, name), "value": value.into() })
+}
+
+fn component(manifest_resource: &ManifestResource, directory: &Path) -> Value {
+    let resource = &manifest_resource.resource;
+    let kind = resource
+        .types
+        .as_ref()
+        .map(|types| types.kind.as_str())
+        .unwrap_or_default();
+    let name = resource.metadata.name.as_deref().unwrap_or_default();
+    let namespace = resource.metadata.namespace.as_deref();
+    let file = manifest_resource
+        .file
+        .strip_prefix(directory)
+        .unwrap_or(&manifest_resource.file)
+        .to_string_lossy()
+        .replace('\\', "/");
+    let bom_ref = [
+        Some(manifest_resource.platform.name.as_str()),
+        namespace,
+        Some(kind),
+        Some(name),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join("/");
+
+    let mut properties = vec![
+        property("platform", manifest_resource.platform.name.as_str()),
+        property("component", manifest_resource.component.name.as_str()),
+        property("kind", kind),
+        property("file", file),
+    ];
+    if let Some(line) = manifest_resource.line {
+        properties.push(property("line", line.to_string()));
+    }
+    if let Some(target) = produced_secret(resource) {
+        properties.push(property("target", target.name));
+    }
+    // Properties may repeat, once per store and remote key the resource uses.
+    properties.extend(
+        store_refs(resource)
+            .iter()
+            .map(|store_ref| property("store", store_ref.to_string())),
+    );
+    properties.extend(
+        remote_refs(resource)
+            .iter()
+            .map(|remote_ref| property("remote-key", remote_ref.to_string())),
+    );
+
+    let mut component = json!({
+        "type": "data",
+        "bom-ref": bom_ref,
+        "name": name,
+        "properties": properties,
+    });
+    if let Some(namespace) = namespace {
+        component["group"] = json!(namespace);
+    }
+    component
+}
+
+/// Returns the revision of the system manifests as properties of the metadata of a BOM.
+pub fn git_properties(git: &GitInfo) -> Value {
+    let mut properties = vec![property("git-commit", git.commit.as_str())];
+    if let Some(branch) = &git.branch {
+        properties.push(property("git-branch", branch.as_str()));
+    }
+    properties.push(property("git-dirty", git.dirty.to_string()));
+    json!(properties)
+}
+
+/// Converts secret resources into a CycloneDX 1.5 BOM with a data component per resource,
+/// carrying its platform, stores and remote keys as properties. Files are relative to the given
+/// directory.
+pub fn to_cyclonedx(manifest_resources: &[ManifestResource], directory: &Path) -> Value {
+    // References have to be unique, which they aren't for resources declared more than once.
+    let mut references: HashMap<String, usize> = HashMap::new();
+    let components: Vec<Value> = manifest_resources
+        .iter()
+        .map(|manifest_resource| {
+            let mut component = component(manifest_resource, directory);
+            let bom_ref = component["bom-ref"].as_str().unwrap_or_default().to_owned();
+            let count = references.entry(bom_ref.clone()).or_default();
+            *count += 1;
+            if *count > 1 {
+                component["bom-ref"] = json!(format!("{}#{}", bom_ref, count));
+            }
+            component
+        })
+        .collect();
+    json!({
+        "$schema": CYCLONEDX_SCHEMA,
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "version": 1,
+        "metadata": {
+            "timestamp": chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            "tools": {
+                "components": [{
+                    "type": "application",
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+            },
+        },
+        "components": components,
+    })
+}
@@ -0,0 +1,236 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+use crate::inventory::{self, remote_refs, store_refs};
+use crate::references::produced_secret;
+use crate::system_manifests::{Discovery, ManifestResource, SystemManifests};
+
+/// The system manifests as found in a directory or at a git reference.
+pub struct Snapshot {
+    pub system_manifests: SystemManifests,
+    /// Keeps the checkout of a git reference alive for as long as the snapshot is used.
+    _checkout: Option<tempfile::TempDir>,
+}
+
+impl Snapshot {
+    /// Opens `ref_or_path` as a directory if one exists, or else as a git reference in the
+    /// repository containing `repository_directory`, finding components as `discovery` says
+    /// and failing on platforms missing a directory if `strict`.
+    pub fn open(
+        repository_directory: &Path,
+        ref_or_path: &str,
+        discovery: Discovery,
+        strict: bool,
+    ) -> Result<Self> {
+        let path = Path::new(ref_or_path);
+        if path.is_dir() {
+            return Ok(Snapshot {
+                system_manifests: SystemManifests::from_directory(
+                    path.to_owned(),
+                    discovery,
+                    strict,
+                )?,
+                _checkout: None,
+            });
+        }
+
+        let checkout =
+            tempfile::tempdir().with_context(|| "Failed to create a temporary directory")?;
+        git::export_reference(repository_directory, ref_or_path, checkout.path())?;
+        let system_manifests =
+            SystemManifests::from_directory(checkout.path().to_owned(), discovery, strict)
+                .with_context(|| format!("Failed to read system manifests at {}", ref_or_path))?;
+        Ok(Snapshot {
+            system_manifests,
+            _checkout: Some(checkout),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeType {
+    Added,
+    Removed,
+    Changed,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldChange {
+    pub field: &'static str,
+    pub base: Value,
+    pub head: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SecretChange {
+    /// The repository the platform is read from, if several are.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    pub platform_name: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub change: ChangeType,
+    /// Path relative to the system manifests directory, from head unless the secret was removed.
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldChange>,
+}
+
+/// Identifies a secret resource by repository, platform, kind, namespace and name. Duplicates
+/// of a resource in different files share one.
+type SecretKey = (Option<String>, String, String, Option<String>, String);
+
+struct SecretSummary {
+    file: PathBuf,
+    fields: Vec<(&'static str, Value)>,
+}
+
+fn summarize(
+    manifest_resource: &ManifestResource,
+    system_manifests: &SystemManifests,
+) -> SecretSummary {
+    let resource = &manifest_resource.resource;
+    let mut store_refs: Vec<String> = store_refs(resource).iter().map(|s| s.to_string()).collect();
+    store_refs.sort();
+    let mut remote_refs: Vec<String> = remote_refs(resource)
+        .iter()
+        .map(|r| r.to_string())
+        .collect();
+    remote_refs.sort();
+
+    SecretSummary {
+        file: manifest_resource
+            .file
+            .strip_prefix(system_manifests.repository_directory(&manifest_resource.file))
+            .unwrap_or(&manifest_resource.file)
+            .to_owned(),
+        fields: vec![
+            ("component", json!(manifest_resource.component.name)),
+            (
+                "api_version",
+                json!(resource.types.as_ref().map(|t| &t.api_version)),
+            ),
+            ("labels", json!(resource.metadata.labels)),
+            ("annotations", json!(resource.metadata.annotations)),
+            ("target", json!(produced_secret(resource).map(|s| s.name))),
+            ("store_refs", json!(store_refs)),
+            ("remote_refs", json!(remote_refs)),
+        ],
+    }
+}
+
+fn secret_summaries(
+    system_manifests: &SystemManifests,
+) -> Result<BTreeMap<SecretKey, Vec<SecretSummary>>> {
+    let mut summaries: BTreeMap<SecretKey, Vec<SecretSummary>> = BTreeMap::new();
+    for manifest_resource in inventory::secret_resources(system_manifests, &[])? {
+        let key = (
+            manifest_resource.platform.repo.clone(),
+            manifest_resource.platform.name.clone(),
+            manifest_resource
+                .resource
+                .types
+                .as_ref()
+                .map(|t| t.kind.clone())
+                .unwrap_or_default(),
+            manifest_resource.resource.metadata.namespace.clone(),
+            manifest_resource
+                .resource
+                .metadata
+                .name
+                .clone()
+                .unwrap_or_default(),
+        );
+        summaries
+            .entry(key)
+            .or_default()
+            .push(summarize(&manifest_resource, system_manifests));
+    }
+    Ok(summaries)
+}
+
+/// Takes the base summary of a head summary out of the ones sharing its key, preferring the one
+/// in the same file so that duplicates are compared with their own counterpart.
+fn take_base_summary(
+    base_summaries: &mut BTreeMap<SecretKey, Vec<SecretSummary>>,
+    key: &SecretKey,
+    head_summary: &SecretSummary,
+) -> Option<SecretSummary> {
+    let summaries = base_summaries.get_mut(key)?;
+    let index = summaries
+        .iter()
+        .position(|summary| summary.file == head_summary.file)
+        .unwrap_or(0);
+    let summary = (index < summaries.len()).then(|| summaries.remove(index));
+    if summaries.is_empty() {
+        base_summaries.remove(key);
+    }
+    summary
+}
+
+/// Returns the secret resources added, removed or changed between two snapshots.
+pub fn diff(base: &SystemManifests, head: &SystemManifests) -> Result<Vec<SecretChange>> {
+    let mut base_summaries = secret_summaries(base)?;
+    let head_summaries = secret_summaries(head)?;
+
+    let change = |key: &SecretKey, change, file: &Path, fields| {
+        let (repo, platform_name, kind, namespace, name) = key.clone();
+        SecretChange {
+            repo,
+            platform_name,
+            kind,
+            namespace,
+            name,
+            change,
+            file: file.to_owned(),
+            fields,
+        }
+    };
+
+    let mut changes = Vec::new();
+    for (key, head_summaries) in &head_summaries {
+        for head_summary in head_summaries {
+            match take_base_summary(&mut base_summaries, key, head_summary) {
+                None => changes.push(change(
+                    key,
+                    ChangeType::Added,
+                    &head_summary.file,
+                    Vec::new(),
+                )),
+                Some(base_summary) => {
+                    let fields: Vec<FieldChange> = base_summary
+                        .fields
+                        .into_iter()
+                        .zip(&head_summary.fields)
+                        .filter(|((_, base_value), (_, head_value))| base_value != head_value)
+                        .map(|((field, base_value), (_, head_value))| FieldChange {
+                            field,
+                            base: base_value,
+                            head: head_value.clone(),
+                        })
+                        .collect();
+                    if !fields.is_empty() {
+                        changes.push(change(key, ChangeType::Changed, &head_summary.file, fields));
+                    }
+                }
+            }
+        }
+    }
+    for (key, base_summaries) in &base_summaries {
+        for base_summary in base_summaries {
+            changes.push(change(
+                key,
+                ChangeType::Removed,
+                &base_summary.file,
+                Vec::new(),
+            ));
+        }
+    }
+    Ok(changes)
+}
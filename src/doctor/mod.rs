@@ -0,0 +1,491 @@
+use anyhow::Result;
+use futures::future::join_all;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::cluster::{self, ClusterContexts};
+use crate::providers::{Registry, Store};
+use crate::stores::{self, STORE_KINDS};
+use crate::system_manifests::{self, Discovery, ManifestResource, Platform, IGNORE_FILE_NAME};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// The directories of a system manifests repository.
+    Layout,
+    /// The directories and components of a platform.
+    Platform,
+    /// A tool commands run.
+    Tool,
+    /// The kubeconfig context of a platform and whether its cluster answers.
+    Cluster,
+    /// Whether the backend of a secret store provider can authenticate.
+    Credentials,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ok,
+    /// Something some commands or options need is missing.
+    Warning,
+    /// Something commands need is missing or broken.
+    Error,
+}
+
+/// The outcome of a check, with a hint on how to fix it if it didn't pass.
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnosis {
+    pub check: Check,
+    /// The platform checked, if the check is about one.
+    pub platform: Option<String>,
+    pub status: Status,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Diagnosis {
+    fn new(check: Check, status: Status, message: String) -> Self {
+        Diagnosis {
+            check,
+            platform: None,
+            status,
+            message,
+            hint: None,
+        }
+    }
+
+    fn ok(check: Check, message: String) -> Self {
+        Self::new(check, Status::Ok, message)
+    }
+
+    fn warning(check: Check, message: String, hint: impl Into<String>) -> Self {
+        Self::new(check, Status::Warning, message).with_hint(hint)
+    }
+
+    fn error(check: Check, message: String, hint: impl Into<String>) -> Self {
+        Self::new(check, Status::Error, message).with_hint(hint)
+    }
+
+    fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    fn on(mut self, platform: &str) -> Self {
+        self.platform = Some(platform.to_owned());
+        self
+    }
+}
+
+/// A tool some commands or options run, by the names of the executables that will do.
+struct Tool {
+    name: &'static str,
+    executables: &'static [&'static str],
+    /// What runs the tool, for the hint when it's missing.
+    needed_for: &'static str,
+}
+
+const TOOLS: &[Tool] = &[
+    Tool {
+        name: "git",
+        executables: &["git"],
+        needed_for: "repository URLs, --changed-since, --include-git-info and pull requests",
+    },
+    Tool {
+        name: "kustomize",
+        executables: &["kustomize", "kubectl"],
+        needed_for: "--render kustomize",
+    },
+    Tool {
+        name: "helm",
+        executables: &["helm"],
+        needed_for: "--render helm",
+    },
+    Tool {
+        name: "sops",
+        executables: &["sops"],
+        needed_for: "--decrypt-sops",
+    },
+    Tool {
+        name: "flux",
+        executables: &["flux"],
+        needed_for: "--oci",
+    },
+    Tool {
+        name: "cosign",
+        executables: &["cosign"],
+        needed_for: "export --sign",
+    },
+];
+
+/// How to get credentials for the built-in secret store providers.
+const CREDENTIALS_HINTS: &[(&str, &str)] = &[
+    (
+        "vault",
+        "Set VAULT_TOKEN or run `vault login`, or pass --vault-auth oidc to log in through the \
+        browser",
+    ),
+    (
+        "aws",
+        "Run `aws sso login` or `aws configure`, or pass --aws-profile PLATFORM=PROFILE",
+    ),
+    (
+        "azurekv",
+        "Run `az login`, or pass --azure-auth managed-identity or --azure-auth environment",
+    ),
+    (
+        "gcpsm",
+        "Run `gcloud auth login`, or pass --gcp-impersonate-service-account",
+    ),
+];
+
+fn relative<'a>(path: &'a Path, directory: &Path) -> &'a Path {
+    path.strip_prefix(directory).unwrap_or(path)
+}
+
+/// Checks that a system manifests directory has the `clusters`, `environments` and `manifests`
+/// directories, and that every platform has its directories in each of them. Missing
+/// directories are warnings, as platforms are read without them, unless `strict`. Returns the
+/// platforms that could be read, rather than failing on the first one that couldn't.
+pub fn check_layout(
+    directory: &Path,
+    discovery: Discovery,
+    strict: bool,
+) -> (Vec<Diagnosis>, Vec<Rc<Platform>>) {
+    let mut diagnoses = Vec::new();
+    if !directory.is_dir() {
+        diagnoses.push(Diagnosis::error(
+            Check::Layout,
+            format!("{} is not a directory", directory.display()),
+            "Pass the path or URL of a system manifests repository with --system-manifests",
+        ));
+        return (diagnoses, Vec::new());
+    }
+
+    // Without the fixed layout, components are found from the clusters directory alone.
+    let required: &[&str] = match discovery {
+        Discovery::Directories => &["clusters", "environments", "manifests"],
+        _ => &["clusters"],
+    };
+    let mut missing = Vec::new();
+    for name in required {
+        if !directory.join(name).is_dir() {
+            missing.push(*name);
+        }
+    }
+    if missing.contains(&"clusters") {
+        diagnoses.push(Diagnosis::error(
+            Check::Layout,
+            format!("{} has no clusters directory", directory.display()),
+            "Point --system-manifests at the root of the repository, the directory with \
+            clusters/, environments/ and manifests/",
+        ));
+        return (diagnoses, Vec::new());
+    }
+    let missing_status = if strict {
+        Status::Error
+    } else {
+        Status::Warning
+    };
+    for name in &missing {
+        diagnoses.push(
+            Diagnosis::new(
+                Check::Layout,
+                missing_status,
+                format!("{} has no {} directory", directory.display(), name),
+            )
+            .with_hint(format!(
+                "Create {}/ with a directory per platform, or pass --discover flux or \
+                --discover argocd if components are found from the clusters directory",
+                name
+            )),
+        );
+    }
+
+    let names = match system_manifests::platform_names(directory) {
+        Ok(names) => names,
+        Err(error) => {
+            diagnoses.push(Diagnosis::error(
+                Check::Layout,
+                format!("{:#}", error),
+                format!(
+                    "Fix or remove {}",
+                    directory.join(IGNORE_FILE_NAME).display()
+                ),
+            ));
+            return (diagnoses, Vec::new());
+        }
+    };
+    if missing.is_empty() {
+        diagnoses.push(Diagnosis::ok(
+            Check::Layout,
+            format!(
+                "{} has {} platforms: {}",
+                directory.display(),
+                names.len(),
+                names.join(", ")
+            ),
+        ));
+    }
+
+    let mut platforms = Vec::new();
+    for name in names {
+        let platform_directories: Vec<PathBuf> = required
+            .iter()
+            .filter(|parent| !missing.contains(parent))
+            .map(|parent| directory.join(parent).join(&name))
+            .filter(|platform_directory| !platform_directory.is_dir())
+            .collect();
+        if !platform_directories.is_empty() {
+            let platform_directories: Vec<String> = platform_directories
+                .iter()
+                .map(|path| relative(path, directory).display().to_string())
+                .collect();
+            diagnoses.push(
+                Diagnosis::new(
+                    Check::Platform,
+                    missing_status,
+                    format!("Missing {}", platform_directories.join(" and ")),
+                )
+                .with_hint(format!(
+                    "Create {}, or leave the platform out by adding clusters/{} to {}",
+                    platform_directories.join(" and "),
+                    name,
+                    IGNORE_FILE_NAME
+                ))
+                .on(&name),
+            );
+            if strict {
+                continue;
+            }
+        }
+        if strict && !missing.is_empty() {
+            continue;
+        }
+        match Platform::new(name.clone(), directory.to_owned(), discovery, strict) {
+            Ok(platform) => {
+                diagnoses.push(
+                    Diagnosis::ok(
+                        Check::Platform,
+                        format!("Found {} components", platform.components.len()),
+                    )
+                    .on(&name),
+                );
+                platforms.push(Rc::new(platform));
+            }
+            Err(error) => diagnoses.push(
+                Diagnosis::error(
+                    Check::Platform,
+                    format!("{:#}", error),
+                    "Fix the platform's directories, or leave it out with the ignore file",
+                )
+                .on(&name),
+            ),
+        }
+    }
+    (diagnoses, platforms)
+}
+
+/// Returns the first of the executables found on `PATH`, along with where it was found.
+fn find_executable<'a>(executables: &[&'a str]) -> Option<(&'a str, PathBuf)> {
+    let path = std::env::var_os("PATH")?;
+    let directories: Vec<PathBuf> = std::env::split_paths(&path).collect();
+    executables.iter().find_map(|executable| {
+        let file_name = format!("{}{}", executable, std::env::consts::EXE_SUFFIX);
+        directories
+            .iter()
+            .map(|directory| directory.join(&file_name))
+            .find(|file| file.is_file())
+            .map(|file| (*executable, file))
+    })
+}
+
+/// Checks that the tools commands run are installed. Missing tools are errors if the given
+/// options need them, and warnings otherwise.
+pub fn check_tools(needed: &[&str]) -> Vec<Diagnosis> {
+    TOOLS
+        .iter()
+        .map(|tool| match find_executable(tool.executables) {
+            Some((executable, file)) => Diagnosis::ok(
+                Check::Tool,
+                format!("{} is installed at {}", executable, file.display()),
+            ),
+            None => {
+                let message = format!("{} is not installed", tool.executables.join(" or "));
+                let hint = format!("Install {} to use {}", tool.name, tool.needed_for);
+                if needed.contains(&tool.name) {
+                    Diagnosis::error(Check::Tool, message, hint)
+                } else {
+                    Diagnosis::warning(Check::Tool, message, hint)
+                }
+            }
+        })
+        .collect()
+}
+
+/// Checks that the kubeconfig has the context of every platform and, unless offline, that its
+/// cluster answers within the timeout.
+pub fn check_clusters(
+    platforms: &[Rc<Platform>],
+    contexts: &ClusterContexts,
+    offline: bool,
+) -> Result<Vec<Diagnosis>> {
+    let hint = |platform: &Platform| {
+        format!(
+            "Set KUBECONFIG or the kubeconfig of clusters.{} in the config, and pass --context \
+            {}=CONTEXT unless the context is named after the platform",
+            platform.name, platform.name
+        )
+    };
+    let mut diagnoses = Vec::new();
+    let mut reachable = Vec::new();
+    for platform in platforms {
+        match contexts.resolve_context(platform) {
+            Ok(context) if offline => diagnoses.push(
+                Diagnosis::ok(Check::Cluster, format!("Context {} exists", context))
+                    .on(&platform.name),
+            ),
+            Ok(context) => reachable.push((platform, context)),
+            Err(error) => diagnoses.push(
+                Diagnosis::error(Check::Cluster, format!("{:#}", error), hint(platform))
+                    .on(&platform.name),
+            ),
+        }
+    }
+
+    let timeout = contexts.timeout;
+    let queries = reachable.into_iter().map(|(platform, context)| async move {
+        let query = async {
+            let client = contexts.client_for(platform).await?;
+            anyhow::Ok(client.apiserver_version().await?)
+        };
+        let diagnosis = match tokio::time::timeout(timeout, query).await {
+            Ok(Ok(version)) => Diagnosis::ok(
+                Check::Cluster,
+                format!(
+                    "Cluster of context {} answers, running Kubernetes {}",
+                    context, version.git_version
+                ),
+            ),
+            Ok(Err(error)) => Diagnosis::error(
+                Check::Cluster,
+                format!("Cluster of context {} doesn't answer: {:#}", context, error),
+                "Log in to the cluster, or check the server and credentials of the context",
+            ),
+            Err(_) => Diagnosis::error(
+                Check::Cluster,
+                format!(
+                    "Cluster of context {} didn't answer within {}s",
+                    context,
+                    timeout.as_secs_f64()
+                ),
+                "Check the network path to the cluster, or raise --cluster-timeout",
+            ),
+        };
+        diagnosis.on(&platform.name)
+    });
+    diagnoses.extend(cluster::block_on(join_all(queries))?);
+    Ok(diagnoses)
+}
+
+/// Checks that the backends of the providers of the declared secret stores can authenticate,
+/// once per platform and provider configuration.
+pub fn check_credentials(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+    registry: &mut Registry,
+) -> Result<Vec<Diagnosis>> {
+    let mut checked = BTreeSet::new();
+    let mut diagnoses = Vec::new();
+    for manifest_resource in resources {
+        let manifest_resource = manifest_resource?;
+        let resource = &manifest_resource.resource;
+        let kind = resource
+            .types
+            .as_ref()
+            .map(|types| types.kind.as_str())
+            .unwrap_or_default();
+        if !STORE_KINDS.contains(&kind) {
+            continue;
+        }
+        let Some((provider, configuration)) = stores::provider(&manifest_resource) else {
+            continue;
+        };
+        let platform_name = &manifest_resource.platform.name;
+        if !checked.insert((
+            platform_name.clone(),
+            provider.to_owned(),
+            configuration.to_string(),
+        )) {
+            continue;
+        }
+        let store_name = format!(
+            "{} {}",
+            kind,
+            resource.metadata.name.as_deref().unwrap_or_default()
+        );
+        let Some(backend) = registry.backend(provider) else {
+            diagnoses.push(
+                Diagnosis::ok(
+                    Check::Credentials,
+                    format!(
+                        "{} uses provider {}, which has no backend to check",
+                        store_name, provider
+                    ),
+                )
+                .on(platform_name),
+            );
+            continue;
+        };
+        let store = Store {
+            platform_name,
+            provider: configuration,
+        };
+        let diagnosis = match backend.identity(&store) {
+            Ok(Some(identity)) => Diagnosis::ok(
+                Check::Credentials,
+                format!(
+                    "{} authenticates to {} as {}",
+                    store_name, provider, identity
+                ),
+            ),
+            Ok(None) => Diagnosis::ok(
+                Check::Credentials,
+                format!(
+                    "{} uses provider {}, whose backend can't check its credentials",
+                    store_name, provider
+                ),
+            ),
+            Err(error) => {
+                let not_installed = error.chain().any(|cause| {
+                    cause
+                        .downcast_ref::<std::io::Error>()
+                        .is_some_and(|error| error.kind() == ErrorKind::NotFound)
+                });
+                let hint = if not_installed {
+                    format!("Install the CLI the {} backend runs", provider)
+                } else {
+                    CREDENTIALS_HINTS
+                        .iter()
+                        .find(|(name, _)| *name == provider)
+                        .map_or("Check the credentials of the provider", |(_, hint)| hint)
+                        .to_owned()
+                };
+                Diagnosis::error(
+                    Check::Credentials,
+                    format!(
+                        "{} can't authenticate to {}: {:#}",
+                        store_name, provider, error
+                    ),
+                    hint,
+                )
+            }
+        };
+        diagnoses.push(diagnosis.on(platform_name));
+    }
+    Ok(diagnoses)
+}
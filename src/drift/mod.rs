@@ -0,0 +1,226 @@
+use anyhow::{Context, Result};
+use futures::FutureExt;
+use kube::api::{Api, ApiResource, DynamicObject, ListParams, ObjectMeta};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use crate::cluster::{api_resource_for, ClusterContexts};
+use crate::system_manifests::{ManifestResource, Platform, SystemManifests};
+
+const DRIFT_KINDS: &[&str] = &["Secret", "ExternalSecret"];
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftStatus {
+    /// Declared in the manifests but absent from the cluster.
+    Missing,
+    /// Present in the cluster but not declared in the manifests.
+    Extra,
+    /// Present in both, with labels or annotations that differ.
+    MetadataDivergent,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MetadataDifference {
+    pub field: &'static str,
+    pub key: String,
+    pub declared: Option<String>,
+    pub live: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Drift {
+    pub platform_name: String,
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    pub status: DriftStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component_name: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub differences: Vec<MetadataDifference>,
+}
+
+/// Identifies a resource by kind, namespace and name.
+type ResourceKey = (String, String, String);
+
+fn resource_key(kind: &str, metadata: &ObjectMeta) -> ResourceKey {
+    (
+        kind.to_owned(),
+        metadata
+            .namespace
+            .clone()
+            .unwrap_or_else(|| "default".to_owned()),
+        metadata.name.clone().unwrap_or_default(),
+    )
+}
+
+/// Live resources created by controllers rather than declared by hand.
+fn is_managed_by_cluster(kind: &str, metadata: &ObjectMeta) -> bool {
+    if metadata
+        .owner_references
+        .as_ref()
+        .is_some_and(|owner_references| !owner_references.is_empty())
+    {
+        return true;
+    }
+    kind == "Secret"
+        && (metadata.annotations.as_ref().is_some_and(|annotations| {
+            annotations.contains_key("kubernetes.io/service-account.name")
+        }) || metadata
+            .labels
+            .as_ref()
+            .is_some_and(|labels| labels.get("owner").is_some_and(|owner| owner == "helm")))
+}
+
+fn metadata_differences(declared: &ObjectMeta, live: &ObjectMeta) -> Vec<MetadataDifference> {
+    let mut differences = Vec::new();
+    for (field, declared_map, live_map) in [
+        ("labels", &declared.labels, &live.labels),
+        ("annotations", &declared.annotations, &live.annotations),
+    ] {
+        for (key, declared_value) in declared_map.iter().flatten() {
+            let live_value = live_map.as_ref().and_then(|live_map| live_map.get(key));
+            if live_value != Some(declared_value) {
+                differences.push(MetadataDifference {
+                    field,
+                    key: key.clone(),
+                    declared: Some(declared_value.clone()),
+                    live: live_value.cloned(),
+                });
+            }
+        }
+    }
+    differences
+}
+
+async fn platform_drift(
+    platform: &Platform,
+    declared: Vec<ManifestResource>,
+    contexts: &ClusterContexts,
+) -> Result<Vec<Drift>> {
+    if declared.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut api_resources: BTreeMap<String, ApiResource> = BTreeMap::new();
+    let mut declared_by_key: BTreeMap<ResourceKey, ManifestResource> = BTreeMap::new();
+    for manifest_resource in declared {
+        let api_resource = api_resource_for(&manifest_resource)?;
+        let key = resource_key(&api_resource.kind, &manifest_resource.resource.metadata);
+        api_resources
+            .entry(api_resource.kind.clone())
+            .or_insert(api_resource);
+        declared_by_key.insert(key, manifest_resource);
+    }
+    let namespaces: BTreeSet<String> = declared_by_key
+        .keys()
+        .map(|(_, namespace, _)| namespace.clone())
+        .collect();
+
+    let client = contexts.client_for(platform).await?;
+    let mut live_by_key: BTreeMap<ResourceKey, ObjectMeta> = BTreeMap::new();
+    for (kind, api_resource) in &api_resources {
+        for namespace in &namespaces {
+            let api: Api<DynamicObject> =
+                Api::namespaced_with(client.clone(), namespace, api_resource);
+            let live = api
+                .list_metadata(&ListParams::default())
+                .await
+                .with_context(|| {
+                    format!(
+                        "Failed to list {} in namespace {} on platform {}",
+                        kind, namespace, platform.name
+                    )
+                })?;
+            for item in live.items {
+                live_by_key.insert(resource_key(kind, &item.metadata), item.metadata);
+            }
+        }
+    }
+
+    let mut drifts = Vec::new();
+    for (key, manifest_resource) in &declared_by_key {
+        let (kind, namespace, name) = key.clone();
+        let (status, differences) = match live_by_key.get(key) {
+            None => (DriftStatus::Missing, Vec::new()),
+            Some(live) => {
+                let differences = metadata_differences(&manifest_resource.resource.metadata, live);
+                if differences.is_empty() {
+                    continue;
+                }
+                (DriftStatus::MetadataDivergent, differences)
+            }
+        };
+        drifts.push(Drift {
+            platform_name: platform.name.clone(),
+            kind,
+            namespace,
+            name,
+            status,
+            file: Some(manifest_resource.file.clone()),
+            component_name: Some(manifest_resource.component.name.clone()),
+            differences,
+        });
+    }
+    for (key, live) in &live_by_key {
+        let (kind, namespace, name) = key.clone();
+        if declared_by_key.contains_key(key) || is_managed_by_cluster(&kind, live) {
+            continue;
+        }
+        drifts.push(Drift {
+            platform_name: platform.name.clone(),
+            kind,
+            namespace,
+            name,
+            status: DriftStatus::Extra,
+            file: None,
+            component_name: None,
+            differences: Vec::new(),
+        });
+    }
+
+    Ok(drifts)
+}
+
+/// Compares the Secrets and ExternalSecrets declared per platform with the ones present in the
+/// platform's cluster.
+///
+/// Only namespaces that the manifests declare secrets in are inspected, and live Secrets created
+/// by controllers (owned resources, service account tokens, Helm releases) are never reported as
+/// extra. Clusters are queried concurrently, and the names of the platforms whose cluster
+/// couldn't be queried are returned along with the drift found on the others.
+pub fn find_drift(
+    system_manifests: &SystemManifests,
+    contexts: &ClusterContexts,
+) -> Result<(Vec<Drift>, Vec<String>)> {
+    let mut declared: HashMap<String, Vec<ManifestResource>> = HashMap::new();
+    for manifest_resource_result in system_manifests.resource_iter() {
+        let manifest_resource = manifest_resource_result?;
+        if manifest_resource
+            .resource
+            .types
+            .as_ref()
+            .is_some_and(|t| DRIFT_KINDS.contains(&t.kind.as_str()))
+        {
+            declared
+                .entry(manifest_resource.platform.name.clone())
+                .or_default()
+                .push(manifest_resource);
+        }
+    }
+
+    let queries = system_manifests.platforms.iter().map(|platform| {
+        let platform_declared = declared.remove(&platform.name).unwrap_or_default();
+        (
+            platform.as_ref(),
+            platform_drift(platform, platform_declared, contexts).boxed_local(),
+        )
+    });
+    let (drifts, failed_platforms) = contexts.query_all(queries)?;
+    Ok((drifts.into_iter().flatten().collect(), failed_platforms))
+}
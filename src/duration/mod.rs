@@ -0,0 +1,42 @@
+use anyhow::{Context, Result};
+use std::time::Duration;
+
+/// Parses a Go style duration such as `1h30m`, `15s` or `500ms`, as used by Kubernetes resources.
+///
+/// Days (`30d`) are accepted as well for command line convenience.
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    if value == "0" {
+        return Ok(Duration::ZERO);
+    }
+    anyhow::ensure!(!value.is_empty(), "Empty duration");
+
+    let mut total = Duration::ZERO;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let number_length = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .with_context(|| format!("Duration is missing a unit: {}", value))?;
+        let (number, unit_and_rest) = rest.split_at(number_length);
+        let number: f64 = number
+            .parse()
+            .with_context(|| format!("Invalid number in duration: {}", value))?;
+        let unit_length = unit_and_rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(unit_and_rest.len());
+        let (unit, next) = unit_and_rest.split_at(unit_length);
+        let unit_seconds = match unit {
+            "ns" => 1e-9,
+            "us" | "µs" => 1e-6,
+            "ms" => 1e-3,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            "d" => 86400.0,
+            _ => anyhow::bail!("Unknown unit {} in duration: {}", unit, value),
+        };
+        total += Duration::from_secs_f64(number * unit_seconds);
+        rest = next;
+    }
+    Ok(total)
+}
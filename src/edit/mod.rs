@@ -0,0 +1,72 @@
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use crate::system_manifests::{is_marker, ManifestResource};
+
+/// A resource a command changes the manifest of, as listed in the pull requests it opens.
+#[derive(Debug, Clone)]
+pub struct EditedResource {
+    pub platform_name: String,
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    pub file: PathBuf,
+}
+
+impl From<&ManifestResource> for EditedResource {
+    fn from(manifest_resource: &ManifestResource) -> Self {
+        let resource = &manifest_resource.resource;
+        EditedResource {
+            platform_name: manifest_resource.platform.name.clone(),
+            kind: resource
+                .types
+                .as_ref()
+                .map(|t| t.kind.clone())
+                .unwrap_or_default(),
+            namespace: resource
+                .metadata
+                .namespace
+                .clone()
+                .unwrap_or_else(|| "default".to_owned()),
+            name: resource.metadata.name.clone().unwrap_or_default(),
+            file: manifest_resource.file.clone(),
+        }
+    }
+}
+
+/// Splits the contents of a manifest file into lines, keeping their line endings so that joining
+/// them gives back the contents.
+pub fn lines(contents: &str) -> Vec<String> {
+    contents.split_inclusive('\n').map(str::to_owned).collect()
+}
+
+pub fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+pub fn is_blank_or_comment(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Returns the range of lines of the document starting at the given line, counting from 1, up to
+/// the next `---` or `...` marker.
+pub fn document_range(lines: &[String], line: usize) -> Range<usize> {
+    let start = line.saturating_sub(1).min(lines.len());
+    let end = (start + 1..lines.len())
+        .find(|&index| {
+            let line = lines[index].trim_end();
+            is_marker(line, "---") || is_marker(line, "...")
+        })
+        .unwrap_or(lines.len());
+    start..end
+}
+
+/// Returns a unified diff of the changes to a file, empty if there are none.
+pub fn unified_diff(file: &Path, old: &str, new: &str) -> String {
+    let path = file.display().to_string();
+    similar::TextDiff::from_lines(old, new)
+        .unified_diff()
+        .header(&path, &path)
+        .to_string()
+}
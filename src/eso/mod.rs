@@ -0,0 +1,424 @@
+use kube::api::DynamicObject;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+/// Fields of External Secrets Operator resources that aren't modelled, kept so that specs
+/// serialize back to what they were read from.
+type Other = Map<String, Value>;
+
+/// Deserializes a field, or defaults it if it doesn't have the shape ESO expects, like a null
+/// `dataFrom:` or a list where an object belongs, so that one such field doesn't make the rest of
+/// the spec unreadable. Such values don't serialize back.
+fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned + Default,
+{
+    let value = Value::deserialize(deserializer)?;
+    Ok(serde_json::from_value(value).unwrap_or_default())
+}
+
+/// Deserializes a list like [`lenient`], leaving out the entries that don't have the shape ESO
+/// expects rather than all of them.
+fn lenient_list<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let entries = match Value::deserialize(deserializer)? {
+        Value::Array(entries) => entries,
+        _ => return Ok(Vec::new()),
+    };
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| serde_json::from_value(entry).ok())
+        .collect())
+}
+
+/// A reference to the SecretStore or ClusterSecretStore a resource reads from or writes to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretStoreRef {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// `SecretStore` or `ClusterSecretStore`, ESO defaults to `SecretStore`.
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// A key in a secret store an ExternalSecret reads, with the property and version to read.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteReference {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub property: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// The template of the Secret an ExternalSecret creates.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretTemplate {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Value>,
+    #[serde(rename = "type", default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_type: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Map<String, Value>>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// The Secret an ExternalSecret creates.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalSecretTarget {
+    /// Defaults to the name of the ExternalSecret.
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creation_policy: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deletion_policy: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template: Option<SecretTemplate>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// A key of the Secret an ExternalSecret creates and the remote key its value is read from.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalSecretData {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_ref: Option<RemoteReference>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// The regular expression a `dataFrom` find matches remote key names with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FindName {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub regexp: Option<String>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// Remote keys a `dataFrom` entry finds by path and name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Find {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<FindName>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// An entry of `dataFrom`, reading all the properties of a remote key or the remote keys found.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalSecretDataFrom {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub extract: Option<RemoteReference>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub find: Option<Find>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalSecretSpec {
+    /// Like `1h`, ESO refreshes hourly if unset and never if `0`.
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_interval: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_store_ref: Option<SecretStoreRef>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<ExternalSecretTarget>,
+    #[serde(default, deserialize_with = "lenient_list")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<ExternalSecretData>,
+    #[serde(default, deserialize_with = "lenient_list")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub data_from: Vec<ExternalSecretDataFrom>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// A remote key a PushSecret writes to, and the property of it to write if not all of them.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushSecretRemoteRef {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_key: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub property: Option<String>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// A key of the pushed Secret and the remote key it's written to.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushSecretMatch {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_key: Option<String>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remote_ref: Option<PushSecretRemoteRef>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushSecretData {
+    #[serde(rename = "match", default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub matching: Option<PushSecretMatch>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretSelector {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// What a PushSecret pushes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushSecretSelector {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<SecretSelector>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PushSecretSpec {
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_interval: Option<String>,
+    #[serde(default, deserialize_with = "lenient_list")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub secret_store_refs: Vec<SecretStoreRef>,
+    #[serde(default, deserialize_with = "lenient")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selector: Option<PushSecretSelector>,
+    #[serde(default, deserialize_with = "lenient_list")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub data: Vec<PushSecretData>,
+    #[serde(flatten)]
+    pub other: Other,
+}
+
+/// The spec of an External Secrets Operator resource.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Spec {
+    ExternalSecret(Box<ExternalSecretSpec>),
+    PushSecret(Box<PushSecretSpec>),
+}
+
+fn parse<T: DeserializeOwned>(resource: &DynamicObject) -> Option<T> {
+    serde_json::from_value(resource.data.get("spec")?.clone()).ok()
+}
+
+impl Spec {
+    /// Reads the spec of an ExternalSecret or PushSecret. `None` for other kinds and for specs
+    /// that aren't objects, while fields that don't have the shape ESO expects are left unset.
+    pub fn of(resource: &DynamicObject) -> Option<Self> {
+        match resource.types.as_ref()?.kind.as_str() {
+            "ExternalSecret" => parse(resource).map(|spec| Spec::ExternalSecret(Box::new(spec))),
+            "PushSecret" => parse(resource).map(|spec| Spec::PushSecret(Box::new(spec))),
+            _ => None,
+        }
+    }
+
+    /// The secret stores the resource reads from or writes to.
+    pub fn secret_store_refs(&self) -> Vec<&SecretStoreRef> {
+        match self {
+            Spec::ExternalSecret(spec) => spec.secret_store_ref.iter().collect(),
+            Spec::PushSecret(spec) => spec.secret_store_refs.iter().collect(),
+        }
+    }
+
+    pub fn refresh_interval(&self) -> Option<&str> {
+        match self {
+            Spec::ExternalSecret(spec) => spec.refresh_interval.as_deref(),
+            Spec::PushSecret(spec) => spec.refresh_interval.as_deref(),
+        }
+    }
+}
+
+pub fn external_secret_spec(resource: &DynamicObject) -> Option<ExternalSecretSpec> {
+    match Spec::of(resource)? {
+        Spec::ExternalSecret(spec) => Some(*spec),
+        Spec::PushSecret(_) => None,
+    }
+}
+
+pub fn push_secret_spec(resource: &DynamicObject) -> Option<PushSecretSpec> {
+    match Spec::of(resource)? {
+        Spec::PushSecret(spec) => Some(*spec),
+        Spec::ExternalSecret(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn resource(kind: &str, spec: Value) -> DynamicObject {
+        serde_json::from_value(json!({
+            "apiVersion": "external-secrets.io/v1beta1",
+            "kind": kind,
+            "metadata": { "name": "db", "namespace": "payments" },
+            "spec": spec,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn external_secret_spec_round_trips() {
+        let spec = json!({
+            "refreshInterval": "1h",
+            "secretStoreRef": { "name": "vault", "kind": "ClusterSecretStore" },
+            "target": {
+                "name": "db-credentials",
+                "creationPolicy": "Owner",
+                "template": { "type": "kubernetes.io/basic-auth", "engineVersion": "v2" },
+            },
+            "data": [
+                {
+                    "secretKey": "password",
+                    "remoteRef": { "key": "payments/db", "property": "password" },
+                },
+            ],
+            "dataFrom": [
+                { "extract": { "key": "payments/common" } },
+                { "find": { "path": "payments", "name": { "regexp": "^db-" } } },
+            ],
+            "unmodelled": { "kept": true },
+        });
+        let parsed: ExternalSecretSpec = serde_json::from_value(spec.clone()).unwrap();
+        assert_eq!(parsed.data.len(), 1);
+        assert_eq!(parsed.data_from.len(), 2);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), spec);
+    }
+
+    #[test]
+    fn push_secret_spec_round_trips() {
+        let spec = json!({
+            "refreshInterval": "10m",
+            "secretStoreRefs": [{ "name": "aws", "kind": "ClusterSecretStore" }],
+            "selector": { "secret": { "name": "db-credentials" } },
+            "data": [
+                {
+                    "match": {
+                        "secretKey": "password",
+                        "remoteRef": { "remoteKey": "payments/db", "property": "password" },
+                    },
+                },
+            ],
+            "deletionPolicy": "Delete",
+        });
+        let parsed: PushSecretSpec = serde_json::from_value(spec.clone()).unwrap();
+        assert_eq!(parsed.secret_store_refs.len(), 1);
+        assert_eq!(serde_json::to_value(&parsed).unwrap(), spec);
+    }
+
+    #[test]
+    fn null_lists_read_as_empty() {
+        let spec = json!({
+            "secretStoreRef": { "name": "vault", "kind": "ClusterSecretStore" },
+            "data": [{ "secretKey": "password", "remoteRef": { "key": "payments/db" } }],
+            "dataFrom": null,
+        });
+        let spec = external_secret_spec(&resource("ExternalSecret", spec)).unwrap();
+        assert!(spec.data_from.is_empty());
+        assert_eq!(spec.data.len(), 1);
+        assert_eq!(
+            spec.secret_store_ref.unwrap().name.as_deref(),
+            Some("vault")
+        );
+    }
+
+    #[test]
+    fn misshapen_fields_leave_the_rest_of_the_spec() {
+        let spec = json!({
+            "refreshInterval": 3600,
+            "secretStoreRef": { "name": "vault" },
+            "target": ["db-credentials"],
+            "data": [
+                "password",
+                { "secretKey": "user", "remoteRef": { "key": "payments/db", "version": 2 } },
+            ],
+        });
+        let spec = Spec::of(&resource("ExternalSecret", spec)).unwrap();
+        assert_eq!(spec.refresh_interval(), None);
+        assert_eq!(spec.secret_store_refs()[0].name.as_deref(), Some("vault"));
+        let Spec::ExternalSecret(spec) = spec else {
+            panic!("not an ExternalSecret spec");
+        };
+        assert_eq!(spec.target, None);
+        assert_eq!(spec.data.len(), 1);
+        let remote_ref = spec.data[0].remote_ref.as_ref().unwrap();
+        assert_eq!(remote_ref.key.as_deref(), Some("payments/db"));
+        assert_eq!(remote_ref.version, None);
+    }
+
+    #[test]
+    fn other_kinds_have_no_spec() {
+        assert_eq!(Spec::of(&resource("SecretStore", json!({}))), None);
+        assert_eq!(Spec::of(&resource("ExternalSecret", json!(null))), None);
+    }
+}
@@ -0,0 +1,359 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::diff::{ChangeType, FieldChange, SecretChange};
+use crate::git::GitInfo;
+use crate::inventory;
+use crate::schema::SCHEMA_VERSION;
+use crate::system_manifests::{FlatManifestResource, PlatformWarning, SystemManifests};
+
+/// Everything the secrets inventory holds at a point in time, kept as audit evidence. Only the
+/// metadata of the secret resources, never their values.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Snapshot {
+    schema_version: u32,
+    /// When the snapshot was made, in RFC 3339.
+    created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git: Option<GitInfo>,
+    /// Whether the names of the keys of the secrets are included.
+    keys_included: bool,
+    /// Number of secret resources per platform, including platforms without any.
+    platforms: BTreeMap<String, usize>,
+    secrets: Vec<SnapshotSecret>,
+    /// How platforms were read, like without a directory they don't have.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<PlatformWarning>,
+}
+
+/// A secret resource as kept in a snapshot, with its file relative to the system manifests
+/// directory.
+#[derive(Debug, Serialize)]
+struct SnapshotSecret {
+    #[serde(flatten)]
+    resource: FlatManifestResource,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    store_refs: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    remote_refs: Vec<String>,
+}
+
+/// The parts of an exported snapshot that are compared, read back leniently so that snapshots of
+/// older releases still compare.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExportedSnapshot {
+    schema_version: u32,
+    #[serde(default)]
+    keys_included: bool,
+    secrets: Vec<Value>,
+}
+
+/// Fields of the secrets compared between snapshots, by the name changes are reported with and
+/// their pointer in a snapshot secret. The line isn't, as it changes with every edit above it.
+const COMPARED_FIELDS: &[(&str, &str)] = &[
+    ("component", "/component_name"),
+    ("api_version", "/api_version"),
+    ("file", "/file"),
+    ("labels", "/resource_meta/labels"),
+    ("annotations", "/resource_meta/annotations"),
+    ("keys", "/keys"),
+    ("store_refs", "/store_refs"),
+    ("remote_refs", "/remote_refs"),
+    ("sealed_secret", "/sealed_secret"),
+    ("sops", "/sops"),
+    ("certificate", "/certificate"),
+];
+
+/// Identifies a snapshot secret by repository, platform, kind, namespace and name. Several
+/// secrets can share one, like the duplicates of a Secret in different files.
+type SecretKey = (Option<String>, String, String, Option<String>, String);
+
+/// How an inventory snapshot is signed with cosign.
+#[derive(Debug, Clone)]
+pub struct Signing {
+    /// Key reference passed to `cosign sign-blob --key`, signing keyless with Sigstore if unset.
+    pub key: Option<String>,
+}
+
+/// The files an export wrote.
+#[derive(Debug)]
+pub struct Exported {
+    /// SHA-256 of the snapshot file, hex encoded.
+    pub sha256: String,
+    /// The `sha256sum` style checksum file next to the snapshot.
+    pub checksum_file: PathBuf,
+    /// The Sigstore bundle with the signature of the snapshot, if signed.
+    pub bundle_file: Option<PathBuf>,
+}
+
+/// Returns the file next to the given one, named after it with the suffix appended.
+fn sibling(file: &Path, suffix: &str) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    file.with_file_name(name)
+}
+
+/// Signs a file with `cosign sign-blob`, writing the signature, certificate and transparency log
+/// entry to a Sigstore bundle.
+fn sign(file: &Path, bundle_file: &Path, signing: &Signing) -> Result<()> {
+    let mut command = Command::new("cosign");
+    command.args(["sign-blob", "--yes", "--bundle"]);
+    command.arg(bundle_file);
+    if let Some(key) = &signing.key {
+        command.args(["--key", key]);
+    }
+    let status = command.arg(file).stdout(Stdio::null()).status();
+    let status = match status {
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            anyhow::bail!("Signing snapshots requires the cosign CLI to be installed")
+        }
+        status => status.with_context(|| "Failed to run cosign sign-blob")?,
+    };
+    anyhow::ensure!(status.success(), "Failed to sign {}", file.display());
+    Ok(())
+}
+
+fn sha256(contents: &[u8]) -> String {
+    Sha256::digest(contents)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Returns the secret resources of all platforms as kept in a snapshot, along with their number
+/// per platform.
+fn snapshot_secrets(
+    system_manifests: &SystemManifests,
+    show_keys: bool,
+) -> Result<(Vec<SnapshotSecret>, BTreeMap<String, usize>)> {
+    let mut platforms: BTreeMap<String, usize> = system_manifests
+        .platforms
+        .iter()
+        .map(|platform| (platform.name.clone(), 0))
+        .collect();
+    let secrets = inventory::secret_resources(system_manifests, &[])?
+        .into_iter()
+        .map(|manifest_resource| {
+            *platforms
+                .entry(manifest_resource.platform.name.clone())
+                .or_default() += 1;
+            let resource = &manifest_resource.resource;
+            let mut store_refs: Vec<String> = inventory::store_refs(resource)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            store_refs.sort();
+            let mut remote_refs: Vec<String> = inventory::remote_refs(resource)
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            remote_refs.sort();
+            let mut resource = inventory::flatten(manifest_resource, show_keys);
+            let directory = system_manifests.repository_directory(&resource.file);
+            if let Ok(file) = resource.file.strip_prefix(directory) {
+                resource.file = file.to_owned();
+            }
+            SnapshotSecret {
+                resource,
+                store_refs,
+                remote_refs,
+            }
+        })
+        .collect();
+    Ok((secrets, platforms))
+}
+
+/// Writes a snapshot of the secret resources of all platforms to a JSON file, along with a
+/// `<file>.sha256` checksum file and, if signing, a `<file>.sigstore.json` bundle that
+/// `cosign verify-blob --bundle` checks the snapshot against.
+pub fn export(
+    system_manifests: &SystemManifests,
+    file: &Path,
+    show_keys: bool,
+    git: Option<GitInfo>,
+    signing: Option<&Signing>,
+) -> Result<Exported> {
+    let (secrets, platforms) = snapshot_secrets(system_manifests, show_keys)?;
+    let snapshot = Snapshot {
+        schema_version: SCHEMA_VERSION,
+        created_at: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        git,
+        keys_included: show_keys,
+        platforms,
+        secrets,
+        warnings: system_manifests.platform_warnings(),
+    };
+
+    let mut contents = serde_json::to_string_pretty(&snapshot)?;
+    contents.push('\n');
+    std::fs::write(file, &contents)
+        .with_context(|| format!("Failed to write {}", file.display()))?;
+    let sha256 = sha256(contents.as_bytes());
+    let checksum_file = sibling(file, ".sha256");
+    let file_name = file.file_name().unwrap_or_default().to_string_lossy();
+    std::fs::write(&checksum_file, format!("{}  {}\n", sha256, file_name))
+        .with_context(|| format!("Failed to write {}", checksum_file.display()))?;
+
+    let bundle_file = signing
+        .map(|signing| {
+            let bundle_file = sibling(file, ".sigstore.json");
+            sign(file, &bundle_file, signing).map(|()| bundle_file)
+        })
+        .transpose()?;
+    Ok(Exported {
+        sha256,
+        checksum_file,
+        bundle_file,
+    })
+}
+
+fn secret_key(secret: &Value) -> SecretKey {
+    let string = |pointer: &str| {
+        secret
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+    };
+    (
+        string("/repo"),
+        string("/platform_name").unwrap_or_default(),
+        string("/kind").unwrap_or_default(),
+        string("/resource_meta/namespace"),
+        string("/resource_meta/name").unwrap_or_default(),
+    )
+}
+
+fn secret_file(secret: &Value) -> PathBuf {
+    PathBuf::from(
+        secret
+            .get("file")
+            .and_then(Value::as_str)
+            .unwrap_or_default(),
+    )
+}
+
+/// Reads a snapshot written by [`export`], failing if it doesn't match the checksum file next to
+/// it.
+fn read_snapshot(file: &Path) -> Result<ExportedSnapshot> {
+    let contents =
+        std::fs::read(file).with_context(|| format!("Failed to read {}", file.display()))?;
+    let checksum_file = sibling(file, ".sha256");
+    if let Ok(checksum) = std::fs::read_to_string(&checksum_file) {
+        let expected = checksum.split_whitespace().next().unwrap_or_default();
+        anyhow::ensure!(
+            expected == sha256(&contents),
+            "{} doesn't match its checksum in {}",
+            file.display(),
+            checksum_file.display()
+        );
+    }
+    let snapshot: ExportedSnapshot = serde_json::from_slice(&contents)
+        .with_context(|| format!("Failed to parse snapshot {}", file.display()))?;
+    anyhow::ensure!(
+        snapshot.schema_version <= SCHEMA_VERSION,
+        "Snapshot {} has schema version {}, newer than {} this release reads",
+        file.display(),
+        snapshot.schema_version,
+        SCHEMA_VERSION
+    );
+    Ok(snapshot)
+}
+
+/// Returns the secret resources added, removed or changed since a snapshot written by
+/// [`export`]. Key names are only compared if the snapshot includes them.
+pub fn compare(system_manifests: &SystemManifests, file: &Path) -> Result<Vec<SecretChange>> {
+    let snapshot = read_snapshot(file)?;
+    let mut base: BTreeMap<SecretKey, Vec<Value>> = BTreeMap::new();
+    for secret in snapshot.secrets {
+        base.entry(secret_key(&secret)).or_default().push(secret);
+    }
+    let (secrets, _) = snapshot_secrets(system_manifests, snapshot.keys_included)?;
+
+    let change = |key: &SecretKey, change, file, fields| {
+        let (repo, platform_name, kind, namespace, name) = key.clone();
+        SecretChange {
+            repo,
+            platform_name,
+            kind,
+            namespace,
+            name,
+            change,
+            file,
+            fields,
+        }
+    };
+
+    let mut changes = Vec::new();
+    for secret in secrets {
+        let head = serde_json::to_value(&secret)?;
+        let key = secret_key(&head);
+        match take_base_secret(&mut base, &key, &head) {
+            None => changes.push(change(
+                &key,
+                ChangeType::Added,
+                secret_file(&head),
+                Vec::new(),
+            )),
+            Some(base) => {
+                let fields: Vec<FieldChange> = COMPARED_FIELDS
+                    .iter()
+                    .filter_map(|(field, pointer)| {
+                        let base_value = base.pointer(pointer).cloned().unwrap_or(Value::Null);
+                        let head_value = head.pointer(pointer).cloned().unwrap_or(Value::Null);
+                        (base_value != head_value).then_some(FieldChange {
+                            field,
+                            base: base_value,
+                            head: head_value,
+                        })
+                    })
+                    .collect();
+                if !fields.is_empty() {
+                    changes.push(change(
+                        &key,
+                        ChangeType::Changed,
+                        secret_file(&head),
+                        fields,
+                    ));
+                }
+            }
+        }
+    }
+    for (key, secrets) in &base {
+        for secret in secrets {
+            changes.push(change(
+                key,
+                ChangeType::Removed,
+                secret_file(secret),
+                Vec::new(),
+            ));
+        }
+    }
+    Ok(changes)
+}
+
+/// Takes the snapshot secret a secret is compared with out of those with its key, preferring the
+/// one in the same file.
+fn take_base_secret(
+    base: &mut BTreeMap<SecretKey, Vec<Value>>,
+    key: &SecretKey,
+    head: &Value,
+) -> Option<Value> {
+    let secrets = base.get_mut(key)?;
+    let index = secrets
+        .iter()
+        .position(|secret| secret.pointer("/file") == head.pointer("/file"))
+        .unwrap_or(0);
+    let secret = (index < secrets.len()).then(|| secrets.remove(index));
+    if secrets.is_empty() {
+        base.remove(key);
+    }
+    secret
+}
@@ -0,0 +1,53 @@
+use anyhow::{Context as _, Result};
+use cel::{Context, Program, Value};
+
+use crate::system_manifests::ManifestResource;
+
+/// A CEL expression that resources must satisfy to be listed.
+///
+/// The expression sees the whole resource as `resource`, along with the `kind`, `name`,
+/// `platform`, `component` and `file` of it. There is no `namespace` shorthand as CEL reserves
+/// the word, use `resource.metadata.namespace` instead.
+#[derive(Debug)]
+pub struct Filter {
+    expression: String,
+    program: Program,
+}
+
+impl Filter {
+    pub fn new(expression: &str) -> Result<Self> {
+        let program = Program::compile(expression)
+            .with_context(|| format!("Invalid filter expression: {}", expression))?;
+        Ok(Filter {
+            expression: expression.to_owned(),
+            program,
+        })
+    }
+
+    pub fn matches(&self, manifest_resource: &ManifestResource) -> Result<bool> {
+        let resource = &manifest_resource.resource;
+        let mut context = Context::default();
+        context.add_variable("resource", resource)?;
+        context.add_variable("kind", resource.types.as_ref().map(|t| t.kind.as_str()))?;
+        context.add_variable("name", resource.metadata.name.as_deref())?;
+        context.add_variable("platform", manifest_resource.platform.name.as_str())?;
+        context.add_variable("component", manifest_resource.component.name.as_str())?;
+        context.add_variable("file", manifest_resource.file.to_string_lossy().as_ref())?;
+
+        match self.program.execute(&context) {
+            Ok(Value::Bool(matches)) => Ok(matches),
+            Ok(value) => anyhow::bail!(
+                "Filter {} evaluated to {:?} instead of a bool",
+                self.expression,
+                value
+            ),
+            Err(error) => Err(error).with_context(|| {
+                format!(
+                    "Failed to evaluate filter {} for {}",
+                    self.expression,
+                    manifest_resource.file.display()
+                )
+            }),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::system_manifests::ManifestResource;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// Lowest severity of findings that makes a findings command fail.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailOn {
+    Error,
+    #[default]
+    Warning,
+    Never,
+}
+
+impl FailOn {
+    pub fn fails(self, findings: &[Finding]) -> bool {
+        let threshold = match self {
+            FailOn::Error => Severity::Error,
+            FailOn::Warning => Severity::Warning,
+            FailOn::Never => return false,
+        };
+        findings.iter().any(|finding| finding.severity >= threshold)
+    }
+
+    /// Returns whether findings that have no severity, like orphans or dead references, fail
+    /// the command. They count as errors.
+    pub fn fails_on_any(self, found: bool) -> bool {
+        found && !matches!(self, FailOn::Never)
+    }
+}
+
+/// A policy violation found in the manifests.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Finding {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    pub platform_name: String,
+    pub component_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// JSON pointer to the offending value within the resource.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pointer: Option<String>,
+}
+
+impl Finding {
+    pub fn new(
+        rule: &str,
+        severity: Severity,
+        message: String,
+        manifest_resource: &ManifestResource,
+    ) -> Self {
+        Finding {
+            rule: rule.to_owned(),
+            severity,
+            message,
+            file: manifest_resource.file.clone(),
+            line: manifest_resource.line,
+            platform_name: manifest_resource.platform.name.clone(),
+            component_name: manifest_resource.component.name.clone(),
+            kind: manifest_resource
+                .resource
+                .types
+                .as_ref()
+                .map(|t| t.kind.clone()),
+            namespace: manifest_resource.resource.metadata.namespace.clone(),
+            name: manifest_resource.resource.metadata.name.clone(),
+            pointer: None,
+        }
+    }
+}
+
+/// What a findings command checked, for output formats that report the checks that passed as
+/// well as the findings.
+#[derive(Default)]
+pub struct Checks<'a> {
+    /// Resources the rules were applied to.
+    pub resources: &'a [ManifestResource],
+    /// Rules applied to each of the resources.
+    pub rules: Vec<String>,
+}
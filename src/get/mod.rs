@@ -0,0 +1,57 @@
+use anyhow::Result;
+
+use crate::inventory;
+use crate::selector::LabelSelector;
+use crate::system_manifests::{ManifestResource, SystemManifests};
+
+/// Returns whether a resource is named `<name>` or `<namespace>/<name>`.
+fn is_named(manifest_resource: &ManifestResource, name: &str) -> bool {
+    let metadata = &manifest_resource.resource.metadata;
+    let resource_name = metadata.name.as_deref().unwrap_or_default();
+    match name.split_once('/') {
+        Some((namespace, name)) => {
+            resource_name == name && metadata.namespace.as_deref().unwrap_or("default") == namespace
+        }
+        None => resource_name == name,
+    }
+}
+
+/// Returns the secret resources with the given names, or all of them if none are given, whose
+/// labels match the selector, like `kubectl get`. Also returns the names no resource has.
+pub fn get(
+    system_manifests: &SystemManifests,
+    names: &[String],
+    namespaces: &[String],
+    selector: Option<&LabelSelector>,
+) -> Result<(Vec<ManifestResource>, Vec<String>)> {
+    let mut found = vec![false; names.len()];
+    let mut resources = Vec::new();
+    for manifest_resource in inventory::secret_resource_iter(system_manifests, namespaces) {
+        let manifest_resource = manifest_resource?;
+        if !selector.is_none_or(|selector| {
+            selector.matches(manifest_resource.resource.metadata.labels.as_ref())
+        }) {
+            continue;
+        }
+        if !names.is_empty() {
+            let mut named = false;
+            for (index, name) in names.iter().enumerate() {
+                if is_named(&manifest_resource, name) {
+                    found[index] = true;
+                    named = true;
+                }
+            }
+            if !named {
+                continue;
+            }
+        }
+        resources.push(manifest_resource);
+    }
+    let missing = names
+        .iter()
+        .zip(found)
+        .filter(|(_, found)| !found)
+        .map(|(name, _)| name.clone())
+        .collect();
+    Ok((resources, missing))
+}
@@ -0,0 +1,402 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use crate::system_manifests::CACHE_DIRECTORY_NAME;
+
+/// Returns whether git succeeds, for commands whose failure only answers a question.
+fn git_succeeds(repository: &Path, args: &[&str]) -> Result<bool> {
+    let status = Command::new("git")
+        .arg("-C")
+        .arg(repository)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .with_context(|| "Failed to run git")?;
+    Ok(status.success())
+}
+
+fn git_output(repository: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repository)
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| "Failed to run git")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git {} failed in {}",
+        args.join(" "),
+        repository.display()
+    );
+    String::from_utf8(output.stdout).with_context(|| "git produced invalid UTF-8 output")
+}
+
+/// Writes the contents of a directory inside a git repository, as of the given reference, into
+/// the destination directory.
+pub fn export_reference(directory: &Path, reference: &str, destination: &Path) -> Result<()> {
+    let prefix = git_output(directory, &["rev-parse", "--show-prefix"])?;
+    let tree = format!("{}:{}", reference, prefix.trim());
+
+    let mut archive = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(["archive", "--format=tar", &tree])
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| "Failed to run git archive")?;
+    let archive_stdout = archive
+        .stdout
+        .take()
+        .with_context(|| "Failed to read git archive output")?;
+    let extract_status = Command::new("tar")
+        .arg("-x")
+        .arg("-C")
+        .arg(destination)
+        .stdin(archive_stdout)
+        .status()
+        .with_context(|| "Failed to run tar")?;
+    let archive_status = archive
+        .wait()
+        .with_context(|| "Failed to run git archive")?;
+
+    anyhow::ensure!(
+        archive_status.success(),
+        "git archive of {} failed in {}",
+        reference,
+        directory.display()
+    );
+    anyhow::ensure!(
+        extract_status.success(),
+        "Failed to extract {} into {}",
+        reference,
+        destination.display()
+    );
+    Ok(())
+}
+
+/// Returns the root directory of the repository containing a directory.
+pub fn toplevel(directory: &Path) -> Result<PathBuf> {
+    Ok(PathBuf::from(
+        git_output(directory, &["rev-parse", "--show-toplevel"])?.trim(),
+    ))
+}
+
+/// Returns the canonical paths of the files below a directory that differ from the given
+/// reference, including uncommitted and untracked files.
+pub fn changed_files(directory: &Path, reference: &str) -> Result<HashSet<PathBuf>> {
+    let toplevel = toplevel(directory)?;
+    let changed = git_output(
+        directory,
+        &["diff", "--name-only", "-z", reference, "--", "."],
+    )?;
+    let untracked = git_output(
+        directory,
+        &[
+            "ls-files",
+            "--others",
+            "--exclude-standard",
+            "--full-name",
+            "-z",
+            "--",
+            ".",
+        ],
+    )?;
+
+    Ok(changed
+        .split('\0')
+        .chain(untracked.split('\0'))
+        .filter(|path| !path.is_empty())
+        .filter_map(|path| std::fs::canonicalize(toplevel.join(path)).ok())
+        .collect())
+}
+
+/// Returns the URLs of the remotes of the repository containing a directory.
+pub fn remote_urls(directory: &Path) -> Result<Vec<String>> {
+    let remotes = git_output(directory, &["remote"])?;
+    remotes
+        .lines()
+        .filter(|remote| !remote.is_empty())
+        .map(|remote| {
+            git_output(directory, &["remote", "get-url", remote]).map(|url| url.trim().to_owned())
+        })
+        .collect()
+}
+
+/// Returns whether a system manifests location is the URL of a remote repository rather than a
+/// directory.
+pub fn is_remote_url(location: &str) -> bool {
+    ["https://", "http://", "ssh://", "git://", "file://"]
+        .iter()
+        .any(|scheme| location.starts_with(scheme))
+        || location
+            .split_once(':')
+            .is_some_and(|(host, _)| host.contains('@') && !host.contains('/'))
+}
+
+/// Returns the directory remote repositories are cached in, in `$XDG_CACHE_HOME` or else
+/// `~/.cache`.
+fn cache_directory() -> Result<PathBuf> {
+    let cache_directory = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|directory| !directory.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .with_context(|| "Neither XDG_CACHE_HOME nor HOME is set to cache repositories in")?;
+    Ok(cache_directory.join(env!("CARGO_PKG_NAME")))
+}
+
+/// Fetches a reference of a remote repository into a cached checkout, cloning it the first time,
+/// and returns the checkout's directory. Only the referenced commit is fetched, so the checkout
+/// has no history. Defaults to the remote's HEAD.
+pub fn fetch_cached(url: &str, reference: Option<&str>) -> Result<PathBuf> {
+    let name: String = url
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let checkout = cache_directory()?.join(name);
+    if !checkout.join(".git").is_dir() {
+        std::fs::create_dir_all(&checkout)
+            .with_context(|| format!("Failed to create cache directory: {}", checkout.display()))?;
+        git_output(&checkout, &["init", "--quiet"])?;
+        git_output(&checkout, &["remote", "add", "origin", url])?;
+    }
+    let reference = reference.unwrap_or("HEAD");
+    git_output(
+        &checkout,
+        &["fetch", "--quiet", "--depth", "1", "origin", reference],
+    )
+    .with_context(|| format!("Failed to fetch {} of {}", reference, url))?;
+    git_output(
+        &checkout,
+        &["checkout", "--quiet", "--force", "--detach", "FETCH_HEAD"],
+    )?;
+    git_output(
+        &checkout,
+        &[
+            "clean",
+            "--quiet",
+            "--force",
+            "-d",
+            "-x",
+            "--exclude",
+            CACHE_DIRECTORY_NAME,
+        ],
+    )?;
+    Ok(checkout)
+}
+
+/// Returns the canonical paths of files as pathspecs, which unlike paths relative to the current
+/// directory match the same files whichever directory of the repository git is run in.
+fn pathspecs(files: &[PathBuf]) -> Result<Vec<String>> {
+    files
+        .iter()
+        .map(|file| {
+            std::fs::canonicalize(file)
+                .map(|file| file.to_string_lossy().into_owned())
+                .with_context(|| format!("Failed to resolve {}", file.display()))
+        })
+        .collect()
+}
+
+/// Fails if any of the given files isn't tracked in the repository containing a directory, as
+/// committing it would then fail.
+pub fn ensure_tracked(directory: &Path, files: &[PathBuf]) -> Result<()> {
+    let toplevel = toplevel(directory)?;
+    let pathspecs = pathspecs(files)?;
+    let mut args = vec!["ls-files", "--full-name", "-z", "--"];
+    args.extend(pathspecs.iter().map(String::as_str));
+    let tracked: HashSet<PathBuf> = git_output(directory, &args)?
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .filter_map(|path| std::fs::canonicalize(toplevel.join(path)).ok())
+        .collect();
+    let untracked: Vec<String> = files
+        .iter()
+        .zip(&pathspecs)
+        .filter(|(_, pathspec)| !tracked.contains(Path::new(pathspec)))
+        .map(|(file, _)| file.display().to_string())
+        .collect();
+    anyhow::ensure!(
+        untracked.is_empty(),
+        "Add these files to git in {} first:\n{}",
+        toplevel.display(),
+        untracked.join("\n")
+    );
+    Ok(())
+}
+
+/// Fails if any of the given files has uncommitted changes, which committing them would take
+/// along.
+pub fn ensure_unchanged(directory: &Path, files: &[PathBuf]) -> Result<()> {
+    let files = pathspecs(files)?;
+    let mut args = vec!["status", "--porcelain", "--"];
+    args.extend(files.iter().map(String::as_str));
+    let status = git_output(directory, &args)?;
+    anyhow::ensure!(
+        status.trim().is_empty(),
+        "Commit or stash the changes to these files first:\n{}",
+        status.trim_end()
+    );
+    Ok(())
+}
+
+/// Returns whether a branch exists in the repository containing a directory.
+pub fn branch_exists(directory: &Path, branch: &str) -> Result<bool> {
+    git_succeeds(
+        directory,
+        &[
+            "rev-parse",
+            "--verify",
+            "--quiet",
+            &format!("refs/heads/{}", branch),
+        ],
+    )
+}
+
+/// Creates a branch from the current commit and switches to it, keeping uncommitted changes.
+pub fn create_branch(directory: &Path, branch: &str) -> Result<()> {
+    git_output(directory, &["checkout", "-b", branch])?;
+    Ok(())
+}
+
+/// Returns the branch checked out in the repository containing a directory, or the commit of a
+/// detached HEAD, to check out again later.
+pub fn head(directory: &Path) -> Result<String> {
+    let branch = git_output(directory, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    if branch.trim() != "HEAD" {
+        return Ok(branch.trim().to_owned());
+    }
+    Ok(git_output(directory, &["rev-parse", "HEAD"])?
+        .trim()
+        .to_owned())
+}
+
+/// Checks out a branch or commit, keeping uncommitted changes.
+pub fn checkout(directory: &Path, reference: &str) -> Result<()> {
+    git_output(directory, &["checkout", "--quiet", reference])?;
+    Ok(())
+}
+
+/// Deletes a branch, even if it isn't merged.
+pub fn delete_branch(directory: &Path, branch: &str) -> Result<()> {
+    git_output(directory, &["branch", "--quiet", "-D", branch])?;
+    Ok(())
+}
+
+/// Commits the changes to the given files, and only those.
+pub fn commit(directory: &Path, files: &[PathBuf], message: &str) -> Result<()> {
+    let files = pathspecs(files)?;
+    let mut args = vec!["commit", "-m", message, "--"];
+    args.extend(files.iter().map(String::as_str));
+    git_output(directory, &args)?;
+    Ok(())
+}
+
+/// Returns the name of the branch checked out in the repository containing a directory.
+pub fn current_branch(directory: &Path) -> Result<String> {
+    let branch = git_output(directory, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let branch = branch.trim();
+    anyhow::ensure!(
+        branch != "HEAD",
+        "No branch is checked out in {}",
+        directory.display()
+    );
+    Ok(branch.to_owned())
+}
+
+/// Returns the URL of a remote of the repository containing a directory.
+pub fn remote_url(directory: &Path, remote: &str) -> Result<String> {
+    git_output(directory, &["remote", "get-url", remote]).map(|url| url.trim().to_owned())
+}
+
+/// Pushes a branch to a remote, setting it as the branch's upstream.
+pub fn push(directory: &Path, remote: &str, branch: &str) -> Result<()> {
+    git_output(directory, &["push", "--set-upstream", remote, branch])?;
+    Ok(())
+}
+
+/// The commit a line of a file was last changed in, as told by `git blame`.
+#[derive(Debug, Clone)]
+pub struct BlameLine {
+    /// Commit hash, `None` for uncommitted changes.
+    pub commit: Option<String>,
+    pub author: String,
+    pub author_email: String,
+    /// Author time in seconds since the epoch.
+    pub time: i64,
+}
+
+/// Returns the commit each line of a file was last changed in, or `None` if the file isn't
+/// tracked.
+pub fn blame(file: &Path) -> Result<Option<Vec<BlameLine>>> {
+    let directory = file.parent().unwrap_or(Path::new("."));
+    let pathspecs = pathspecs(&[file.to_path_buf()])?;
+    let file_name = &pathspecs[0];
+    if git_output(directory, &["ls-files", "--", file_name])?
+        .trim()
+        .is_empty()
+    {
+        return Ok(None);
+    }
+    let output = git_output(directory, &["blame", "--line-porcelain", "--", file_name])?;
+
+    let mut lines = Vec::new();
+    let mut commit = None;
+    let mut author = String::new();
+    let mut author_email = String::new();
+    let mut time = 0;
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            lines.push(BlameLine {
+                commit: commit.take(),
+                author: std::mem::take(&mut author),
+                author_email: std::mem::take(&mut author_email),
+                time,
+            });
+        } else if let Some(value) = line.strip_prefix("author ") {
+            value.clone_into(&mut author);
+        } else if let Some(value) = line.strip_prefix("author-mail ") {
+            value
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .clone_into(&mut author_email);
+        } else if let Some(value) = line.strip_prefix("author-time ") {
+            time = value.parse().unwrap_or_default();
+        } else if let Some(hash) = line
+            .split(' ')
+            .next()
+            .filter(|hash| hash.len() >= 40 && hash.chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            commit = Some(hash.to_owned()).filter(|hash| hash.chars().any(|c| c != '0'));
+        }
+    }
+    Ok(Some(lines))
+}
+
+/// The revision of the system manifests an output was made from.
+#[derive(Debug, Clone, Serialize)]
+pub struct GitInfo {
+    pub commit: String,
+    /// Branch checked out, `None` for a detached HEAD as in most CI checkouts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Whether the system manifests directory has uncommitted or untracked changes.
+    pub dirty: bool,
+}
+
+/// Returns the commit and branch checked out in the repository containing a directory, and
+/// whether the directory has changes on top of them.
+pub fn info(directory: &Path) -> Result<GitInfo> {
+    let commit = git_output(directory, &["rev-parse", "HEAD"])?;
+    let branch = git_output(directory, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let status = git_output(directory, &["status", "--porcelain", "--", "."])?;
+    Ok(GitInfo {
+        commit: commit.trim().to_owned(),
+        branch: Some(branch.trim().to_owned()).filter(|branch| branch != "HEAD"),
+        dirty: !status.trim().is_empty(),
+    })
+}
@@ -0,0 +1,269 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+
+use crate::inventory::{store_refs, StoreRef};
+use crate::references::{produced_secret, pushed_secret, referenced_secrets, SecretName};
+use crate::stores::STORE_KINDS;
+use crate::system_manifests::ManifestResource;
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum NodeType {
+    Store,
+    SecretResource,
+    Secret,
+    Consumer,
+}
+
+impl NodeType {
+    fn dot_shape(&self) -> &'static str {
+        match self {
+            NodeType::Store => "cylinder",
+            NodeType::SecretResource => "box",
+            NodeType::Secret => "note",
+            NodeType::Consumer => "ellipse",
+        }
+    }
+}
+
+/// A resource in the graph, identified by kind, namespace and name.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Node {
+    kind: String,
+    namespace: Option<String>,
+    name: String,
+}
+
+impl Node {
+    fn new(kind: &str, namespace: Option<&str>, name: &str) -> Self {
+        Node {
+            kind: kind.to_owned(),
+            namespace: namespace.map(str::to_owned),
+            name: name.to_owned(),
+        }
+    }
+
+    fn of(manifest_resource: &ManifestResource) -> Self {
+        let resource = &manifest_resource.resource;
+        Node::new(
+            resource
+                .types
+                .as_ref()
+                .map(|t| t.kind.as_str())
+                .unwrap_or_default(),
+            resource.metadata.namespace.as_deref(),
+            resource.metadata.name.as_deref().unwrap_or_default(),
+        )
+    }
+
+    fn secret(secret_name: &SecretName) -> Self {
+        Node::new(
+            "Secret",
+            secret_name.namespace.as_deref(),
+            &secret_name.name,
+        )
+    }
+
+    fn store(store_ref: &StoreRef, namespace: Option<&str>) -> Self {
+        let namespace = namespace.filter(|_| store_ref.kind == "SecretStore");
+        Node::new(&store_ref.kind, namespace, &store_ref.name)
+    }
+
+    fn node_type(&self) -> NodeType {
+        match self.kind.as_str() {
+            kind if STORE_KINDS.contains(&kind) => NodeType::Store,
+            "ExternalSecret" | "PushSecret" | "SealedSecret" | "Certificate" => {
+                NodeType::SecretResource
+            }
+            "Secret" => NodeType::Secret,
+            _ => NodeType::Consumer,
+        }
+    }
+
+    fn label(&self) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{}\n{}/{}", self.kind, namespace, self.name),
+            None => format!("{}\n{}", self.kind, self.name),
+        }
+    }
+}
+
+/// The secret relationships of one platform.
+#[derive(Default)]
+struct PlatformGraph {
+    nodes: BTreeSet<Node>,
+    edges: BTreeSet<(Node, Node)>,
+}
+
+impl PlatformGraph {
+    fn add_edge(&mut self, from: Node, to: Node) {
+        self.nodes.insert(from.clone());
+        self.nodes.insert(to.clone());
+        self.edges.insert((from, to));
+    }
+
+    fn add(&mut self, manifest_resource: &ManifestResource) -> Result<()> {
+        let resource = &manifest_resource.resource;
+        let node = Node::of(manifest_resource);
+        let namespace = resource.metadata.namespace.as_deref();
+        match node.kind.as_str() {
+            kind if STORE_KINDS.contains(&kind) => {
+                self.nodes.insert(node);
+            }
+            "Secret" => {
+                self.nodes.insert(node);
+            }
+            "ExternalSecret" => {
+                for store_ref in store_refs(resource) {
+                    self.add_edge(Node::store(&store_ref, namespace), node.clone());
+                }
+                if let Some(secret_name) = produced_secret(resource) {
+                    self.add_edge(node.clone(), Node::secret(&secret_name));
+                }
+                self.nodes.insert(node);
+            }
+            "SealedSecret" | "Certificate" => {
+                if let Some(secret_name) = produced_secret(resource) {
+                    self.add_edge(node.clone(), Node::secret(&secret_name));
+                }
+                self.nodes.insert(node);
+            }
+            "PushSecret" => {
+                if let Some(secret_name) = pushed_secret(resource) {
+                    self.add_edge(Node::secret(&secret_name), node.clone());
+                }
+                for store_ref in store_refs(resource) {
+                    self.add_edge(node.clone(), Node::store(&store_ref, namespace));
+                }
+                self.nodes.insert(node);
+            }
+            _ => {
+                let referenced = referenced_secrets(resource).with_context(|| {
+                    format!(
+                        "Failed to collect secret references from {}",
+                        manifest_resource.file.display()
+                    )
+                })?;
+                for secret_name in referenced {
+                    self.add_edge(Node::secret(&secret_name), node.clone());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn collect(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+) -> Result<BTreeMap<String, PlatformGraph>> {
+    let mut graphs: BTreeMap<String, PlatformGraph> = BTreeMap::new();
+    for manifest_resource_result in resources {
+        let manifest_resource = manifest_resource_result?;
+        graphs
+            .entry(manifest_resource.platform.name.clone())
+            .or_default()
+            .add(&manifest_resource)?;
+    }
+    Ok(graphs)
+}
+
+fn dot_string(value: &str) -> String {
+    format!(
+        "\"{}\"",
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+fn write_dot(writer: &mut impl Write, graphs: &BTreeMap<String, PlatformGraph>) -> Result<()> {
+    writeln!(writer, "digraph secrets {{")?;
+    writeln!(writer, "  rankdir=LR;")?;
+    for (platform_name, graph) in graphs {
+        let id = |node: &Node| dot_string(&format!("{}\n{}", platform_name, node.label()));
+        writeln!(
+            writer,
+            "  subgraph {} {{",
+            dot_string(&format!("cluster_{}", platform_name))
+        )?;
+        writeln!(writer, "    label={};", dot_string(platform_name))?;
+        for node in &graph.nodes {
+            writeln!(
+                writer,
+                "    {} [label={}, shape={}];",
+                id(node),
+                dot_string(&node.label()),
+                node.node_type().dot_shape()
+            )?;
+        }
+        for (from, to) in &graph.edges {
+            writeln!(writer, "    {} -> {};", id(from), id(to))?;
+        }
+        writeln!(writer, "  }}")?;
+    }
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+fn mermaid_label(node: &Node) -> String {
+    let label = node.label().replace('"', "#quot;").replace('\n', "<br/>");
+    match node.node_type() {
+        NodeType::Store => format!("[(\"{}\")]", label),
+        NodeType::SecretResource => format!("[\"{}\"]", label),
+        NodeType::Secret => format!("[/\"{}\"/]", label),
+        NodeType::Consumer => format!("(\"{}\")", label),
+    }
+}
+
+fn write_mermaid(writer: &mut impl Write, graphs: &BTreeMap<String, PlatformGraph>) -> Result<()> {
+    writeln!(writer, "flowchart LR")?;
+    let mut next_id = 0;
+    for (index, (platform_name, graph)) in graphs.iter().enumerate() {
+        let ids: BTreeMap<&Node, String> = graph
+            .nodes
+            .iter()
+            .map(|node| {
+                next_id += 1;
+                (node, format!("n{}", next_id))
+            })
+            .collect();
+        writeln!(
+            writer,
+            "  subgraph p{}[\"{}\"]",
+            index + 1,
+            platform_name.replace('"', "#quot;")
+        )?;
+        for (node, id) in &ids {
+            writeln!(writer, "    {}{}", id, mermaid_label(node))?;
+        }
+        for (from, to) in &graph.edges {
+            writeln!(writer, "    {} --> {}", ids[from], ids[to])?;
+        }
+        writeln!(writer, "  end")?;
+    }
+    Ok(())
+}
+
+/// Prints the edges from secret stores to ExternalSecrets, their target Secrets and the
+/// workloads consuming them, and from pushed Secrets to PushSecrets and their stores, per
+/// platform.
+pub fn write_graph(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+    format: GraphFormat,
+) -> Result<()> {
+    let graphs = collect(resources)?;
+    let stdout = std::io::stdout();
+    let mut writer = std::io::BufWriter::new(stdout.lock());
+    match format {
+        GraphFormat::Dot => write_dot(&mut writer, &graphs),
+        GraphFormat::Mermaid => write_mermaid(&mut writer, &graphs),
+    }
+}
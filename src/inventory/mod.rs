@@ -0,0 +1,410 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use kube::api::DynamicObject;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use crate::eso::{self, RemoteReference, Spec};
+use crate::sops;
+use crate::system_manifests::{FlatManifestResource, ManifestResource, SystemManifests};
+
+/// Built-in kinds of resources that make up the secrets inventory.
+pub const SECRET_KINDS: &[&str] = &[
+    "Secret",
+    "ExternalSecret",
+    "PushSecret",
+    "SealedSecret",
+    "Certificate",
+];
+
+/// Keys cert-manager stores a certificate in.
+const CERTIFICATE_KEYS: &[&str] = &["ca.crt", "tls.crt", "tls.key"];
+
+/// Placeholder for resources that have no namespace or kind set.
+const UNSET: &str = "<none>";
+
+/// Returns whether a resource is of one of the secret kinds or encrypted with SOPS.
+pub fn is_secret_resource(manifest_resource: &ManifestResource, kinds: &[String]) -> bool {
+    manifest_resource
+        .resource
+        .types
+        .as_ref()
+        .is_some_and(|t| kinds.contains(&t.kind))
+        || sops::is_encrypted(&manifest_resource.resource)
+}
+
+/// Iterates over all secret resources that satisfy the filter expression, optionally restricted
+/// to the given namespaces.
+pub fn secret_resource_iter<'a>(
+    system_manifests: &'a SystemManifests,
+    namespaces: &'a [String],
+) -> impl Iterator<Item = Result<ManifestResource>> + 'a {
+    system_manifests
+        .resource_iter()
+        .filter(
+            move |manifest_resource_result| match manifest_resource_result {
+                Ok(manifest_resource) => {
+                    is_secret_resource(manifest_resource, &system_manifests.secret_kinds)
+                        && (namespaces.is_empty()
+                            || manifest_resource
+                                .resource
+                                .metadata
+                                .namespace
+                                .as_ref()
+                                .is_some_and(|namespace| namespaces.contains(namespace)))
+                }
+                Err(_) => true, // propagate errors
+            },
+        )
+        .filter_map(move |manifest_resource_result| {
+            manifest_resource_result
+                .and_then(|manifest_resource| {
+                    let matches = system_manifests.matches_filter(&manifest_resource)?;
+                    Ok(matches.then_some(manifest_resource))
+                })
+                .transpose()
+        })
+}
+
+/// Returns all secret resources, optionally restricted to the given namespaces.
+pub fn secret_resources(
+    system_manifests: &SystemManifests,
+    namespaces: &[String],
+) -> Result<Vec<ManifestResource>> {
+    secret_resource_iter(system_manifests, namespaces).collect()
+}
+
+#[derive(ValueEnum, Debug, Clone)]
+pub enum GroupBy {
+    Namespace,
+    Platform,
+    Component,
+    Kind,
+}
+
+impl GroupBy {
+    fn key(&self, manifest_resource: &ManifestResource) -> String {
+        let key = match self {
+            GroupBy::Namespace => manifest_resource.resource.metadata.namespace.clone(),
+            GroupBy::Platform => Some(manifest_resource.platform.name.clone()),
+            GroupBy::Component => Some(manifest_resource.component.name.clone()),
+            GroupBy::Kind => manifest_resource
+                .resource
+                .types
+                .as_ref()
+                .map(|t| t.kind.clone()),
+        };
+        key.unwrap_or_else(|| UNSET.to_owned())
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum SortBy {
+    Name,
+    Namespace,
+    Platform,
+    /// File and line.
+    File,
+}
+
+impl SortBy {
+    fn compare(self, a: &ManifestResource, b: &ManifestResource) -> Ordering {
+        match self {
+            SortBy::Name => a.resource.metadata.name.cmp(&b.resource.metadata.name),
+            SortBy::Namespace => a
+                .resource
+                .metadata
+                .namespace
+                .cmp(&b.resource.metadata.namespace),
+            SortBy::Platform => a.platform.name.cmp(&b.platform.name),
+            SortBy::File => (&a.file, a.line).cmp(&(&b.file, b.line)),
+        }
+    }
+}
+
+/// Pages through resources, after sorting them if requested: skips the first `offset` and
+/// yields at most `limit` of them. Resources that compare equal stay in the order they're read,
+/// and errors are passed through without counting towards the page.
+pub fn page<'a>(
+    manifest_resources: impl Iterator<Item = Result<ManifestResource>> + 'a,
+    sort_by: Option<SortBy>,
+    offset: usize,
+    limit: Option<usize>,
+) -> Result<Box<dyn Iterator<Item = Result<ManifestResource>> + 'a>> {
+    let manifest_resources: Box<dyn Iterator<Item = Result<ManifestResource>>> = match sort_by {
+        Some(sort_by) => {
+            let mut sorted = manifest_resources.collect::<Result<Vec<_>>>()?;
+            sorted.sort_by(|a, b| sort_by.compare(a, b));
+            Box::new(sorted.into_iter().map(Ok))
+        }
+        None => Box::new(manifest_resources),
+    };
+    let end = limit.map_or(usize::MAX, |limit| offset.saturating_add(limit));
+    let mut index = 0;
+    Ok(Box::new(manifest_resources.filter(
+        move |manifest_resource| {
+            if manifest_resource.is_err() {
+                return true;
+            }
+            index += 1;
+            (offset..end).contains(&(index - 1))
+        },
+    )))
+}
+
+pub fn group_resources(
+    group_by: &GroupBy,
+    manifest_resources: Vec<ManifestResource>,
+) -> BTreeMap<String, Vec<ManifestResource>> {
+    let mut groups: BTreeMap<String, Vec<ManifestResource>> = BTreeMap::new();
+    for manifest_resource in manifest_resources {
+        groups
+            .entry(group_by.key(&manifest_resource))
+            .or_default()
+            .push(manifest_resource);
+    }
+    groups
+}
+
+fn string_field_names(value: Option<&Value>) -> impl Iterator<Item = String> + '_ {
+    value
+        .and_then(Value::as_object)
+        .into_iter()
+        .flat_map(|object| object.keys().cloned())
+}
+
+fn array_field_values<'a>(
+    value: Option<&'a Value>,
+    pointer: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    value
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(move |item| item.pointer(pointer)?.as_str().map(str::to_owned))
+}
+
+/// Returns the names of the keys a secret resource defines, never their values.
+pub fn secret_key_names(resource: &DynamicObject) -> Option<Vec<String>> {
+    let mut keys: Vec<String> = match resource.types.as_ref()?.kind.as_str() {
+        "Secret" => string_field_names(resource.data.get("data"))
+            .chain(string_field_names(resource.data.get("stringData")))
+            .collect(),
+        "ExternalSecret" => eso::external_secret_spec(resource)?
+            .data
+            .into_iter()
+            .filter_map(|data| data.secret_key)
+            .collect(),
+        "PushSecret" => eso::push_secret_spec(resource)?
+            .data
+            .into_iter()
+            .filter_map(|data| data.matching?.secret_key)
+            .collect(),
+        "SealedSecret" => {
+            string_field_names(resource.data.pointer("/spec/encryptedData")).collect()
+        }
+        "Certificate" => CERTIFICATE_KEYS.iter().map(|key| key.to_string()).collect(),
+        _ => return None,
+    };
+    keys.sort();
+    keys.dedup();
+    Some(keys)
+}
+
+/// A reference from an ExternalSecret or PushSecret to a (Cluster)SecretStore.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct StoreRef {
+    pub kind: String,
+    pub name: String,
+}
+
+impl std::fmt::Display for StoreRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.kind, self.name)
+    }
+}
+
+/// Returns the secret stores an ExternalSecret or PushSecret uses.
+pub fn store_refs(resource: &DynamicObject) -> Vec<StoreRef> {
+    let Some(spec) = Spec::of(resource) else {
+        return Vec::new();
+    };
+    spec.secret_store_refs()
+        .into_iter()
+        .filter_map(|store_ref| {
+            Some(StoreRef {
+                kind: store_ref
+                    .kind
+                    .clone()
+                    .unwrap_or_else(|| "SecretStore".to_owned()),
+                name: store_ref.name.clone()?,
+            })
+        })
+        .collect()
+}
+
+/// A key in a secret store that an ExternalSecret reads or a PushSecret writes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct RemoteRef {
+    pub key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub property: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+impl std::fmt::Display for RemoteRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.key)?;
+        if let Some(version) = &self.version {
+            write!(f, "@{}", version)?;
+        }
+        if let Some(property) = &self.property {
+            write!(f, "#{}", property)?;
+        }
+        Ok(())
+    }
+}
+
+fn remote_ref(reference: RemoteReference) -> Option<RemoteRef> {
+    Some(RemoteRef {
+        key: reference.key?,
+        property: reference.property,
+        version: reference.version,
+    })
+}
+
+/// Returns the remote keys an ExternalSecret reads or a PushSecret writes.
+pub fn remote_refs(resource: &DynamicObject) -> Vec<RemoteRef> {
+    match Spec::of(resource) {
+        Some(Spec::ExternalSecret(spec)) => spec
+            .data
+            .into_iter()
+            .filter_map(|data| remote_ref(data.remote_ref?))
+            .chain(
+                spec.data_from
+                    .into_iter()
+                    .filter_map(|data_from| remote_ref(data_from.extract?)),
+            )
+            .collect(),
+        Some(Spec::PushSecret(spec)) => spec
+            .data
+            .into_iter()
+            .filter_map(|data| {
+                let remote_ref = data.matching?.remote_ref?;
+                Some(RemoteRef {
+                    key: remote_ref.remote_key?,
+                    property: remote_ref.property,
+                    version: None,
+                })
+            })
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Which names and namespaces a SealedSecret can be unsealed under, as set by its annotations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum SealingScope {
+    /// Only under its own name and namespace.
+    Strict,
+    /// Under any name in its namespace.
+    NamespaceWide,
+    /// Under any name in any namespace.
+    ClusterWide,
+}
+
+/// The Secret a SealedSecret unseals to.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SealedSecretTarget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub scope: SealingScope,
+}
+
+fn is_annotated(resource: &DynamicObject, annotation: &str) -> bool {
+    resource
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(annotation))
+        .is_some_and(|value| value == "true")
+}
+
+/// Returns the Secret a SealedSecret unseals to, named after the SealedSecret unless its
+/// template names it otherwise.
+pub fn sealed_secret_target(resource: &DynamicObject) -> Option<SealedSecretTarget> {
+    if resource.types.as_ref()?.kind != "SealedSecret" {
+        return None;
+    }
+    let scope = if is_annotated(resource, "sealedsecrets.bitnami.com/cluster-wide") {
+        SealingScope::ClusterWide
+    } else if is_annotated(resource, "sealedsecrets.bitnami.com/namespace-wide") {
+        SealingScope::NamespaceWide
+    } else {
+        SealingScope::Strict
+    };
+    let name = resource
+        .data
+        .pointer("/spec/template/metadata/name")
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+        .or_else(|| resource.metadata.name.clone());
+    Some(SealedSecretTarget { name, scope })
+}
+
+/// The Secret a cert-manager Certificate is issued into, and what it's issued for.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct CertificateTarget {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_name: Option<String>,
+    /// The issuer, like `ClusterIssuer/letsencrypt`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    pub dns_names: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration: Option<String>,
+}
+
+/// Returns the Secret a Certificate is issued into, along with its issuer, DNS names and
+/// duration.
+pub fn certificate_target(resource: &DynamicObject) -> Option<CertificateTarget> {
+    if resource.types.as_ref()?.kind != "Certificate" {
+        return None;
+    }
+    let spec_string = |pointer: &str| {
+        resource
+            .data
+            .pointer(pointer)
+            .and_then(Value::as_str)
+            .map(str::to_owned)
+    };
+    let issuer = spec_string("/spec/issuerRef/name").map(|name| {
+        let kind = spec_string("/spec/issuerRef/kind").unwrap_or_else(|| "Issuer".to_owned());
+        format!("{}/{}", kind, name)
+    });
+    Some(CertificateTarget {
+        secret_name: spec_string("/spec/secretName"),
+        issuer,
+        dns_names: array_field_values(resource.data.pointer("/spec/dnsNames"), "").collect(),
+        duration: spec_string("/spec/duration"),
+    })
+}
+
+/// Flattens a secret resource for output, including its key names if requested.
+pub fn flatten(manifest_resource: ManifestResource, show_keys: bool) -> FlatManifestResource {
+    let keys = show_keys
+        .then(|| secret_key_names(&manifest_resource.resource))
+        .flatten();
+    FlatManifestResource {
+        keys,
+        sealed_secret: sealed_secret_target(&manifest_resource.resource),
+        sops: sops::metadata(&manifest_resource.resource),
+        certificate: certificate_target(&manifest_resource.resource),
+        ..manifest_resource.into()
+    }
+}
@@ -0,0 +1,172 @@
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::findings::{Checks, Finding, Severity};
+use crate::report::escape;
+use crate::system_manifests::ManifestResource;
+
+/// A checked resource, identified by where it is declared and what it is.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct TestCase {
+    platform_name: String,
+    file: PathBuf,
+    line: Option<usize>,
+    kind: Option<String>,
+    namespace: Option<String>,
+    name: Option<String>,
+}
+
+impl TestCase {
+    fn of_resource(manifest_resource: &ManifestResource) -> Self {
+        let resource = &manifest_resource.resource;
+        TestCase {
+            platform_name: manifest_resource.platform.name.clone(),
+            file: manifest_resource.file.clone(),
+            line: manifest_resource.line,
+            kind: resource.types.as_ref().map(|t| t.kind.clone()),
+            namespace: resource.metadata.namespace.clone(),
+            name: resource.metadata.name.clone(),
+        }
+    }
+
+    fn of_finding(finding: &Finding) -> Self {
+        TestCase {
+            platform_name: finding.platform_name.clone(),
+            file: finding.file.clone(),
+            line: finding.line,
+            kind: finding.kind.clone(),
+            namespace: finding.namespace.clone(),
+            name: finding.name.clone(),
+        }
+    }
+
+    fn name(&self) -> String {
+        [
+            Some(self.platform_name.as_str()),
+            self.namespace.as_deref(),
+            self.kind.as_deref(),
+            self.name.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("/")
+    }
+}
+
+fn write_test_case(
+    writer: &mut impl Write,
+    rule: &str,
+    test_case: &TestCase,
+    findings: &[&Finding],
+    directory: &Path,
+) -> Result<()> {
+    let file = test_case
+        .file
+        .strip_prefix(directory)
+        .unwrap_or(&test_case.file)
+        .to_string_lossy()
+        .replace('\\', "/");
+    write!(
+        writer,
+        "    <testcase name=\"{}\" classname=\"{}\" file=\"{}\"",
+        escape(&test_case.name()),
+        escape(rule),
+        escape(&file)
+    )?;
+    if let Some(line) = test_case.line {
+        write!(writer, " line=\"{}\"", line)?;
+    }
+    if findings.is_empty() {
+        writeln!(writer, "/>")?;
+        return Ok(());
+    }
+    writeln!(writer, ">")?;
+    for finding in findings {
+        let severity = match finding.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        let location = match finding.line {
+            Some(line) => format!("{}:{}", file, line),
+            None => file.clone(),
+        };
+        let mut text = format!("{}: {}", location, finding.message);
+        if let Some(pointer) = &finding.pointer {
+            text.push_str(&format!(" ({})", pointer));
+        }
+        writeln!(
+            writer,
+            "      <failure message=\"{}\" type=\"{}\">{}</failure>",
+            escape(&finding.message),
+            severity,
+            escape(&text)
+        )?;
+    }
+    writeln!(writer, "    </testcase>")?;
+    Ok(())
+}
+
+/// Writes a JUnit XML report with a test suite per rule and a test case per resource in it,
+/// failing with the findings about the resource. Checked resources without findings pass.
+pub fn write_junit(
+    writer: &mut impl Write,
+    findings: &[Finding],
+    checks: &Checks,
+    directory: &Path,
+) -> Result<()> {
+    let checked: BTreeSet<TestCase> = checks.resources.iter().map(TestCase::of_resource).collect();
+    let mut suites: BTreeMap<&str, BTreeMap<TestCase, Vec<&Finding>>> = checks
+        .rules
+        .iter()
+        .map(|rule| (rule.as_str(), BTreeMap::new()))
+        .collect();
+    for finding in findings {
+        suites
+            .entry(finding.rule.as_str())
+            .or_default()
+            .entry(TestCase::of_finding(finding))
+            .or_default()
+            .push(finding);
+    }
+    for test_cases in suites.values_mut() {
+        for test_case in &checked {
+            test_cases.entry(test_case.clone()).or_default();
+        }
+    }
+
+    let tests: usize = suites.values().map(BTreeMap::len).sum();
+    let failures = |test_cases: &BTreeMap<TestCase, Vec<&Finding>>| {
+        test_cases
+            .values()
+            .filter(|findings| !findings.is_empty())
+            .count()
+    };
+    let total_failures: usize = suites.values().map(failures).sum();
+
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(
+        writer,
+        "<testsuites name=\"{}\" tests=\"{}\" failures=\"{}\">",
+        env!("CARGO_PKG_NAME"),
+        tests,
+        total_failures
+    )?;
+    for (rule, test_cases) in &suites {
+        writeln!(
+            writer,
+            "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\">",
+            escape(rule),
+            test_cases.len(),
+            failures(test_cases)
+        )?;
+        for (test_case, findings) in test_cases {
+            write_test_case(writer, rule, test_case, findings, directory)?;
+        }
+        writeln!(writer, "  </testsuite>")?;
+    }
+    writeln!(writer, "</testsuites>")?;
+    Ok(())
+}
@@ -0,0 +1,617 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::annotate::{self, AnnotatedFile};
+use crate::config::RuleLevel;
+use crate::duration::parse_duration;
+use crate::edit::EditedResource;
+use crate::eso::{self, Spec};
+use crate::findings::{Finding, Severity};
+use crate::plain_secrets::{self, Allowlist};
+use crate::sops;
+use crate::system_manifests::{ManifestResource, SystemManifests};
+
+pub const REQUIRED_LABELS_RULE: &str = "required-labels";
+pub const NAMING_CONVENTION_RULE: &str = "naming-convention";
+pub const FORBIDDEN_NAMESPACE_RULE: &str = "forbidden-namespace";
+pub const REFRESH_INTERVAL_RULE: &str = "refresh-interval";
+pub const SOPS_RECIPIENTS_RULE: &str = "sops-recipients";
+pub const UNDECLARED_NAMESPACE_RULE: &str = "undeclared-namespace";
+pub const COMPONENT_NAMESPACE_RULE: &str = "component-namespace";
+pub const TARGET_POLICY_RULE: &str = "target-policy";
+pub const IMMUTABLE_SECRET_RULE: &str = "immutable-secret";
+
+/// Refresh interval External Secrets Operator uses when a resource doesn't set one.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Namespaces every cluster has, which manifests don't need to declare.
+const BUILT_IN_NAMESPACES: &[&str] = &["default", "kube-system", "kube-public", "kube-node-lease"];
+
+/// Policies External Secrets Operator applies to the Secret of an ExternalSecret that doesn't
+/// set them.
+const DEFAULT_CREATION_POLICY: &str = "Owner";
+const DEFAULT_DELETION_POLICY: &str = "Retain";
+
+/// Names of the Namespaces declared in the manifests, per platform.
+pub type DeclaredNamespaces = HashMap<String, BTreeSet<String>>;
+
+/// The `[lint]` table of the config file. Rules without settings are off, except for the plain
+/// Secrets rule.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct LintConfig {
+    /// Labels every secret resource must carry.
+    pub required_labels: Vec<String>,
+    /// Regular expression the names of secret resources must match.
+    pub name_pattern: Option<String>,
+    /// Namespaces secret resources may not be declared in.
+    pub forbidden_namespaces: Vec<String>,
+    /// Longest refresh interval ExternalSecrets and PushSecrets may use, like `24h`.
+    pub max_refresh_interval: Option<String>,
+    /// Shortest refresh interval ExternalSecrets and PushSecrets may use, like `1m`.
+    pub min_refresh_interval: Option<String>,
+    /// Whether ExternalSecrets and PushSecrets have to set a refresh interval rather than rely on
+    /// the default.
+    pub require_refresh_interval: bool,
+    /// Keys SOPS-encrypted manifests may be encrypted to, like `age:age1...` or
+    /// `kms:arn:aws:kms:...`.
+    pub sops_recipients: Vec<String>,
+    /// Keys that were rotated out, which SOPS-encrypted manifests have to be re-encrypted from.
+    pub stale_sops_recipients: Vec<String>,
+    /// Whether to report Secrets carrying inline data, on by default.
+    pub deny_plain_secrets: Option<bool>,
+    /// Whether secret resources have to be in a namespace declared on the same platform.
+    pub require_declared_namespaces: bool,
+    /// Namespaces the secret resources of a component may be declared in, keyed by component.
+    /// Components left out may declare them in any namespace.
+    pub component_namespaces: BTreeMap<String, Vec<String>>,
+    /// Policies the targets of ExternalSecrets may not use, keyed by platform.
+    pub target_policies: BTreeMap<String, TargetPolicies>,
+    /// Whether plain Secrets have to be immutable, or may not be, keyed by platform.
+    pub immutable_secrets: BTreeMap<String, bool>,
+    /// Level to report the findings of a rule at, or `off` to leave them out.
+    pub rules: BTreeMap<String, RuleLevel>,
+}
+
+/// The `creationPolicy` and `deletionPolicy` values ExternalSecrets of a platform may not use,
+/// like `Orphan` or `Delete`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct TargetPolicies {
+    pub forbidden_creation_policies: Vec<String>,
+    pub forbidden_deletion_policies: Vec<String>,
+}
+
+impl LintConfig {
+    /// Overrides settings with the ones from `other`, adding up lists and rule levels.
+    pub fn merge(&mut self, other: LintConfig) {
+        self.required_labels.extend(other.required_labels);
+        self.name_pattern = other.name_pattern.or(self.name_pattern.take());
+        self.forbidden_namespaces.extend(other.forbidden_namespaces);
+        self.max_refresh_interval = other
+            .max_refresh_interval
+            .or(self.max_refresh_interval.take());
+        self.min_refresh_interval = other
+            .min_refresh_interval
+            .or(self.min_refresh_interval.take());
+        self.require_refresh_interval |= other.require_refresh_interval;
+        self.sops_recipients.extend(other.sops_recipients);
+        self.stale_sops_recipients
+            .extend(other.stale_sops_recipients);
+        self.deny_plain_secrets = other.deny_plain_secrets.or(self.deny_plain_secrets);
+        self.require_declared_namespaces |= other.require_declared_namespaces;
+        for (component, namespaces) in other.component_namespaces {
+            self.component_namespaces
+                .entry(component)
+                .or_default()
+                .extend(namespaces);
+        }
+        self.target_policies.extend(other.target_policies);
+        self.immutable_secrets.extend(other.immutable_secrets);
+        self.rules.extend(other.rules);
+    }
+}
+
+fn kind(manifest_resource: &ManifestResource) -> &str {
+    manifest_resource
+        .resource
+        .types
+        .as_ref()
+        .map(|t| t.kind.as_str())
+        .unwrap_or_default()
+}
+
+fn finding(
+    rule: &str,
+    severity: Severity,
+    message: String,
+    manifest_resource: &ManifestResource,
+    pointer: &str,
+) -> Finding {
+    Finding {
+        pointer: Some(pointer.to_owned()),
+        ..Finding::new(rule, severity, message, manifest_resource)
+    }
+}
+
+fn missing_labels(manifest_resource: &ManifestResource, labels: &[String]) -> Option<Finding> {
+    let present = manifest_resource.resource.metadata.labels.as_ref();
+    let missing: Vec<&str> = labels
+        .iter()
+        .filter(|label| !present.is_some_and(|present| present.contains_key(*label)))
+        .map(String::as_str)
+        .collect();
+    (!missing.is_empty()).then(|| {
+        finding(
+            REQUIRED_LABELS_RULE,
+            Severity::Error,
+            format!("Missing required labels: {}", missing.join(", ")),
+            manifest_resource,
+            "/metadata/labels",
+        )
+    })
+}
+
+fn misnamed(manifest_resource: &ManifestResource, pattern: &Regex) -> Option<Finding> {
+    let name = manifest_resource.resource.metadata.name.as_deref()?;
+    (!pattern.is_match(name)).then(|| {
+        finding(
+            NAMING_CONVENTION_RULE,
+            Severity::Warning,
+            format!("Name {} doesn't match {}", name, pattern),
+            manifest_resource,
+            "/metadata/name",
+        )
+    })
+}
+
+fn in_forbidden_namespace(
+    manifest_resource: &ManifestResource,
+    namespaces: &[String],
+) -> Option<Finding> {
+    let namespace = manifest_resource.resource.metadata.namespace.as_ref()?;
+    namespaces.contains(namespace).then(|| {
+        finding(
+            FORBIDDEN_NAMESPACE_RULE,
+            Severity::Error,
+            format!(
+                "{} may not be declared in namespace {}",
+                kind(manifest_resource),
+                namespace
+            ),
+            manifest_resource,
+            "/metadata/namespace",
+        )
+    })
+}
+
+/// Bounds of the refresh interval from the config, each along with how it's written there.
+struct RefreshBounds<'a> {
+    min: Option<(Duration, &'a str)>,
+    max: Option<(Duration, &'a str)>,
+    required: bool,
+}
+
+impl<'a> RefreshBounds<'a> {
+    fn new(config: &'a LintConfig) -> Result<Self> {
+        let bound = |text: &'a Option<String>, setting: &str| {
+            text.as_deref()
+                .map(|text| parse_duration(text).map(|duration| (duration, text)))
+                .transpose()
+                .with_context(|| format!("Invalid {} in lint config", setting))
+        };
+        Ok(RefreshBounds {
+            min: bound(&config.min_refresh_interval, "min-refresh-interval")?,
+            max: bound(&config.max_refresh_interval, "max-refresh-interval")?,
+            required: config.require_refresh_interval,
+        })
+    }
+
+    fn is_set(&self) -> bool {
+        self.min.is_some() || self.max.is_some() || self.required
+    }
+}
+
+fn refresh_out_of_bounds(
+    manifest_resource: &ManifestResource,
+    bounds: &RefreshBounds,
+) -> Option<Finding> {
+    let spec = Spec::of(&manifest_resource.resource)?;
+    let refresh_interval = spec.refresh_interval();
+    let message = match refresh_interval.map(parse_duration) {
+        None if bounds.required => "Refresh interval isn't set".to_owned(),
+        None => match (bounds.min, bounds.max) {
+            (_, Some((ceiling, ceiling_text))) if DEFAULT_REFRESH_INTERVAL > ceiling => {
+                format!("Default refresh interval of 1h exceeds {}", ceiling_text)
+            }
+            (Some((floor, floor_text)), _) if DEFAULT_REFRESH_INTERVAL < floor => {
+                format!(
+                    "Default refresh interval of 1h is shorter than {}",
+                    floor_text
+                )
+            }
+            _ => return None,
+        },
+        // Never refreshing can't hammer the API, it can only be too slow.
+        Some(Ok(Duration::ZERO)) if bounds.max.is_some() => "Refreshing is disabled".to_owned(),
+        Some(Ok(Duration::ZERO)) => return None,
+        Some(Ok(interval)) => match (bounds.min, bounds.max) {
+            (_, Some((ceiling, ceiling_text))) if interval > ceiling => format!(
+                "Refresh interval {} exceeds {}",
+                refresh_interval.unwrap_or_default(),
+                ceiling_text
+            ),
+            (Some((floor, floor_text)), _) if interval < floor => format!(
+                "Refresh interval {} is shorter than {}",
+                refresh_interval.unwrap_or_default(),
+                floor_text
+            ),
+            _ => return None,
+        },
+        Some(Err(error)) => format!("Invalid refresh interval: {:#}", error),
+    };
+    Some(finding(
+        REFRESH_INTERVAL_RULE,
+        Severity::Warning,
+        message,
+        manifest_resource,
+        "/spec/refreshInterval",
+    ))
+}
+
+fn in_undeclared_namespace(
+    manifest_resource: &ManifestResource,
+    declared_namespaces: &DeclaredNamespaces,
+) -> Option<Finding> {
+    let namespace = manifest_resource.resource.metadata.namespace.as_ref()?;
+    let declared = BUILT_IN_NAMESPACES.contains(&namespace.as_str())
+        || declared_namespaces
+            .get(&manifest_resource.platform.name)
+            .is_some_and(|namespaces| namespaces.contains(namespace));
+    (!declared).then(|| {
+        finding(
+            UNDECLARED_NAMESPACE_RULE,
+            Severity::Warning,
+            format!(
+                "Namespace {} isn't declared on platform {}",
+                namespace, manifest_resource.platform.name
+            ),
+            manifest_resource,
+            "/metadata/namespace",
+        )
+    })
+}
+
+fn outside_component_namespaces(
+    manifest_resource: &ManifestResource,
+    component_namespaces: &BTreeMap<String, Vec<String>>,
+) -> Option<Finding> {
+    let component = &manifest_resource.component.name;
+    let allowed = component_namespaces.get(component)?;
+    let namespace = manifest_resource.resource.metadata.namespace.as_ref()?;
+    (!allowed.contains(namespace)).then(|| {
+        finding(
+            COMPONENT_NAMESPACE_RULE,
+            Severity::Error,
+            format!(
+                "Component {} may only declare {} in namespaces {}, not in {}",
+                component,
+                kind(manifest_resource),
+                allowed.join(", "),
+                namespace
+            ),
+            manifest_resource,
+            "/metadata/namespace",
+        )
+    })
+}
+
+fn forbidden_target_policies(
+    manifest_resource: &ManifestResource,
+    target_policies: &BTreeMap<String, TargetPolicies>,
+) -> Vec<Finding> {
+    let Some(policies) = target_policies.get(&manifest_resource.platform.name) else {
+        return Vec::new();
+    };
+    let Some(spec) = eso::external_secret_spec(&manifest_resource.resource) else {
+        return Vec::new();
+    };
+    let target = spec.target.unwrap_or_default();
+    [
+        (
+            "creationPolicy",
+            target.creation_policy,
+            DEFAULT_CREATION_POLICY,
+            &policies.forbidden_creation_policies,
+        ),
+        (
+            "deletionPolicy",
+            target.deletion_policy,
+            DEFAULT_DELETION_POLICY,
+            &policies.forbidden_deletion_policies,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(field, policy, default, forbidden)| {
+        let (policy, set) = match &policy {
+            Some(policy) => (policy.as_str(), true),
+            None => (default, false),
+        };
+        forbidden
+            .iter()
+            .any(|forbidden| forbidden == policy)
+            .then(|| {
+                finding(
+                    TARGET_POLICY_RULE,
+                    Severity::Error,
+                    format!(
+                        "{}{} {} is forbidden on platform {}",
+                        if set { "" } else { "Default " },
+                        field,
+                        policy,
+                        manifest_resource.platform.name
+                    ),
+                    manifest_resource,
+                    &format!("/spec/target/{}", field),
+                )
+            })
+    })
+    .collect()
+}
+
+/// Returns whether a plain Secret has to be made immutable, or mutable, on its platform.
+fn required_immutability(
+    manifest_resource: &ManifestResource,
+    immutable_secrets: &BTreeMap<String, bool>,
+) -> Option<bool> {
+    if kind(manifest_resource) != "Secret" {
+        return None;
+    }
+    let required = *immutable_secrets.get(&manifest_resource.platform.name)?;
+    let immutable = manifest_resource.resource.data["immutable"].as_bool() == Some(true);
+    (immutable != required).then_some(required)
+}
+
+fn immutability_mismatch(
+    manifest_resource: &ManifestResource,
+    immutable_secrets: &BTreeMap<String, bool>,
+) -> Option<Finding> {
+    let required = required_immutability(manifest_resource, immutable_secrets)?;
+    Some(finding(
+        IMMUTABLE_SECRET_RULE,
+        Severity::Error,
+        format!(
+            "Secret {} be immutable on platform {}",
+            if required { "has to" } else { "may not" },
+            manifest_resource.platform.name
+        ),
+        manifest_resource,
+        "/immutable",
+    ))
+}
+
+/// Fixes the findings of the rules that can be fixed in place, making Secrets immutable or not
+/// as their platform requires, and edits the lines of their manifest files so that the formatting
+/// and comments are kept. Resources rendered from kustomizations or Helm charts and
+/// SOPS-encrypted ones are skipped with a warning.
+///
+/// Returns the files to change, without writing them.
+pub fn fix(
+    manifest_resources: &[ManifestResource],
+    config: &LintConfig,
+) -> Result<Vec<AnnotatedFile>> {
+    let mut documents: BTreeMap<PathBuf, BTreeMap<usize, (bool, EditedResource)>> = BTreeMap::new();
+    for manifest_resource in manifest_resources {
+        let Some(required) = required_immutability(manifest_resource, &config.immutable_secrets)
+        else {
+            continue;
+        };
+        let name = manifest_resource
+            .resource
+            .metadata
+            .name
+            .as_deref()
+            .unwrap_or_default();
+        let Some(line) = manifest_resource.line else {
+            eprintln!(
+                "Not fixing {} rendered from {}, fix its source instead",
+                name,
+                manifest_resource.file.display()
+            );
+            continue;
+        };
+        if sops::is_encrypted(&manifest_resource.resource) {
+            eprintln!(
+                "Not fixing {} in {}:{}, editing it would break its SOPS MAC",
+                name,
+                manifest_resource.file.display(),
+                line
+            );
+            continue;
+        }
+        documents
+            .entry(manifest_resource.file.clone())
+            .or_default()
+            .insert(line, (required, EditedResource::from(manifest_resource)));
+    }
+    annotate::edit_documents(documents, |lines, line, required| {
+        annotate::set_field(lines, line, "immutable", required.then_some("true"))
+    })
+}
+
+/// Reads the Namespaces declared on each platform, if the config requires secret resources to be
+/// in one.
+pub fn declared_namespaces(
+    system_manifests: &SystemManifests,
+    config: &LintConfig,
+) -> Result<DeclaredNamespaces> {
+    let mut declared_namespaces = DeclaredNamespaces::new();
+    if !config.require_declared_namespaces {
+        return Ok(declared_namespaces);
+    }
+    for manifest_resource_result in system_manifests.all_resource_iter() {
+        let manifest_resource = manifest_resource_result?;
+        if kind(&manifest_resource) == "Namespace" {
+            if let Some(name) = manifest_resource.resource.metadata.name {
+                declared_namespaces
+                    .entry(manifest_resource.platform.name.clone())
+                    .or_default()
+                    .insert(name);
+            }
+        }
+    }
+    Ok(declared_namespaces)
+}
+
+/// Returns findings for the keys a SOPS-encrypted manifest is encrypted to that are stale, or
+/// unknown if known keys are configured.
+fn sops_recipient_findings(
+    manifest_resource: &ManifestResource,
+    config: &LintConfig,
+) -> Vec<Finding> {
+    let Some(metadata) = sops::metadata(&manifest_resource.resource) else {
+        return Vec::new();
+    };
+    metadata
+        .recipients
+        .iter()
+        .filter_map(|recipient| {
+            if config.stale_sops_recipients.contains(recipient) {
+                Some((
+                    Severity::Error,
+                    format!("Encrypted to stale key {}", recipient),
+                ))
+            } else if !config.sops_recipients.is_empty()
+                && !config.sops_recipients.contains(recipient)
+            {
+                Some((
+                    Severity::Warning,
+                    format!("Encrypted to unknown key {}", recipient),
+                ))
+            } else {
+                None
+            }
+        })
+        .map(|(severity, message)| {
+            finding(
+                SOPS_RECIPIENTS_RULE,
+                severity,
+                message,
+                manifest_resource,
+                "/sops",
+            )
+        })
+        .collect()
+}
+
+/// Returns the rules the config sets up.
+pub fn rules(config: &LintConfig) -> Vec<&'static str> {
+    [
+        (
+            plain_secrets::RULE,
+            config.deny_plain_secrets.unwrap_or(true),
+        ),
+        (REQUIRED_LABELS_RULE, !config.required_labels.is_empty()),
+        (NAMING_CONVENTION_RULE, config.name_pattern.is_some()),
+        (
+            FORBIDDEN_NAMESPACE_RULE,
+            !config.forbidden_namespaces.is_empty(),
+        ),
+        (
+            REFRESH_INTERVAL_RULE,
+            config.max_refresh_interval.is_some()
+                || config.min_refresh_interval.is_some()
+                || config.require_refresh_interval,
+        ),
+        (
+            SOPS_RECIPIENTS_RULE,
+            !config.sops_recipients.is_empty() || !config.stale_sops_recipients.is_empty(),
+        ),
+        (
+            UNDECLARED_NAMESPACE_RULE,
+            config.require_declared_namespaces,
+        ),
+        (
+            COMPONENT_NAMESPACE_RULE,
+            !config.component_namespaces.is_empty(),
+        ),
+        (TARGET_POLICY_RULE, !config.target_policies.is_empty()),
+        (IMMUTABLE_SECRET_RULE, !config.immutable_secrets.is_empty()),
+    ]
+    .into_iter()
+    .filter_map(|(rule, enabled)| enabled.then_some(rule))
+    .collect()
+}
+
+/// Checks secret resources against the rules set up in the config file.
+pub fn lint(
+    manifest_resources: &[ManifestResource],
+    config: &LintConfig,
+    allowlist: &Allowlist,
+    declared_namespaces: &DeclaredNamespaces,
+) -> Result<Vec<Finding>> {
+    let name_pattern = config
+        .name_pattern
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .with_context(|| "Invalid name-pattern in lint config")?;
+    let refresh_bounds = RefreshBounds::new(config)?;
+
+    let mut findings = Vec::new();
+    if config.deny_plain_secrets.unwrap_or(true) {
+        findings.extend(plain_secrets::find_plain_secrets(
+            manifest_resources,
+            allowlist,
+        ));
+    }
+    for manifest_resource in manifest_resources {
+        if !config.required_labels.is_empty() {
+            findings.extend(missing_labels(manifest_resource, &config.required_labels));
+        }
+        if let Some(pattern) = &name_pattern {
+            findings.extend(misnamed(manifest_resource, pattern));
+        }
+        if !config.forbidden_namespaces.is_empty() {
+            findings.extend(in_forbidden_namespace(
+                manifest_resource,
+                &config.forbidden_namespaces,
+            ));
+        }
+        if refresh_bounds.is_set() {
+            findings.extend(refresh_out_of_bounds(manifest_resource, &refresh_bounds));
+        }
+        if !config.sops_recipients.is_empty() || !config.stale_sops_recipients.is_empty() {
+            findings.extend(sops_recipient_findings(manifest_resource, config));
+        }
+        if !config.component_namespaces.is_empty() {
+            findings.extend(outside_component_namespaces(
+                manifest_resource,
+                &config.component_namespaces,
+            ));
+        }
+        if !config.target_policies.is_empty() {
+            findings.extend(forbidden_target_policies(
+                manifest_resource,
+                &config.target_policies,
+            ));
+        }
+        if !config.immutable_secrets.is_empty() {
+            findings.extend(immutability_mismatch(
+                manifest_resource,
+                &config.immutable_secrets,
+            ));
+        }
+        if config.require_declared_namespaces {
+            findings.extend(in_undeclared_namespace(
+                manifest_resource,
+                declared_namespaces,
+            ));
+        }
+    }
+    Ok(findings)
+}
@@ -0,0 +1,33 @@
+use clap::ValueEnum;
+use tracing_subscriber::EnvFilter;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of each event.
+    Json,
+}
+
+/// Returns the log filter for a number of `-v` flags: only warnings by default, debug logs of
+/// this tool with one and its trace logs along with debug logs of its dependencies with more.
+fn filter(verbosity: u8) -> &'static str {
+    match verbosity {
+        0 => "warn",
+        1 => "warn,dp_secrets_helper=debug",
+        _ => "debug,dp_secrets_helper=trace",
+    }
+}
+
+/// Writes logs to stderr, filtered by `RUST_LOG` if it's set and else by the verbosity.
+pub fn init(verbosity: u8, format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| filter(verbosity).into());
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
@@ -1,73 +1,2010 @@
-use clap::{arg, command, Parser, Subcommand, ValueEnum};
-use system_manifests::{FlatManifestResource, SystemManifests};
+use clap::{ArgAction, CommandFactory, Parser, Subcommand};
+use clap_complete::{ArgValueCandidates, CompleteEnv};
+use findings::Checks;
+use kube::api::DynamicObject;
+use output::{write_findings, write_output, write_output_with_git_info, write_records, OutputArgs};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::Duration;
+use system_manifests::{FlatManifestResource, ManifestResource, SystemManifests};
 
+mod age;
+mod annotate;
+mod archive;
+mod browse;
+mod cert_expiry;
+mod cluster;
+mod compare_platforms;
+mod completions;
+mod config;
+mod convert;
+mod cyclonedx;
+mod diff;
+mod doctor;
+mod drift;
+mod duration;
+mod edit;
+mod eso;
+mod export;
+mod filter;
+mod findings;
+mod get;
+mod git;
+mod graph;
+mod inventory;
+mod junit;
+mod lint;
+mod logging;
+mod migrate_store;
+mod oci;
+mod output;
+mod owners;
+mod plain_secrets;
+mod policy;
+mod progress;
+mod providers;
+mod pull_request;
+mod redact;
+mod references;
+mod render;
+mod report;
+mod rotate;
+mod rotate_plan;
+mod sarif;
+mod scaffold;
+mod scan;
+mod schema;
+mod search;
+mod selector;
+mod serve;
+mod sops;
+mod stats;
+mod stores;
+mod summary;
+mod sync_status;
 mod system_manifests;
+mod tree;
+mod validate;
+mod verify_remote;
+mod watch;
 
 /// Tool to help you manage CDP secrets.
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
-    /// Local clone of the system manifests repository.
-    #[arg(long, short = 's', env = "SYSTEM_MANIFESTS")]
-    system_manifests: String,
+    /// Local clone of the system manifests repository, a `.tar`, `.tar.gz` or `.zip` archive of
+    /// it, or the URL of the repository to read from a checkout cached in
+    /// `$XDG_CACHE_HOME/dp-secrets-helper`. Repeat it, or separate them by commas, to read the
+    /// platforms of several repositories together, telling them apart by a `repo` field. The
+    /// configuration, git info and cache are those of the first.
+    #[arg(
+        long,
+        short = 's',
+        env = "SYSTEM_MANIFESTS",
+        global = true,
+        value_delimiter = ','
+    )]
+    system_manifests: Vec<String>,
+
+    /// Flux OCI artifact to read the system manifests from instead, like
+    /// `oci://ghcr.io/org/manifests:v1.2.3`, pulled with `flux pull artifact` using the registry
+    /// credentials of the docker config.
+    #[arg(long, global = true)]
+    oci: Option<String>,
+
+    /// Branch, tag or commit to check out when the system manifests are a repository URL,
+    /// defaults to the repository's default branch.
+    #[arg(long = "ref", global = true)]
+    reference: Option<String>,
+
+    /// Only read manifests of this platform, can be repeated.
+    #[arg(long, global = true, add = ArgValueCandidates::new(completions::platform_candidates))]
+    platform: Vec<String>,
+
+    /// Only read manifests of this component, can be repeated.
+    #[arg(long, global = true, add = ArgValueCandidates::new(completions::component_candidates))]
+    component: Vec<String>,
+
+    /// Only read manifest files that changed since this git reference. Commands checking
+    /// resources against the others, like orphans and check-stores, still read all files but
+    /// only report on the changed ones.
+    #[arg(long, global = true)]
+    changed_since: Option<String>,
+
+    /// How deep to walk into component manifest directories, 1 only reads the files directly
+    /// inside them. Unlimited by default.
+    #[arg(long, global = true)]
+    max_depth: Option<usize>,
+
+    /// Leave out manifest files matching this glob, relative to the system manifests directory,
+    /// like `manifests/*/fixtures/**`. Can be repeated, adding to the config's `exclude`.
+    #[arg(long, global = true, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Also read manifests in the environments and clusters directories of each platform, listed
+    /// as the "environments" and "clusters" components.
+    #[arg(long, global = true)]
+    include_bootstrap: bool,
+
+    /// Skip manifest files and documents that aren't valid resources, listing them at the end.
+    #[arg(long, global = true)]
+    skip_invalid: bool,
+
+    /// Fail if a platform in the clusters directory has no directory in the environments or
+    /// manifests directory, instead of reading it without and listing it at the end.
+    #[arg(long, global = true)]
+    strict: bool,
+
+    /// Render component manifests before reading them, can be repeated. With kustomize, every
+    /// directory with a kustomization is built with `kustomize build`, or `kubectl kustomize` if
+    /// kustomize isn't installed, and the resources it renders are reported at its kustomization
+    /// file. With helm, the charts of Flux HelmReleases are rendered with `helm template` and
+    /// their resources reported at the HelmRelease.
+    #[arg(long, global = true, value_enum, value_delimiter = ',')]
+    render: Vec<render::Render>,
+
+    /// How to find the components of each platform: the directories in `manifests/<platform>`,
+    /// or the paths Flux Kustomizations or ArgoCD Applications and ApplicationSets apply, starting
+    /// from the ones in `clusters/<platform>`.
+    #[arg(long, global = true, value_enum)]
+    discover: Option<system_manifests::Discovery>,
+
+    /// Decrypt SOPS-encrypted manifests with `sops --decrypt` in memory, so their data is
+    /// validated like that of plain manifests. Requires the sops CLI and access to the keys
+    /// they're encrypted to. Decrypted manifests are never written to disk or the cache.
+    #[arg(long, global = true)]
+    decrypt_sops: bool,
+
+    /// Cache the resources read from each manifest file in `.dp-secrets-cache` in the system
+    /// manifests directory, so later runs only read the files that changed.
+    #[arg(long, global = true)]
+    cache: bool,
+
+    /// How many manifest files to read and parse at a time, defaults to the number of CPUs.
+    #[arg(long, short = 'j', global = true)]
+    jobs: Option<usize>,
+
+    /// CEL expression secret resources must satisfy to be listed, like
+    /// `resource.metadata.namespace == "payments" && kind == "ExternalSecret"`. The whole resource
+    /// is available as `resource`, along with `kind`, `name`, `platform`, `component` and `file`.
+    #[arg(long, global = true)]
+    filter: Option<String>,
+
+    /// Annotation secret resources must have to be listed, like `owner=team-x`, or just `owner`
+    /// to only require the key, and `owner!=team-x` or `!owner` to exclude resources. Can be
+    /// repeated, resources have to satisfy all of them. Not named `--annotation`, as annotate and
+    /// rotate take that for the annotation they write.
+    #[arg(
+        long,
+        global = true,
+        value_name = "REQUIREMENT",
+        value_parser = selector::parse_annotation_selector
+    )]
+    annotation_selector: Vec<selector::LabelSelector>,
+
+    /// Kubeconfig context to reach a platform's cluster with, overriding the config's `clusters`
+    /// and `contexts`. Can be repeated.
+    #[arg(
+        long,
+        global = true,
+        value_name = "PLATFORM=CONTEXT",
+        add = ArgValueCandidates::new(completions::platform_context_candidates),
+        value_parser = parse_key_value
+    )]
+    context_override: Vec<(String, String)>,
+
+    /// How long to wait for a platform's cluster before reporting it as failed and going on
+    /// with the others, like `30s` or `2m`.
+    #[arg(long, global = true, default_value = "30s", value_parser = duration::parse_duration)]
+    cluster_timeout: Duration,
+
+    /// Lowest severity of findings that makes findings commands exit with code 1, defaults to
+    /// warning. Findings without a severity, like orphans or drift, count as errors.
+    #[arg(long, global = true, value_enum)]
+    fail_on: Option<findings::FailOn>,
+
+    /// Only write how many secret resources there are per kind and platform, and for findings
+    /// commands how many findings, instead of listing them. Findings commands still fail on
+    /// findings like `--fail-on` says.
+    #[arg(long, global = true)]
+    summary: bool,
+
+    /// Run the command again whenever files in the system manifests directory change, until
+    /// interrupted.
+    #[arg(long, global = true)]
+    watch: bool,
+
+    /// Log what's being done to stderr: `-v` logs the directories and files discovered, how long
+    /// each file took to parse and the calls to clusters and secret providers, `-vv` also every
+    /// resource read. `RUST_LOG` overrides what's logged.
+    #[arg(long, short = 'v', global = true, action = ArgAction::Count)]
+    verbose: u8,
+
+    /// Format of the logs.
+    #[arg(long, global = true, value_enum, default_value_t)]
+    log_format: logging::LogFormat,
 
     #[command(subcommand)]
     command: Commands,
 }
 
-#[derive(Subcommand, Debug)]
+impl Cli {
+    /// Returns the locations of the system manifests repositories given.
+    fn system_manifests_locations(&self) -> anyhow::Result<&[String]> {
+        anyhow::ensure!(
+            !self.system_manifests.is_empty(),
+            "The system manifests directory is required, pass --system-manifests or set \
+            SYSTEM_MANIFESTS"
+        );
+        Ok(&self.system_manifests)
+    }
+
+    /// Returns the directory of a system manifests repository, fetching the checkout of a
+    /// repository URL.
+    fn system_manifests_directory(&self, location: &str) -> anyhow::Result<PathBuf> {
+        if git::is_remote_url(location) {
+            return git::fetch_cached(location, self.reference.as_deref());
+        }
+        anyhow::ensure!(
+            self.reference.is_none(),
+            "--ref requires --system-manifests to be a repository URL"
+        );
+        Ok(PathBuf::from(location))
+    }
+
+    /// Returns the system manifests directories by repository name, along with the temporary
+    /// directories holding those pulled from an OCI artifact or extracted from an archive.
+    fn fetch_system_manifests(
+        &self,
+    ) -> anyhow::Result<(
+        system_manifests::RepositoryDirectories,
+        Vec<archive::Extracted>,
+    )> {
+        if let Some(reference) = &self.oci {
+            let extracted = oci::pull(reference)?;
+            let name = system_manifests::repository_name(reference);
+            return Ok((vec![(name, extracted.directory.clone())], vec![extracted]));
+        }
+        let mut directories = Vec::new();
+        let mut extracted = Vec::new();
+        for location in self.system_manifests_locations()? {
+            let name = system_manifests::repository_name(location);
+            let directory = self.system_manifests_directory(location)?;
+            if !archive::is_archive(&directory) {
+                directories.push((name, directory));
+                continue;
+            }
+            let archive = archive::extract(&directory)?;
+            directories.push((name, archive.directory.clone()));
+            extracted.push(archive);
+        }
+        Ok((directories, extracted))
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
 enum Commands {
     /// Lists all secrets found in rendered environment manifests.
     List {
-        // Output format
-        #[arg(long, short = 'o', value_enum, default_value = "json")]
-        output: ListOutputFormat,
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Only list secrets in this namespace, can be repeated.
+        #[arg(long, short = 'n')]
+        namespace: Vec<String>,
+
+        /// Nest the output in groups.
+        #[arg(long, value_enum)]
+        group_by: Option<inventory::GroupBy>,
+
+        /// Sort the secrets by this field, they're listed in the order they're read otherwise.
+        #[arg(long, value_enum)]
+        sort_by: Option<inventory::SortBy>,
+
+        /// List at most this many secrets.
+        #[arg(long)]
+        limit: Option<usize>,
+
+        /// Skip this many secrets first, to page through them along with `--limit`.
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Include the names of the keys each secret defines, values are never shown.
+        #[arg(long)]
+        show_keys: bool,
+
+        /// Wrap the output in an object with the `schemaVersion` of the output, the commit, branch
+        /// and dirty status of the system manifests repository under `git`, and the secrets
+        /// under `secrets`.
+        #[arg(long)]
+        include_git_info: bool,
+        /// Write the complete resources instead of a summary of them, with every value of their
+        /// data, stringData and templated data replaced by `***REDACTED***`.
+        #[arg(long, conflicts_with = "show_keys")]
+        output_full: bool,
+    },
+    /// Gets secret resources by name, as `<name>` or `<namespace>/<name>`, or all of them,
+    /// filtered by a label selector like `kubectl get -l`. Exits with a non-zero code if a name
+    /// matches no secret resource.
+    Get {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Names of the secret resources, as `<name>` or `<namespace>/<name>`.
+        names: Vec<String>,
+
+        /// Only get secrets in this namespace, can be repeated.
+        #[arg(long, short = 'n')]
+        namespace: Vec<String>,
+
+        /// Label selector with kubectl's syntax, like `app.kubernetes.io/part-of=payments`,
+        /// `tier!=db`, `env in (prod,staging)`, `env notin (dev)`, `legacy` or `!legacy`.
+        #[arg(long, short = 'l', value_parser = selector::parse_label_selector)]
+        selector: Option<selector::LabelSelector>,
+
+        /// Include the names of the keys each secret defines, values are never shown.
+        #[arg(long)]
+        show_keys: bool,
+
+        /// Write the complete resources instead of a summary of them, with every value of their
+        /// data, stringData and templated data replaced by `***REDACTED***`.
+        #[arg(long, conflicts_with = "show_keys")]
+        output_full: bool,
+    },
+    /// Searches secret names, namespaces, labels, annotations, remote keys and secret store
+    /// references for a regular expression.
+    #[command(alias = "grep")]
+    Search {
+        /// Regular expression to search for.
+        pattern: regex::Regex,
+
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Lists Secrets carrying inline data and exits with a non-zero code if there are any.
+    ForbidPlainSecrets {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// File listing sanctioned plain Secrets as `<platform>/<namespace>/<name>` lines.
+        #[arg(long)]
+        allowlist: Option<PathBuf>,
+    },
+    /// Checks secret resources against the lint rules of the config file, like required labels
+    /// and naming conventions, and exits with a non-zero code if there are findings.
+    Lint {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// File listing sanctioned plain Secrets as `<platform>/<namespace>/<name>` lines.
+        #[arg(long)]
+        allowlist: Option<PathBuf>,
+
+        /// Fix the findings that can be fixed in the manifests, like Secrets that have to be
+        /// immutable, keeping their formatting and comments. Lists the findings that remain.
+        #[arg(long)]
+        fix: bool,
+
+        /// Print a unified diff of the fixes instead of writing them and listing findings.
+        #[arg(long, requires = "fix")]
+        dry_run: bool,
+
+        #[command(flatten)]
+        pull_request: pull_request::PullRequestArgs,
+    },
+    /// Evaluates Rego policies against every secret resource and exits with a non-zero code if
+    /// they deny or warn about any.
+    Policy {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Rego policy file, or directory of them, can be repeated.
+        #[arg(long, required = true)]
+        policy: Vec<PathBuf>,
+
+        /// Package of the policies' `deny` and `warn` rules.
+        #[arg(long, default_value = "secrets")]
+        package: String,
+    },
+    /// Scans all manifest values for probable leaked credentials and exits with a non-zero code
+    /// if there are any.
+    Scan {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// File with fingerprints of known findings to suppress.
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+
+        /// Write the current findings to the baseline file instead of reporting them.
+        #[arg(long, requires = "baseline")]
+        update_baseline: bool,
+    },
+    /// Prints the platforms, their components and the secret resources of each component as a
+    /// tree.
+    Tree {
+        /// Number of levels to show: 1 for platforms, 2 for components and 3 for secrets.
+        #[arg(long, default_value_t = 3)]
+        depth: usize,
+    },
+    /// Browses secret resources interactively in the terminal, narrowing them down by platform
+    /// and component and searching them, with their YAML shown redacted.
+    Browse,
+    /// Prints a graph of secret stores, ExternalSecrets and PushSecrets, Secrets and the
+    /// workloads consuming them per platform.
+    Graph {
+        /// Graph description language to print.
+        #[arg(long, value_enum, default_value = "dot")]
+        format: graph::GraphFormat,
+    },
+    /// Lists secrets added, removed or changed between two directories or git references of the
+    /// system manifests repository.
+    Diff {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Directory or git reference to compare against.
+        #[arg(long)]
+        base: String,
+
+        /// Directory or git reference to compare, defaults to the system manifests directory.
+        #[arg(long)]
+        head: Option<String>,
+    },
+    /// Lists ExternalSecrets and PushSecrets referencing a SecretStore or ClusterSecretStore that
+    /// doesn't exist on the same platform and exits with a non-zero code if there are any.
+    CheckStores {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Checks that the specs of ExternalSecrets and PushSecrets can be read, that Secrets carry
+    /// valid base64 data and the keys their type requires and, with `--schemas`, that External
+    /// Secrets Operator resources conform to the schemas of their CRDs. Exits with a non-zero code
+    /// if they don't.
+    Validate {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Validate ExternalSecrets, PushSecrets and secret stores against the bundled CRD
+        /// schemas, catching unknown fields like misspelled ones.
+        #[arg(long)]
+        schemas: bool,
+
+        /// Directory of CRD files whose schemas replace the bundled ones for the same versions,
+        /// or add schemas of further kinds.
+        #[arg(long, requires = "schemas")]
+        schema_dir: Option<PathBuf>,
+
+        /// Validate against the schemas of the CRDs installed in each platform's cluster instead,
+        /// catching API versions the cluster doesn't serve.
+        #[arg(long, conflicts_with = "schemas")]
+        schemas_from_cluster: bool,
+
+        /// Kubeconfig context to use for a platform, defaults to the platform name.
+        #[arg(
+            long,
+            value_name = "PLATFORM=CONTEXT",
+            add = ArgValueCandidates::new(completions::platform_context_candidates),
+            value_parser = parse_key_value,
+            requires = "schemas_from_cluster"
+        )]
+        context: Vec<(String, String)>,
+    },
+    /// Checks that the remote keys ExternalSecrets read exist and that the ones PushSecrets write
+    /// to are writable, listing dead references per platform.
+    VerifyRemote {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Only check stores of this provider type, as named in `spec.provider`, can be repeated.
+        #[arg(long)]
+        provider: Vec<String>,
+
+        #[command(flatten)]
+        provider_args: providers::ProviderArgs,
+    },
+    /// Rewrites the Secrets with inline data in a manifest file into ExternalSecrets reading
+    /// their values as properties of a remote key, optionally writing the values to the store's
+    /// backend first. Vault and AWS Secrets Manager and Parameter Store can be written to.
+    Convert {
+        /// Manifest file with the Secrets to convert.
+        file: PathBuf,
+
+        /// Name of the secret store the ExternalSecrets read from.
+        #[arg(long)]
+        store: String,
+
+        /// Kind of the secret store the ExternalSecrets read from.
+        #[arg(long, default_value = "ClusterSecretStore")]
+        store_kind: String,
+
+        /// Remote key to store the values of each Secret at, `{namespace}` and `{name}` are
+        /// replaced with the Secret's.
+        #[arg(long, default_value = "{namespace}/{name}")]
+        key: String,
+
+        /// How often the ExternalSecrets refresh their Secret.
+        #[arg(long, default_value = "1h")]
+        refresh_interval: String,
+
+        /// Write the values of the Secrets to the store's backend, as resolved on the file's
+        /// platform.
+        #[arg(long)]
+        write: bool,
+
+        /// Print the rewritten file instead of writing it, and with `--write` only resolve the
+        /// backends the values would be written to.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        pull_request: pull_request::PullRequestArgs,
+
+        #[command(flatten)]
+        provider_args: providers::ProviderArgs,
+    },
+    /// Adds or updates labels and annotations on the secret resources matching the namespaces,
+    /// label selector and filter, editing the manifests in place so that their formatting and
+    /// comments are kept.
+    Annotate {
+        /// Label to add or update, can be repeated.
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+        label: Vec<(String, String)>,
+
+        /// Annotation to add or update, can be repeated.
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_value)]
+        annotation: Vec<(String, String)>,
+
+        /// Only change secret resources in this namespace, can be repeated.
+        #[arg(long, short = 'n')]
+        namespace: Vec<String>,
+
+        /// Only change secret resources whose labels match this selector, like
+        /// `app=payments,tier!=db,!legacy`.
+        #[arg(long, short = 'l', value_parser = selector::parse_label_selector)]
+        selector: Option<selector::LabelSelector>,
+
+        /// Print a unified diff of the changes instead of writing them.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        pull_request: pull_request::PullRequestArgs,
+    },
+    /// Makes ExternalSecrets sync their Secrets again after a credential was rotated, by setting
+    /// an annotation to the current time in their manifests and committing the change on a new
+    /// git branch, ready for a pull request.
+    Rotate {
+        /// ExternalSecrets to rotate, as `<name>` or `<namespace>/<name>`. Defaults to all that
+        /// match the other options.
+        names: Vec<String>,
+
+        /// Only rotate ExternalSecrets in this namespace, can be repeated.
+        #[arg(long, short = 'n')]
+        namespace: Vec<String>,
+
+        /// Only rotate ExternalSecrets whose labels match this selector, like `app=payments`.
+        #[arg(long, short = 'l', value_parser = selector::parse_label_selector)]
+        selector: Option<selector::LabelSelector>,
+
+        /// Annotation to set to the time of the rotation. Use `force-sync` to have ESO refresh
+        /// the Secrets right away rather than on their next refresh.
+        #[arg(long, default_value = "secrets.cdp/last-rotated")]
+        annotation: String,
+
+        /// Branch to commit on, defaults to `rotate-secrets-<time>`.
+        #[arg(long)]
+        branch: Option<String>,
+
+        /// Commit message, defaults to one naming the annotation and time.
+        #[arg(long)]
+        message: Option<String>,
+
+        /// Print a unified diff of the changes instead of writing and committing them.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        pull_request: pull_request::PullRequestArgs,
+    },
+    /// Rewrites the references of ExternalSecrets and PushSecrets to one secret store into
+    /// references to another in place, keeping the formatting and comments of the manifests, and
+    /// lists the files touched. Use `--platform` to only migrate some platforms.
+    MigrateStore {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Name of the secret store to migrate from.
+        #[arg(long)]
+        from: String,
+
+        /// Name of the secret store to migrate to.
+        #[arg(long)]
+        to: String,
+
+        /// List the files that would be rewritten without writing them.
+        #[arg(long)]
+        dry_run: bool,
+
+        #[command(flatten)]
+        pull_request: pull_request::PullRequestArgs,
+    },
+    /// Writes a standalone report of the secrets per platform, their counts and the findings of
+    /// the lint rules and of any Rego policies given.
+    Report {
+        /// Format of the report.
+        #[arg(long, value_enum, default_value = "html")]
+        format: report::ReportFormat,
+
+        /// File listing sanctioned plain Secrets as `<platform>/<namespace>/<name>` lines.
+        #[arg(long)]
+        allowlist: Option<PathBuf>,
+
+        /// Rego policy file, or directory of them, to evaluate as well, can be repeated.
+        #[arg(long)]
+        policy: Vec<PathBuf>,
+
+        /// Package of the policies' `deny` and `warn` rules.
+        #[arg(long, default_value = "secrets")]
+        package: String,
+
+        /// Show the commit, branch and dirty status of the system manifests repository.
+        #[arg(long)]
+        include_git_info: bool,
+    },
+    /// Writes a snapshot of the secret resources of all platforms, metadata only, to a JSON file
+    /// to keep as audit evidence, along with its SHA-256 in `<file>.sha256` and, if signed, a
+    /// Sigstore bundle in `<file>.sigstore.json`.
+    Export {
+        /// File to write the snapshot to.
+        file: PathBuf,
+
+        /// Include the names of the keys each secret defines, values are never exported.
+        #[arg(long)]
+        show_keys: bool,
+
+        /// Include the commit, branch and dirty status of the system manifests repository.
+        #[arg(long)]
+        include_git_info: bool,
+
+        /// Sign the snapshot with `cosign sign-blob`, keyless with Sigstore unless a key is given.
+        #[arg(long)]
+        sign: bool,
+
+        /// Key to sign with, as any key reference cosign accepts, like a file or `awskms://...`.
+        #[arg(long, requires = "sign")]
+        key: Option<String>,
+    },
+    /// Lists the secret resources added, removed or changed since a snapshot written by `export`,
+    /// failing if the snapshot doesn't match the checksum file next to it.
+    CompareSnapshot {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Snapshot to compare against.
+        file: PathBuf,
+    },
+    /// Counts secrets per kind in total, per platform, per component and per namespace.
+    Stats {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Lists Secrets and ExternalSecrets whose secret is never referenced by a workload,
+    /// ServiceAccount, Ingress or webhook configuration on the same platform.
+    Orphans {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Lists secret references of workloads, ServiceAccounts, Ingresses and webhook
+    /// configurations that no Secret, ExternalSecret or PushSecret on the same platform accounts
+    /// for.
+    Missing {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Lists Secrets that more than one Secret, ExternalSecret, SealedSecret or Certificate on
+    /// the same platform results in, with the components and files declaring them.
+    Duplicates {
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Plans the rotation of a credential, given as a remote key ExternalSecrets read or the name
+    /// of the Secret it's in: where to change it, which ExternalSecrets and PushSecrets to
+    /// refresh and which workloads to restart afterwards, platform by platform.
+    RotatePlan {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Remote key, or Secret name as `<name>` or `<namespace>/<name>`.
+        target: String,
+    },
+    /// Lists the secret resources grouped by the team owning them, as named by their owner
+    /// annotation or label, or else the repository's CODEOWNERS file, and exits with a non-zero
+    /// code if any have no owner.
+    Owners {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Annotation, or else label, naming the owner of a secret. Defaults to the config's
+        /// `owner-key`, or `owner`.
+        #[arg(long)]
+        owner_key: Option<String>,
+    },
+    /// Reports when the manifest of each secret resource was last changed and by whom, from the
+    /// git history, flagging the ones unchanged for longer than the maximum age as rotation
+    /// candidates.
+    #[command(visible_alias = "history")]
+    Age {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Flag secrets unchanged for longer than this as rotation candidates, like `365d`.
+        #[arg(long, default_value = "365d", value_parser = duration::parse_duration)]
+        max_age: Duration,
+
+        /// List only the rotation candidates.
+        #[arg(long)]
+        candidates: bool,
+    },
+    /// Compares the secret resources of platforms by component, kind and name, listing the ones
+    /// some of the platforms don't declare.
+    ComparePlatforms {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Platforms to compare, at least two. Defaults to all platforms.
+        #[arg(add = ArgValueCandidates::new(completions::platform_candidates))]
+        platforms: Vec<String>,
+    },
+    /// Compares the secrets declared in the manifests with the ones present in each platform's
+    /// cluster.
+    Drift {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Kubeconfig context to use for a platform, defaults to the platform name.
+        #[arg(
+            long,
+            value_name = "PLATFORM=CONTEXT",
+            add = ArgValueCandidates::new(completions::platform_context_candidates),
+            value_parser = parse_key_value
+        )]
+        context: Vec<(String, String)>,
+    },
+    /// Lists ExternalSecrets that are missing, failing to sync or stale in their platform's
+    /// cluster, grouped by platform.
+    SyncStatus {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Kubeconfig context to use for a platform, defaults to the platform name.
+        #[arg(
+            long,
+            value_name = "PLATFORM=CONTEXT",
+            add = ArgValueCandidates::new(completions::platform_context_candidates),
+            value_parser = parse_key_value
+        )]
+        context: Vec<(String, String)>,
+    },
+    /// Lists the TLS certificates of Secrets declared in the manifests, issued by cert-manager
+    /// Certificates or templated by ExternalSecrets that expire soon in their platform's cluster,
+    /// soonest first. Certificates are read from the status of the Certificate if there is one,
+    /// and from the live Secret otherwise.
+    CertExpiry {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Kubeconfig context to use for a platform, defaults to the platform name.
+        #[arg(
+            long,
+            value_name = "PLATFORM=CONTEXT",
+            add = ArgValueCandidates::new(completions::platform_context_candidates),
+            value_parser = parse_key_value
+        )]
+        context: Vec<(String, String)>,
+
+        /// List certificates expiring within this time, like `30d` or `12h`.
+        #[arg(long, default_value = "30d", value_parser = duration::parse_duration)]
+        within: Duration,
+    },
+    /// Checks that the system manifests repositories have the `clusters`, `environments` and
+    /// `manifests` directories for every platform, that the kubeconfig has the context of each
+    /// platform and its cluster answers, that the backends of the secret store providers can
+    /// authenticate and that the tools commands run are installed, with hints on how to fix what
+    /// isn't. Fails if any check has status error.
+    Doctor {
+        #[command(flatten)]
+        output: OutputArgs,
+
+        /// Kubeconfig context to use for a platform, defaults to the platform name.
+        #[arg(
+            long,
+            value_name = "PLATFORM=CONTEXT",
+            add = ArgValueCandidates::new(completions::platform_context_candidates),
+            value_parser = parse_key_value
+        )]
+        context: Vec<(String, String)>,
+
+        /// Don't connect to the clusters and secret store providers, only checking the layout,
+        /// the kubeconfig contexts and the tools.
+        #[arg(long)]
+        offline: bool,
+
+        #[command(flatten)]
+        provider_args: providers::ProviderArgs,
+    },
+    /// Scaffolds a resource in a component directory.
+    New {
+        #[command(subcommand)]
+        resource: scaffold::NewResource,
     },
+    /// Serves the secrets inventory over HTTP until interrupted, reading the system manifests
+    /// again every interval. `/platforms` lists the platforms and their components, `/secrets`
+    /// lists secret resources filtered by the `platform`, `component`, `kind`, `namespace` and
+    /// `name` query parameters, and `/secrets/{namespace}/{name}` looks secret resources up.
+    Serve {
+        /// Also serve Prometheus metrics on `/metrics` with the number of secret resources, plain
+        /// Secrets and lint findings.
+        #[arg(long)]
+        metrics: bool,
+
+        /// Address to listen on.
+        #[arg(long, default_value = "0.0.0.0:9090")]
+        listen: SocketAddr,
+
+        /// How often to refresh the metrics, like `5m` or `30s`.
+        #[arg(long, default_value = "5m", value_parser = duration::parse_duration)]
+        interval: Duration,
+
+        /// File listing sanctioned plain Secrets as `<platform>/<namespace>/<name>` lines.
+        #[arg(long)]
+        allowlist: Option<PathBuf>,
+    },
+    /// Prints a script that sets up completions for a shell, completing platform and component
+    /// names from the SYSTEM_MANIFESTS directory.
+    Completions {
+        #[arg(value_enum)]
+        shell: completions::CompletionShell,
+    },
+    /// Prints the JSON Schema of a command's JSON output, or of all of them keyed by command,
+    /// with the `schemaVersion` of the output formats. Outputs only carry it with `--versioned`,
+    /// or `--include-git-info` for list.
+    Schema {
+        #[arg(value_enum)]
+        output: Option<schema::Output>,
+    },
+}
+
+impl Commands {
+    /// Returns how the command writes its output, if it writes one.
+    fn output_mut(&mut self) -> Option<&mut OutputArgs> {
+        match self {
+            Commands::List { output, .. }
+            | Commands::Get { output, .. }
+            | Commands::Search { output, .. }
+            | Commands::ForbidPlainSecrets { output, .. }
+            | Commands::Lint { output, .. }
+            | Commands::Policy { output, .. }
+            | Commands::Scan { output, .. }
+            | Commands::Diff { output, .. }
+            | Commands::CheckStores { output, .. }
+            | Commands::Validate { output, .. }
+            | Commands::VerifyRemote { output, .. }
+            | Commands::MigrateStore { output, .. }
+            | Commands::CompareSnapshot { output, .. }
+            | Commands::Stats { output, .. }
+            | Commands::Orphans { output, .. }
+            | Commands::Missing { output, .. }
+            | Commands::Duplicates { output, .. }
+            | Commands::RotatePlan { output, .. }
+            | Commands::Owners { output, .. }
+            | Commands::Age { output, .. }
+            | Commands::ComparePlatforms { output, .. }
+            | Commands::Drift { output, .. }
+            | Commands::SyncStatus { output, .. }
+            | Commands::CertExpiry { output, .. }
+            | Commands::Doctor { output, .. } => Some(output),
+            _ => None,
+        }
+    }
 }
 
-#[derive(ValueEnum, Debug, Clone)]
-enum ListOutputFormat {
-    Json,
-    Yaml,
+fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{}`", value))
 }
 
-fn main() -> anyhow::Result<()> {
+/// Exit code of findings commands when there are findings at or above the `--fail-on` severity.
+const EXIT_FINDINGS: u8 = 1;
+/// Exit code when a command can't be carried out, like clap uses for usage errors.
+const EXIT_ERROR: u8 = 2;
+
+/// How a command that ran to the end turned out.
+enum Outcome {
+    Ok,
+    /// There are findings that fail the command.
+    Findings,
+    /// Part of the command couldn't be carried out, like querying a platform's cluster.
+    Error,
+}
+
+fn main() -> ExitCode {
+    CompleteEnv::with_factory(Cli::command)
+        .var(completions::COMPLETE_VAR)
+        .complete();
     let cli = Cli::parse();
+    logging::init(cli.verbose, cli.log_format);
+    if cli.watch {
+        return match watch(cli) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("Error: {:?}", error);
+                ExitCode::from(EXIT_ERROR)
+            }
+        };
+    }
+
+    match run(cli) {
+        Ok(Outcome::Ok) => ExitCode::SUCCESS,
+        Ok(Outcome::Findings) => ExitCode::from(EXIT_FINDINGS),
+        Ok(Outcome::Error) => ExitCode::from(EXIT_ERROR),
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Runs a command whenever the system manifests change, reporting errors without stopping.
+fn watch(cli: Cli) -> anyhow::Result<()> {
+    let is_local = cli.oci.is_none()
+        && !cli
+            .system_manifests
+            .iter()
+            .any(|location| git::is_remote_url(location));
+    let error = "--watch requires --system-manifests to be local directories";
+    anyhow::ensure!(is_local, error);
+    let directories = cli
+        .system_manifests_locations()?
+        .iter()
+        .map(|location| cli.system_manifests_directory(location))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    anyhow::ensure!(
+        directories.iter().all(|directory| directory.is_dir()),
+        error
+    );
+    watch::watch(&directories, || {
+        if let Err(error) = run(cli.clone()) {
+            eprintln!("Error: {:?}", error);
+        }
+    })
+}
+
+/// Reads the system manifests to serve, along with their metrics if requested.
+fn refresh_snapshot(
+    cli: &Cli,
+    metrics: bool,
+    allowlist: Option<&Path>,
+) -> anyhow::Result<serve::Snapshot> {
+    let (directories, _extracted) = cli.fetch_system_manifests()?;
+    let config = config::Config::load(&directories[0].1)?;
+    let system_manifests = SystemManifests::new(cli, &config, directories)?;
+    let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+    let index = serve::Index::new(&system_manifests.platforms, &secret_resource_manifests);
+    let metrics = if metrics {
+        let allowlist = match allowlist {
+            Some(path) => plain_secrets::Allowlist::read(path)?,
+            None => plain_secrets::Allowlist::default(),
+        };
+        let plain_secrets = config.apply_lint(plain_secrets::find_plain_secrets(
+            &secret_resource_manifests,
+            &allowlist,
+        ));
+        let declared_namespaces = lint::declared_namespaces(&system_manifests, &config.lint)?;
+        let lint_findings = config.apply_lint(lint::lint(
+            &secret_resource_manifests,
+            &config.lint,
+            &allowlist,
+            &declared_namespaces,
+        )?);
+        Some(serve::metrics(
+            &secret_resource_manifests,
+            &plain_secrets,
+            &lint_findings,
+        ))
+    } else {
+        None
+    };
+
+    system_manifests.report_invalid();
+    Ok(serve::Snapshot { index, metrics })
+}
+
+/// Runs the checks of the doctor command. The layout is checked before reading the system
+/// manifests, which fails on the first platform missing a directory.
+fn doctor(
+    cli: &Cli,
+    context: &[(String, String)],
+    offline: bool,
+    provider_args: &providers::ProviderArgs,
+) -> anyhow::Result<Vec<doctor::Diagnosis>> {
+    let (directories, _extracted) = cli.fetch_system_manifests()?;
+    let config = config::Config::load(&directories[0].1)?;
+    let discovery = cli.discover.or(config.discover).unwrap_or_default();
+    let strict = cli.strict || config.strict.unwrap_or_default();
+
+    let mut diagnoses = Vec::new();
+    let mut platforms = Vec::new();
+    for (_, directory) in &directories {
+        let (layout, readable) = doctor::check_layout(directory, discovery, strict);
+        diagnoses.extend(layout);
+        platforms.extend(readable);
+    }
+    let layout_failed = diagnoses
+        .iter()
+        .any(|diagnosis| diagnosis.status == doctor::Status::Error);
+
+    let render = if cli.render.is_empty() {
+        config.render.clone().unwrap_or_default()
+    } else {
+        cli.render.clone()
+    };
+    let needed = [
+        (
+            "git",
+            cli.changed_since.is_some()
+                || cli
+                    .system_manifests
+                    .iter()
+                    .any(|location| git::is_remote_url(location)),
+        ),
+        ("kustomize", render.contains(&render::Render::Kustomize)),
+        ("helm", render.contains(&render::Render::Helm)),
+        ("sops", cli.decrypt_sops),
+        ("flux", cli.oci.is_some()),
+    ];
+    let needed: Vec<&str> = needed
+        .into_iter()
+        .filter_map(|(tool, needed)| needed.then_some(tool))
+        .collect();
+    diagnoses.extend(doctor::check_tools(&needed));
+
+    platforms.retain(|platform| cli.platform.is_empty() || cli.platform.contains(&platform.name));
+    let contexts = cluster_contexts(
+        &config,
+        &cli.context_override,
+        context.to_vec(),
+        cli.cluster_timeout,
+    );
+    diagnoses.extend(doctor::check_clusters(&platforms, &contexts, offline)?);
+
+    if offline {
+        return Ok(diagnoses);
+    }
+    if layout_failed {
+        eprintln!(
+            "Not checking the credentials of secret store providers until the layout is fixed"
+        );
+        return Ok(diagnoses);
+    }
+    let system_manifests = SystemManifests::new(cli, &config, directories)?;
+    let mut registry = providers::registry(provider_args.clone());
+    diagnoses.extend(doctor::check_credentials(
+        system_manifests.resource_iter(),
+        &mut registry,
+    )?);
+    system_manifests.report_invalid();
+    Ok(diagnoses)
+}
+
+/// Writes findings, or with `--summary` only how many there are, along with the counts of the
+/// secret resources checked if the command read them.
+fn write_findings_or_summary(
+    summary: bool,
+    output: &OutputArgs,
+    findings: &[findings::Finding],
+    checks: &Checks,
+    directory: &Path,
+    secret_resource_manifests: Option<&[ManifestResource]>,
+) -> anyhow::Result<()> {
+    if !summary {
+        return write_findings(output, findings, checks, directory);
+    }
+    let counts = match secret_resource_manifests {
+        Some(secret_resource_manifests) => summary::Summary::resources(secret_resource_manifests),
+        None => summary::Summary::default(),
+    };
+    print!("{}", counts.with_findings(findings));
+    Ok(())
+}
+
+/// Returns how to reach the cluster of each platform, from the config, then `--context-override`
+/// and then the command's `--context` flags.
+fn cluster_contexts(
+    config: &config::Config,
+    context_overrides: &[(String, String)],
+    contexts: Vec<(String, String)>,
+    timeout: Duration,
+) -> cluster::ClusterContexts {
+    let mut cluster_contexts = cluster::ClusterContexts::new(
+        config.clusters(),
+        context_overrides.iter().cloned().chain(contexts),
+    );
+    cluster_contexts.timeout = timeout;
+    cluster_contexts
+}
+
+/// Runs a command, returning whether it failed because of its findings or because part of it
+/// couldn't be carried out.
+fn run(mut cli: Cli) -> anyhow::Result<Outcome> {
+    if let Commands::Completions { shell } = cli.command {
+        completions::write_completions(shell, &Cli::command())?;
+        return Ok(Outcome::Ok);
+    }
+    if let Commands::Schema { output } = cli.command {
+        let schema = match output {
+            Some(output) => schema::schema(output),
+            None => schema::schemas(),
+        };
+        println!("{}", serde_json::to_string_pretty(&schema)?);
+        return Ok(Outcome::Ok);
+    }
+    if let Commands::Serve {
+        metrics,
+        listen,
+        interval,
+        allowlist,
+    } = &cli.command
+    {
+        serve::serve(*listen, *interval, || {
+            refresh_snapshot(&cli, *metrics, allowlist.as_deref())
+        })?;
+        return Ok(Outcome::Ok);
+    }
+
+    if let Commands::Doctor {
+        output,
+        context,
+        offline,
+        provider_args,
+    } = &cli.command
+    {
+        let diagnoses = doctor(&cli, context, *offline, provider_args)?;
+        write_output(output, &diagnoses)?;
+        let failed = diagnoses
+            .iter()
+            .any(|diagnosis| diagnosis.status == doctor::Status::Error);
+        return Ok(if failed {
+            Outcome::Findings
+        } else {
+            Outcome::Ok
+        });
+    }
 
-    let system_manifests = SystemManifests::new(&cli)?;
+    let (directories, _extracted) = cli.fetch_system_manifests()?;
+    let config = config::Config::load(&directories[0].1)?;
+    let mut system_manifests = SystemManifests::new(&cli, &config, directories)?;
+    if let Some(output) = cli.command.output_mut() {
+        output.warnings = system_manifests.platform_warnings();
+    }
 
+    let fail_on = cli.fail_on.or(config.fail_on).unwrap_or_default();
+    let summary = cli.summary;
+    let mut failed = false;
+    let mut errored = false;
     match cli.command {
-        Commands::List { output } => {
-            let mut secret_resource_manifests = Vec::new();
-            for manifest_resource_result in system_manifests.resource_iter() {
-                let manifest_resource = manifest_resource_result?;
-                if let Some(t) = &manifest_resource.resource.types {
-                    match t.kind.as_str() {
-                        "Secret" | "ExternalSecret" | "PushSecret" => {
-                            secret_resource_manifests.push(manifest_resource)
+        Commands::List {
+            output,
+            namespace,
+            group_by,
+            sort_by,
+            limit,
+            offset,
+            show_keys,
+            include_git_info,
+            output_full,
+        } => {
+            // Streamed records show the progress themselves.
+            system_manifests.show_progress = !output.is_streamed();
+            let flatten = |srm| inventory::flatten(srm, show_keys);
+            let resource = |srm: ManifestResource| match output_full {
+                true => redact::redacted(&srm.resource),
+                false => srm.resource,
+            };
+            let git_info = include_git_info
+                .then(|| git::info(&system_manifests.directory))
+                .transpose()?;
+            let secret_resource_manifests = inventory::page(
+                inventory::secret_resource_iter(&system_manifests, &namespace),
+                sort_by,
+                offset,
+                limit,
+            )?;
+
+            match group_by {
+                _ if output.is_cyclonedx() => {
+                    let secret_resource_manifests =
+                        secret_resource_manifests.collect::<anyhow::Result<Vec<_>>>()?;
+                    output::write_cyclonedx(
+                        &secret_resource_manifests,
+                        &system_manifests.directory,
+                        git_info.as_ref(),
+                    )?;
+                }
+                _ if summary => {
+                    let secret_resource_manifests =
+                        secret_resource_manifests.collect::<anyhow::Result<Vec<_>>>()?;
+                    print!(
+                        "{}",
+                        summary::Summary::resources(&secret_resource_manifests)
+                    );
+                }
+                Some(group_by) if output.is_custom_columns() || output_full => {
+                    let secret_resource_manifests =
+                        secret_resource_manifests.collect::<anyhow::Result<Vec<_>>>()?;
+                    let groups: BTreeMap<String, Vec<DynamicObject>> =
+                        inventory::group_resources(&group_by, secret_resource_manifests)
+                            .into_iter()
+                            .map(|(group, srms)| (group, srms.into_iter().map(resource).collect()))
+                            .collect();
+                    write_output_with_git_info(&output, git_info.as_ref(), &groups)?;
+                }
+                Some(group_by) => {
+                    let secret_resource_manifests =
+                        secret_resource_manifests.collect::<anyhow::Result<Vec<_>>>()?;
+                    let groups: BTreeMap<String, Vec<FlatManifestResource>> =
+                        inventory::group_resources(&group_by, secret_resource_manifests)
+                            .into_iter()
+                            .map(|(group, srms)| (group, srms.into_iter().map(flatten).collect()))
+                            .collect();
+                    write_output_with_git_info(&output, git_info.as_ref(), &groups)?;
+                }
+                None if output.is_custom_columns() || output_full => {
+                    let resources = secret_resource_manifests.map(|srm| srm.map(resource));
+                    match &git_info {
+                        Some(git_info) => {
+                            let resources = resources.collect::<anyhow::Result<Vec<_>>>()?;
+                            write_output_with_git_info(&output, Some(git_info), &resources)?;
                         }
-                        _ => (),
+                        None => write_records(&output, resources)?,
                     }
                 }
+                None => {
+                    let secret_resource_manifests_flat =
+                        secret_resource_manifests.map(|srm| srm.map(flatten));
+                    match &git_info {
+                        Some(git_info) => {
+                            let secrets = secret_resource_manifests_flat
+                                .collect::<anyhow::Result<Vec<_>>>()?;
+                            write_output_with_git_info(&output, Some(git_info), &secrets)?;
+                        }
+                        None => write_records(&output, secret_resource_manifests_flat)?,
+                    }
+                }
+            }
+        }
+        Commands::Get {
+            output,
+            names,
+            namespace,
+            selector,
+            show_keys,
+            output_full,
+        } => {
+            let (secret_resource_manifests, missing) =
+                get::get(&system_manifests, &names, &namespace, selector.as_ref())?;
+            if output_full {
+                let resources: Vec<DynamicObject> = secret_resource_manifests
+                    .iter()
+                    .map(|srm| redact::redacted(&srm.resource))
+                    .collect();
+                write_output(&output, &resources)?;
+            } else if output.is_custom_columns() {
+                let resources: Vec<DynamicObject> = secret_resource_manifests
+                    .into_iter()
+                    .map(|srm| srm.resource)
+                    .collect();
+                write_output(&output, &resources)?;
+            } else {
+                let resources: Vec<FlatManifestResource> = secret_resource_manifests
+                    .into_iter()
+                    .map(|srm| inventory::flatten(srm, show_keys))
+                    .collect();
+                write_output(&output, &resources)?;
             }
+            for name in &missing {
+                eprintln!("No secret resource is named {}", name);
+            }
+            failed |= !missing.is_empty();
+        }
+        Commands::Search { pattern, output } => {
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let results = search::search(&pattern, secret_resource_manifests);
 
-            let secret_resource_manifest_flat: Vec<FlatManifestResource> = secret_resource_manifests
-                .into_iter()
-                .map(|srm| srm.into())
-                .collect();
+            write_output(&output, &results)?;
+        }
+        Commands::ForbidPlainSecrets { output, allowlist } => {
+            let allowlist = match allowlist {
+                Some(path) => plain_secrets::Allowlist::read(&path)?,
+                None => plain_secrets::Allowlist::default(),
+            };
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let findings = config.apply_lint(plain_secrets::find_plain_secrets(
+                &secret_resource_manifests,
+                &allowlist,
+            ));
+            let checks = config.checks(&secret_resource_manifests, [plain_secrets::RULE]);
 
-            let stdout = std::io::stdout();
-            let mut writer = std::io::BufWriter::new(stdout.lock());
+            write_findings_or_summary(
+                summary,
+                &output,
+                &findings,
+                &checks,
+                &system_manifests.directory,
+                Some(&secret_resource_manifests),
+            )?;
+            if fail_on.fails(&findings) {
+                failed = true;
+            }
+        }
+        Commands::Lint {
+            output,
+            allowlist,
+            fix,
+            dry_run,
+            pull_request,
+        } => {
+            system_manifests.show_progress = true;
+            let allowlist = match allowlist {
+                Some(path) => plain_secrets::Allowlist::read(&path)?,
+                None => plain_secrets::Allowlist::default(),
+            };
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let declared_namespaces = lint::declared_namespaces(&system_manifests, &config.lint)?;
+            let mut findings = config.apply_lint(lint::lint(
+                &secret_resource_manifests,
+                &config.lint,
+                &allowlist,
+                &declared_namespaces,
+            )?);
+            let checks = config.checks(&secret_resource_manifests, lint::rules(&config.lint));
+            if fix {
+                let fixed = lint::fix(&secret_resource_manifests, &config.lint)?;
+                let resources: Vec<_> = fixed
+                    .iter()
+                    .flat_map(|file| file.resources.clone())
+                    .collect();
+                eprintln!(
+                    "{} {} resources in {} files",
+                    if dry_run { "Would fix" } else { "Fixed" },
+                    resources.len(),
+                    fixed.len()
+                );
+                if dry_run {
+                    for file in &fixed {
+                        print!(
+                            "{}",
+                            edit::unified_diff(&file.file, &file.original, &file.contents)
+                        );
+                    }
+                    system_manifests.report_invalid();
+                    return Ok(match fail_on.fails(&findings) {
+                        true => Outcome::Findings,
+                        false => Outcome::Ok,
+                    });
+                }
+                findings.retain(|finding| {
+                    finding.rule != lint::IMMUTABLE_SECRET_RULE
+                        || !resources.iter().any(|resource| {
+                            resource.file == finding.file
+                                && resource.platform_name == finding.platform_name
+                                && Some(&resource.kind) == finding.kind.as_ref()
+                                && Some(&resource.name) == finding.name.as_ref()
+                        })
+                });
+                if !fixed.is_empty() {
+                    let changes = pull_request::FileChanges {
+                        command: "lint",
+                        title: format!("Fix lint findings of {} resources", resources.len()),
+                        files: fixed
+                            .iter()
+                            .map(|file| (file.file.clone(), file.contents.clone()))
+                            .collect(),
+                        resources,
+                    };
+                    pull_request::apply(&system_manifests, &changes, None, &pull_request)?;
+                }
+            }
 
-            match output {
-                ListOutputFormat::Json => {
-                    serde_json::to_writer(&mut writer, &secret_resource_manifest_flat)?
+            write_findings_or_summary(
+                summary,
+                &output,
+                &findings,
+                &checks,
+                &system_manifests.directory,
+                Some(&secret_resource_manifests),
+            )?;
+            if fail_on.fails(&findings) {
+                failed = true;
+            }
+        }
+        Commands::Policy {
+            output,
+            policy,
+            package,
+        } => {
+            let mut policies = policy::Policies::load(&policy, &package)?;
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let mut findings = Vec::new();
+            for manifest_resource in &secret_resource_manifests {
+                findings.extend(policies.evaluate(manifest_resource, &system_manifests.directory)?);
+            }
+            let findings = config.apply_lint(findings);
+            let checks = config.checks(&secret_resource_manifests, [policy::RULE]);
+
+            write_findings_or_summary(
+                summary,
+                &output,
+                &findings,
+                &checks,
+                &system_manifests.directory,
+                Some(&secret_resource_manifests),
+            )?;
+            if fail_on.fails(&findings) {
+                failed = true;
+            }
+        }
+        Commands::Scan {
+            output,
+            baseline,
+            update_baseline,
+        } => {
+            let directory = &system_manifests.directory;
+            if let Some(path) = baseline.as_ref().filter(|_| update_baseline) {
+                let findings = scan::scan(
+                    system_manifests.resource_iter(),
+                    &scan::Baseline::default(),
+                    directory,
+                )?;
+                scan::Baseline::write(path, &findings, directory)?;
+            } else {
+                let baseline = match baseline {
+                    Some(path) => scan::Baseline::read(&path)?,
+                    None => scan::Baseline::default(),
+                };
+                let findings = scan::scan(system_manifests.resource_iter(), &baseline, directory)?;
+
+                write_findings_or_summary(
+                    summary,
+                    &output,
+                    &findings,
+                    &Checks::default(),
+                    directory,
+                    None,
+                )?;
+                if fail_on.fails(&findings) {
+                    failed = true;
                 }
-                ListOutputFormat::Yaml => {
-                    serde_yaml::to_writer(&mut writer, &secret_resource_manifest_flat)?
+            }
+        }
+        Commands::Tree { depth } => {
+            tree::write_tree(&system_manifests, depth)?;
+        }
+        Commands::Browse => {
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+
+            browse::browse(&secret_resource_manifests, &system_manifests.directory)?;
+        }
+        Commands::Graph { format } => {
+            graph::write_graph(system_manifests.resource_iter(), format)?;
+        }
+        Commands::Diff { output, base, head } => {
+            let mut base = diff::Snapshot::open(
+                &system_manifests.directory,
+                &base,
+                system_manifests.discovery,
+                system_manifests.strict,
+            )?;
+            base.system_manifests.copy_options(&system_manifests)?;
+            let changes = match head {
+                Some(head) => {
+                    let mut head = diff::Snapshot::open(
+                        &system_manifests.directory,
+                        &head,
+                        system_manifests.discovery,
+                        system_manifests.strict,
+                    )?;
+                    head.system_manifests.copy_options(&system_manifests)?;
+                    let changes = diff::diff(&base.system_manifests, &head.system_manifests)?;
+                    head.system_manifests.report_invalid();
+                    changes
                 }
+                None => diff::diff(&base.system_manifests, &system_manifests)?,
+            };
+            base.system_manifests.report_invalid();
+
+            write_output(&output, &changes)?;
+        }
+        Commands::CheckStores { output } => {
+            let mut findings =
+                config.apply_lint(stores::check_stores(system_manifests.all_resource_iter())?);
+            findings.retain(|finding| system_manifests.is_changed(&finding.file));
+
+            write_findings_or_summary(
+                summary,
+                &output,
+                &findings,
+                &Checks::default(),
+                &system_manifests.directory,
+                None,
+            )?;
+            if fail_on.fails(&findings) {
+                failed = true;
+            }
+        }
+        Commands::Validate {
+            output,
+            schemas,
+            schema_dir,
+            schemas_from_cluster,
+            context,
+        } => {
+            let bundled_schemas = schemas
+                .then(|| validate::Schemas::load(schema_dir.as_deref()))
+                .transpose()?;
+            let manifest_resources: Vec<_> = system_manifests
+                .resource_iter()
+                .collect::<anyhow::Result<_>>()?;
+            let cluster_schemas = if schemas_from_cluster {
+                let contexts =
+                    cluster_contexts(&config, &cli.context_override, context, cli.cluster_timeout);
+                let platforms = system_manifests.platforms.iter().filter(|platform| {
+                    manifest_resources
+                        .iter()
+                        .any(|manifest_resource| manifest_resource.platform.name == platform.name)
+                });
+                let (cluster_schemas, failed_platforms) =
+                    validate::cluster_schemas(platforms, &contexts)?;
+                errored |= !failed_platforms.is_empty();
+                cluster_schemas
+            } else {
+                HashMap::new()
             };
+
+            let mut resources = Vec::new();
+            let mut findings = Vec::new();
+            for manifest_resource in manifest_resources {
+                let schemas = bundled_schemas
+                    .as_ref()
+                    .or_else(|| cluster_schemas.get(&manifest_resource.platform.name));
+                let kind = manifest_resource
+                    .resource
+                    .types
+                    .as_ref()
+                    .map(|t| t.kind.as_str());
+                let checked = matches!(kind, Some("Secret" | "ExternalSecret" | "PushSecret"))
+                    || schemas.is_some_and(|schemas| schemas.covers(kind));
+                if !checked {
+                    continue;
+                }
+                findings.extend(validate::check_spec(&manifest_resource));
+                findings.extend(validate::check_secret_data(&manifest_resource));
+                if let Some(schemas) = schemas {
+                    findings.extend(schemas.validate(&manifest_resource)?);
+                }
+                resources.push(manifest_resource);
+            }
+            let findings = config.apply_lint(findings);
+            let mut rules = vec![validate::SPEC_RULE, validate::SECRET_DATA_RULE];
+            if schemas || schemas_from_cluster {
+                rules.push(validate::SCHEMA_RULE);
+            }
+            let checks = config.checks(&resources, rules);
+
+            write_findings_or_summary(
+                summary,
+                &output,
+                &findings,
+                &checks,
+                &system_manifests.directory,
+                None,
+            )?;
+            if fail_on.fails(&findings) {
+                failed = true;
+            }
         }
+        Commands::VerifyRemote {
+            output,
+            provider,
+            provider_args,
+        } => {
+            system_manifests.show_progress = true;
+            let mut registry = providers::registry(provider_args);
+            let dead_references = verify_remote::verify_remote(
+                system_manifests.resource_iter(),
+                &provider,
+                &mut registry,
+            )?;
+
+            write_output(&output, &dead_references)?;
+            failed |= fail_on.fails_on_any(!dead_references.is_empty());
+        }
+        Commands::Convert {
+            file,
+            store,
+            store_kind,
+            key,
+            refresh_interval,
+            write,
+            dry_run,
+            provider_args,
+            pull_request,
+        } => {
+            let mut registry = providers::registry(provider_args);
+            let options = convert::ConvertOptions {
+                store: inventory::StoreRef {
+                    kind: store_kind,
+                    name: store,
+                },
+                key: &key,
+                refresh_interval: &refresh_interval,
+                write,
+                dry_run,
+            };
+            let (contents, converted) =
+                convert::convert(&system_manifests, &file, &options, &mut registry)?;
+            if dry_run {
+                print!("{}", contents);
+            } else if !converted.is_empty() {
+                let changes = pull_request::FileChanges {
+                    command: "convert",
+                    title: format!(
+                        "Convert {} Secrets in {} into ExternalSecrets",
+                        converted.len(),
+                        file.file_name().unwrap_or_default().to_string_lossy()
+                    ),
+                    files: vec![(file.clone(), contents)],
+                    resources: converted.clone(),
+                };
+                pull_request::apply(&system_manifests, &changes, None, &pull_request)?;
+            }
+            eprintln!(
+                "{} {} Secrets in {}",
+                if dry_run {
+                    "Would convert"
+                } else {
+                    "Converted"
+                },
+                converted.len(),
+                file.display()
+            );
+        }
+        Commands::Annotate {
+            label,
+            annotation,
+            namespace,
+            selector,
+            dry_run,
+            pull_request,
+        } => {
+            anyhow::ensure!(
+                !label.is_empty() || !annotation.is_empty(),
+                "Pass the labels or annotations to set with --label or --annotation"
+            );
+            let changes = annotate::MetadataChanges {
+                labels: label,
+                annotations: annotation,
+            };
+            let annotated = annotate::annotate(
+                &system_manifests,
+                &namespace,
+                selector.as_ref(),
+                |_| true,
+                &changes,
+            )?;
+            let resources: usize = annotated.iter().map(|file| file.resources.len()).sum();
+            eprintln!(
+                "{} {} resources in {} files",
+                if dry_run { "Would change" } else { "Changed" },
+                resources,
+                annotated.len()
+            );
+            if dry_run {
+                for file in &annotated {
+                    print!(
+                        "{}",
+                        edit::unified_diff(&file.file, &file.original, &file.contents)
+                    );
+                }
+            } else if !annotated.is_empty() {
+                let entries: Vec<String> = changes
+                    .labels
+                    .iter()
+                    .chain(&changes.annotations)
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect();
+                let changes = pull_request::FileChanges {
+                    command: "annotate",
+                    title: format!("Set {} on {} resources", entries.join(", "), resources),
+                    files: annotated
+                        .iter()
+                        .map(|file| (file.file.clone(), file.contents.clone()))
+                        .collect(),
+                    resources: annotated
+                        .into_iter()
+                        .flat_map(|file| file.resources)
+                        .collect(),
+                };
+                pull_request::apply(&system_manifests, &changes, None, &pull_request)?;
+            }
+        }
+        Commands::Rotate {
+            names,
+            namespace,
+            selector,
+            annotation,
+            branch,
+            message,
+            dry_run,
+            pull_request,
+        } => {
+            let options = rotate::RotateOptions {
+                names: &names,
+                namespaces: &namespace,
+                selector: selector.as_ref(),
+                annotation: &annotation,
+                branch: branch.as_deref(),
+                message: message.as_deref(),
+                dry_run,
+                pull_request: &pull_request,
+            };
+            rotate::rotate(&system_manifests, &options)?;
+        }
+        Commands::MigrateStore {
+            output,
+            from,
+            to,
+            dry_run,
+            pull_request,
+        } => {
+            let migrated = migrate_store::migrate_store(&system_manifests, &from, &to)?;
+            let references: usize = migrated.iter().map(|file| file.references).sum();
+            if !dry_run && !migrated.is_empty() {
+                let changes = pull_request::FileChanges {
+                    command: "migrate-store",
+                    title: format!(
+                        "Migrate {} references from secret store {} to {}",
+                        references, from, to
+                    ),
+                    files: migrated
+                        .iter()
+                        .map(|file| (file.file.clone(), file.contents.clone()))
+                        .collect(),
+                    resources: migrated
+                        .iter()
+                        .flat_map(|file| file.edited.clone())
+                        .collect(),
+                };
+                pull_request::apply(&system_manifests, &changes, None, &pull_request)?;
+            }
+            eprintln!(
+                "{} {} references to {} in {} files",
+                if dry_run { "Would rewrite" } else { "Rewrote" },
+                references,
+                from,
+                migrated.len()
+            );
+
+            write_output(&output, &migrated)?;
+        }
+        Commands::Report {
+            format,
+            allowlist,
+            policy,
+            package,
+            include_git_info,
+        } => {
+            let git_info = include_git_info
+                .then(|| git::info(&system_manifests.directory))
+                .transpose()?;
+            let allowlist = match allowlist {
+                Some(path) => plain_secrets::Allowlist::read(&path)?,
+                None => plain_secrets::Allowlist::default(),
+            };
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let declared_namespaces = lint::declared_namespaces(&system_manifests, &config.lint)?;
+            let mut findings = lint::lint(
+                &secret_resource_manifests,
+                &config.lint,
+                &allowlist,
+                &declared_namespaces,
+            )?;
+            if !policy.is_empty() {
+                let mut policies = policy::Policies::load(&policy, &package)?;
+                for manifest_resource in &secret_resource_manifests {
+                    findings
+                        .extend(policies.evaluate(manifest_resource, &system_manifests.directory)?);
+                }
+            }
+            let findings = config.apply_lint(findings);
+            let counts = stats::secret_counts(&secret_resource_manifests);
+
+            report::write_report(
+                &secret_resource_manifests,
+                &counts,
+                &findings,
+                &system_manifests.directory,
+                git_info.as_ref(),
+                format,
+            )?;
+        }
+        Commands::Export {
+            file,
+            show_keys,
+            include_git_info,
+            sign,
+            key,
+        } => {
+            let git_info = include_git_info
+                .then(|| git::info(&system_manifests.directory))
+                .transpose()?;
+            let signing = sign.then_some(export::Signing { key });
+            let exported = export::export(
+                &system_manifests,
+                &file,
+                show_keys,
+                git_info,
+                signing.as_ref(),
+            )?;
+            eprintln!("Wrote {} with SHA-256 {}", file.display(), exported.sha256);
+            eprintln!("Wrote {}", exported.checksum_file.display());
+            if let Some(bundle_file) = &exported.bundle_file {
+                eprintln!("Wrote {}", bundle_file.display());
+            }
+        }
+        Commands::CompareSnapshot { output, file } => {
+            let changes = export::compare(&system_manifests, &file)?;
+
+            write_output(&output, &changes)?;
+        }
+        Commands::Stats { output } => {
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let counts = stats::secret_counts(&secret_resource_manifests);
+
+            write_output(&output, &counts)?;
+        }
+        Commands::Orphans { output } => {
+            let orphans = references::find_orphans(system_manifests.all_resource_iter())?
+                .into_iter()
+                .filter_map(|orphan| {
+                    system_manifests
+                        .matches_filter(&orphan)
+                        .map(|matches| matches.then(|| orphan.into()))
+                        .transpose()
+                })
+                .collect::<anyhow::Result<Vec<FlatManifestResource>>>()?;
+
+            write_output(&output, &orphans)?;
+            failed |= fail_on.fails_on_any(!orphans.is_empty());
+        }
+        Commands::Missing { output } => {
+            let mut missing = references::find_missing(system_manifests.all_resource_iter())?;
+            missing.retain(|missing| system_manifests.is_changed(&missing.referenced_by.file));
+
+            write_output(&output, &missing)?;
+            failed |= fail_on.fails_on_any(!missing.is_empty());
+        }
+        Commands::Duplicates { output } => {
+            let mut duplicates = references::find_duplicates(system_manifests.all_resource_iter())?;
+            duplicates.retain(|duplicate| {
+                duplicate
+                    .files
+                    .iter()
+                    .any(|file| system_manifests.is_changed(file))
+            });
+
+            write_output(&output, &duplicates)?;
+            failed |= fail_on.fails_on_any(!duplicates.is_empty());
+        }
+        Commands::RotatePlan { output, target } => {
+            let plan = rotate_plan::rotate_plan(&system_manifests, &target)?;
+
+            write_output(&output, &plan)?;
+        }
+        Commands::Owners { output, owner_key } => {
+            let owner_key = owner_key
+                .or_else(|| config.owner_key.clone())
+                .unwrap_or_else(|| owners::DEFAULT_OWNER_KEY.to_owned());
+            let repository = git::toplevel(&system_manifests.directory)
+                .unwrap_or_else(|_| system_manifests.directory.clone());
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let groups = owners::find_owners(secret_resource_manifests, &owner_key, &repository)?;
+
+            write_output(&output, &groups)?;
+            if let Some(unowned) = groups.get(owners::UNOWNED) {
+                eprintln!("{} secret resources have no owner", unowned.len());
+                failed = true;
+            }
+        }
+        Commands::Age {
+            output,
+            max_age,
+            candidates,
+        } => {
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let mut ages = age::find_ages(secret_resource_manifests, max_age)?;
+            let candidate_count = ages.iter().filter(|age| age.rotation_candidate).count();
+            if candidates {
+                ages.retain(|age| age.rotation_candidate);
+            }
+
+            write_output(&output, &ages)?;
+            if candidate_count > 0 {
+                eprintln!("{} secret resources are due for rotation", candidate_count);
+            }
+        }
+        Commands::ComparePlatforms { output, platforms } => {
+            let secret_resource_manifests = inventory::secret_resources(&system_manifests, &[])?;
+            let skew = compare_platforms::compare_platforms(
+                &system_manifests,
+                &secret_resource_manifests,
+                &platforms,
+            )?;
+
+            write_output(&output, &skew)?;
+        }
+        Commands::Drift { output, context } => {
+            let contexts =
+                cluster_contexts(&config, &cli.context_override, context, cli.cluster_timeout);
+            let (drifts, failed_platforms) = drift::find_drift(&system_manifests, &contexts)?;
+
+            write_output(&output, &drifts)?;
+            failed |= fail_on.fails_on_any(!drifts.is_empty());
+            errored |= !failed_platforms.is_empty();
+        }
+        Commands::SyncStatus { output, context } => {
+            let contexts =
+                cluster_contexts(&config, &cli.context_override, context, cli.cluster_timeout);
+            let (issues, failed_platforms) =
+                sync_status::find_sync_issues(&system_manifests, &contexts)?;
+
+            write_output(&output, &issues)?;
+            failed |= fail_on.fails_on_any(!issues.is_empty());
+            errored |= !failed_platforms.is_empty();
+        }
+        Commands::CertExpiry {
+            output,
+            context,
+            within,
+        } => {
+            let contexts =
+                cluster_contexts(&config, &cli.context_override, context, cli.cluster_timeout);
+            let (expiries, failed_platforms) =
+                cert_expiry::find_expiring(&system_manifests, &contexts, within)?;
+
+            write_output(&output, &expiries)?;
+            failed |= fail_on.fails_on_any(!expiries.is_empty());
+            errored |= !failed_platforms.is_empty();
+        }
+        Commands::New { resource } => {
+            scaffold::new_resource(&system_manifests, &config.lint, &resource)?;
+        }
+        Commands::Completions { .. } => unreachable!("completions are written before reading"),
+        Commands::Schema { .. } => unreachable!("schemas are written before reading"),
+        Commands::Serve { .. } => unreachable!("metrics are served before reading"),
+        Commands::Doctor { .. } => unreachable!("the doctor checks run before reading"),
     };
-    Ok(())
+
+    system_manifests.report_invalid();
+    Ok(if errored {
+        Outcome::Error
+    } else if failed {
+        Outcome::Findings
+    } else {
+        Outcome::Ok
+    })
 }
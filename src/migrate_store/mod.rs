@@ -0,0 +1,173 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::edit::{self, indent, is_blank_or_comment, EditedResource};
+use crate::inventory::store_refs;
+use crate::system_manifests::SystemManifests;
+
+/// A manifest file whose store references were rewritten.
+#[derive(Debug, Clone, Serialize)]
+pub struct MigratedFile {
+    pub file: PathBuf,
+    /// Number of ExternalSecrets and PushSecrets rewritten.
+    pub resources: usize,
+    /// Number of store references rewritten.
+    pub references: usize,
+    #[serde(skip)]
+    pub edited: Vec<EditedResource>,
+    /// The rewritten contents of the file.
+    #[serde(skip)]
+    pub contents: String,
+}
+
+/// Rewrites a `name: <from>` entry starting at the given column of a line, keeping its quotes
+/// and trailing comment. Returns None if the entry isn't there or names another store.
+fn rewrite_name(
+    line: &str,
+    column: usize,
+    from: &str,
+    to: &str,
+    pattern: &Regex,
+) -> Option<String> {
+    let captures = pattern.captures(line.get(column..)?)?;
+    let (open, name, close) = (&captures[2], &captures[3], &captures[4]);
+    if open != close || name != from {
+        return None;
+    }
+    let value = captures.get(3)?;
+    Some(format!(
+        "{}{}{}",
+        &line[..column + value.start()],
+        to,
+        &line[column + value.end()..]
+    ))
+}
+
+/// Rewrites the `name` of the `secretStoreRef` or the entries of `secretStoreRefs` in the lines
+/// of one document, returning how many were rewritten. Nested fields like label selectors are
+/// left alone.
+fn rewrite_document(lines: &mut [String], from: &str, to: &str) -> usize {
+    let key_pattern = Regex::new(r"^(\s*)secretStoreRef(s?):\s*(#.*)?$").unwrap();
+    let name_pattern = Regex::new(r#"^name:(\s+)(["']?)([^\s"'#]+)(["']?)\s*(#.*)?$"#).unwrap();
+    let mut rewritten = 0;
+    let mut index = 0;
+    while index < lines.len() {
+        let Some(captures) = key_pattern.captures(lines[index].trim_end()) else {
+            index += 1;
+            continue;
+        };
+        let key_indent = captures[1].len();
+        let is_list = !captures[2].is_empty();
+        // Column of the fields of the mapping or the list entry the lines are in.
+        let mut field_column = None;
+        index += 1;
+        while index < lines.len() {
+            let line = lines[index].trim_end().to_owned();
+            if is_blank_or_comment(&line) {
+                index += 1;
+                continue;
+            }
+            let line_indent = indent(&line);
+            let is_entry = line[line_indent..].starts_with('-');
+            if line_indent < key_indent || (line_indent == key_indent && !(is_list && is_entry)) {
+                break;
+            }
+            let column = if is_list && is_entry {
+                let after_dash = &line[line_indent + 1..];
+                let column = line_indent + 1 + indent(after_dash);
+                field_column = Some(column);
+                Some(column)
+            } else {
+                let column = *field_column.get_or_insert(line_indent);
+                (line_indent == column).then_some(column)
+            };
+            if let Some(renamed) =
+                column.and_then(|column| rewrite_name(&line, column, from, to, &name_pattern))
+            {
+                let ending = &lines[index][line.len()..];
+                lines[index] = format!("{}{}", renamed, ending);
+                rewritten += 1;
+            }
+            index += 1;
+        }
+    }
+    rewritten
+}
+
+/// Rewrites the references of ExternalSecrets and PushSecrets to the store `from` to the store
+/// `to`, editing the lines of the manifest files so that their formatting and comments are kept.
+/// Resources rendered from kustomizations or Helm charts are skipped, as they have no lines to
+/// edit.
+///
+/// Returns the files to rewrite, without writing them.
+pub fn migrate_store(
+    system_manifests: &SystemManifests,
+    from: &str,
+    to: &str,
+) -> Result<Vec<MigratedFile>> {
+    let mut resources: BTreeMap<PathBuf, BTreeMap<usize, (usize, EditedResource)>> =
+        BTreeMap::new();
+    for manifest_resource_result in system_manifests.resource_iter() {
+        let manifest_resource = manifest_resource_result?;
+        let is_store_user = manifest_resource
+            .resource
+            .types
+            .as_ref()
+            .is_some_and(|t| matches!(t.kind.as_str(), "ExternalSecret" | "PushSecret"));
+        if !is_store_user {
+            continue;
+        }
+        let references = store_refs(&manifest_resource.resource)
+            .iter()
+            .filter(|store_ref| store_ref.name == from)
+            .count();
+        let Some(line) = manifest_resource.line.filter(|_| references > 0) else {
+            continue;
+        };
+        let edited = EditedResource::from(&manifest_resource);
+        resources
+            .entry(manifest_resource.file)
+            .or_default()
+            .insert(line, (references, edited));
+    }
+
+    let mut migrated = Vec::new();
+    for (file, documents) in resources {
+        let contents = std::fs::read_to_string(&file)
+            .with_context(|| format!("Failed to read {}", file.display()))?;
+        let mut lines = edit::lines(&contents);
+        let mut migrated_file = MigratedFile {
+            file: file.clone(),
+            resources: 0,
+            references: 0,
+            edited: Vec::new(),
+            contents: String::new(),
+        };
+        for (line, (expected, edited)) in documents {
+            let range = edit::document_range(&lines, line);
+            let rewritten = rewrite_document(&mut lines[range], from, to);
+            if rewritten < expected {
+                eprintln!(
+                    "Couldn't rewrite all references to {} in {}:{}, edit them by hand",
+                    from,
+                    file.display(),
+                    line
+                );
+            }
+            if rewritten > 0 {
+                migrated_file.resources += 1;
+                migrated_file.references += rewritten;
+                migrated_file.edited.push(edited);
+            }
+        }
+        if migrated_file.references == 0 {
+            continue;
+        }
+        migrated_file.contents = lines.concat();
+        migrated.push(migrated_file);
+    }
+    Ok(migrated)
+}
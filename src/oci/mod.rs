@@ -0,0 +1,35 @@
+use anyhow::{Context, Result};
+use std::io::ErrorKind;
+use std::process::{Command, Stdio};
+use tempfile::TempDir;
+
+use crate::archive::Extracted;
+
+/// Pulls a Flux OCI artifact, like `ghcr.io/org/manifests:v1.2.3` or
+/// `oci://ghcr.io/org/manifests@sha256:...`, and extracts its manifests into a temporary
+/// directory.
+///
+/// The artifact is pulled with `flux pull artifact`, which authenticates to the registry with the
+/// docker config like the OCIRepositories in the clusters do.
+pub fn pull(reference: &str) -> Result<Extracted> {
+    let url = if reference.starts_with("oci://") {
+        reference.to_owned()
+    } else {
+        format!("oci://{}", reference)
+    };
+    let temporary_directory =
+        TempDir::new().with_context(|| "Failed to create a temporary directory")?;
+    let status = Command::new("flux")
+        .args(["pull", "artifact", &url, "--output"])
+        .arg(temporary_directory.path())
+        .stdout(Stdio::null())
+        .status();
+    let status = match status {
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            anyhow::bail!("Pulling OCI artifacts requires the flux CLI to be installed")
+        }
+        status => status.with_context(|| "Failed to run flux pull artifact")?,
+    };
+    anyhow::ensure!(status.success(), "Failed to pull OCI artifact {}", url);
+    Extracted::new(temporary_directory)
+}
@@ -0,0 +1,95 @@
+use serde_json::Value;
+
+/// A step of a JSONPath like `.spec.data[0].remoteRef.key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+}
+
+/// A column of `custom-columns` output, like `STORE:.spec.secretStoreRef.name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomColumn {
+    pub header: String,
+    path: Vec<Segment>,
+}
+
+/// Parses a JSONPath of fields and indexes, where dots in field names are escaped with a
+/// backslash like kubectl does, as in `.metadata.labels.app\.kubernetes\.io/name`.
+fn parse_path(path: &str) -> Result<Vec<Segment>, String> {
+    let trimmed = path.trim();
+    let trimmed = trimmed
+        .strip_prefix('{')
+        .and_then(|inner| inner.strip_suffix('}'))
+        .unwrap_or(trimmed);
+    let rest = trimmed
+        .strip_prefix('.')
+        .ok_or_else(|| format!("JSONPath must start with a dot: {}", path))?;
+
+    let mut segments = Vec::new();
+    let mut field = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => field.extend(chars.next()),
+            '.' => {
+                if !field.is_empty() {
+                    segments.push(Segment::Field(std::mem::take(&mut field)));
+                }
+            }
+            '[' => {
+                if !field.is_empty() {
+                    segments.push(Segment::Field(std::mem::take(&mut field)));
+                }
+                let index: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                let index = index
+                    .parse()
+                    .map_err(|_| format!("Invalid index [{}] in JSONPath: {}", index, path))?;
+                segments.push(Segment::Index(index));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() {
+        segments.push(Segment::Field(field));
+    }
+    Ok(segments)
+}
+
+/// Parses a `HEADER:JSONPATH,...` column specification.
+pub fn parse(spec: &str) -> Result<Vec<CustomColumn>, String> {
+    let columns = spec
+        .split(',')
+        .map(|column| {
+            let (header, path) = column
+                .split_once(':')
+                .ok_or_else(|| format!("expected HEADER:JSONPATH, got `{}`", column))?;
+            Ok(CustomColumn {
+                header: header.to_owned(),
+                path: parse_path(path)?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    if columns.is_empty() {
+        return Err("custom-columns needs at least one column".to_owned());
+    }
+    Ok(columns)
+}
+
+impl CustomColumn {
+    /// Looks up the column's value in a record, rendering missing values as `<none>`.
+    pub fn cell(&self, record: &Value) -> String {
+        let value = self
+            .path
+            .iter()
+            .try_fold(record, |value, segment| match segment {
+                Segment::Field(field) => value.get(field),
+                Segment::Index(index) => value.get(index),
+            });
+        match value {
+            None | Some(Value::Null) => "<none>".to_owned(),
+            Some(Value::String(string)) => string.clone(),
+            Some(value) => value.to_string(),
+        }
+    }
+}
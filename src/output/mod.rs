@@ -0,0 +1,607 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::findings::{Checks, Finding};
+use crate::git::GitInfo;
+use crate::schema::SCHEMA_VERSION;
+use crate::system_manifests::{ManifestResource, PlatformWarning};
+use custom_columns::CustomColumn;
+
+mod custom_columns;
+
+#[derive(Debug, Clone)]
+pub enum ListOutputFormat {
+    Json,
+    Yaml,
+    Csv,
+    Table,
+    /// A GitHub flavoured Markdown table, as for pull request comments.
+    Markdown,
+    /// One JSON record per line, streamed as records are found where supported.
+    Ndjson,
+    /// Only supported for findings.
+    Sarif,
+    /// JUnit XML with a test case per rule and resource, only supported for findings.
+    Junit,
+    /// A CycloneDX BOM with a component per secret, only supported for the secrets list.
+    Cyclonedx,
+    /// A table of the given columns, each looking up a JSONPath in the records.
+    CustomColumns(Vec<CustomColumn>),
+    /// The report rendered through the handlebars template given with `--template-file`.
+    Template,
+}
+
+fn parse_output_format(value: &str) -> Result<ListOutputFormat, String> {
+    if let Some(spec) = value.strip_prefix("custom-columns=") {
+        return custom_columns::parse(spec).map(ListOutputFormat::CustomColumns);
+    }
+    match value {
+        "json" => Ok(ListOutputFormat::Json),
+        "yaml" => Ok(ListOutputFormat::Yaml),
+        "csv" => Ok(ListOutputFormat::Csv),
+        "table" => Ok(ListOutputFormat::Table),
+        "markdown" => Ok(ListOutputFormat::Markdown),
+        "ndjson" => Ok(ListOutputFormat::Ndjson),
+        "sarif" => Ok(ListOutputFormat::Sarif),
+        "junit" => Ok(ListOutputFormat::Junit),
+        "cyclonedx" => Ok(ListOutputFormat::Cyclonedx),
+        "template" => Ok(ListOutputFormat::Template),
+        _ => Err(format!(
+            "expected one of json, yaml, csv, table, markdown, ndjson, sarif, junit, cyclonedx, \
+            template or custom-columns=HEADER:JSONPATH,..., got `{}`",
+            value
+        )),
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct OutputArgs {
+    /// Output format: json, yaml, csv, table, markdown, ndjson, sarif or junit for findings,
+    /// cyclonedx for the secrets list, template, or custom-columns=HEADER:JSONPATH,... for a table
+    /// of the given columns.
+    #[arg(long, short = 'o', default_value = "json", value_parser = parse_output_format)]
+    pub output: ListOutputFormat,
+
+    /// Handlebars template to render the report with for template output.
+    #[arg(long)]
+    pub template_file: Option<PathBuf>,
+
+    /// Comma separated dot paths of the columns to include in CSV, table and Markdown output,
+    /// defaults to all.
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Vec<String>,
+
+    /// Comma separated dot paths of the fields of each record to write in JSON, YAML and NDJSON
+    /// output, like `file,platform_name,resource_meta.name`, defaults to all.
+    #[arg(long, value_delimiter = ',')]
+    pub fields: Vec<String>,
+
+    /// Wrap JSON and YAML output in an object with the `schemaVersion` of the output formats,
+    /// the output under `items` and any warnings about how the platforms were read under
+    /// `warnings`. That's the stable format to check against the JSON Schemas of the `schema`
+    /// command, the output on its own isn't versioned.
+    #[arg(long)]
+    pub versioned: bool,
+
+    /// Warnings about how the platforms were read, written along with versioned output.
+    #[arg(skip)]
+    pub warnings: Vec<PlatformWarning>,
+}
+
+impl OutputArgs {
+    pub fn is_custom_columns(&self) -> bool {
+        matches!(self.output, ListOutputFormat::CustomColumns(_))
+    }
+
+    pub fn is_cyclonedx(&self) -> bool {
+        matches!(self.output, ListOutputFormat::Cyclonedx)
+    }
+
+    /// Whether records are written one at a time as they're read, rather than all at the end.
+    pub fn is_streamed(&self) -> bool {
+        matches!(self.output, ListOutputFormat::Ndjson)
+    }
+}
+
+/// A report record flattened into cells keyed by dot path.
+type Row = Map<String, Value>;
+
+fn flatten_into(prefix: &str, value: &Value, row: &mut Row) {
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_into(&path, value, row);
+            }
+        }
+        _ => {
+            row.insert(prefix.to_owned(), value.clone());
+        }
+    }
+}
+
+/// Turns a serialized report into flat rows keyed by dot path.
+///
+/// Reports grouped in a map of lists get their group key in an additional `group` column.
+fn to_rows(value: Value) -> Vec<Row> {
+    let flatten = |value: &Value| {
+        let mut row = Row::new();
+        flatten_into("", value, &mut row);
+        row
+    };
+    match value {
+        Value::Array(items) => items.iter().map(flatten).collect(),
+        Value::Object(groups) if groups.values().all(Value::is_array) => groups
+            .iter()
+            .flat_map(|(group, items)| {
+                items.as_array().into_iter().flatten().map(move |item| {
+                    let mut row = Row::new();
+                    row.insert("group".to_owned(), Value::String(group.clone()));
+                    flatten_into("", item, &mut row);
+                    row
+                })
+            })
+            .collect(),
+        value => vec![flatten(&value)],
+    }
+}
+
+/// Copies the values at the given dot paths of a record into a record of their own, nested as
+/// they are in the original. Paths the record doesn't have are left out.
+fn select_record(record: &Value, fields: &[String]) -> Value {
+    let mut selected = Value::Object(Map::new());
+    'fields: for field in fields {
+        let keys: Vec<&str> = field.split('.').collect();
+        let Some(value) = keys.iter().try_fold(record, |value, key| value.get(key)) else {
+            continue;
+        };
+        let (last, parents) = keys.split_last().unwrap_or((&"", &[]));
+        let mut target = &mut selected;
+        for key in parents {
+            target = match target {
+                Value::Object(object) => object
+                    .entry(key.to_string())
+                    .or_insert_with(|| Value::Object(Map::new())),
+                _ => continue 'fields,
+            };
+        }
+        if let Value::Object(object) = target {
+            object.insert(last.to_string(), value.clone());
+        }
+    }
+    selected
+}
+
+/// Selects the given fields of each record of a serialized report, keeping reports that are
+/// maps of lists grouped.
+fn select_fields(value: Value, fields: &[String]) -> Value {
+    match value {
+        Value::Array(items) => items
+            .iter()
+            .map(|item| select_record(item, fields))
+            .collect(),
+        Value::Object(groups) if groups.values().all(Value::is_array) => Value::Object(
+            groups
+                .into_iter()
+                .map(|(group, items)| (group, select_fields(items, fields)))
+                .collect(),
+        ),
+        value => select_record(&value, fields),
+    }
+}
+
+fn csv_cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(string)) => string.clone(),
+        Some(value) => value.to_string(),
+    }
+}
+
+fn rows_and_columns<T: Serialize>(
+    columns: &[String],
+    value: &T,
+) -> Result<(Vec<Row>, Vec<String>)> {
+    let rows = to_rows(serde_json::to_value(value)?);
+    let columns: Vec<String> = if columns.is_empty() {
+        let mut all_columns: Vec<String> = Vec::new();
+        for row in &rows {
+            for key in row.keys() {
+                if !all_columns.contains(key) {
+                    all_columns.push(key.clone());
+                }
+            }
+        }
+        all_columns
+    } else {
+        columns.to_vec()
+    };
+    Ok((rows, columns))
+}
+
+fn write_csv<T: Serialize>(writer: impl Write, columns: &[String], value: &T) -> Result<()> {
+    let (rows, columns) = rows_and_columns(columns, value)?;
+
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    csv_writer.write_record(&columns)?;
+    for row in &rows {
+        csv_writer.write_record(columns.iter().map(|column| csv_cell(row.get(column))))?;
+    }
+    csv_writer
+        .flush()
+        .with_context(|| "Failed to write CSV output")?;
+    Ok(())
+}
+
+fn write_table<T: Serialize>(writer: impl Write, columns: &[String], value: &T) -> Result<()> {
+    let (rows, columns) = rows_and_columns(columns, value)?;
+
+    let header: Vec<String> = columns
+        .iter()
+        .map(|column| column.to_uppercase().replace('.', "_"))
+        .collect();
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|column| csv_cell(row.get(column)))
+                .collect()
+        })
+        .collect();
+    write_aligned(writer, &header, &cells)
+}
+
+fn markdown_cell(cell: &str) -> String {
+    cell.replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace("\r\n", "<br>")
+        .replace('\n', "<br>")
+}
+
+/// Writes a GitHub flavoured Markdown table, or a note that there is nothing to report.
+fn write_markdown<T: Serialize>(
+    mut writer: impl Write,
+    columns: &[String],
+    value: &T,
+) -> Result<()> {
+    let (rows, columns) = rows_and_columns(columns, value)?;
+    if rows.is_empty() || columns.is_empty() {
+        writeln!(writer, "_Nothing to report._")?;
+        return Ok(());
+    }
+
+    let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+    writeln!(
+        writer,
+        "{}",
+        line(columns.iter().map(|column| markdown_cell(column)).collect())
+    )?;
+    writeln!(
+        writer,
+        "{}",
+        line(columns.iter().map(|_| "---".to_owned()).collect())
+    )?;
+    for row in &rows {
+        let cells = columns
+            .iter()
+            .map(|column| markdown_cell(&csv_cell(row.get(column))))
+            .collect();
+        writeln!(writer, "{}", line(cells))?;
+    }
+    Ok(())
+}
+
+/// Writes a header and rows of cells as left aligned columns.
+fn write_aligned(mut writer: impl Write, header: &[String], cells: &[Vec<String>]) -> Result<()> {
+    let widths: Vec<usize> = (0..header.len())
+        .map(|index| {
+            cells
+                .iter()
+                .map(|row| row[index].chars().count())
+                .chain([header[index].len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    for line in [header].into_iter().chain(cells.iter().map(Vec::as_slice)) {
+        let formatted: Vec<String> = line
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        writeln!(writer, "{}", formatted.join("   ").trim_end())?;
+    }
+    Ok(())
+}
+
+/// Writes a table of custom columns, each looked up in the records of a report. Reports grouped
+/// in a map of lists get their group key in an additional first column.
+fn write_custom_columns<T: Serialize>(
+    writer: impl Write,
+    columns: &[CustomColumn],
+    value: &T,
+) -> Result<()> {
+    let mut header: Vec<String> = columns.iter().map(|column| column.header.clone()).collect();
+    let cells = |record: &Value| -> Vec<String> {
+        columns.iter().map(|column| column.cell(record)).collect()
+    };
+    let rows: Vec<Vec<String>> = match serde_json::to_value(value)? {
+        Value::Array(items) => items.iter().map(cells).collect(),
+        Value::Object(groups) if groups.values().all(Value::is_array) => {
+            header.insert(0, "GROUP".to_owned());
+            groups
+                .iter()
+                .flat_map(|(group, items)| {
+                    items.as_array().into_iter().flatten().map(move |item| {
+                        let mut row = vec![group.clone()];
+                        row.extend(cells(item));
+                        row
+                    })
+                })
+                .collect()
+        }
+        value => vec![cells(&value)],
+    };
+    write_aligned(writer, &header, &rows)
+}
+
+/// Writes each record of a report on its own line, with the group key in a `group` field for
+/// reports grouped in a map of lists.
+fn write_ndjson<T: Serialize>(mut writer: impl Write, value: &T) -> Result<()> {
+    let records: Vec<Value> = match serde_json::to_value(value)? {
+        Value::Array(items) => items,
+        Value::Object(groups) if groups.values().all(Value::is_array) => groups
+            .into_iter()
+            .flat_map(|(group, items)| {
+                let items = match items {
+                    Value::Array(items) => items,
+                    _ => Vec::new(),
+                };
+                items.into_iter().map(move |item| match item {
+                    Value::Object(object) => {
+                        let mut record = Map::new();
+                        record.insert("group".to_owned(), Value::String(group.clone()));
+                        record.extend(object);
+                        Value::Object(record)
+                    }
+                    item => item,
+                })
+            })
+            .collect(),
+        value => vec![value],
+    };
+    for record in records {
+        serde_json::to_writer(&mut writer, &record)?;
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Renders a report through a handlebars template, which sees the report as `this`.
+fn write_template<T: Serialize>(
+    mut writer: impl Write,
+    template_file: &Path,
+    value: &T,
+) -> Result<()> {
+    let template = std::fs::read_to_string(template_file)
+        .with_context(|| format!("Failed to read template: {}", template_file.display()))?;
+    let rendered = handlebars::Handlebars::new()
+        .render_template(&template, value)
+        .with_context(|| format!("Failed to render template: {}", template_file.display()))?;
+    writer.write_all(rendered.as_bytes())?;
+    Ok(())
+}
+
+pub fn write_output<T: Serialize>(output: &OutputArgs, value: &T) -> Result<()> {
+    if output.versioned {
+        anyhow::ensure!(
+            matches!(
+                output.output,
+                ListOutputFormat::Json | ListOutputFormat::Yaml
+            ),
+            "--versioned is only supported for json and yaml output"
+        );
+        // The fields are those of the records, not of the object they're wrapped in.
+        let mut items = serde_json::to_value(value)?;
+        if !output.fields.is_empty() {
+            items = select_fields(items, &output.fields);
+        }
+        let output = OutputArgs {
+            fields: Vec::new(),
+            versioned: false,
+            ..output.clone()
+        };
+        return write_output(
+            &output,
+            &Versioned {
+                schema_version: SCHEMA_VERSION,
+                items: &items,
+                warnings: &output.warnings,
+            },
+        );
+    }
+    if !output.fields.is_empty() {
+        anyhow::ensure!(
+            matches!(
+                output.output,
+                ListOutputFormat::Json | ListOutputFormat::Yaml | ListOutputFormat::Ndjson
+            ),
+            "--fields is only supported for json, yaml and ndjson output, --columns selects the \
+            columns of tables"
+        );
+        let selected = select_fields(serde_json::to_value(value)?, &output.fields);
+        let output = OutputArgs {
+            fields: Vec::new(),
+            ..output.clone()
+        };
+        return write_output(&output, &selected);
+    }
+
+    let stdout = std::io::stdout();
+    let mut writer = std::io::BufWriter::new(stdout.lock());
+
+    match &output.output {
+        ListOutputFormat::Json => serde_json::to_writer(&mut writer, value)?,
+        ListOutputFormat::Yaml => serde_yaml::to_writer(&mut writer, value)?,
+        ListOutputFormat::Csv => write_csv(&mut writer, &output.columns, value)?,
+        ListOutputFormat::Table => write_table(&mut writer, &output.columns, value)?,
+        ListOutputFormat::Markdown => write_markdown(&mut writer, &output.columns, value)?,
+        ListOutputFormat::Ndjson => write_ndjson(&mut writer, value)?,
+        ListOutputFormat::CustomColumns(columns) => {
+            write_custom_columns(&mut writer, columns, value)?
+        }
+        ListOutputFormat::Template => {
+            let template_file = output
+                .template_file
+                .as_deref()
+                .with_context(|| "Template output requires --template-file")?;
+            write_template(&mut writer, template_file, value)?
+        }
+        ListOutputFormat::Sarif => anyhow::bail!("SARIF output is only supported for findings"),
+        ListOutputFormat::Junit => anyhow::bail!("JUnit output is only supported for findings"),
+        ListOutputFormat::Cyclonedx => {
+            anyhow::bail!("CycloneDX output is only supported for the secrets list")
+        }
+    };
+    Ok(())
+}
+
+/// Writes records as they are produced for NDJSON output, and collects them first for the other
+/// formats.
+pub fn write_records<T: Serialize>(
+    output: &OutputArgs,
+    records: impl Iterator<Item = Result<T>>,
+) -> Result<()> {
+    if !matches!(output.output, ListOutputFormat::Ndjson) {
+        let records = records.collect::<Result<Vec<T>>>()?;
+        return write_output(output, &records);
+    }
+
+    let stdout = std::io::stdout();
+    let mut writer = std::io::BufWriter::new(stdout.lock());
+    for record in records {
+        let record = record?;
+        if output.fields.is_empty() {
+            serde_json::to_writer(&mut writer, &record)?;
+        } else {
+            let record = select_record(&serde_json::to_value(record)?, &output.fields);
+            serde_json::to_writer(&mut writer, &record)?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush().with_context(|| "Failed to write output")?;
+    Ok(())
+}
+
+/// An output along with the version of the output formats.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Versioned<'a, T> {
+    schema_version: u32,
+    items: &'a T,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    warnings: &'a [PlatformWarning],
+}
+
+/// An output along with the revision of the system manifests it was made from.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WithGitInfo<'a, T> {
+    schema_version: u32,
+    git: &'a GitInfo,
+    secrets: &'a T,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    warnings: &'a [PlatformWarning],
+}
+
+/// Writes an output, wrapped in an object along with the revision of the system manifests if
+/// given. That's only supported for the formats that aren't tables.
+pub fn write_output_with_git_info<T: Serialize>(
+    output: &OutputArgs,
+    git_info: Option<&GitInfo>,
+    value: &T,
+) -> Result<()> {
+    let Some(git) = git_info else {
+        return write_output(output, value);
+    };
+    anyhow::ensure!(
+        matches!(
+            output.output,
+            ListOutputFormat::Json | ListOutputFormat::Yaml | ListOutputFormat::Template
+        ),
+        "Git info is only supported for json, yaml and template output"
+    );
+    // The fields are those of the records, not of the object they're wrapped in.
+    let mut secrets = serde_json::to_value(value)?;
+    if !output.fields.is_empty() {
+        secrets = select_fields(secrets, &output.fields);
+    }
+    let output = OutputArgs {
+        fields: Vec::new(),
+        versioned: false,
+        ..output.clone()
+    };
+    write_output(
+        &output,
+        &WithGitInfo {
+            schema_version: SCHEMA_VERSION,
+            git,
+            secrets: &secrets,
+            warnings: &output.warnings,
+        },
+    )
+}
+
+/// Writes secret resources as a CycloneDX BOM, with the revision of the system manifests in its
+/// metadata if given.
+pub fn write_cyclonedx(
+    manifest_resources: &[ManifestResource],
+    directory: &Path,
+    git_info: Option<&GitInfo>,
+) -> Result<()> {
+    let mut bom = crate::cyclonedx::to_cyclonedx(manifest_resources, directory);
+    if let Some(git) = git_info {
+        bom["metadata"]["properties"] = crate::cyclonedx::git_properties(git);
+    }
+    let stdout = std::io::stdout();
+    let mut writer = std::io::BufWriter::new(stdout.lock());
+    serde_json::to_writer(&mut writer, &bom)?;
+    writer.flush().with_context(|| "Failed to write output")?;
+    Ok(())
+}
+
+/// Writes findings, supporting the findings specific output formats on top of the common ones.
+/// The checks are only needed to report passing checks in JUnit output.
+pub fn write_findings(
+    output: &OutputArgs,
+    findings: &[Finding],
+    checks: &Checks,
+    directory: &Path,
+) -> Result<()> {
+    match output.output {
+        ListOutputFormat::Sarif => {
+            let stdout = std::io::stdout();
+            let mut writer = std::io::BufWriter::new(stdout.lock());
+            serde_json::to_writer(&mut writer, &crate::sarif::to_sarif(findings, directory))?;
+            Ok(())
+        }
+        ListOutputFormat::Junit => {
+            let stdout = std::io::stdout();
+            let mut writer = std::io::BufWriter::new(stdout.lock());
+            crate::junit::write_junit(&mut writer, findings, checks, directory)?;
+            writer.flush().with_context(|| "Failed to write output")?;
+            Ok(())
+        }
+        _ => write_output(output, &findings),
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::inventory;
+use crate::system_manifests::{FlatManifestResource, ManifestResource};
+
+/// Label or annotation naming the owner of a secret, unless the config sets another.
+pub const DEFAULT_OWNER_KEY: &str = "owner";
+
+/// Group of the secrets no owner is found for.
+pub const UNOWNED: &str = "<unowned>";
+
+/// Where CODEOWNERS files are looked for in a repository, in the order GitHub and GitLab do.
+const CODEOWNERS_PATHS: &[&str] = &[
+    ".github/CODEOWNERS",
+    "CODEOWNERS",
+    "docs/CODEOWNERS",
+    ".gitlab/CODEOWNERS",
+];
+
+/// Where the owner of a secret was found.
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum OwnerSource {
+    Annotation,
+    Label,
+    Codeowners,
+}
+
+/// A secret resource with its owner.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct OwnedSecret {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_source: Option<OwnerSource>,
+    #[serde(flatten)]
+    pub resource: FlatManifestResource,
+}
+
+/// The rules of a CODEOWNERS file, where the last rule matching a path applies.
+#[derive(Debug, Default)]
+pub struct Codeowners {
+    patterns: GlobSet,
+    /// Owners of each pattern, empty for patterns that leave files without an owner.
+    owners: Vec<Vec<String>>,
+}
+
+/// Compiles a CODEOWNERS pattern into globs matching the paths and the contents of directories
+/// it matches, relative to the repository. Patterns without a slash but at their end match at any
+/// depth, like in gitignore files.
+fn pattern_globs(pattern: &str) -> [String; 2] {
+    let directory_only = pattern.ends_with('/');
+    let trimmed = pattern.trim_end_matches('/');
+    let glob = match trimmed.strip_prefix('/') {
+        Some(anchored) => anchored.to_owned(),
+        None if trimmed.contains('/') => trimmed.to_owned(),
+        None => format!("**/{}", trimmed),
+    };
+    let contents = format!("{}/**", glob);
+    if directory_only {
+        [contents.clone(), contents]
+    } else {
+        [glob, contents]
+    }
+}
+
+impl Codeowners {
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        let mut owners = Vec::new();
+        for line in contents.lines() {
+            let line = line.split(" #").next().unwrap_or_default().trim();
+            // GitLab sections like `[Backend]` group the rules after them.
+            if line.is_empty() || line.starts_with('#') || line.starts_with('[') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let Some(pattern) = fields.next() else {
+                continue;
+            };
+            for glob in pattern_globs(pattern) {
+                builder.add(
+                    GlobBuilder::new(&glob)
+                        .literal_separator(true)
+                        .build()
+                        .with_context(|| format!("Invalid CODEOWNERS pattern: {}", pattern))?,
+                );
+                owners.push(fields.clone().map(str::to_owned).collect());
+            }
+        }
+        Ok(Codeowners {
+            patterns: builder
+                .build()
+                .with_context(|| "Failed to compile CODEOWNERS patterns")?,
+            owners,
+        })
+    }
+
+    /// Reads the CODEOWNERS file of a repository, if it has one.
+    pub fn read(repository: &Path) -> Result<Option<Self>> {
+        let Some(path) = CODEOWNERS_PATHS
+            .iter()
+            .map(|path| repository.join(path))
+            .find(|path| path.is_file())
+        else {
+            return Ok(None);
+        };
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Codeowners::parse(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+            .map(Some)
+    }
+
+    /// Returns the owners of a path relative to the repository, if the last rule matching it
+    /// names any.
+    pub fn owners(&self, path: &Path) -> Option<&[String]> {
+        let index = self.patterns.matches(path).into_iter().max()?;
+        Some(self.owners[index].as_slice()).filter(|owners| !owners.is_empty())
+    }
+}
+
+/// Returns the owner of a secret resource and where it was found: its annotation, or else its
+/// label, or else the CODEOWNERS rule for its file.
+fn owner(
+    manifest_resource: &ManifestResource,
+    owner_key: &str,
+    codeowners: Option<&Codeowners>,
+    repository: &Path,
+) -> Option<(String, OwnerSource)> {
+    let metadata = &manifest_resource.resource.metadata;
+    let from_metadata = |entries: &Option<BTreeMap<String, String>>, source| {
+        entries
+            .as_ref()?
+            .get(owner_key)
+            .filter(|owner| !owner.trim().is_empty())
+            .map(|owner| (owner.clone(), source))
+    };
+    from_metadata(&metadata.annotations, OwnerSource::Annotation)
+        .or_else(|| from_metadata(&metadata.labels, OwnerSource::Label))
+        .or_else(|| {
+            let file = std::fs::canonicalize(&manifest_resource.file)
+                .unwrap_or_else(|_| manifest_resource.file.clone());
+            let path = file.strip_prefix(repository).ok()?;
+            let owners = codeowners?.owners(path)?;
+            Some((owners.join(" "), OwnerSource::Codeowners))
+        })
+}
+
+/// Groups the secret resources by owner, with the ones without one under `<unowned>`. The
+/// CODEOWNERS file is looked up in the given repository directory.
+pub fn find_owners(
+    secret_resource_manifests: Vec<ManifestResource>,
+    owner_key: &str,
+    repository: &Path,
+) -> Result<BTreeMap<String, Vec<OwnedSecret>>> {
+    let repository: PathBuf =
+        std::fs::canonicalize(repository).unwrap_or_else(|_| repository.to_owned());
+    let codeowners = Codeowners::read(&repository)?;
+    let mut groups: BTreeMap<String, Vec<OwnedSecret>> = BTreeMap::new();
+    for manifest_resource in secret_resource_manifests {
+        let found = owner(
+            &manifest_resource,
+            owner_key,
+            codeowners.as_ref(),
+            &repository,
+        );
+        let (owner, owner_source) = found.unzip();
+        let group = owner.clone().unwrap_or_else(|| UNOWNED.to_owned());
+        groups.entry(group).or_default().push(OwnedSecret {
+            owner,
+            owner_source,
+            resource: inventory::flatten(manifest_resource, false),
+        });
+    }
+    Ok(groups)
+}
@@ -0,0 +1,102 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
+use crate::findings::{Finding, Severity};
+use crate::sops;
+use crate::system_manifests::ManifestResource;
+
+pub const RULE: &str = "forbid-plain-secrets";
+
+/// Sanctioned plain Secrets, one `<platform>/<namespace>/<name>` entry per line.
+///
+/// Each segment may be `*` to match anything, empty lines and lines starting with `#` are
+/// ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist {
+    entries: Vec<[String; 3]>,
+}
+
+impl Allowlist {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read allowlist: {}", path.display()))?;
+        let entries = contents
+            .lines()
+            .map(str::trim)
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(index, line)| {
+                let segments: Vec<&str> = line.split('/').collect();
+                match segments.as_slice() {
+                    [platform, namespace, name] => Ok([
+                        platform.to_string(),
+                        namespace.to_string(),
+                        name.to_string(),
+                    ]),
+                    _ => anyhow::bail!(
+                        "Invalid allowlist entry on line {} of {}, expected <platform>/<namespace>/<name>: {}",
+                        index + 1,
+                        path.display(),
+                        line
+                    ),
+                }
+            })
+            .collect::<Result<_>>()?;
+        Ok(Allowlist { entries })
+    }
+
+    pub fn allows(&self, manifest_resource: &ManifestResource) -> bool {
+        let metadata = &manifest_resource.resource.metadata;
+        let values = [
+            manifest_resource.platform.name.as_str(),
+            metadata.namespace.as_deref().unwrap_or_default(),
+            metadata.name.as_deref().unwrap_or_default(),
+        ];
+        self.entries.iter().any(|entry| {
+            entry
+                .iter()
+                .zip(values)
+                .all(|(segment, value)| segment == "*" || segment == value)
+        })
+    }
+}
+
+fn has_inline_data(manifest_resource: &ManifestResource) -> bool {
+    ["data", "stringData"].iter().any(|field| {
+        manifest_resource
+            .resource
+            .data
+            .get(field)
+            .and_then(|value| value.as_object())
+            .is_some_and(|object| !object.is_empty())
+    })
+}
+
+/// Returns a finding for every Secret carrying inline data that the allowlist doesn't sanction.
+/// Secrets encrypted with SOPS don't carry plain data.
+pub fn find_plain_secrets<'a>(
+    manifest_resources: impl IntoIterator<Item = &'a ManifestResource>,
+    allowlist: &Allowlist,
+) -> Vec<Finding> {
+    manifest_resources
+        .into_iter()
+        .filter(|manifest_resource| {
+            manifest_resource
+                .resource
+                .types
+                .as_ref()
+                .is_some_and(|t| t.kind == "Secret")
+                && has_inline_data(manifest_resource)
+                && !sops::is_encrypted(&manifest_resource.resource)
+                && !allowlist.allows(manifest_resource)
+        })
+        .map(|manifest_resource| {
+            Finding::new(
+                RULE,
+                Severity::Error,
+                "Secret defines inline data, use an ExternalSecret instead".to_owned(),
+                manifest_resource,
+            )
+        })
+        .collect()
+}
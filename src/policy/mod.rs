@@ -0,0 +1,108 @@
+use anyhow::{Context, Result};
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::findings::{Finding, Severity};
+use crate::system_manifests::ManifestResource;
+
+/// Rule of policy findings that don't name their own rule.
+pub const RULE: &str = "policy";
+
+/// Rego policies evaluated against every secret resource.
+///
+/// The `deny` and `warn` rules of the policy package produce errors and warnings. Each of their
+/// values is a message, or an object with a `msg` and optionally a `rule` to report it under.
+pub struct Policies {
+    engine: regorus::Engine,
+    package: String,
+}
+
+impl Policies {
+    /// Loads the `.rego` files at the given paths, looking through directories recursively.
+    pub fn load(paths: &[PathBuf], package: &str) -> Result<Self> {
+        let mut engine = regorus::Engine::new();
+        let mut loaded = 0;
+        for path in paths {
+            for entry in WalkDir::new(path).follow_links(true).sort_by_file_name() {
+                let entry = entry
+                    .with_context(|| format!("Failed to read policies: {}", path.display()))?;
+                let is_policy = entry.file_type().is_file()
+                    && (entry.depth() == 0
+                        || entry.path().extension().is_some_and(|ext| ext == "rego"));
+                if !is_policy {
+                    continue;
+                }
+                engine.add_policy_from_file(entry.path()).with_context(|| {
+                    format!("Failed to load policy: {}", entry.path().display())
+                })?;
+                loaded += 1;
+            }
+        }
+        anyhow::ensure!(loaded > 0, "No .rego policies found");
+        Ok(Policies {
+            engine,
+            package: package.to_owned(),
+        })
+    }
+
+    /// Returns the values of a rule for the current input, none if the rule is undefined.
+    fn values(&mut self, rule: &str) -> Result<Vec<Value>> {
+        let query = format!("data.{}.{}", self.package, rule);
+        let results = self
+            .engine
+            .eval_query(query.clone(), false)
+            .with_context(|| format!("Failed to evaluate {}", query))?;
+        let mut values = Vec::new();
+        for result in results.result {
+            for expression in result.expressions {
+                match serde_json::to_value(&expression.value)? {
+                    Value::Array(items) => values.extend(items),
+                    Value::Null => {}
+                    value => values.push(value),
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Evaluates the policies against a secret resource, along with its file relative to the
+    /// system manifests directory, its platform and its component.
+    pub fn evaluate(
+        &mut self,
+        manifest_resource: &ManifestResource,
+        directory: &Path,
+    ) -> Result<Vec<Finding>> {
+        let input = json!({
+            "file": manifest_resource
+                .file
+                .strip_prefix(directory)
+                .unwrap_or(&manifest_resource.file),
+            "platform": manifest_resource.platform.name,
+            "component": manifest_resource.component.name,
+            "resource": manifest_resource.resource,
+        });
+        self.engine
+            .set_input(regorus::Value::from_json_str(&input.to_string())?);
+
+        let mut findings = Vec::new();
+        for (rule, severity) in [("deny", Severity::Error), ("warn", Severity::Warning)] {
+            for value in self.values(rule)? {
+                let (rule, message) = match &value {
+                    Value::String(message) => (RULE, message.clone()),
+                    Value::Object(object) => (
+                        object.get("rule").and_then(Value::as_str).unwrap_or(RULE),
+                        object
+                            .get("msg")
+                            .and_then(Value::as_str)
+                            .map(str::to_owned)
+                            .unwrap_or_else(|| value.to_string()),
+                    ),
+                    value => (RULE, value.to_string()),
+                };
+                findings.push(Finding::new(rule, severity, message, manifest_resource));
+            }
+        }
+        Ok(findings)
+    }
+}
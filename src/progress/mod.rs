@@ -0,0 +1,57 @@
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressFinish, ProgressStyle};
+use std::io::IsTerminal;
+use std::time::Duration;
+
+/// Progress of a long running step, drawn on stderr while it's a terminal and debug logs aren't
+/// written to it. Cleared once dropped.
+#[derive(Debug, Clone)]
+pub struct Progress(ProgressBar);
+
+impl Progress {
+    fn new(bar: ProgressBar, template: &str) -> Self {
+        let shown = std::io::stderr().is_terminal() && !tracing::enabled!(tracing::Level::DEBUG);
+        if !shown {
+            return Progress(ProgressBar::hidden());
+        }
+        let style = ProgressStyle::with_template(template)
+            .unwrap_or_else(|_| ProgressStyle::default_spinner());
+        let bar = bar.with_style(style).with_finish(ProgressFinish::AndClear);
+        bar.set_draw_target(ProgressDrawTarget::stderr());
+        bar.enable_steady_tick(Duration::from_millis(100));
+        Progress(bar)
+    }
+
+    /// Progress of reading manifest files, whose number isn't known up front.
+    pub fn files() -> Self {
+        Progress::new(
+            ProgressBar::new_spinner(),
+            "{spinner} [{elapsed}] {pos} files read, {msg}",
+        )
+    }
+
+    /// Progress of going through a known number of items.
+    pub fn items(count: usize, what: &str) -> Self {
+        Progress::new(
+            ProgressBar::new(count as u64),
+            &format!("[{{elapsed}}] {{bar:30}} {{pos}}/{{len}} {}", what),
+        )
+    }
+
+    /// Shows which platform, counted among all, and component are being read.
+    pub fn reading(
+        &self,
+        platform: &str,
+        platform_number: usize,
+        platforms: usize,
+        component: &str,
+    ) {
+        self.0.set_message(format!(
+            "platform {} ({}/{}), component {}",
+            platform, platform_number, platforms, component
+        ));
+    }
+
+    pub fn inc(&self) {
+        self.0.inc(1);
+    }
+}
@@ -0,0 +1,424 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::{json_property_paths, SecretBackend, SecretMetadata, SecretState, Store};
+use crate::inventory::RemoteRef;
+
+#[derive(Args, Debug, Clone)]
+pub struct AwsArgs {
+    /// AWS profile to use for a platform, defaults to the ambient credentials.
+    #[arg(long, value_name = "PLATFORM=PROFILE", value_parser = crate::parse_key_value)]
+    aws_profile: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Service {
+    SecretsManager,
+    ParameterStore,
+}
+
+/// How a SecretStore reaches AWS.
+struct AwsStore {
+    service: Service,
+    region: Option<String>,
+    role: Option<String>,
+}
+
+impl AwsStore {
+    fn new(provider: &Value) -> Result<Self> {
+        let field = |name| {
+            provider
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+        };
+        let service = match field("service").as_deref() {
+            Some("SecretsManager") => Service::SecretsManager,
+            Some("ParameterStore") => Service::ParameterStore,
+            Some(service) => anyhow::bail!("Unsupported AWS service: {}", service),
+            None => anyhow::bail!("SecretStore has no AWS service configured"),
+        };
+        Ok(AwsStore {
+            service,
+            region: field("region"),
+            role: field("role"),
+        })
+    }
+}
+
+fn aws_command(profile: Option<&str>, store: &AwsStore) -> Command {
+    let mut command = Command::new("aws");
+    if let Some(profile) = profile {
+        command.args(["--profile", profile]);
+    }
+    if let Some(region) = &store.region {
+        command.args(["--region", region]);
+    }
+    command.args(["--output", "json"]);
+    command
+}
+
+/// Session credentials of an assumed role, as environment variables.
+type Credentials = Vec<(&'static str, String)>;
+
+/// Runs the `aws` CLI with a platform's profile, assuming a store's role once per session.
+pub struct Aws {
+    profiles: HashMap<String, String>,
+    sessions: HashMap<(String, String), Credentials>,
+    responses: HashMap<(String, Vec<String>), Option<Value>>,
+}
+
+impl Aws {
+    pub fn new(args: AwsArgs) -> Self {
+        Aws {
+            profiles: args.aws_profile.into_iter().collect(),
+            sessions: HashMap::new(),
+            responses: HashMap::new(),
+        }
+    }
+
+    fn base_command(&self, platform_name: &str, store: &AwsStore) -> Command {
+        aws_command(self.profiles.get(platform_name).map(String::as_str), store)
+    }
+
+    fn session_command(&mut self, platform_name: &str, store: &AwsStore) -> Result<Command> {
+        Ok(match &store.role {
+            Some(role) => {
+                let credentials = self.assume_role(platform_name, store, role)?;
+                let mut command = aws_command(None, store);
+                command.envs(credentials).env_remove("AWS_PROFILE");
+                command
+            }
+            None => self.base_command(platform_name, store),
+        })
+    }
+
+    fn command(
+        &mut self,
+        platform_name: &str,
+        store: &AwsStore,
+        args: &[&str],
+    ) -> Result<std::process::Output> {
+        self.session_command(platform_name, store)?
+            .args(args)
+            .output()
+            .with_context(|| "Failed to run aws, is the AWS CLI installed?")
+    }
+
+    /// Runs an aws command that reads the value it writes from stdin, so it doesn't show up in
+    /// the arguments other users can see.
+    fn write_command(
+        &mut self,
+        platform_name: &str,
+        store: &AwsStore,
+        args: &[&str],
+        value: &str,
+    ) -> Result<std::process::Output> {
+        let mut child = self
+            .session_command(platform_name, store)?
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| "Failed to run aws, is the AWS CLI installed?")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(value.as_bytes())
+                .with_context(|| "Failed to pass the secret value to aws")?;
+        }
+        child
+            .wait_with_output()
+            .with_context(|| "Failed to run aws")
+    }
+
+    fn assume_role(
+        &mut self,
+        platform_name: &str,
+        store: &AwsStore,
+        role: &str,
+    ) -> Result<Credentials> {
+        let session = (platform_name.to_owned(), role.to_owned());
+        if let Some(credentials) = self.sessions.get(&session) {
+            return Ok(credentials.clone());
+        }
+        let output = self
+            .base_command(platform_name, store)
+            .args([
+                "sts",
+                "assume-role",
+                "--role-arn",
+                role,
+                "--role-session-name",
+                env!("CARGO_PKG_NAME"),
+            ])
+            .output()
+            .with_context(|| "Failed to run aws, is the AWS CLI installed?")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "Assuming role {} failed: {}",
+            role,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        let response: Value = serde_json::from_slice(&output.stdout)
+            .with_context(|| "aws sts assume-role produced invalid JSON")?;
+        let credential = |field: &str| -> Result<String> {
+            response
+                .pointer(&format!("/Credentials/{}", field))
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+                .with_context(|| format!("aws sts assume-role returned no {}", field))
+        };
+        let credentials = vec![
+            ("AWS_ACCESS_KEY_ID", credential("AccessKeyId")?),
+            ("AWS_SECRET_ACCESS_KEY", credential("SecretAccessKey")?),
+            ("AWS_SESSION_TOKEN", credential("SessionToken")?),
+        ];
+        self.sessions.insert(session, credentials.clone());
+        Ok(credentials)
+    }
+
+    /// Runs an aws command, returning None if it failed because the resource doesn't exist.
+    fn query(
+        &mut self,
+        platform_name: &str,
+        store: &AwsStore,
+        args: &[&str],
+        not_found: &str,
+    ) -> Result<Option<Value>> {
+        let mut cache_args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        cache_args.extend(store.region.clone());
+        cache_args.extend(store.role.clone());
+        let cache_key = (platform_name.to_owned(), cache_args);
+        if let Some(response) = self.responses.get(&cache_key) {
+            return Ok(response.clone());
+        }
+
+        let output = self.command(platform_name, store, args)?;
+        let response = if output.status.success() {
+            Some(
+                serde_json::from_slice(&output.stdout)
+                    .with_context(|| format!("aws {} produced invalid JSON", args.join(" ")))?,
+            )
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !stderr.contains(not_found) {
+                anyhow::bail!("aws {} failed: {}", args.join(" "), stderr.trim());
+            }
+            None
+        };
+        self.responses.insert(cache_key, response.clone());
+        Ok(response)
+    }
+
+    fn describe_secret(
+        &mut self,
+        platform_name: &str,
+        store: &AwsStore,
+        key: &str,
+    ) -> Result<Option<Value>> {
+        self.query(
+            platform_name,
+            store,
+            &["secretsmanager", "describe-secret", "--secret-id", key],
+            "ResourceNotFoundException",
+        )
+    }
+
+    /// Gets a parameter, with the plaintext of a SecureString only if `decrypt`, which takes
+    /// `kms:Decrypt` permissions.
+    fn get_parameter(
+        &mut self,
+        platform_name: &str,
+        store: &AwsStore,
+        key: &str,
+        decrypt: bool,
+    ) -> Result<Option<Value>> {
+        let args: &[&str] = match decrypt {
+            true => &["ssm", "get-parameter", "--with-decryption", "--name", key],
+            false => &["ssm", "get-parameter", "--name", key],
+        };
+        self.query(platform_name, store, args, "ParameterNotFound")
+    }
+}
+
+impl SecretBackend for Aws {
+    fn exists(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<bool> {
+        let aws_store = AwsStore::new(store.provider)?;
+        let found = match aws_store.service {
+            Service::SecretsManager => {
+                self.describe_secret(store.platform_name, &aws_store, &remote_ref.key)?
+            }
+            Service::ParameterStore => {
+                self.get_parameter(store.platform_name, &aws_store, &remote_ref.key, false)?
+            }
+        };
+        Ok(found.is_some())
+    }
+
+    fn list(&mut self, store: &Store, path: Option<&str>) -> Result<Vec<String>> {
+        let aws_store = AwsStore::new(store.provider)?;
+        let mut names: Vec<String> = match aws_store.service {
+            Service::SecretsManager => self
+                .query(
+                    store.platform_name,
+                    &aws_store,
+                    &["secretsmanager", "list-secrets"],
+                    "ResourceNotFoundException",
+                )?
+                .and_then(|response| response.get("SecretList").cloned())
+                .and_then(|secrets| secrets.as_array().cloned())
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|secret| secret.get("Name")?.as_str().map(str::to_owned))
+                .filter(|name| path.is_none_or(|path| name.starts_with(path)))
+                .collect(),
+            Service::ParameterStore => self
+                .query(
+                    store.platform_name,
+                    &aws_store,
+                    &[
+                        "ssm",
+                        "get-parameters-by-path",
+                        "--recursive",
+                        "--path",
+                        path.unwrap_or("/"),
+                    ],
+                    "ParameterNotFound",
+                )?
+                .and_then(|response| response.get("Parameters").cloned())
+                .and_then(|parameters| parameters.as_array().cloned())
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|parameter| parameter.get("Name")?.as_str().map(str::to_owned))
+                .collect(),
+        };
+        names.sort();
+        Ok(names)
+    }
+
+    fn metadata(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<SecretMetadata> {
+        let aws_store = AwsStore::new(store.provider)?;
+        let key = remote_ref.key.as_str();
+        let want_properties = remote_ref.property.is_some();
+        match aws_store.service {
+            Service::SecretsManager => {
+                let description = self.describe_secret(store.platform_name, &aws_store, key)?;
+                if description
+                    .as_ref()
+                    .is_some_and(|description| description.get("DeletedDate").is_some())
+                {
+                    return Ok(SecretMetadata {
+                        state: SecretState::PendingDeletion,
+                        ..Default::default()
+                    });
+                }
+                let properties = match want_properties {
+                    true => self
+                        .query(
+                            store.platform_name,
+                            &aws_store,
+                            &["secretsmanager", "get-secret-value", "--secret-id", key],
+                            "ResourceNotFoundException",
+                        )?
+                        .and_then(|secret| {
+                            secret
+                                .get("SecretString")?
+                                .as_str()
+                                .map(json_property_paths)
+                        }),
+                    false => None,
+                };
+                Ok(SecretMetadata {
+                    properties,
+                    ..Default::default()
+                })
+            }
+            Service::ParameterStore => {
+                let properties = match want_properties {
+                    true => self
+                        .get_parameter(store.platform_name, &aws_store, key, true)?
+                        .and_then(|parameter| {
+                            parameter
+                                .pointer("/Parameter/Value")?
+                                .as_str()
+                                .map(json_property_paths)
+                        }),
+                    false => None,
+                };
+                Ok(SecretMetadata {
+                    properties,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+
+    fn identity(&mut self, store: &Store) -> Result<Option<String>> {
+        let aws_store = AwsStore::new(store.provider)?;
+        let output = self.command(
+            store.platform_name,
+            &aws_store,
+            &["sts", "get-caller-identity"],
+        )?;
+        anyhow::ensure!(
+            output.status.success(),
+            "aws sts get-caller-identity failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        let identity: Value = serde_json::from_slice(&output.stdout)
+            .with_context(|| "aws sts get-caller-identity produced invalid JSON")?;
+        Ok(identity
+            .get("Arn")
+            .and_then(Value::as_str)
+            .map(str::to_owned))
+    }
+
+    fn write(&mut self, store: &Store, key: &str, data: &Map<String, Value>) -> Result<()> {
+        let aws_store = AwsStore::new(store.provider)?;
+        let value = Value::Object(data.clone()).to_string();
+        let platform_name = store.platform_name;
+        let output = match aws_store.service {
+            Service::SecretsManager => {
+                let exists = self
+                    .describe_secret(platform_name, &aws_store, key)?
+                    .is_some();
+                let args: &[&str] = if exists {
+                    &["secretsmanager", "put-secret-value", "--secret-id", key]
+                } else {
+                    &["secretsmanager", "create-secret", "--name", key]
+                };
+                let args = [args, &["--secret-string", "file:///dev/stdin"]].concat();
+                self.write_command(platform_name, &aws_store, &args, &value)?
+            }
+            Service::ParameterStore => self.write_command(
+                platform_name,
+                &aws_store,
+                &[
+                    "ssm",
+                    "put-parameter",
+                    "--type",
+                    "SecureString",
+                    "--overwrite",
+                    "--name",
+                    key,
+                    "--value",
+                    "file:///dev/stdin",
+                ],
+                &value,
+            )?,
+        };
+        anyhow::ensure!(
+            output.status.success(),
+            "Writing {} with aws failed: {}",
+            key,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        self.responses.clear();
+        Ok(())
+    }
+}
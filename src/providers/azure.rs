@@ -0,0 +1,264 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, ValueEnum};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::process::{Command, Stdio};
+
+use super::{json_property_paths, SecretBackend, SecretMetadata, SecretState, Store};
+use crate::inventory::RemoteRef;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AzureAuth {
+    /// Use the identity `az login` signed in with.
+    Cli,
+    /// Log in with the managed identity of the machine.
+    ManagedIdentity,
+    /// Log in with the service principal in `AZURE_CLIENT_ID`, `AZURE_CLIENT_SECRET` and
+    /// `AZURE_TENANT_ID`.
+    Environment,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct AzureArgs {
+    /// How to authenticate to Azure Key Vault.
+    #[arg(long, value_enum, default_value = "cli")]
+    azure_auth: AzureAuth,
+}
+
+/// Runs the `az` CLI, logging in to a private configuration directory unless the existing
+/// `az login` is used.
+pub struct Azure {
+    auth: AzureAuth,
+    config_directory: Option<tempfile::TempDir>,
+    objects: HashMap<String, KeyVaultObject>,
+}
+
+/// The result of looking up a secret, key or certificate.
+#[derive(Clone)]
+enum KeyVaultObject {
+    Missing,
+    Disabled,
+    Found(Value),
+}
+
+/// Returns the `az keyvault` object type and the ID of the object behind a remote key.
+fn object_id(vault_url: &str, remote_ref: &RemoteRef) -> (&'static str, String) {
+    let (object_type, collection, name) = match remote_ref.key.split_once('/') {
+        Some(("cert", name)) => ("certificate", "certificates", name),
+        Some(("key", name)) => ("key", "keys", name),
+        Some(("secret", name)) => ("secret", "secrets", name),
+        _ => ("secret", "secrets", remote_ref.key.as_str()),
+    };
+    let mut id = format!("{}/{}/{}", vault_url, collection, name);
+    if let Some(version) = &remote_ref.version {
+        id = format!("{}/{}", id, version);
+    }
+    (object_type, id)
+}
+
+fn vault_url<'a>(store: &Store<'a>) -> Result<&'a str> {
+    Ok(store
+        .provider
+        .get("vaultUrl")
+        .and_then(Value::as_str)
+        .with_context(|| "SecretStore has no Key Vault URL configured")?
+        .trim_end_matches('/'))
+}
+
+impl Azure {
+    pub fn new(args: AzureArgs) -> Self {
+        Azure {
+            auth: args.azure_auth,
+            config_directory: None,
+            objects: HashMap::new(),
+        }
+    }
+
+    fn command(&mut self) -> Result<Command> {
+        let mut command = Command::new("az");
+        if self.auth == AzureAuth::Cli {
+            return Ok(command);
+        }
+        if self.config_directory.is_none() {
+            self.config_directory = Some(self.login()?);
+        }
+        if let Some(config_directory) = &self.config_directory {
+            command.env("AZURE_CONFIG_DIR", config_directory.path());
+        }
+        Ok(command)
+    }
+
+    fn login(&self) -> Result<tempfile::TempDir> {
+        let config_directory =
+            tempfile::tempdir().with_context(|| "Failed to create a temporary directory")?;
+        let mut command = Command::new("az");
+        command
+            .env("AZURE_CONFIG_DIR", config_directory.path())
+            .stdout(Stdio::null());
+        match self.auth {
+            AzureAuth::Cli => (),
+            AzureAuth::ManagedIdentity => {
+                command.args(["login", "--identity"]);
+            }
+            AzureAuth::Environment => {
+                let variable =
+                    |name| std::env::var(name).with_context(|| format!("{} is not set", name));
+                command.args([
+                    "login",
+                    "--service-principal",
+                    "--username",
+                    &variable("AZURE_CLIENT_ID")?,
+                    "--password",
+                    &variable("AZURE_CLIENT_SECRET")?,
+                    "--tenant",
+                    &variable("AZURE_TENANT_ID")?,
+                ]);
+            }
+        }
+        let status = command
+            .status()
+            .with_context(|| "Failed to run az, is the Azure CLI installed?")?;
+        anyhow::ensure!(status.success(), "az login failed");
+        Ok(config_directory)
+    }
+
+    fn show(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<KeyVaultObject> {
+        let (object_type, id) = object_id(vault_url(store)?, remote_ref);
+        if let Some(object) = self.objects.get(&id) {
+            return Ok(object.clone());
+        }
+
+        let output = self
+            .command()?
+            .args([
+                "keyvault",
+                object_type,
+                "show",
+                "--id",
+                &id,
+                "--output",
+                "json",
+            ])
+            .output()
+            .with_context(|| "Failed to run az, is the Azure CLI installed?")?;
+        let object = if output.status.success() {
+            KeyVaultObject::Found(serde_json::from_slice(&output.stdout).with_context(|| {
+                format!("az keyvault {} show produced invalid JSON", object_type)
+            })?)
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("NotFound") {
+                KeyVaultObject::Missing
+            } else if stderr.contains("disabled") {
+                KeyVaultObject::Disabled
+            } else {
+                anyhow::bail!(
+                    "az keyvault {} show {} failed: {}",
+                    object_type,
+                    id,
+                    stderr.trim()
+                );
+            }
+        };
+        self.objects.insert(id, object.clone());
+        Ok(object)
+    }
+}
+
+impl SecretBackend for Azure {
+    fn exists(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<bool> {
+        Ok(!matches!(
+            self.show(store, remote_ref)?,
+            KeyVaultObject::Missing
+        ))
+    }
+
+    fn list(&mut self, store: &Store, path: Option<&str>) -> Result<Vec<String>> {
+        let vault_url = vault_url(store)?;
+        let output = self
+            .command()?
+            .args([
+                "keyvault", "secret", "list", "--id", vault_url, "--output", "json",
+            ])
+            .output()
+            .with_context(|| "Failed to run az, is the Azure CLI installed?")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "az keyvault secret list {} failed: {}",
+            vault_url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        let secrets: Vec<Value> = serde_json::from_slice(&output.stdout)
+            .with_context(|| "az keyvault secret list produced invalid JSON")?;
+        let mut names: Vec<String> = secrets
+            .iter()
+            .filter_map(|secret| secret.get("name")?.as_str().map(str::to_owned))
+            .filter(|name| path.is_none_or(|path| name.starts_with(path)))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn metadata(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<SecretMetadata> {
+        let object = match self.show(store, remote_ref)? {
+            KeyVaultObject::Found(object) => object,
+            _ => {
+                return Ok(SecretMetadata {
+                    state: SecretState::Disabled,
+                    ..Default::default()
+                })
+            }
+        };
+
+        let attributes = object.get("attributes");
+        let enabled = attributes
+            .and_then(|attributes| attributes.get("enabled"))
+            .and_then(Value::as_bool)
+            .unwrap_or(true);
+        let expires = attributes
+            .and_then(|attributes| attributes.get("expires"))
+            .and_then(Value::as_str)
+            .map(|expires| {
+                expires
+                    .parse::<DateTime<Utc>>()
+                    .with_context(|| format!("Invalid expiry time: {}", expires))
+            })
+            .transpose()?;
+        let properties = remote_ref.property.as_ref().map(|_| {
+            object
+                .get("value")
+                .and_then(Value::as_str)
+                .map(json_property_paths)
+                .unwrap_or_default()
+        });
+        Ok(SecretMetadata {
+            state: if enabled {
+                SecretState::Enabled
+            } else {
+                SecretState::Disabled
+            },
+            expires,
+            properties,
+        })
+    }
+
+    fn identity(&mut self, _store: &Store) -> Result<Option<String>> {
+        let output = self
+            .command()?
+            .args(["account", "show", "--output", "json"])
+            .output()
+            .with_context(|| "Failed to run az, is the Azure CLI installed?")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "az account show failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        let account: Value = serde_json::from_slice(&output.stdout)
+            .with_context(|| "az account show produced invalid JSON")?;
+        Ok(account
+            .pointer("/user/name")
+            .and_then(Value::as_str)
+            .map(str::to_owned))
+    }
+}
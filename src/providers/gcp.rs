@@ -0,0 +1,189 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::process::Command;
+
+use super::{json_property_paths, SecretBackend, SecretMetadata, SecretState, Store};
+use crate::inventory::RemoteRef;
+
+#[derive(Args, Debug, Clone)]
+pub struct GcpArgs {
+    /// Service account to impersonate when calling Google Secret Manager.
+    #[arg(long)]
+    gcp_impersonate_service_account: Option<String>,
+}
+
+/// Runs the `gcloud` CLI with its active account or an impersonated service account.
+pub struct Gcp {
+    args: GcpArgs,
+    responses: HashMap<Vec<String>, Option<Vec<u8>>>,
+}
+
+fn project<'a>(store: &Store<'a>) -> Result<&'a str> {
+    store
+        .provider
+        .get("projectID")
+        .and_then(Value::as_str)
+        .with_context(|| "SecretStore has no GCP project configured")
+}
+
+/// Returns the version a remote ref pins, treating `latest` as no version.
+fn pinned_version(remote_ref: &RemoteRef) -> Option<&str> {
+    remote_ref
+        .version
+        .as_deref()
+        .filter(|version| *version != "latest")
+}
+
+fn is_enabled(version: &Value) -> bool {
+    version.get("state").and_then(Value::as_str) == Some("ENABLED")
+}
+
+impl Gcp {
+    pub fn new(args: GcpArgs) -> Self {
+        Gcp {
+            args,
+            responses: HashMap::new(),
+        }
+    }
+
+    /// Runs a gcloud command, returning None if it failed because the secret doesn't exist.
+    fn gcloud(&mut self, project: &str, args: &[&str]) -> Result<Option<Vec<u8>>> {
+        let mut cache_key: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        cache_key.push(project.to_owned());
+        if let Some(response) = self.responses.get(&cache_key) {
+            return Ok(response.clone());
+        }
+
+        let mut command = Command::new("gcloud");
+        command.args(args).args(["--project", project]);
+        if let Some(service_account) = &self.args.gcp_impersonate_service_account {
+            command.args(["--impersonate-service-account", service_account]);
+        }
+        let output = command
+            .output()
+            .with_context(|| "Failed to run gcloud, is the Google Cloud CLI installed?")?;
+        let response = if output.status.success() {
+            Some(output.stdout)
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if !stderr.contains("NOT_FOUND") {
+                anyhow::bail!("gcloud {} failed: {}", args.join(" "), stderr.trim());
+            }
+            None
+        };
+        self.responses.insert(cache_key, response.clone());
+        Ok(response)
+    }
+
+    /// Lists the versions of a secret, returning None if the secret doesn't exist.
+    fn versions(&mut self, project: &str, secret: &str) -> Result<Option<Vec<Value>>> {
+        self.gcloud(
+            project,
+            &[
+                "secrets",
+                "versions",
+                "list",
+                secret,
+                "--format=json(name,state)",
+            ],
+        )?
+        .map(|output| {
+            serde_json::from_slice(&output)
+                .with_context(|| "gcloud secrets versions list produced invalid JSON")
+        })
+        .transpose()
+    }
+}
+
+/// Finds a version by its number in a list of versions.
+fn find_version<'a>(versions: &'a [Value], version: &str) -> Option<&'a Value> {
+    let suffix = format!("/versions/{}", version);
+    versions.iter().find(|candidate| {
+        candidate
+            .get("name")
+            .and_then(Value::as_str)
+            .is_some_and(|name| name.ends_with(&suffix))
+    })
+}
+
+impl SecretBackend for Gcp {
+    fn exists(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<bool> {
+        let Some(versions) = self.versions(project(store)?, &remote_ref.key)? else {
+            return Ok(false);
+        };
+        Ok(pinned_version(remote_ref)
+            .is_none_or(|version| find_version(&versions, version).is_some()))
+    }
+
+    fn list(&mut self, store: &Store, path: Option<&str>) -> Result<Vec<String>> {
+        let output = self
+            .gcloud(project(store)?, &["secrets", "list", "--format=json(name)"])?
+            .unwrap_or_default();
+        let secrets: Vec<Value> = serde_json::from_slice(&output)
+            .with_context(|| "gcloud secrets list produced invalid JSON")?;
+        let mut names: Vec<String> = secrets
+            .iter()
+            .filter_map(|secret| secret.get("name")?.as_str())
+            .filter_map(|name| name.rsplit('/').next().map(str::to_owned))
+            .filter(|name| path.is_none_or(|path| name.starts_with(path)))
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    fn metadata(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<SecretMetadata> {
+        let project = project(store)?;
+        let versions = self.versions(project, &remote_ref.key)?.unwrap_or_default();
+        let version = pinned_version(remote_ref);
+        let state = match version {
+            Some(version) if !find_version(&versions, version).is_some_and(is_enabled) => {
+                SecretState::Disabled
+            }
+            Some(_) => SecretState::Enabled,
+            None if !versions.iter().any(is_enabled) => SecretState::NoEnabledVersion,
+            None => SecretState::Enabled,
+        };
+
+        let properties = match (&remote_ref.property, state) {
+            (Some(_), SecretState::Enabled) => Some(
+                self.gcloud(
+                    project,
+                    &[
+                        "secrets",
+                        "versions",
+                        "access",
+                        version.unwrap_or("latest"),
+                        "--secret",
+                        &remote_ref.key,
+                    ],
+                )?
+                .map(|data| json_property_paths(&String::from_utf8_lossy(&data)))
+                .unwrap_or_default(),
+            ),
+            _ => None,
+        };
+        Ok(SecretMetadata {
+            state,
+            properties,
+            ..Default::default()
+        })
+    }
+
+    fn identity(&mut self, store: &Store) -> Result<Option<String>> {
+        let project = project(store)?;
+        self.gcloud(
+            project,
+            &["projects", "describe", project, "--format=value(projectId)"],
+        )?
+        .with_context(|| format!("GCP project {} doesn't exist", project))?;
+        if let Some(service_account) = &self.args.gcp_impersonate_service_account {
+            return Ok(Some(service_account.clone()));
+        }
+        let account = self
+            .gcloud(project, &["config", "get-value", "account"])?
+            .map(|account| String::from_utf8_lossy(&account).trim().to_owned());
+        Ok(account.filter(|account| !account.is_empty()))
+    }
+}
@@ -0,0 +1,148 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use clap::Args;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::inventory::RemoteRef;
+
+mod aws;
+mod azure;
+mod gcp;
+mod vault;
+
+/// The SecretStore a backend is asked about.
+pub struct Store<'a> {
+    pub platform_name: &'a str,
+    /// The provider configuration, the value of the provider's key in `spec.provider`.
+    pub provider: &'a Value,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SecretState {
+    #[default]
+    Enabled,
+    /// The referenced version, or else the latest one, is disabled.
+    Disabled,
+    /// None of the secret's versions are enabled.
+    NoEnabledVersion,
+    /// The secret is scheduled for deletion.
+    PendingDeletion,
+}
+
+/// What a backend knows about a secret, never its value.
+#[derive(Debug, Clone, Default)]
+pub struct SecretMetadata {
+    pub state: SecretState,
+    pub expires: Option<DateTime<Utc>>,
+    /// Paths of the properties in the secret's JSON value, only looked up when the remote ref
+    /// references a property.
+    pub properties: Option<BTreeSet<String>>,
+}
+
+/// Access to the secrets of one kind of SecretStore provider.
+pub trait SecretBackend {
+    /// Returns whether a secret, and the version it references if any, exists at a remote key.
+    fn exists(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<bool>;
+
+    /// Returns the remote keys below a path, or all of them if there is no path.
+    fn list(&mut self, store: &Store, path: Option<&str>) -> Result<Vec<String>>;
+
+    /// Describes an existing secret.
+    fn metadata(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<SecretMetadata>;
+
+    /// Returns whether a PushSecret may write to a remote key, or None if the backend can't tell.
+    fn writable(&mut self, _store: &Store, _remote_ref: &RemoteRef) -> Result<Option<bool>> {
+        Ok(None)
+    }
+
+    /// Returns who the backend authenticates to the provider of a store as, failing if it can't
+    /// authenticate, or None if the backend can't tell.
+    fn identity(&mut self, _store: &Store) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    /// Writes key/value data to a remote key, creating the secret or replacing its value.
+    fn write(&mut self, _store: &Store, key: &str, _data: &Map<String, Value>) -> Result<()> {
+        anyhow::bail!(
+            "Writing secrets isn't supported for this provider, can't write {}",
+            key
+        )
+    }
+}
+
+/// Secret backends keyed by the provider type used in SecretStore `spec.provider`.
+#[derive(Default)]
+pub struct Registry {
+    backends: BTreeMap<String, Box<dyn SecretBackend>>,
+}
+
+impl Registry {
+    pub fn register(&mut self, provider: &str, backend: impl SecretBackend + 'static) {
+        self.backends.insert(provider.to_owned(), Box::new(backend));
+    }
+
+    pub fn backend(&mut self, provider: &str) -> Option<&mut Box<dyn SecretBackend>> {
+        self.backends.get_mut(provider)
+    }
+
+    pub fn providers(&self) -> impl Iterator<Item = &str> {
+        self.backends.keys().map(String::as_str)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ProviderArgs {
+    #[command(flatten)]
+    vault: vault::VaultArgs,
+
+    #[command(flatten)]
+    aws: aws::AwsArgs,
+
+    #[command(flatten)]
+    azure: azure::AzureArgs,
+
+    #[command(flatten)]
+    gcp: gcp::GcpArgs,
+}
+
+/// Returns a registry with all built-in backends. Backends for other providers, like in-house
+/// secret services, only need to implement [`SecretBackend`] and be registered here.
+pub fn registry(args: ProviderArgs) -> Registry {
+    let mut registry = Registry::default();
+    registry.register("vault", vault::Vault::new(args.vault));
+    registry.register("aws", aws::Aws::new(args.aws));
+    registry.register("azurekv", azure::Azure::new(args.azure));
+    registry.register("gcpsm", gcp::Gcp::new(args.gcp));
+    registry
+}
+
+fn collect_property_paths(value: &Value, prefix: &str, paths: &mut BTreeSet<String>) {
+    let Some(object) = value.as_object() else {
+        return;
+    };
+    for (key, item) in object {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        collect_property_paths(item, &path, paths);
+        paths.insert(path);
+    }
+}
+
+/// Returns the top level keys and dot separated paths of a JSON secret value, which are the
+/// properties External Secrets Operator can look up.
+pub fn property_paths(value: &Value) -> BTreeSet<String> {
+    let mut paths = BTreeSet::new();
+    collect_property_paths(value, "", &mut paths);
+    paths
+}
+
+/// Returns the property paths of a secret value if it is JSON.
+fn json_property_paths(value: &str) -> BTreeSet<String> {
+    serde_json::from_str::<Value>(value)
+        .map(|value| property_paths(&value))
+        .unwrap_or_default()
+}
@@ -0,0 +1,314 @@
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use super::{property_paths, SecretBackend, SecretMetadata, Store};
+use crate::inventory::RemoteRef;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VaultAuth {
+    /// Use the token given with `--vault-token` or stored by `vault login`.
+    Token,
+    /// Log in through the browser with `vault login -method=oidc`.
+    Oidc,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct VaultArgs {
+    /// Vault address to use instead of the server configured in each SecretStore.
+    #[arg(long, env = "VAULT_ADDR")]
+    vault_address: Option<String>,
+
+    /// Vault token to authenticate with.
+    #[arg(long, env = "VAULT_TOKEN", hide_env_values = true)]
+    vault_token: Option<String>,
+
+    /// How to authenticate to Vault.
+    #[arg(long, value_enum, default_value = "token")]
+    vault_auth: VaultAuth,
+
+    /// Role to request when logging in with OIDC.
+    #[arg(long)]
+    vault_role: Option<String>,
+}
+
+/// How a SecretStore reaches its Vault server and KV mount.
+struct VaultStore {
+    address: String,
+    namespace: Option<String>,
+    mount: Option<String>,
+    version: String,
+}
+
+impl VaultStore {
+    fn new(provider: &Value, address_override: Option<&str>) -> Result<Self> {
+        let field = |name| {
+            provider
+                .get(name)
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+        };
+        Ok(VaultStore {
+            address: address_override
+                .map(str::to_owned)
+                .or_else(|| field("server"))
+                .with_context(|| "SecretStore has no Vault server configured")?,
+            namespace: field("namespace"),
+            mount: field("path"),
+            version: field("version").unwrap_or_else(|| "v2".to_owned()),
+        })
+    }
+
+    /// Returns the API path of a remote key the same way External Secrets Operator builds it,
+    /// `data` is the KV v2 path segment to use.
+    fn path(&self, key: &str, data: &str) -> String {
+        let key = key.trim_start_matches('/');
+        let (mount, key) = match &self.mount {
+            Some(mount) => {
+                let mount = mount.trim_matches('/');
+                (
+                    mount.to_owned(),
+                    key.strip_prefix(&format!("{}/", mount)).unwrap_or(key),
+                )
+            }
+            None => match key.split_once('/') {
+                Some((mount, key)) => (mount.to_owned(), key),
+                None => (key.to_owned(), ""),
+            },
+        };
+        match self.version.as_str() {
+            "v1" => format!("{}/{}", mount, key),
+            _ => format!("{}/{}/{}", mount, data, key),
+        }
+    }
+
+    /// Returns the secret's key/value data from a `vault read` response.
+    fn data<'a>(&self, response: &'a Value) -> Option<&'a Value> {
+        let data = response.get("data")?;
+        match self.version.as_str() {
+            "v1" => Some(data),
+            _ => data.get("data").filter(|data| !data.is_null()),
+        }
+    }
+}
+
+/// Runs the `vault` CLI, logging in once per server when OIDC is used.
+pub struct Vault {
+    args: VaultArgs,
+    tokens: HashMap<String, String>,
+    responses: HashMap<(String, String), Option<Value>>,
+}
+
+impl Vault {
+    pub fn new(args: VaultArgs) -> Self {
+        Vault {
+            args,
+            tokens: HashMap::new(),
+            responses: HashMap::new(),
+        }
+    }
+
+    fn store(&self, store: &Store) -> Result<VaultStore> {
+        VaultStore::new(store.provider, self.args.vault_address.as_deref())
+    }
+
+    fn vault_command(&mut self, store: &VaultStore, args: &[&str]) -> Result<Command> {
+        let token = match self.args.vault_auth {
+            VaultAuth::Token => self.args.vault_token.clone(),
+            VaultAuth::Oidc => Some(self.oidc_token(store)?),
+        };
+        let mut command = Command::new("vault");
+        command.args(args).env("VAULT_ADDR", &store.address);
+        if let Some(token) = token {
+            command.env("VAULT_TOKEN", token);
+        }
+        if let Some(namespace) = &store.namespace {
+            command.env("VAULT_NAMESPACE", namespace);
+        }
+        Ok(command)
+    }
+
+    fn command(&mut self, store: &VaultStore, args: &[&str]) -> Result<std::process::Output> {
+        self.vault_command(store, args)?
+            .output()
+            .with_context(|| "Failed to run vault, is the Vault CLI installed?")
+    }
+
+    fn oidc_token(&mut self, store: &VaultStore) -> Result<String> {
+        if let Some(token) = self.tokens.get(&store.address) {
+            return Ok(token.clone());
+        }
+        let mut command = Command::new("vault");
+        command
+            .args(["login", "-method=oidc", "-token-only"])
+            .env("VAULT_ADDR", &store.address)
+            .stderr(Stdio::inherit());
+        if let Some(role) = &self.args.vault_role {
+            command.arg(format!("role={}", role));
+        }
+        if let Some(namespace) = &store.namespace {
+            command.env("VAULT_NAMESPACE", namespace);
+        }
+        let output = command
+            .output()
+            .with_context(|| "Failed to run vault, is the Vault CLI installed?")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "OIDC login to {} failed",
+            store.address
+        );
+        let token = String::from_utf8(output.stdout)
+            .with_context(|| "vault login produced invalid UTF-8 output")?
+            .trim()
+            .to_owned();
+        self.tokens.insert(store.address.clone(), token.clone());
+        Ok(token)
+    }
+
+    /// Runs `vault read` or `vault list` on a path, returning None if nothing exists there.
+    fn query(&mut self, store: &VaultStore, operation: &str, path: &str) -> Result<Option<Value>> {
+        let cache_key = (store.address.clone(), format!("{} {}", operation, path));
+        if let Some(response) = self.responses.get(&cache_key) {
+            return Ok(response.clone());
+        }
+        let output = self.command(store, &[operation, "-format=json", path])?;
+        let response =
+            if output.status.success() {
+                Some(serde_json::from_slice(&output.stdout).with_context(|| {
+                    format!("vault {} {} produced invalid JSON", operation, path)
+                })?)
+            } else {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                if !stderr.contains("No value found") {
+                    anyhow::bail!("vault {} {} failed: {}", operation, path, stderr.trim());
+                }
+                None
+            };
+        self.responses.insert(cache_key, response.clone());
+        Ok(response)
+    }
+
+    /// Reads the key/value data at a remote key, returning None if no secret exists there.
+    fn read(&mut self, store: &VaultStore, key: &str) -> Result<Option<Value>> {
+        let response = self.query(store, "read", &store.path(key, "data"))?;
+        Ok(response.and_then(|response| store.data(&response).cloned()))
+    }
+}
+
+impl SecretBackend for Vault {
+    fn exists(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<bool> {
+        let store = self.store(store)?;
+        Ok(self.read(&store, &remote_ref.key)?.is_some())
+    }
+
+    fn list(&mut self, store: &Store, path: Option<&str>) -> Result<Vec<String>> {
+        let store = self.store(store)?;
+        let mut keys = Vec::new();
+        let mut pending = vec![path.unwrap_or_default().trim_matches('/').to_owned()];
+        while let Some(directory) = pending.pop() {
+            let response = self.query(&store, "list", &store.path(&directory, "metadata"))?;
+            let entries = response
+                .as_ref()
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str);
+            for entry in entries {
+                let key = if directory.is_empty() {
+                    entry.to_owned()
+                } else {
+                    format!("{}/{}", directory, entry)
+                };
+                match key.strip_suffix('/') {
+                    Some(subdirectory) => pending.push(subdirectory.to_owned()),
+                    None => keys.push(key),
+                }
+            }
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    fn metadata(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<SecretMetadata> {
+        let store = self.store(store)?;
+        let properties = match remote_ref.property {
+            Some(_) => self
+                .read(&store, &remote_ref.key)?
+                .map(|data| property_paths(&data)),
+            None => None,
+        };
+        Ok(SecretMetadata {
+            properties,
+            ..Default::default()
+        })
+    }
+
+    fn writable(&mut self, store: &Store, remote_ref: &RemoteRef) -> Result<Option<bool>> {
+        let store = self.store(store)?;
+        let path = store.path(&remote_ref.key, "data");
+        let output = self.command(&store, &["token", "capabilities", &path])?;
+        anyhow::ensure!(
+            output.status.success(),
+            "vault token capabilities {} failed: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        let capabilities = String::from_utf8_lossy(&output.stdout);
+        Ok(Some(capabilities.split(',').map(str::trim).any(
+            |capability| ["create", "update", "root"].contains(&capability),
+        )))
+    }
+
+    fn identity(&mut self, store: &Store) -> Result<Option<String>> {
+        let store = self.store(store)?;
+        let output = self.command(&store, &["token", "lookup", "-format=json"])?;
+        anyhow::ensure!(
+            output.status.success(),
+            "vault token lookup on {} failed: {}",
+            store.address,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        let response: Value = serde_json::from_slice(&output.stdout)
+            .with_context(|| "vault token lookup produced invalid JSON")?;
+        Ok(response
+            .pointer("/data/display_name")
+            .and_then(Value::as_str)
+            .map(|name| format!("{} on {}", name, store.address)))
+    }
+
+    fn write(&mut self, store: &Store, key: &str, data: &Map<String, Value>) -> Result<()> {
+        let store = self.store(store)?;
+        let path = store.path(key, "data");
+        let body = match store.version.as_str() {
+            "v1" => Value::Object(data.clone()),
+            _ => json!({ "data": data }),
+        };
+        // Pass the data on stdin rather than as arguments, which other users can see.
+        let mut child = self
+            .vault_command(&store, &["write", "-format=json", &path, "-"])?
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| "Failed to run vault, is the Vault CLI installed?")?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(body.to_string().as_bytes())
+                .with_context(|| format!("Failed to pass the data for {} to vault", path))?;
+        }
+        let output = child
+            .wait_with_output()
+            .with_context(|| "Failed to run vault")?;
+        anyhow::ensure!(
+            output.status.success(),
+            "vault write {} failed: {}",
+            path,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        self.responses.clear();
+        Ok(())
+    }
+}
@@ -0,0 +1,404 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use clap::Args;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::edit::EditedResource;
+use crate::git;
+use crate::system_manifests::SystemManifests;
+
+const REMOTE: &str = "origin";
+
+#[derive(Args, Debug, Clone)]
+pub struct PullRequestArgs {
+    /// Commit the changes on a new branch, push it to the origin remote and open a pull request
+    /// on GitHub, or a merge request on GitLab, listing the platforms and resources changed. The
+    /// token is read from GITHUB_TOKEN or GH_TOKEN, or GITLAB_TOKEN. GITHUB_API_URL and
+    /// CI_API_V4_URL override the API the remote's host implies.
+    #[arg(long)]
+    pub create_pr: bool,
+
+    /// Branch to open the pull request against, defaults to the branch checked out.
+    #[arg(long, requires = "create_pr")]
+    pub pr_base: Option<String>,
+}
+
+/// Changes a command makes to manifest files, with what to tell reviewers about them.
+pub struct FileChanges {
+    /// Name of the command making the changes, which the default branch name starts with.
+    pub command: &'static str,
+    /// Title of the commit and pull request.
+    pub title: String,
+    /// The files to write along with their new contents.
+    pub files: Vec<(PathBuf, String)>,
+    pub resources: Vec<EditedResource>,
+}
+
+/// Where a repository is hosted, as told by the URL of its remote.
+enum Hosting {
+    GitHub { api: String, repository: String },
+    GitLab { api: String, project: String },
+}
+
+/// Returns the host and repository path of a remote URL, like `github.com` and `org/repo` for
+/// `git@github.com:org/repo.git` or `https://github.com/org/repo`.
+fn parse_remote_url(url: &str) -> Option<(String, String)> {
+    let (host, path) = match url.split_once("://") {
+        Some((_, rest)) => {
+            let (authority, path) = rest.split_once('/')?;
+            let host = authority.rsplit('@').next()?;
+            (host.to_owned(), path)
+        }
+        None => {
+            let (authority, path) = url.split_once(':')?;
+            (authority.rsplit('@').next()?.to_owned(), path)
+        }
+    };
+    let path = path.trim_matches('/').trim_end_matches(".git");
+    (!path.is_empty()).then(|| (host, path.to_owned()))
+}
+
+fn hosting(url: &str) -> Result<Hosting> {
+    let (host, path) = parse_remote_url(url)
+        .with_context(|| format!("Can't tell the repository of remote URL {}", url))?;
+    // The port of SSH URLs isn't the one of the web interface.
+    let web_host = host.split(':').next().unwrap_or(&host);
+    if web_host.contains("gitlab") {
+        let api = std::env::var("CI_API_V4_URL")
+            .unwrap_or_else(|_| format!("https://{}/api/v4", web_host));
+        return Ok(Hosting::GitLab { api, project: path });
+    }
+    let api = std::env::var("GITHUB_API_URL").unwrap_or_else(|_| match web_host {
+        "github.com" => "https://api.github.com".to_owned(),
+        _ => format!("https://{}/api/v3", web_host),
+    });
+    Ok(Hosting::GitHub {
+        api,
+        repository: path,
+    })
+}
+
+fn token(variables: &[&str]) -> Result<String> {
+    variables
+        .iter()
+        .find_map(|variable| {
+            std::env::var(variable)
+                .ok()
+                .filter(|token| !token.is_empty())
+        })
+        .with_context(|| {
+            format!(
+                "Opening a pull request requires a token in {}",
+                variables.join(" or ")
+            )
+        })
+}
+
+/// Returns the description of a pull request: the platforms changed and a table of the resources
+/// with their files relative to the system manifests directory.
+fn description(changes: &FileChanges, directory: &Path) -> String {
+    let platforms: BTreeSet<&str> = changes
+        .resources
+        .iter()
+        .map(|resource| resource.platform_name.as_str())
+        .collect();
+    let platforms: Vec<String> = platforms
+        .into_iter()
+        .map(|platform| format!("`{}`", platform))
+        .collect();
+    let mut description = format!(
+        "Platforms: {}\n\n| Platform | Kind | Namespace | Name | File |\n| --- | --- | --- | --- | --- |\n",
+        platforms.join(", ")
+    );
+    for resource in &changes.resources {
+        let file = resource
+            .file
+            .strip_prefix(directory)
+            .unwrap_or(&resource.file);
+        description.push_str(&format!(
+            "| {} | {} | {} | {} | `{}` |\n",
+            resource.platform_name,
+            resource.kind,
+            resource.namespace,
+            resource.name,
+            file.display()
+        ));
+    }
+    description.push_str(&format!(
+        "\nOpened by `dp-secrets-helper {}`.\n",
+        changes.command
+    ));
+    description
+}
+
+/// Returns the token of the API a repository is hosted with.
+fn hosting_token(hosting: &Hosting) -> Result<String> {
+    match hosting {
+        Hosting::GitHub { .. } => token(&["GITHUB_TOKEN", "GH_TOKEN"]),
+        Hosting::GitLab { .. } => token(&["GITLAB_TOKEN"]),
+    }
+}
+
+/// Opens a pull request from a pushed branch, returning its URL.
+fn open(
+    hosting: &Hosting,
+    token: &str,
+    branch: &str,
+    base: &str,
+    title: &str,
+    body: &str,
+) -> Result<String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let (request, payload, url_field) = match hosting {
+        Hosting::GitHub { api, repository } => (
+            agent
+                .post(format!(
+                    "{}/repos/{}/pulls",
+                    api.trim_end_matches('/'),
+                    repository
+                ))
+                .header("Authorization", format!("Bearer {}", token))
+                .header("Accept", "application/vnd.github+json"),
+            json!({ "title": title, "head": branch, "base": base, "body": body }),
+            "html_url",
+        ),
+        Hosting::GitLab { api, project } => (
+            agent
+                .post(format!(
+                    "{}/projects/{}/merge_requests",
+                    api.trim_end_matches('/'),
+                    utf8_percent_encode(project, NON_ALPHANUMERIC)
+                ))
+                .header("PRIVATE-TOKEN", token),
+            json!({
+                "title": title,
+                "source_branch": branch,
+                "target_branch": base,
+                "description": body,
+            }),
+            "web_url",
+        ),
+    };
+    let mut response = request
+        .header("User-Agent", "dp-secrets-helper")
+        .send_json(payload)
+        .with_context(|| "Failed to open pull request")?;
+    let status = response.status();
+    let body: Value = response
+        .body_mut()
+        .read_json()
+        .with_context(|| format!("Invalid response opening pull request ({})", status))?;
+    anyhow::ensure!(
+        status.is_success(),
+        "Failed to open pull request ({}): {}",
+        status,
+        body.get("message").unwrap_or(&body)
+    );
+    body.get(url_field)
+        .and_then(Value::as_str)
+        .map(str::to_owned)
+        .with_context(|| "The response to opening the pull request has no URL")
+}
+
+/// A commit of the changes to the files of one repository, checked before anything is written.
+struct Commit<'a> {
+    directory: &'a Path,
+    changes: FileChanges,
+    /// What's checked out, to check out again on failure.
+    head: String,
+    base: String,
+    /// Where the branch is pushed to and the pull request opened, if one is.
+    hosting: Option<(Hosting, String)>,
+}
+
+impl<'a> Commit<'a> {
+    /// Checks that the changes can be committed on a new branch, and that a pull request can be
+    /// opened if asked.
+    fn check(
+        directory: &'a Path,
+        changes: FileChanges,
+        branch: &str,
+        args: &PullRequestArgs,
+    ) -> Result<Self> {
+        let files: Vec<PathBuf> = changes.files.iter().map(|(file, _)| file.clone()).collect();
+        git::ensure_tracked(directory, &files)?;
+        git::ensure_unchanged(directory, &files)?;
+        anyhow::ensure!(
+            !git::branch_exists(directory, branch)?,
+            "Branch {} already exists",
+            branch
+        );
+        let head = git::head(directory)?;
+        let base = match &args.pr_base {
+            Some(base) => base.clone(),
+            None => git::current_branch(directory)?,
+        };
+        let hosting = if args.create_pr {
+            let hosting = hosting(&git::remote_url(directory, REMOTE)?)?;
+            let token = hosting_token(&hosting)?;
+            Some((hosting, token))
+        } else {
+            None
+        };
+        Ok(Commit {
+            directory,
+            changes,
+            head,
+            base,
+            hosting,
+        })
+    }
+
+    fn apply(&self, branch: &str) -> Result<()> {
+        let directory = self.directory;
+        let changes = &self.changes;
+        let files: Vec<PathBuf> = changes.files.iter().map(|(file, _)| file.clone()).collect();
+        let originals = files
+            .iter()
+            .map(|file| {
+                std::fs::read_to_string(file)
+                    .map(|contents| (file.clone(), contents))
+                    .with_context(|| format!("Failed to read {}", file.display()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        git::create_branch(directory, branch)?;
+        let description = description(changes, directory);
+        let message = format!("{}\n\n{}", changes.title, description);
+        // A failure leaves the repository as it was, on the branch it was on.
+        if let Err(error) =
+            write(&changes.files).and_then(|()| git::commit(directory, &files, &message))
+        {
+            write(&originals)?;
+            git::checkout(directory, &self.head)?;
+            git::delete_branch(directory, branch)?;
+            return Err(error);
+        }
+        eprintln!(
+            "Committed the changes on branch {} in {}",
+            branch,
+            directory.display()
+        );
+        let Some((hosting, token)) = &self.hosting else {
+            return Ok(());
+        };
+
+        let opened = git::push(directory, REMOTE, branch).and_then(|()| {
+            open(
+                hosting,
+                token,
+                branch,
+                &self.base,
+                &changes.title,
+                &description,
+            )
+        });
+        match opened {
+            Ok(url) => {
+                eprintln!("Opened {}", url);
+                Ok(())
+            }
+            Err(error) => {
+                git::checkout(directory, &self.head)?;
+                Err(error.context(format!(
+                    "Failed to open a pull request, the changes are committed on branch {}",
+                    branch
+                )))
+            }
+        }
+    }
+}
+
+/// Splits changes by the system manifests repository their files are in.
+fn by_repository<'a>(
+    system_manifests: &'a SystemManifests,
+    changes: &FileChanges,
+) -> Vec<(&'a Path, FileChanges)> {
+    let mut repositories: Vec<(&Path, FileChanges)> = Vec::new();
+    for (file, contents) in &changes.files {
+        let directory = system_manifests.repository_directory(file);
+        let index = match repositories
+            .iter()
+            .position(|(other, _)| *other == directory)
+        {
+            Some(index) => index,
+            None => {
+                repositories.push((
+                    directory,
+                    FileChanges {
+                        command: changes.command,
+                        title: changes.title.clone(),
+                        files: Vec::new(),
+                        resources: changes
+                            .resources
+                            .iter()
+                            .filter(|resource| {
+                                system_manifests.repository_directory(&resource.file) == directory
+                            })
+                            .cloned()
+                            .collect(),
+                    },
+                ));
+                repositories.len() - 1
+            }
+        };
+        repositories[index]
+            .1
+            .files
+            .push((file.clone(), contents.clone()));
+    }
+    repositories
+}
+
+/// Writes the changed files, committing them on a new branch if one is given or a pull request
+/// is to be opened. That fails if the files have uncommitted changes, so that only the command's
+/// changes are committed. For a pull request, the branch is then pushed and the pull request
+/// opened, printing its URL. Changes to the files of several repositories are committed on a
+/// branch of the same name in each. Everything that can be checked is checked, in every
+/// repository, before a branch is created and the files written.
+pub fn apply(
+    system_manifests: &SystemManifests,
+    changes: &FileChanges,
+    branch: Option<&str>,
+    args: &PullRequestArgs,
+) -> Result<()> {
+    let commit = branch.is_some() || args.create_pr;
+    let branch = branch.map_or_else(
+        || {
+            format!(
+                "dp-secrets-helper/{}-{}",
+                changes.command,
+                Utc::now().format("%Y%m%d-%H%M%S")
+            )
+        },
+        str::to_owned,
+    );
+    if !commit {
+        return write(&changes.files);
+    }
+
+    let commits = by_repository(system_manifests, changes)
+        .into_iter()
+        .map(|(directory, changes)| {
+            Commit::check(directory, changes, &branch, args)
+                .with_context(|| format!("Can't commit the changes in {}", directory.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    for commit in &commits {
+        commit.apply(&branch)?;
+    }
+    Ok(())
+}
+
+fn write(files: &[(PathBuf, String)]) -> Result<()> {
+    for (file, contents) in files {
+        std::fs::write(file, contents)
+            .with_context(|| format!("Failed to write {}", file.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use kube::api::DynamicObject;
+use serde_json::Value;
+
+/// Placeholder written instead of secret values.
+pub const REDACTED: &str = "***REDACTED***";
+
+/// Fields holding secret values, as JSON pointers into a resource: the data of Secrets and the
+/// templated data of the Secrets ExternalSecrets and SealedSecrets create.
+const VALUE_FIELDS: &[&str] = &[
+    "/data",
+    "/stringData",
+    "/spec/target/template/data",
+    "/spec/target/template/stringData",
+    "/spec/template/data",
+    "/spec/template/stringData",
+];
+
+/// Returns a copy of a resource with every value of its data, stringData and templated data
+/// replaced, so the key names remain visible but no secret material is.
+pub fn redacted(resource: &DynamicObject) -> DynamicObject {
+    let mut resource = resource.clone();
+    for field in VALUE_FIELDS {
+        if let Some(Value::Object(values)) = resource.data.pointer_mut(field) {
+            for value in values.values_mut() {
+                *value = Value::String(REDACTED.to_owned());
+            }
+        }
+    }
+    resource
+}
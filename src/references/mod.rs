@@ -0,0 +1,632 @@
+use anyhow::{Context, Result};
+use k8s_openapi::api::core::v1::{
+    EnvFromSource, EnvVar, LocalObjectReference, ObjectReference, PodSpec,
+};
+use k8s_openapi::api::networking::v1::IngressSpec;
+use kube::api::DynamicObject;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use crate::eso;
+use crate::inventory;
+use crate::system_manifests::{FlatManifestResource, ManifestResource};
+
+/// A Kubernetes Secret identified by its namespace and name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, JsonSchema)]
+pub struct SecretName {
+    pub namespace: Option<String>,
+    pub name: String,
+}
+
+impl SecretName {
+    fn new(resource: &DynamicObject, name: impl Into<String>) -> Self {
+        SecretName {
+            namespace: resource.metadata.namespace.clone(),
+            name: name.into(),
+        }
+    }
+}
+
+fn resource_kind(resource: &DynamicObject) -> Option<&str> {
+    resource.types.as_ref().map(|t| t.kind.as_str())
+}
+
+fn resource_field<T: DeserializeOwned>(
+    resource: &DynamicObject,
+    pointer: &str,
+) -> Result<Option<T>> {
+    resource
+        .data
+        .pointer(pointer)
+        .map(|value| serde_json::from_value(value.clone()))
+        .transpose()
+        .with_context(|| {
+            format!(
+                "Failed to parse {} of {} {}",
+                pointer,
+                resource_kind(resource).unwrap_or("resource"),
+                resource.metadata.name.as_deref().unwrap_or("<unnamed>")
+            )
+        })
+}
+
+/// Returns the Secret that the given resource results in, if any.
+pub fn produced_secret(resource: &DynamicObject) -> Option<SecretName> {
+    match resource_kind(resource)? {
+        "Secret" => Some(SecretName::new(resource, resource.metadata.name.clone()?)),
+        "ExternalSecret" => {
+            let target_name = eso::external_secret_spec(resource)
+                .and_then(|spec| spec.target?.name)
+                .or_else(|| resource.metadata.name.clone())?;
+            Some(SecretName::new(resource, target_name))
+        }
+        "SealedSecret" => {
+            let target_name = resource
+                .data
+                .pointer("/spec/template/metadata/name")
+                .and_then(|name| name.as_str())
+                .map(str::to_owned)
+                .or_else(|| resource.metadata.name.clone())?;
+            Some(SecretName::new(resource, target_name))
+        }
+        "Certificate" => {
+            let secret_name = resource
+                .data
+                .pointer("/spec/secretName")
+                .and_then(|name| name.as_str())?;
+            Some(SecretName::new(resource, secret_name))
+        }
+        _ => None,
+    }
+}
+
+fn env_secret_names(
+    env: Option<&Vec<EnvVar>>,
+    env_from: Option<&Vec<EnvFromSource>>,
+    names: &mut Vec<String>,
+) {
+    for env_var in env.into_iter().flatten() {
+        if let Some(secret_key_ref) = env_var
+            .value_from
+            .as_ref()
+            .and_then(|value_from| value_from.secret_key_ref.as_ref())
+        {
+            names.push(secret_key_ref.name.clone());
+        }
+    }
+    for env_from_source in env_from.into_iter().flatten() {
+        if let Some(secret_ref) = &env_from_source.secret_ref {
+            names.push(secret_ref.name.clone());
+        }
+    }
+}
+
+fn pod_spec_secret_names(pod_spec: &PodSpec) -> Vec<String> {
+    let mut names = Vec::new();
+
+    let containers = pod_spec
+        .containers
+        .iter()
+        .chain(pod_spec.init_containers.iter().flatten());
+    for container in containers {
+        env_secret_names(
+            container.env.as_ref(),
+            container.env_from.as_ref(),
+            &mut names,
+        );
+    }
+    for container in pod_spec.ephemeral_containers.iter().flatten() {
+        env_secret_names(
+            container.env.as_ref(),
+            container.env_from.as_ref(),
+            &mut names,
+        );
+    }
+
+    for volume in pod_spec.volumes.iter().flatten() {
+        if let Some(secret_name) = volume
+            .secret
+            .as_ref()
+            .and_then(|secret| secret.secret_name.clone())
+        {
+            names.push(secret_name);
+        }
+        let projected_sources = volume
+            .projected
+            .iter()
+            .flat_map(|projected| projected.sources.iter().flatten());
+        for source in projected_sources {
+            if let Some(secret) = &source.secret {
+                names.push(secret.name.clone());
+            }
+        }
+    }
+
+    for image_pull_secret in pod_spec.image_pull_secrets.iter().flatten() {
+        names.push(image_pull_secret.name.clone());
+    }
+
+    names
+}
+
+/// Annotations of ingress-nginx naming a Secret as `<namespace>/<name>`, or just `<name>` in the
+/// namespace of the Ingress.
+const INGRESS_SECRET_ANNOTATIONS: &[&str] = &[
+    "nginx.ingress.kubernetes.io/auth-secret",
+    "nginx.ingress.kubernetes.io/auth-tls-secret",
+    "nginx.ingress.kubernetes.io/proxy-ssl-secret",
+];
+
+/// Annotation of the cert-manager CA injector naming the Secret, as `<namespace>/<name>`, whose
+/// CA it injects as the caBundle of a webhook configuration, CRD conversion webhook or APIService.
+const INJECT_CA_FROM_SECRET_ANNOTATION: &str = "cert-manager.io/inject-ca-from-secret";
+
+/// Returns the Secret the given annotation of the resource names, if set.
+fn annotated_secret(resource: &DynamicObject, annotation: &str) -> Option<SecretName> {
+    annotated_secret_value(
+        resource,
+        resource.metadata.annotations.as_ref()?.get(annotation)?,
+    )
+}
+
+/// Parses a Secret named as `<namespace>/<name>`, or just `<name>` in the namespace of the
+/// resource.
+fn annotated_secret_value(resource: &DynamicObject, value: &str) -> Option<SecretName> {
+    let value = value.trim();
+    match value.split_once('/') {
+        Some((namespace, name)) if !namespace.is_empty() && !name.is_empty() => Some(SecretName {
+            namespace: Some(namespace.to_owned()),
+            name: name.to_owned(),
+        }),
+        Some(_) => None,
+        None if value.is_empty() => None,
+        None => Some(SecretName::new(resource, value)),
+    }
+}
+
+/// Annotations of kubernetes-replicator and reflector that make a Secret a copy of the Secret they
+/// name as `<namespace>/<name>`.
+const REPLICATE_FROM_ANNOTATIONS: &[&str] = &[
+    "replicator.v1.mittwald.de/replicate-from",
+    "reflector.v1.k8s.emberstack.com/reflects",
+];
+
+const REPLICATE_TO_ANNOTATION: &str = "replicator.v1.mittwald.de/replicate-to";
+const REPLICATE_TO_MATCHING_ANNOTATION: &str = "replicator.v1.mittwald.de/replicate-to-matching";
+const REFLECTION_ALLOWED_ANNOTATION: &str = "reflector.v1.k8s.emberstack.com/reflection-allowed";
+const REFLECTION_ALLOWED_NAMESPACES_ANNOTATION: &str =
+    "reflector.v1.k8s.emberstack.com/reflection-allowed-namespaces";
+const REFLECTION_AUTO_ENABLED_ANNOTATION: &str =
+    "reflector.v1.k8s.emberstack.com/reflection-auto-enabled";
+const REFLECTION_AUTO_NAMESPACES_ANNOTATION: &str =
+    "reflector.v1.k8s.emberstack.com/reflection-auto-namespaces";
+
+/// Returns the annotations of the Secret the given resource results in, which for an
+/// ExternalSecret or SealedSecret are those of its template.
+fn secret_annotations(resource: &DynamicObject) -> Option<BTreeMap<String, String>> {
+    let pointer = match resource_kind(resource)? {
+        "Secret" => return resource.metadata.annotations.clone(),
+        "ExternalSecret" => "/spec/target/template/metadata/annotations",
+        "SealedSecret" => "/spec/template/metadata/annotations",
+        _ => return None,
+    };
+    serde_json::from_value(resource.data.pointer(pointer)?.clone()).ok()
+}
+
+/// Namespaces kubernetes-replicator or reflector copy a Secret to.
+#[derive(Debug, Clone)]
+enum ReplicaNamespaces {
+    /// Any namespace, also used for namespaces selected by labels, which manifests don't tell.
+    All,
+    /// Namespaces matching any of the patterns.
+    Matching(Vec<Regex>),
+}
+
+impl ReplicaNamespaces {
+    /// Parses a comma separated list of namespaces and regular expressions, treating patterns
+    /// that don't parse as plain names.
+    fn parse(list: &str) -> Self {
+        let patterns = list
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .filter_map(|pattern| {
+                Regex::new(&format!("^(?:{})$", pattern))
+                    .or_else(|_| Regex::new(&format!("^{}$", regex::escape(pattern))))
+                    .ok()
+            })
+            .collect();
+        ReplicaNamespaces::Matching(patterns)
+    }
+
+    fn contains(&self, namespace: &str) -> bool {
+        match self {
+            ReplicaNamespaces::All => true,
+            ReplicaNamespaces::Matching(patterns) => {
+                patterns.iter().any(|pattern| pattern.is_match(namespace))
+            }
+        }
+    }
+}
+
+/// Returns the namespaces kubernetes-replicator or reflector copy the Secret the given resource
+/// results in to, if its annotations have them push copies.
+fn replica_namespaces(resource: &DynamicObject) -> Option<ReplicaNamespaces> {
+    let annotations = secret_annotations(resource)?;
+    let annotation = |name: &str| {
+        annotations
+            .get(name)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    if annotation(REPLICATE_TO_MATCHING_ANNOTATION).is_some() {
+        return Some(ReplicaNamespaces::All);
+    }
+    if let Some(list) = annotation(REPLICATE_TO_ANNOTATION) {
+        return Some(ReplicaNamespaces::parse(list));
+    }
+    let enabled = |name: &str| annotation(name) == Some("true");
+    if !enabled(REFLECTION_ALLOWED_ANNOTATION) || !enabled(REFLECTION_AUTO_ENABLED_ANNOTATION) {
+        return None;
+    }
+    // Reflector copies to the allowed namespaces if the automatic ones aren't listed, and to all
+    // if neither is.
+    match annotation(REFLECTION_AUTO_NAMESPACES_ANNOTATION)
+        .or_else(|| annotation(REFLECTION_ALLOWED_NAMESPACES_ANNOTATION))
+    {
+        Some(list) => Some(ReplicaNamespaces::parse(list)),
+        None => Some(ReplicaNamespaces::All),
+    }
+}
+
+/// Returns every Secret the given resource depends on at runtime.
+pub fn referenced_secrets(resource: &DynamicObject) -> Result<Vec<SecretName>> {
+    let mut annotated = Vec::new();
+    let names = match resource_kind(resource) {
+        Some("Pod") => resource_field::<PodSpec>(resource, "/spec")?
+            .map(|pod_spec| pod_spec_secret_names(&pod_spec))
+            .unwrap_or_default(),
+        Some("Deployment" | "StatefulSet" | "DaemonSet" | "ReplicaSet" | "Job") => {
+            resource_field::<PodSpec>(resource, "/spec/template/spec")?
+                .map(|pod_spec| pod_spec_secret_names(&pod_spec))
+                .unwrap_or_default()
+        }
+        Some("CronJob") => {
+            resource_field::<PodSpec>(resource, "/spec/jobTemplate/spec/template/spec")?
+                .map(|pod_spec| pod_spec_secret_names(&pod_spec))
+                .unwrap_or_default()
+        }
+        Some("ServiceAccount") => {
+            let secrets = resource_field::<Vec<ObjectReference>>(resource, "/secrets")?
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|secret| secret.name);
+            let image_pull_secrets =
+                resource_field::<Vec<LocalObjectReference>>(resource, "/imagePullSecrets")?
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|secret| secret.name);
+            secrets.chain(image_pull_secrets).collect()
+        }
+        Some("Ingress") => {
+            annotated.extend(
+                INGRESS_SECRET_ANNOTATIONS
+                    .iter()
+                    .filter_map(|annotation| annotated_secret(resource, annotation)),
+            );
+            resource_field::<IngressSpec>(resource, "/spec")?
+                .and_then(|spec| spec.tls)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|tls| tls.secret_name)
+                .collect()
+        }
+        Some(
+            "MutatingWebhookConfiguration"
+            | "ValidatingWebhookConfiguration"
+            | "CustomResourceDefinition"
+            | "APIService",
+        ) => {
+            // Cluster scoped, so only a Secret with a namespace counts.
+            annotated.extend(
+                annotated_secret(resource, INJECT_CA_FROM_SECRET_ANNOTATION)
+                    .filter(|secret_name| secret_name.namespace.is_some()),
+            );
+            Vec::new()
+        }
+        Some("Secret" | "ExternalSecret" | "SealedSecret") => {
+            // A copy made by kubernetes-replicator or reflector depends on the Secret it copies.
+            if let Some(annotations) = secret_annotations(resource) {
+                annotated.extend(
+                    REPLICATE_FROM_ANNOTATIONS
+                        .iter()
+                        .filter_map(|annotation| annotations.get(*annotation))
+                        .filter_map(|value| annotated_secret_value(resource, value)),
+                );
+            }
+            Vec::new()
+        }
+        _ => Vec::new(),
+    };
+
+    let mut secret_names: Vec<SecretName> = names
+        .into_iter()
+        .map(|name| SecretName::new(resource, name))
+        .chain(annotated)
+        .collect();
+    secret_names.sort();
+    secret_names.dedup();
+    Ok(secret_names)
+}
+
+/// Returns the Secret a PushSecret pushes to its secret store, if any.
+pub fn pushed_secret(resource: &DynamicObject) -> Option<SecretName> {
+    let name = eso::push_secret_spec(resource)?.selector?.secret?.name?;
+    Some(SecretName::new(resource, name))
+}
+
+/// Secrets produced and consumed by the resources of a system manifests repository.
+struct SecretUsage {
+    producers: Vec<(SecretName, ManifestResource)>,
+    pushed: Vec<(SecretName, ManifestResource)>,
+    consumers: Vec<(SecretName, ManifestResource)>,
+    /// Produced Secrets that kubernetes-replicator or reflector copy to other namespaces, by
+    /// platform.
+    replicated: Vec<(String, SecretName, ReplicaNamespaces)>,
+}
+
+impl SecretUsage {
+    fn collect(resources: impl Iterator<Item = Result<ManifestResource>>) -> Result<Self> {
+        let mut usage = SecretUsage {
+            producers: Vec::new(),
+            pushed: Vec::new(),
+            consumers: Vec::new(),
+            replicated: Vec::new(),
+        };
+
+        for manifest_resource_result in resources {
+            let manifest_resource = manifest_resource_result?;
+            let referenced =
+                referenced_secrets(&manifest_resource.resource).with_context(|| {
+                    format!(
+                        "Failed to collect secret references from {}",
+                        manifest_resource.file.display()
+                    )
+                })?;
+            for secret_name in referenced {
+                usage
+                    .consumers
+                    .push((secret_name, manifest_resource.clone()));
+            }
+            if let Some(secret_name) = pushed_secret(&manifest_resource.resource) {
+                usage.pushed.push((secret_name, manifest_resource.clone()));
+            }
+            if let Some(secret_name) = produced_secret(&manifest_resource.resource) {
+                if let Some(namespaces) = replica_namespaces(&manifest_resource.resource) {
+                    usage.replicated.push((
+                        manifest_resource.platform.name.clone(),
+                        secret_name.clone(),
+                        namespaces,
+                    ));
+                }
+                usage.producers.push((secret_name, manifest_resource));
+            }
+        }
+
+        Ok(usage)
+    }
+
+    /// Whether the Secret of the given platform is a copy kubernetes-replicator or reflector
+    /// makes of the original one.
+    fn is_replica(&self, platform_name: &str, secret_name: &SecretName) -> bool {
+        self.replicated
+            .iter()
+            .any(|(platform, original, namespaces)| {
+                platform == platform_name
+                    && original.name == secret_name.name
+                    && original.namespace != secret_name.namespace
+                    && secret_name
+                        .namespace
+                        .as_deref()
+                        .is_some_and(|namespace| namespaces.contains(namespace))
+            })
+    }
+
+    /// Whether a copy of the given Secret that kubernetes-replicator or reflector makes is
+    /// referenced on the platform.
+    fn has_referenced_replica(&self, platform_name: &str, secret_name: &SecretName) -> bool {
+        self.consumers
+            .iter()
+            .any(|(referenced, manifest_resource)| {
+                manifest_resource.platform.name == platform_name
+                    && referenced.name == secret_name.name
+                    && self
+                        .replicated
+                        .iter()
+                        .any(|(platform, original, namespaces)| {
+                            platform == platform_name
+                                && original == secret_name
+                                && referenced.namespace != original.namespace
+                                && referenced
+                                    .namespace
+                                    .as_deref()
+                                    .is_some_and(|namespace| namespaces.contains(namespace))
+                        })
+            })
+    }
+}
+
+fn secret_names_by_platform<'a>(
+    entries: impl Iterator<Item = &'a (SecretName, ManifestResource)>,
+) -> HashMap<String, BTreeSet<SecretName>> {
+    let mut secret_names: HashMap<String, BTreeSet<SecretName>> = HashMap::new();
+    for (secret_name, manifest_resource) in entries {
+        secret_names
+            .entry(manifest_resource.platform.name.clone())
+            .or_default()
+            .insert(secret_name.clone());
+    }
+    secret_names
+}
+
+fn contains_secret(
+    secret_names: &HashMap<String, BTreeSet<SecretName>>,
+    platform_name: &str,
+    secret_name: &SecretName,
+) -> bool {
+    secret_names
+        .get(platform_name)
+        .is_some_and(|platform_secret_names| platform_secret_names.contains(secret_name))
+}
+
+/// Returns the Secret and ExternalSecret resources whose resulting Secret is not referenced by
+/// any workload, ServiceAccount, Ingress or webhook configuration of the same platform, neither
+/// directly nor through a copy kubernetes-replicator or reflector makes in another namespace.
+pub fn find_orphans(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+) -> Result<Vec<ManifestResource>> {
+    let usage = SecretUsage::collect(resources)?;
+    let references = secret_names_by_platform(usage.consumers.iter());
+
+    Ok(usage
+        .producers
+        .iter()
+        .filter(|(secret_name, manifest_resource)| {
+            let platform_name = &manifest_resource.platform.name;
+            !contains_secret(&references, platform_name, secret_name)
+                && !usage.has_referenced_replica(platform_name, secret_name)
+        })
+        .map(|(_, manifest_resource)| manifest_resource.clone())
+        .collect())
+}
+
+/// A reference to a Secret that nothing on the platform produces.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct MissingSecret {
+    pub secret: SecretName,
+    pub referenced_by: FlatManifestResource,
+}
+
+/// Returns the secret references of workloads, ServiceAccounts, Ingresses and webhook
+/// configurations that no Secret, ExternalSecret, SealedSecret or Certificate target or
+/// PushSecret of the same platform accounts for, directly or by kubernetes-replicator or reflector
+/// copying it from another namespace.
+pub fn find_missing(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+) -> Result<Vec<MissingSecret>> {
+    let usage = SecretUsage::collect(resources)?;
+    let known = secret_names_by_platform(usage.producers.iter().chain(usage.pushed.iter()));
+
+    Ok(usage
+        .consumers
+        .iter()
+        .filter(|(secret_name, manifest_resource)| {
+            let platform_name = &manifest_resource.platform.name;
+            !contains_secret(&known, platform_name, secret_name)
+                && !usage.is_replica(platform_name, secret_name)
+        })
+        .map(|(secret, manifest_resource)| MissingSecret {
+            secret: secret.clone(),
+            referenced_by: manifest_resource.clone().into(),
+        })
+        .collect())
+}
+
+/// A Secret that several resources of a platform result in, which makes Flux or ArgoCD keep
+/// overwriting one with the other.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct DuplicateSecret {
+    pub platform_name: String,
+    pub secret: SecretName,
+    /// How many resources result in the Secret.
+    pub count: usize,
+    pub components: Vec<String>,
+    pub files: Vec<PathBuf>,
+    /// Keys that several of the ExternalSecrets merging into the Secret set, whose value depends
+    /// on which of them synced last.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub conflicting_keys: Vec<ConflictingKey>,
+}
+
+/// A key of a Secret that several ExternalSecrets write, with `creationPolicy: Merge`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ConflictingKey {
+    pub key: String,
+    /// Names of the ExternalSecrets setting the key.
+    pub sources: Vec<String>,
+}
+
+/// Returns the keys that more than one of the ExternalSecrets resulting in a Secret set, if any
+/// of them merges into the Secret rather than owning it alone.
+fn conflicting_keys(manifest_resources: &[ManifestResource]) -> Vec<ConflictingKey> {
+    let external_secrets: Vec<&DynamicObject> = manifest_resources
+        .iter()
+        .map(|manifest_resource| &manifest_resource.resource)
+        .filter(|resource| resource_kind(resource) == Some("ExternalSecret"))
+        .collect();
+    let merges = external_secrets.iter().any(|resource| {
+        eso::external_secret_spec(resource)
+            .and_then(|spec| spec.target?.creation_policy)
+            .is_some_and(|policy| policy == "Merge")
+    });
+    if !merges {
+        return Vec::new();
+    }
+
+    let mut sources: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for resource in external_secrets {
+        let name = resource.metadata.name.clone().unwrap_or_default();
+        for key in inventory::secret_key_names(resource).unwrap_or_default() {
+            sources.entry(key).or_default().push(name.clone());
+        }
+    }
+    sources
+        .into_iter()
+        .filter(|(_, sources)| sources.len() > 1)
+        .map(|(key, sources)| ConflictingKey { key, sources })
+        .collect()
+}
+
+/// Returns the Secrets that more than one Secret, ExternalSecret, SealedSecret or Certificate of
+/// the same platform results in, whether in different components, files or documents, along
+/// with the keys ExternalSecrets merging into them conflict on.
+pub fn find_duplicates(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+) -> Result<Vec<DuplicateSecret>> {
+    let mut declarations: BTreeMap<(String, SecretName), Vec<ManifestResource>> = BTreeMap::new();
+    for manifest_resource_result in resources {
+        let manifest_resource = manifest_resource_result?;
+        if let Some(secret_name) = produced_secret(&manifest_resource.resource) {
+            declarations
+                .entry((manifest_resource.platform.name.clone(), secret_name))
+                .or_default()
+                .push(manifest_resource);
+        }
+    }
+
+    Ok(declarations
+        .into_iter()
+        .filter(|(_, manifest_resources)| manifest_resources.len() > 1)
+        .map(|((platform_name, secret), manifest_resources)| {
+            let components: BTreeSet<String> = manifest_resources
+                .iter()
+                .map(|manifest_resource| manifest_resource.component.name.clone())
+                .collect();
+            let files: BTreeSet<PathBuf> = manifest_resources
+                .iter()
+                .map(|manifest_resource| manifest_resource.file.clone())
+                .collect();
+            DuplicateSecret {
+                platform_name,
+                secret,
+                count: manifest_resources.len(),
+                components: components.into_iter().collect(),
+                files: files.into_iter().collect(),
+                conflicting_keys: conflicting_keys(&manifest_resources),
+            }
+        })
+        .collect())
+}
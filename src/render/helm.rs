@@ -0,0 +1,202 @@
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::process::Command;
+
+use crate::system_manifests::{InvalidManifest, ManifestResource};
+
+fn kind(manifest_resource: &ManifestResource) -> Option<(&str, &str)> {
+    manifest_resource
+        .resource
+        .types
+        .as_ref()
+        .map(|t| (t.api_version.as_str(), t.kind.as_str()))
+}
+
+/// Returns whether a resource is a Flux HelmRelease.
+pub fn is_helm_release(manifest_resource: &ManifestResource) -> bool {
+    kind(manifest_resource).is_some_and(|(api_version, kind)| {
+        kind == "HelmRelease" && api_version.starts_with("helm.toolkit.fluxcd.io/")
+    })
+}
+
+/// The URLs of the Flux HelmRepositories of a platform, by namespace and name.
+#[derive(Debug, Default)]
+pub struct HelmRepositories {
+    urls: HashMap<(Option<String>, String), String>,
+}
+
+impl HelmRepositories {
+    pub fn collect<'a>(manifest_resources: impl IntoIterator<Item = &'a ManifestResource>) -> Self {
+        let urls = manifest_resources
+            .into_iter()
+            .filter(|manifest_resource| {
+                kind(manifest_resource).is_some_and(|(api_version, kind)| {
+                    kind == "HelmRepository" && api_version.starts_with("source.toolkit.fluxcd.io/")
+                })
+            })
+            .filter_map(|manifest_resource| {
+                let resource = &manifest_resource.resource;
+                let url = resource.data.pointer("/spec/url")?.as_str()?;
+                Some((
+                    (
+                        resource.metadata.namespace.clone(),
+                        resource.metadata.name.clone()?,
+                    ),
+                    url.to_owned(),
+                ))
+            })
+            .collect();
+        HelmRepositories { urls }
+    }
+}
+
+fn invalid(release: &ManifestResource, message: String) -> anyhow::Error {
+    let metadata = &release.resource.metadata;
+    InvalidManifest {
+        file: release.file.clone(),
+        position: None,
+        message: format!(
+            "HelmRelease {}/{}: {}",
+            metadata.namespace.as_deref().unwrap_or_default(),
+            metadata.name.as_deref().unwrap_or_default(),
+            message
+        ),
+    }
+    .into()
+}
+
+/// Returns the chart argument of `helm template` for a HelmRelease, along with the repository
+/// to pass with `--repo` if any.
+///
+/// Charts from HelmRepositories are fetched from the repository's URL. Charts from
+/// GitRepositories and Buckets are looked up as paths within the system manifests directory.
+fn chart_source(
+    release: &ManifestResource,
+    repositories: &HelmRepositories,
+    directory: &Path,
+) -> Result<(String, Option<String>)> {
+    let chart_spec = release.resource.data.pointer("/spec/chart/spec");
+    let chart = chart_spec
+        .and_then(|spec| spec.get("chart"))
+        .and_then(Value::as_str)
+        .ok_or_else(|| invalid(release, "spec.chart.spec.chart is required".to_owned()))?;
+    let source_ref = chart_spec.and_then(|spec| spec.get("sourceRef"));
+    let source_kind = source_ref
+        .and_then(|source_ref| source_ref.get("kind"))
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    match source_kind {
+        "HelmRepository" => {
+            let name = source_ref
+                .and_then(|source_ref| source_ref.get("name"))
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned();
+            let namespace = source_ref
+                .and_then(|source_ref| source_ref.get("namespace"))
+                .and_then(Value::as_str)
+                .map(str::to_owned)
+                .or_else(|| release.resource.metadata.namespace.clone());
+            let url = repositories
+                .urls
+                .get(&(namespace, name.clone()))
+                .ok_or_else(|| {
+                    invalid(
+                        release,
+                        format!("HelmRepository {} not found on the platform", name),
+                    )
+                })?;
+            if url.starts_with("oci://") {
+                Ok((format!("{}/{}", url.trim_end_matches('/'), chart), None))
+            } else {
+                Ok((chart.to_owned(), Some(url.clone())))
+            }
+        }
+        "GitRepository" | "Bucket" => {
+            let path = directory.join(chart);
+            if !path.is_dir() {
+                return Err(invalid(
+                    release,
+                    format!("Chart {} not found in {}", chart, directory.display()),
+                ));
+            }
+            Ok((path.to_string_lossy().into_owned(), None))
+        }
+        kind => Err(invalid(
+            release,
+            format!("Unsupported chart source kind {:?}", kind),
+        )),
+    }
+}
+
+/// Renders the chart of a HelmRelease with `helm template`, with its inline values.
+///
+/// Fails with an [`InvalidManifest`] for the HelmRelease if the chart can't be rendered. Values
+/// from ConfigMaps and Secrets (`spec.valuesFrom`) are not read.
+pub fn helm_template(
+    release: &ManifestResource,
+    repositories: &HelmRepositories,
+    directory: &Path,
+) -> Result<String> {
+    let (chart, repository) = chart_source(release, repositories, directory)?;
+    let resource = &release.resource;
+    let spec = resource.data.get("spec");
+    let spec_str = |field: &str| {
+        spec.and_then(|spec| spec.get(field))
+            .and_then(Value::as_str)
+    };
+    let name = resource.metadata.name.as_deref().unwrap_or_default();
+    let target_namespace = spec_str("targetNamespace");
+    // Flux names releases after the HelmRelease, prefixed with the target namespace if set.
+    let release_name = match (spec_str("releaseName"), target_namespace) {
+        (Some(release_name), _) => release_name.to_owned(),
+        (None, Some(target_namespace)) => format!("{}-{}", target_namespace, name),
+        (None, None) => name.to_owned(),
+    };
+
+    let mut command = Command::new("helm");
+    command.args(["template", &release_name, &chart]);
+    if let Some(repository) = &repository {
+        command.args(["--repo", repository]);
+    }
+    if let Some(version) = resource
+        .data
+        .pointer("/spec/chart/spec/version")
+        .and_then(Value::as_str)
+        .filter(|version| *version != "*")
+    {
+        command.args(["--version", version]);
+    }
+    if let Some(namespace) = target_namespace.or(resource.metadata.namespace.as_deref()) {
+        command.args(["--namespace", namespace]);
+    }
+    let mut values_file = tempfile::NamedTempFile::new()
+        .with_context(|| "Failed to create a temporary values file")?;
+    if let Some(values) = spec.and_then(|spec| spec.get("values")) {
+        serde_yaml::to_writer(&mut values_file, values)?;
+        values_file.flush()?;
+        command.arg("--values").arg(values_file.path());
+    }
+
+    let output = match command.output() {
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            anyhow::bail!("Rendering HelmReleases requires helm to be installed")
+        }
+        output => output.with_context(|| "Failed to run helm template")?,
+    };
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(invalid(
+            release,
+            if message.is_empty() {
+                format!("helm template failed with {}", output.status)
+            } else {
+                message
+            },
+        ));
+    }
+    String::from_utf8(output.stdout).with_context(|| "helm produced invalid UTF-8 output")
+}
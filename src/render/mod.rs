@@ -0,0 +1,69 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use crate::system_manifests::InvalidManifest;
+
+pub mod helm;
+
+/// How to render component manifests before reading resources from them.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Render {
+    /// Build directories with a kustomization, reading the plain manifests elsewhere as they are.
+    Kustomize,
+    /// Add the resources rendered from the charts of Flux HelmReleases with `helm template`.
+    Helm,
+}
+
+/// File names kustomize recognizes as a kustomization.
+const KUSTOMIZATION_FILE_NAMES: [&str; 3] =
+    ["kustomization.yaml", "kustomization.yml", "Kustomization"];
+
+/// Returns whether a file is named like a kustomization file.
+pub fn is_kustomization_file(file: &Path) -> bool {
+    file.file_name()
+        .is_some_and(|name| KUSTOMIZATION_FILE_NAMES.iter().any(|other| name == *other))
+}
+
+/// Returns the kustomization file of a directory, if it has one.
+pub fn kustomization_file(directory: &Path) -> Option<PathBuf> {
+    KUSTOMIZATION_FILE_NAMES
+        .iter()
+        .map(|name| directory.join(name))
+        .find(|file| file.is_file())
+}
+
+fn run(program: &str, args: &[&str], directory: &Path) -> std::io::Result<Output> {
+    Command::new(program).args(args).arg(directory).output()
+}
+
+/// Builds the kustomization in a directory, with `kustomize build` or else `kubectl kustomize`.
+///
+/// Fails with an [`InvalidManifest`] for the kustomization file if the build fails.
+pub fn kustomize_build(directory: &Path, kustomization_file: &Path) -> Result<String> {
+    let output = match run("kustomize", &["build"], directory) {
+        Err(error) if error.kind() == ErrorKind::NotFound => {
+            run("kubectl", &["kustomize"], directory)
+                .with_context(|| "Failed to run kustomize or kubectl kustomize")?
+        }
+        output => output.with_context(|| "Failed to run kustomize")?,
+    };
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(InvalidManifest {
+            file: kustomization_file.to_owned(),
+            position: None,
+            message: if message.is_empty() {
+                format!("kustomize build failed with {}", output.status)
+            } else {
+                message
+            },
+        }
+        .into());
+    }
+    String::from_utf8(output.stdout).with_context(|| "kustomize produced invalid UTF-8 output")
+}
@@ -0,0 +1,303 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+
+use crate::findings::{Finding, Severity};
+use crate::git::GitInfo;
+use crate::stats::{Scope, SecretCounts};
+use crate::system_manifests::ManifestResource;
+
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum ReportFormat {
+    Html,
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1, h2 { font-weight: normal; }
+table { border-collapse: collapse; margin-bottom: 2em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }
+th { background: #f0f0f0; }
+td.count { text-align: right; }
+tr.error td { background: #fde8e8; }
+tr.warning td { background: #fdf6e3; }
+input.filter { margin-bottom: 0.5em; padding: 0.3em; width: 20em; }
+";
+
+/// Hides the rows of a table that don't contain the text of its filter input.
+const SCRIPT: &str = "\
+document.querySelectorAll('input.filter').forEach(function (input) {
+  input.addEventListener('input', function () {
+    var text = input.value.toLowerCase();
+    document.getElementById(input.dataset.table).querySelectorAll('tbody tr').forEach(function (row) {
+      row.hidden = !row.textContent.toLowerCase().includes(text);
+    });
+  });
+});
+";
+
+/// Escapes text for HTML and XML content and attribute values.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn relative<'a>(file: &'a Path, directory: &Path) -> std::borrow::Cow<'a, str> {
+    file.strip_prefix(directory)
+        .unwrap_or(file)
+        .to_string_lossy()
+}
+
+fn location(file: &Path, line: Option<usize>, directory: &Path) -> String {
+    match line {
+        Some(line) => format!("{}:{}", relative(file, directory), line),
+        None => relative(file, directory).into_owned(),
+    }
+}
+
+fn write_row(writer: &mut impl Write, class: Option<&str>, cells: &[String]) -> Result<()> {
+    match class {
+        Some(class) => write!(writer, "<tr class=\"{}\">", class)?,
+        None => write!(writer, "<tr>")?,
+    }
+    for cell in cells {
+        write!(writer, "<td>{}</td>", escape(cell))?;
+    }
+    writeln!(writer, "</tr>")?;
+    Ok(())
+}
+
+fn write_table_start(
+    writer: &mut impl Write,
+    id: &str,
+    headers: &[&str],
+    filterable: bool,
+) -> Result<()> {
+    if filterable {
+        writeln!(
+            writer,
+            "<input class=\"filter\" type=\"search\" placeholder=\"Filter\" data-table=\"{}\">",
+            escape(id)
+        )?;
+    }
+    write!(writer, "<table id=\"{}\"><thead><tr>", escape(id))?;
+    for header in headers {
+        write!(writer, "<th>{}</th>", escape(header))?;
+    }
+    writeln!(writer, "</tr></thead><tbody>")?;
+    Ok(())
+}
+
+fn write_counts(writer: &mut impl Write, counts: &[SecretCounts]) -> Result<()> {
+    writeln!(writer, "<h2>Counts</h2>")?;
+    write_table_start(
+        writer,
+        "counts",
+        &[
+            "Platform",
+            "Secret",
+            "ExternalSecret",
+            "PushSecret",
+            "SealedSecret",
+            "Certificate",
+            "Total",
+        ],
+        false,
+    )?;
+    let rows = counts
+        .iter()
+        .filter(|counts| counts.scope == Scope::Platform)
+        .chain(counts.iter().filter(|counts| counts.scope == Scope::Total));
+    for counts in rows {
+        let name = match counts.scope {
+            Scope::Total => "All platforms",
+            _ => &counts.name,
+        };
+        write!(writer, "<tr><td>{}</td>", escape(name))?;
+        for count in [
+            counts.secret,
+            counts.external_secret,
+            counts.push_secret,
+            counts.sealed_secret,
+            counts.certificate,
+            counts.total,
+        ] {
+            write!(writer, "<td class=\"count\">{}</td>", count)?;
+        }
+        writeln!(writer, "</tr>")?;
+    }
+    writeln!(writer, "</tbody></table>")?;
+    Ok(())
+}
+
+fn write_secrets(
+    writer: &mut impl Write,
+    manifest_resources: &[ManifestResource],
+    directory: &Path,
+) -> Result<()> {
+    let mut platforms: BTreeMap<&str, Vec<&ManifestResource>> = BTreeMap::new();
+    for manifest_resource in manifest_resources {
+        platforms
+            .entry(&manifest_resource.platform.name)
+            .or_default()
+            .push(manifest_resource);
+    }
+    for (index, (platform_name, manifest_resources)) in platforms.iter().enumerate() {
+        writeln!(writer, "<h2>Secrets of {}</h2>", escape(platform_name))?;
+        write_table_start(
+            writer,
+            &format!("secrets-{}", index + 1),
+            &["Kind", "Namespace", "Name", "Component", "File"],
+            true,
+        )?;
+        for manifest_resource in manifest_resources {
+            let resource = &manifest_resource.resource;
+            write_row(
+                writer,
+                None,
+                &[
+                    resource
+                        .types
+                        .as_ref()
+                        .map(|t| t.kind.clone())
+                        .unwrap_or_default(),
+                    resource.metadata.namespace.clone().unwrap_or_default(),
+                    resource.metadata.name.clone().unwrap_or_default(),
+                    manifest_resource.component.name.clone(),
+                    location(&manifest_resource.file, manifest_resource.line, directory),
+                ],
+            )?;
+        }
+        writeln!(writer, "</tbody></table>")?;
+    }
+    Ok(())
+}
+
+fn write_findings(writer: &mut impl Write, findings: &[Finding], directory: &Path) -> Result<()> {
+    writeln!(writer, "<h2>Findings</h2>")?;
+    if findings.is_empty() {
+        writeln!(writer, "<p>No findings.</p>")?;
+        return Ok(());
+    }
+    write_table_start(
+        writer,
+        "findings",
+        &[
+            "Severity",
+            "Rule",
+            "Platform",
+            "Kind",
+            "Namespace",
+            "Name",
+            "Message",
+            "File",
+        ],
+        true,
+    )?;
+    for finding in findings {
+        let severity = match finding.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write_row(
+            writer,
+            Some(severity),
+            &[
+                severity.to_owned(),
+                finding.rule.clone(),
+                finding.platform_name.clone(),
+                finding.kind.clone().unwrap_or_default(),
+                finding.namespace.clone().unwrap_or_default(),
+                finding.name.clone().unwrap_or_default(),
+                finding.message.clone(),
+                location(&finding.file, finding.line, directory),
+            ],
+        )?;
+    }
+    writeln!(writer, "</tbody></table>")?;
+    Ok(())
+}
+
+/// Writes a standalone HTML page with the secret counts per platform, filterable tables of the
+/// secret resources of each platform and the findings about them.
+fn write_html(
+    writer: &mut impl Write,
+    manifest_resources: &[ManifestResource],
+    counts: &[SecretCounts],
+    findings: &[Finding],
+    directory: &Path,
+    git_info: Option<&GitInfo>,
+) -> Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html lang=\"en\">")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>Secrets inventory</title>")?;
+    writeln!(writer, "<style>\n{}</style>", STYLE)?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<h1>Secrets inventory</h1>")?;
+    writeln!(
+        writer,
+        "<p>{} secret resources, {} findings in {}.</p>",
+        manifest_resources.len(),
+        findings.len(),
+        escape(&directory.to_string_lossy())
+    )?;
+    if let Some(git_info) = git_info {
+        write!(
+            writer,
+            "<p>Commit <code>{}</code>",
+            escape(&git_info.commit)
+        )?;
+        if let Some(branch) = &git_info.branch {
+            write!(writer, " on branch <code>{}</code>", escape(branch))?;
+        }
+        if git_info.dirty {
+            write!(writer, ", with uncommitted changes")?;
+        }
+        writeln!(writer, ".</p>")?;
+    }
+    write_counts(writer, counts)?;
+    write_findings(writer, findings, directory)?;
+    write_secrets(writer, manifest_resources, directory)?;
+    writeln!(writer, "<script>\n{}</script>", SCRIPT)?;
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")?;
+    Ok(())
+}
+
+/// Prints a report of the secret resources, their counts and the findings about them.
+pub fn write_report(
+    manifest_resources: &[ManifestResource],
+    counts: &[SecretCounts],
+    findings: &[Finding],
+    directory: &Path,
+    git_info: Option<&GitInfo>,
+    format: ReportFormat,
+) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut writer = std::io::BufWriter::new(stdout.lock());
+    match format {
+        ReportFormat::Html => write_html(
+            &mut writer,
+            manifest_resources,
+            counts,
+            findings,
+            directory,
+            git_info,
+        ),
+    }
+}
@@ -0,0 +1,112 @@
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+
+use crate::annotate::{self, MetadataChanges};
+use crate::edit;
+use crate::pull_request::{self, FileChanges, PullRequestArgs};
+use crate::selector::LabelSelector;
+use crate::system_manifests::{ManifestResource, SystemManifests};
+
+/// Which ExternalSecrets to rotate and how to record it.
+pub struct RotateOptions<'a> {
+    /// Names of the ExternalSecrets, as `<name>` or `<namespace>/<name>`. All ExternalSecrets
+    /// matching the other options if empty.
+    pub names: &'a [String],
+    pub namespaces: &'a [String],
+    pub selector: Option<&'a LabelSelector>,
+    /// Annotation to set to the time of the rotation.
+    pub annotation: &'a str,
+    /// Branch to commit on, a name with the time of the rotation if not given.
+    pub branch: Option<&'a str>,
+    /// Title of the commit, and the pull request if one is opened.
+    pub message: Option<&'a str>,
+    pub dry_run: bool,
+    pub pull_request: &'a PullRequestArgs,
+}
+
+fn is_selected(manifest_resource: &ManifestResource, names: &[String]) -> bool {
+    let resource = &manifest_resource.resource;
+    if resource.types.as_ref().map(|t| t.kind.as_str()) != Some("ExternalSecret") {
+        return false;
+    }
+    let name = resource.metadata.name.as_deref().unwrap_or_default();
+    let namespace = resource.metadata.namespace.as_deref().unwrap_or("default");
+    names.is_empty()
+        || names.iter().any(|selected| match selected.split_once('/') {
+            Some((selected_namespace, selected_name)) => {
+                selected_namespace == namespace && selected_name == name
+            }
+            None => selected == name,
+        })
+}
+
+/// Makes ExternalSecrets sync their Secrets again by setting an annotation to the current time,
+/// editing their manifests in place and committing them on a new branch, ready for a pull
+/// request or opening one. For a dry run, the changes are printed as a unified diff instead.
+pub fn rotate(system_manifests: &SystemManifests, options: &RotateOptions) -> Result<()> {
+    let now = Utc::now();
+    let metadata_changes = MetadataChanges {
+        labels: Vec::new(),
+        annotations: vec![(
+            options.annotation.to_owned(),
+            now.to_rfc3339_opts(SecondsFormat::Secs, true),
+        )],
+    };
+    let annotated = annotate::annotate(
+        system_manifests,
+        options.namespaces,
+        options.selector,
+        |manifest_resource| is_selected(manifest_resource, options.names),
+        &metadata_changes,
+    )?;
+    let resources: usize = annotated.iter().map(|file| file.resources.len()).sum();
+    anyhow::ensure!(resources > 0, "No ExternalSecrets to rotate");
+
+    if options.dry_run {
+        for file in &annotated {
+            print!(
+                "{}",
+                edit::unified_diff(&file.file, &file.original, &file.contents)
+            );
+        }
+        eprintln!(
+            "Would rotate {} ExternalSecrets in {} files",
+            resources,
+            annotated.len()
+        );
+        return Ok(());
+    }
+
+    let branch = options.branch.map_or_else(
+        || format!("rotate-secrets-{}", now.format("%Y%m%d-%H%M%S")),
+        str::to_owned,
+    );
+    let title = options.message.map_or_else(
+        || format!("Rotate {} ExternalSecrets", resources),
+        str::to_owned,
+    );
+    let changes = FileChanges {
+        command: "rotate",
+        title,
+        files: annotated
+            .iter()
+            .map(|file| (file.file.clone(), file.contents.clone()))
+            .collect(),
+        resources: annotated
+            .into_iter()
+            .flat_map(|file| file.resources)
+            .collect(),
+    };
+    pull_request::apply(
+        system_manifests,
+        &changes,
+        Some(&branch),
+        options.pull_request,
+    )?;
+    eprintln!(
+        "Rotated {} ExternalSecrets in {} files",
+        resources,
+        changes.files.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,287 @@
+use anyhow::{Context, Result};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::PathBuf;
+
+use crate::inventory::{remote_refs, store_refs};
+use crate::references::{produced_secret, pushed_secret, referenced_secrets, SecretName};
+use crate::system_manifests::{ManifestResource, SystemManifests};
+
+/// What to do in a step of a rotation plan, in the order the steps are taken on a platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum RotationAction {
+    /// Rotate the credential at the remote keys an ExternalSecret reads.
+    Rotate,
+    /// Change the value in the manifest of a plain Secret.
+    Edit,
+    /// Seal the new value into a SealedSecret.
+    Reseal,
+    /// Have cert-manager issue a new certificate.
+    Renew,
+    /// Force an ExternalSecret to sync the rotated value into its Secret.
+    Refresh,
+    /// Force a PushSecret to push the rotated Secret to its stores.
+    Push,
+    /// Restart a workload so that its pods read the rotated Secret.
+    Restart,
+    /// Check that a resource that reads the Secret live picks up the rotated value.
+    Check,
+}
+
+/// A step of a rotation plan.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct RotationStep {
+    pub step: usize,
+    pub platform_name: String,
+    pub action: RotationAction,
+    pub kind: String,
+    pub namespace: String,
+    pub name: String,
+    /// The remote keys or Secret the step is about.
+    pub target: String,
+    /// Command that carries out the step, if it can be done with one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    pub component_name: String,
+    pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+}
+
+fn kind(manifest_resource: &ManifestResource) -> &str {
+    manifest_resource
+        .resource
+        .types
+        .as_ref()
+        .map_or("", |t| t.kind.as_str())
+}
+
+fn namespace(manifest_resource: &ManifestResource) -> &str {
+    manifest_resource
+        .resource
+        .metadata
+        .namespace
+        .as_deref()
+        .unwrap_or("default")
+}
+
+fn name(manifest_resource: &ManifestResource) -> &str {
+    manifest_resource
+        .resource
+        .metadata
+        .name
+        .as_deref()
+        .unwrap_or_default()
+}
+
+/// Returns whether a Secret name given as `<name>` or `<namespace>/<name>` is the one given.
+fn is_named(secret_name: &SecretName, target: &str) -> bool {
+    match target.split_once('/') {
+        Some((namespace, name)) => {
+            secret_name.name == name
+                && secret_name.namespace.as_deref().unwrap_or("default") == namespace
+        }
+        None => secret_name.name == target,
+    }
+}
+
+/// Returns the action that carries the rotated credential into the Secret a resource produces.
+fn producer_action(manifest_resource: &ManifestResource) -> RotationAction {
+    match kind(manifest_resource) {
+        "ExternalSecret" => RotationAction::Rotate,
+        "SealedSecret" => RotationAction::Reseal,
+        "Certificate" => RotationAction::Renew,
+        _ => RotationAction::Edit,
+    }
+}
+
+/// Returns the action that makes a consumer of a Secret pick up its rotated value, with the
+/// command for it. Workloads mount Secrets when their pods start, while Jobs and CronJobs pick
+/// them up on their next run and Ingresses and ServiceAccounts read them live.
+fn consumer_action(manifest_resource: &ManifestResource) -> (RotationAction, Option<String>) {
+    let (namespace, name) = (namespace(manifest_resource), name(manifest_resource));
+    match kind(manifest_resource) {
+        kind @ ("Deployment" | "StatefulSet" | "DaemonSet") => (
+            RotationAction::Restart,
+            Some(format!(
+                "kubectl -n {} rollout restart {}/{}",
+                namespace,
+                kind.to_lowercase(),
+                name
+            )),
+        ),
+        kind @ ("Pod" | "ReplicaSet") => (
+            RotationAction::Restart,
+            Some(format!(
+                "kubectl -n {} delete {} {}",
+                namespace,
+                kind.to_lowercase(),
+                name
+            )),
+        ),
+        _ => (RotationAction::Check, None),
+    }
+}
+
+fn step(
+    manifest_resource: &ManifestResource,
+    action: RotationAction,
+    target: String,
+    command: Option<String>,
+) -> RotationStep {
+    RotationStep {
+        step: 0,
+        platform_name: manifest_resource.platform.name.clone(),
+        action,
+        kind: kind(manifest_resource).to_owned(),
+        namespace: namespace(manifest_resource).to_owned(),
+        name: name(manifest_resource).to_owned(),
+        target,
+        command,
+        component_name: manifest_resource.component.name.clone(),
+        file: manifest_resource.file.clone(),
+        line: manifest_resource.line,
+    }
+}
+
+/// Plans the rotation of a credential, given as a remote key ExternalSecrets read or the name of
+/// the Secret it's in, as `<name>` or `<namespace>/<name>`.
+///
+/// For every platform, the plan first changes the credential where it comes from, then refreshes
+/// the ExternalSecrets and PushSecrets carrying it and finally restarts or checks the workloads,
+/// ServiceAccounts and Ingresses using the Secrets it ends up in.
+pub fn rotate_plan(system_manifests: &SystemManifests, target: &str) -> Result<Vec<RotationStep>> {
+    let mut producers = Vec::new();
+    let mut pushers = Vec::new();
+    let mut consumers = Vec::new();
+    for manifest_resource_result in system_manifests.resource_iter() {
+        let manifest_resource = manifest_resource_result?;
+        let referenced = referenced_secrets(&manifest_resource.resource).with_context(|| {
+            format!(
+                "Failed to collect secret references from {}",
+                manifest_resource.file.display()
+            )
+        })?;
+        if !referenced.is_empty() {
+            consumers.push((referenced, manifest_resource.clone()));
+        }
+        if let Some(secret_name) = pushed_secret(&manifest_resource.resource) {
+            pushers.push((secret_name, manifest_resource.clone()));
+        }
+        if let Some(secret_name) = produced_secret(&manifest_resource.resource) {
+            producers.push((secret_name, manifest_resource));
+        }
+    }
+
+    let mut steps = Vec::new();
+    let mut affected: HashMap<String, BTreeSet<SecretName>> = HashMap::new();
+    for (secret_name, manifest_resource) in &producers {
+        let reads_key = kind(manifest_resource) == "ExternalSecret"
+            && remote_refs(&manifest_resource.resource)
+                .iter()
+                .any(|remote_ref| remote_ref.key == target);
+        if !reads_key && !is_named(secret_name, target) {
+            continue;
+        }
+        affected
+            .entry(manifest_resource.platform.name.clone())
+            .or_default()
+            .insert(secret_name.clone());
+
+        let action = producer_action(manifest_resource);
+        let source = match action {
+            RotationAction::Rotate => {
+                let stores: Vec<String> = store_refs(&manifest_resource.resource)
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                let keys: BTreeSet<String> = remote_refs(&manifest_resource.resource)
+                    .into_iter()
+                    .map(|remote_ref| remote_ref.key)
+                    .filter(|key| !reads_key || key == target)
+                    .collect();
+                let keys: Vec<String> = keys.into_iter().collect();
+                format!("{} in {}", keys.join(", "), stores.join(", "))
+            }
+            _ => secret_name.name.clone(),
+        };
+        let command = (action == RotationAction::Renew).then(|| {
+            format!(
+                "cmctl renew -n {} {}",
+                namespace(manifest_resource),
+                name(manifest_resource)
+            )
+        });
+        steps.push(step(manifest_resource, action, source, command));
+        if action == RotationAction::Rotate {
+            let command = format!(
+                "kubectl -n {} annotate externalsecret {} force-sync=$(date +%s) --overwrite",
+                namespace(manifest_resource),
+                name(manifest_resource)
+            );
+            steps.push(step(
+                manifest_resource,
+                RotationAction::Refresh,
+                secret_name.name.clone(),
+                Some(command),
+            ));
+        }
+    }
+    anyhow::ensure!(
+        !affected.is_empty(),
+        "No ExternalSecret reads the key {} and no resource results in a Secret of that name",
+        target
+    );
+
+    let is_affected = |manifest_resource: &ManifestResource, secret_name: &SecretName| {
+        affected
+            .get(&manifest_resource.platform.name)
+            .is_some_and(|secret_names| secret_names.contains(secret_name))
+    };
+    for (secret_name, manifest_resource) in &pushers {
+        if is_affected(manifest_resource, secret_name) {
+            let command = format!(
+                "kubectl -n {} annotate pushsecret {} force-sync=$(date +%s) --overwrite",
+                namespace(manifest_resource),
+                name(manifest_resource)
+            );
+            steps.push(step(
+                manifest_resource,
+                RotationAction::Push,
+                secret_name.name.clone(),
+                Some(command),
+            ));
+        }
+    }
+    for (secret_names, manifest_resource) in &consumers {
+        let used: Vec<&str> = secret_names
+            .iter()
+            .filter(|secret_name| is_affected(manifest_resource, secret_name))
+            .map(|secret_name| secret_name.name.as_str())
+            .collect();
+        if used.is_empty() {
+            continue;
+        }
+        let (action, command) = consumer_action(manifest_resource);
+        steps.push(step(manifest_resource, action, used.join(", "), command));
+    }
+
+    let platform_order: HashMap<&str, usize> = system_manifests
+        .platforms
+        .iter()
+        .enumerate()
+        .map(|(index, platform)| (platform.name.as_str(), index))
+        .collect();
+    steps.sort_by_key(|step| {
+        (
+            platform_order.get(step.platform_name.as_str()).copied(),
+            step.action,
+        )
+    });
+    for (index, step) in steps.iter_mut().enumerate() {
+        step.step = index + 1;
+    }
+    Ok(steps)
+}
@@ -0,0 +1,85 @@
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use crate::findings::{Finding, Severity};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+fn level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    }
+}
+
+fn result(finding: &Finding, directory: &Path) -> Value {
+    let uri = finding
+        .file
+        .strip_prefix(directory)
+        .unwrap_or(&finding.file)
+        .to_string_lossy()
+        .replace('\\', "/");
+    let logical_name = [
+        Some(finding.platform_name.as_str()),
+        finding.namespace.as_deref(),
+        finding.kind.as_deref(),
+        finding.name.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join("/");
+
+    let mut properties = json!({
+        "platform": finding.platform_name,
+        "component": finding.component_name,
+    });
+    if let Some(pointer) = &finding.pointer {
+        properties["pointer"] = json!(pointer);
+    }
+
+    let mut physical_location = json!({
+        "artifactLocation": { "uri": uri, "uriBaseId": "%SRCROOT%" },
+    });
+    if let Some(line) = finding.line {
+        physical_location["region"] = json!({ "startLine": line });
+    }
+
+    json!({
+        "ruleId": finding.rule,
+        "level": level(finding.severity),
+        "message": { "text": finding.message },
+        "locations": [{
+            "physicalLocation": physical_location,
+            "logicalLocations": [{
+                "fullyQualifiedName": logical_name,
+                "kind": "resource",
+            }],
+        }],
+        "properties": properties,
+    })
+}
+
+/// Converts findings into a SARIF 2.1.0 log with file locations relative to the given directory.
+pub fn to_sarif(findings: &[Finding], directory: &Path) -> Value {
+    let rules: BTreeSet<&str> = findings
+        .iter()
+        .map(|finding| finding.rule.as_str())
+        .collect();
+
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules.iter().map(|rule| json!({ "id": rule })).collect::<Vec<_>>(),
+                },
+            },
+            "results": findings.iter().map(|finding| result(finding, directory)).collect::<Vec<_>>(),
+        }],
+    })
+}
@@ -0,0 +1,259 @@
+use anyhow::{Context, Result};
+use clap::{Args, Subcommand};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::duration::parse_duration;
+use crate::lint::LintConfig;
+use crate::system_manifests::{Component, SystemManifests};
+
+/// Refresh interval of scaffolded ExternalSecrets, unless the lint config asks for less.
+const DEFAULT_REFRESH_INTERVAL: &str = "1h";
+
+/// Kinds of resources `new` scaffolds.
+#[derive(Subcommand, Debug, Clone)]
+pub enum NewResource {
+    /// Writes an ExternalSecret reading a key from a secret store into the component directory
+    /// given with `--platform` and `--component`, with the labels, name and refresh interval the
+    /// lint config requires.
+    ExternalSecret(ExternalSecretArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ExternalSecretArgs {
+    /// Name of the ExternalSecret and the Secret it creates.
+    #[arg(long)]
+    pub name: String,
+
+    /// Namespace of the ExternalSecret, defaults to the one most secret resources of the
+    /// component use, or else the component name.
+    #[arg(long)]
+    pub namespace: Option<String>,
+
+    /// Name of the secret store to read from.
+    #[arg(long)]
+    pub store: String,
+
+    /// Kind of the secret store to read from.
+    #[arg(long, default_value = "ClusterSecretStore")]
+    pub store_kind: String,
+
+    /// Key to read in the secret store.
+    #[arg(long)]
+    pub key: String,
+
+    /// Properties of the key to read into Secret keys of the same name, can be repeated.
+    /// Extracts all properties if not given.
+    #[arg(long)]
+    pub property: Vec<String>,
+
+    /// Label to set on the ExternalSecret and the Secret it creates, can be repeated. Labels the
+    /// lint config requires have to be given.
+    #[arg(long, value_name = "KEY=VALUE", value_parser = crate::parse_key_value)]
+    pub label: Vec<(String, String)>,
+
+    /// How often to refresh the Secret, defaults to 1h or the lint config's maximum if lower.
+    #[arg(long)]
+    pub refresh_interval: Option<String>,
+
+    /// Print the ExternalSecret instead of writing it.
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+/// Returns the one component `--platform` and `--component` select.
+fn selected_component(system_manifests: &SystemManifests) -> Result<Rc<Component>> {
+    let [platform] = system_manifests.platforms.as_slice() else {
+        anyhow::bail!("Select the platform to write to with a single --platform");
+    };
+    let [component] = platform.components.as_slice() else {
+        anyhow::bail!(
+            "Select the component of platform {} to write to with a single --component",
+            platform.name
+        );
+    };
+    Ok(component.clone())
+}
+
+/// Returns the namespace most secret resources of a component are declared in.
+fn component_namespace(
+    system_manifests: &SystemManifests,
+    component: &Component,
+) -> Result<Option<String>> {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for manifest_resource in crate::inventory::secret_resource_iter(system_manifests, &[]) {
+        let manifest_resource = manifest_resource?;
+        if manifest_resource.component.name != component.name {
+            continue;
+        }
+        if let Some(namespace) = manifest_resource.resource.metadata.namespace {
+            *counts.entry(namespace).or_default() += 1;
+        }
+    }
+    Ok(counts
+        .into_iter()
+        .max_by(|(a_name, a_count), (b_name, b_count)| {
+            a_count.cmp(b_count).then_with(|| b_name.cmp(a_name))
+        })
+        .map(|(namespace, _)| namespace))
+}
+
+/// Returns the refresh interval to use, failing if it's outside the lint config's bounds.
+fn refresh_interval(args: &ExternalSecretArgs, lint: &LintConfig) -> Result<String> {
+    let ceiling = lint
+        .max_refresh_interval
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+        .with_context(|| "Invalid max-refresh-interval in lint config")?;
+    let floor = lint
+        .min_refresh_interval
+        .as_deref()
+        .map(parse_duration)
+        .transpose()
+        .with_context(|| "Invalid min-refresh-interval in lint config")?;
+    let Some(interval) = &args.refresh_interval else {
+        let default = parse_duration(DEFAULT_REFRESH_INTERVAL)?;
+        return Ok(
+            match (
+                ceiling,
+                &lint.max_refresh_interval,
+                floor,
+                &lint.min_refresh_interval,
+            ) {
+                (Some(ceiling), Some(text), _, _) if ceiling < default => text.clone(),
+                (_, _, Some(floor), Some(text)) if floor > default => text.clone(),
+                _ => DEFAULT_REFRESH_INTERVAL.to_owned(),
+            },
+        );
+    };
+    let parsed = parse_duration(interval)?;
+    if let (Some(ceiling), Some(text)) = (ceiling, &lint.max_refresh_interval) {
+        anyhow::ensure!(
+            parsed <= ceiling,
+            "Refresh interval {} exceeds the max-refresh-interval of {}",
+            interval,
+            text
+        );
+    }
+    if let (Some(floor), Some(text)) = (floor, &lint.min_refresh_interval) {
+        anyhow::ensure!(
+            parsed == Duration::ZERO || parsed >= floor,
+            "Refresh interval {} is shorter than the min-refresh-interval of {}",
+            interval,
+            text
+        );
+    }
+    Ok(interval.clone())
+}
+
+/// Checks the ExternalSecret against the lint rules that depend on what is passed.
+fn check_lint(args: &ExternalSecretArgs, namespace: &str, lint: &LintConfig) -> Result<()> {
+    let missing: Vec<&str> = lint
+        .required_labels
+        .iter()
+        .filter(|required| !args.label.iter().any(|(key, _)| key == *required))
+        .map(String::as_str)
+        .collect();
+    anyhow::ensure!(
+        missing.is_empty(),
+        "Missing required labels: {}, set them with --label KEY=VALUE",
+        missing.join(", ")
+    );
+    if let Some(pattern) = &lint.name_pattern {
+        let pattern = Regex::new(pattern).with_context(|| "Invalid name-pattern in lint config")?;
+        anyhow::ensure!(
+            pattern.is_match(&args.name),
+            "Name {} doesn't match {}",
+            args.name,
+            pattern
+        );
+    }
+    anyhow::ensure!(
+        !lint
+            .forbidden_namespaces
+            .iter()
+            .any(|forbidden| forbidden == namespace),
+        "ExternalSecrets may not be declared in namespace {}",
+        namespace
+    );
+    Ok(())
+}
+
+fn external_secret(args: &ExternalSecretArgs, namespace: &str, refresh_interval: &str) -> Value {
+    let labels: Map<String, Value> = args
+        .label
+        .iter()
+        .map(|(key, value)| (key.clone(), Value::String(value.clone())))
+        .collect();
+    let mut metadata = json!({ "name": args.name, "namespace": namespace });
+    let mut template_metadata = json!({});
+    if !labels.is_empty() {
+        metadata["labels"] = Value::Object(labels.clone());
+        template_metadata["labels"] = Value::Object(labels);
+    }
+    let mut spec = json!({
+        "refreshInterval": refresh_interval,
+        "secretStoreRef": { "name": args.store, "kind": args.store_kind },
+        "target": {
+            "name": args.name,
+            "creationPolicy": "Owner",
+            "template": { "metadata": template_metadata },
+        },
+    });
+    if args.property.is_empty() {
+        spec["dataFrom"] = json!([{ "extract": { "key": args.key } }]);
+    } else {
+        spec["data"] = args
+            .property
+            .iter()
+            .map(|property| {
+                json!({
+                    "secretKey": property,
+                    "remoteRef": { "key": args.key, "property": property },
+                })
+            })
+            .collect();
+    }
+    json!({
+        "apiVersion": "external-secrets.io/v1beta1",
+        "kind": "ExternalSecret",
+        "metadata": metadata,
+        "spec": spec,
+    })
+}
+
+/// Scaffolds a resource into the selected component directory, or prints it for a dry run.
+pub fn new_resource(
+    system_manifests: &SystemManifests,
+    lint: &LintConfig,
+    resource: &NewResource,
+) -> Result<()> {
+    let NewResource::ExternalSecret(args) = resource;
+    let component = selected_component(system_manifests)?;
+    let namespace = match &args.namespace {
+        Some(namespace) => namespace.clone(),
+        None => component_namespace(system_manifests, &component)?
+            .unwrap_or_else(|| component.name.clone()),
+    };
+    check_lint(args, &namespace, lint)?;
+    let refresh_interval = refresh_interval(args, lint)?;
+    let yaml = serde_yaml::to_string(&external_secret(args, &namespace, &refresh_interval))
+        .with_context(|| "Failed to serialize ExternalSecret")?;
+
+    if args.dry_run {
+        print!("{}", yaml);
+        return Ok(());
+    }
+    let file: PathBuf = component
+        .manifests_directory
+        .join(format!("{}.yaml", args.name));
+    anyhow::ensure!(!file.exists(), "{} already exists", file.display());
+    std::fs::write(&file, yaml).with_context(|| format!("Failed to write {}", file.display()))?;
+    eprintln!("Wrote {}", file.display());
+    Ok(())
+}
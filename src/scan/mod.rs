@@ -0,0 +1,250 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::findings::{Finding, Severity};
+use crate::system_manifests::ManifestResource;
+
+const HIGH_ENTROPY_RULE: &str = "high-entropy-string";
+const HIGH_ENTROPY_MIN_LENGTH: usize = 20;
+const HIGH_ENTROPY_MIN_BITS: f64 = 4.5;
+
+/// Shows only the first characters of a probable credential.
+fn redact(value: &str) -> String {
+    let prefix: String = value.chars().take(4).collect();
+    format!("{}…", prefix)
+}
+
+fn shannon_entropy(value: &str) -> f64 {
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in value.chars() {
+        *counts.entry(c).or_default() += 1;
+    }
+    let length = value.chars().count() as f64;
+    counts
+        .values()
+        .map(|&count| {
+            let probability = count as f64 / length;
+            -probability * probability.log2()
+        })
+        .sum()
+}
+
+pub fn escape_pointer_segment(segment: &str) -> String {
+    segment.replace('~', "~0").replace('/', "~1")
+}
+
+fn visit_strings(value: &Value, pointer: &str, visit: &mut impl FnMut(&str, &str)) {
+    match value {
+        Value::String(string) => visit(pointer, string),
+        Value::Array(items) => {
+            for (index, item) in items.iter().enumerate() {
+                visit_strings(item, &format!("{}/{}", pointer, index), visit);
+            }
+        }
+        Value::Object(object) => {
+            for (key, item) in object {
+                visit_strings(
+                    item,
+                    &format!("{}/{}", pointer, escape_pointer_segment(key)),
+                    visit,
+                );
+            }
+        }
+        _ => (),
+    }
+}
+
+struct Rule {
+    id: &'static str,
+    description: &'static str,
+    pattern: Regex,
+}
+
+/// Detects probable credentials in manifest string values.
+pub struct Scanner {
+    rules: Vec<Rule>,
+    token: Regex,
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        let rule = |id, description, pattern| Rule {
+            id,
+            description,
+            pattern: Regex::new(pattern).expect("built-in scan rules are valid"),
+        };
+        Scanner {
+            rules: vec![
+                rule(
+                    "aws-access-key-id",
+                    "AWS access key ID",
+                    r"\b(AKIA|ASIA)[0-9A-Z]{16}\b",
+                ),
+                rule(
+                    "github-token",
+                    "GitHub token",
+                    r"\b(gh[pousr]_[A-Za-z0-9]{36,}|github_pat_[A-Za-z0-9_]{60,})\b",
+                ),
+                rule(
+                    "private-key",
+                    "private key",
+                    r"-----BEGIN ((RSA|EC|DSA|OPENSSH|ENCRYPTED|PGP) )?PRIVATE KEY( BLOCK)?-----",
+                ),
+                rule(
+                    "jwt",
+                    "JSON web token",
+                    r"\beyJ[A-Za-z0-9_-]{10,}\.eyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+                ),
+            ],
+            token: Regex::new(r"[A-Za-z0-9+/=_-]+").expect("token pattern is valid"),
+        }
+    }
+
+    /// Returns the rule and description of every probable credential in the text.
+    fn scan_text(&self, text: &str) -> Vec<(&'static str, String)> {
+        let matches: Vec<(&'static str, String)> = self
+            .rules
+            .iter()
+            .filter_map(|rule| {
+                let found = rule.pattern.find(text)?;
+                Some((
+                    rule.id,
+                    format!("Possible {} ({})", rule.description, redact(found.as_str())),
+                ))
+            })
+            .collect();
+        if !matches.is_empty() {
+            return matches;
+        }
+
+        self.token
+            .find_iter(text)
+            .map(|token| token.as_str())
+            .filter(|token| {
+                token.len() >= HIGH_ENTROPY_MIN_LENGTH
+                    && shannon_entropy(token) >= HIGH_ENTROPY_MIN_BITS
+            })
+            .map(|token| {
+                (
+                    HIGH_ENTROPY_RULE,
+                    format!("High entropy string ({})", redact(token)),
+                )
+            })
+            .take(1)
+            .collect()
+    }
+
+    /// Scans every string value of a resource, including base64 decoded Secret data.
+    pub fn scan_resource(&self, manifest_resource: &ManifestResource) -> Result<Vec<Finding>> {
+        let value = serde_json::to_value(&manifest_resource.resource)
+            .with_context(|| "Failed to serialize resource for scanning")?;
+        let is_secret = manifest_resource
+            .resource
+            .types
+            .as_ref()
+            .is_some_and(|t| t.kind == "Secret");
+
+        let mut findings = Vec::new();
+        visit_strings(&value, "", &mut |pointer, text| {
+            let mut matches = self.scan_text(text);
+            if matches.is_empty() && is_secret && pointer.starts_with("/data/") {
+                if let Some(decoded) = base64::engine::general_purpose::STANDARD
+                    .decode(text)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                {
+                    matches = self.scan_text(&decoded);
+                }
+            }
+            for (rule, message) in matches {
+                let severity = if rule == HIGH_ENTROPY_RULE {
+                    Severity::Warning
+                } else {
+                    Severity::Error
+                };
+                findings.push(Finding {
+                    pointer: Some(pointer.to_owned()),
+                    ..Finding::new(rule, severity, message, manifest_resource)
+                });
+            }
+        });
+        Ok(findings)
+    }
+}
+
+/// Known findings to suppress, one `<rule>:<file>:<pointer>` fingerprint per line with the file
+/// relative to the system manifests directory.
+#[derive(Debug, Clone, Default)]
+pub struct Baseline {
+    fingerprints: BTreeSet<String>,
+}
+
+impl Baseline {
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read baseline: {}", path.display()))?;
+        Ok(Baseline {
+            fingerprints: contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_owned)
+                .collect(),
+        })
+    }
+
+    pub fn write(path: &Path, findings: &[Finding], directory: &Path) -> Result<()> {
+        let fingerprints: BTreeSet<String> = findings
+            .iter()
+            .map(|finding| fingerprint(finding, directory))
+            .collect();
+        let mut contents = String::new();
+        for fingerprint in fingerprints {
+            contents.push_str(&fingerprint);
+            contents.push('\n');
+        }
+        std::fs::write(path, contents)
+            .with_context(|| format!("Failed to write baseline: {}", path.display()))
+    }
+
+    fn contains(&self, finding: &Finding, directory: &Path) -> bool {
+        self.fingerprints.contains(&fingerprint(finding, directory))
+    }
+}
+
+fn fingerprint(finding: &Finding, directory: &Path) -> String {
+    let file = finding
+        .file
+        .strip_prefix(directory)
+        .unwrap_or(&finding.file);
+    format!(
+        "{}:{}:{}",
+        finding.rule,
+        file.display(),
+        finding.pointer.as_deref().unwrap_or_default()
+    )
+}
+
+/// Scans all resources for probable leaked credentials, leaving out findings in the baseline.
+pub fn scan(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+    baseline: &Baseline,
+    directory: &Path,
+) -> Result<Vec<Finding>> {
+    let scanner = Scanner::new();
+    let mut findings = Vec::new();
+    for manifest_resource_result in resources {
+        let manifest_resource = manifest_resource_result?;
+        findings.extend(
+            scanner
+                .scan_resource(&manifest_resource)?
+                .into_iter()
+                .filter(|finding| !baseline.contains(finding, directory)),
+        );
+    }
+    Ok(findings)
+}
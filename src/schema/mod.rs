@@ -0,0 +1,88 @@
+use clap::ValueEnum;
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+use crate::age::SecretAge;
+use crate::cert_expiry::CertificateExpiry;
+use crate::compare_platforms::PlatformSkew;
+use crate::drift::Drift;
+use crate::findings::Finding;
+use crate::owners::OwnedSecret;
+use crate::references::{DuplicateSecret, MissingSecret};
+use crate::rotate_plan::RotationStep;
+use crate::search::SearchResult;
+use crate::stats::SecretCounts;
+use crate::sync_status::SyncIssues;
+use crate::system_manifests::FlatManifestResource;
+
+/// Version of the output formats, raised whenever a change to them could break consumers.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The outputs there's a JSON Schema for, named after the commands writing them.
+#[derive(ValueEnum, Debug, Clone, Copy)]
+pub enum Output {
+    /// `list`, without `--group-by`.
+    List,
+    /// `lint`, `check`, `policy` and the other commands writing findings.
+    Findings,
+    Stats,
+    Search,
+    Missing,
+    Duplicates,
+    ComparePlatforms,
+    Drift,
+    SyncStatus,
+    CertExpiry,
+    RotatePlan,
+    Owners,
+    Age,
+}
+
+impl Output {
+    fn root_schema(self) -> RootSchema {
+        match self {
+            Output::List => schema_for!(Vec<FlatManifestResource>),
+            Output::Findings => schema_for!(Vec<Finding>),
+            Output::Stats => schema_for!(Vec<SecretCounts>),
+            Output::Search => schema_for!(Vec<SearchResult>),
+            Output::Missing => schema_for!(Vec<MissingSecret>),
+            Output::Duplicates => schema_for!(Vec<DuplicateSecret>),
+            Output::ComparePlatforms => schema_for!(Vec<PlatformSkew>),
+            Output::Drift => schema_for!(Vec<Drift>),
+            Output::SyncStatus => schema_for!(SyncIssues),
+            Output::CertExpiry => schema_for!(Vec<CertificateExpiry>),
+            Output::RotatePlan => schema_for!(Vec<RotationStep>),
+            Output::Owners => schema_for!(BTreeMap<String, Vec<OwnedSecret>>),
+            Output::Age => schema_for!(Vec<SecretAge>),
+        }
+    }
+
+    fn name(self) -> String {
+        self.to_possible_value()
+            .map(|value| value.get_name().to_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// Returns the JSON Schema of the JSON output of a command, titled after it and carrying the
+/// `schemaVersion` of the output formats.
+pub fn schema(output: Output) -> Value {
+    let mut schema = serde_json::to_value(output.root_schema()).unwrap_or_default();
+    if let Value::Object(object) = &mut schema {
+        object.insert("title".to_owned(), json!(output.name()));
+        object.insert("schemaVersion".to_owned(), json!(SCHEMA_VERSION));
+    }
+    schema
+}
+
+/// Returns the JSON Schemas of the JSON outputs of all commands, keyed by command, along with
+/// the `schemaVersion` of the output formats.
+pub fn schemas() -> Value {
+    let schemas: Map<String, Value> = Output::value_variants()
+        .iter()
+        .map(|output| (output.name(), schema(*output)))
+        .collect();
+    json!({ "schemaVersion": SCHEMA_VERSION, "schemas": schemas })
+}
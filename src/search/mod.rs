@@ -0,0 +1,75 @@
+use kube::api::DynamicObject;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::eso;
+use crate::inventory::{remote_refs, store_refs};
+use crate::system_manifests::{FlatManifestResource, ManifestResource};
+
+/// A field of a secret resource whose value matched the search pattern.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SearchMatch {
+    pub field: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub resource: FlatManifestResource,
+    pub matches: Vec<SearchMatch>,
+}
+
+/// Returns the searchable fields of a secret resource as field name and value pairs.
+fn searchable_fields(resource: &DynamicObject) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    let metadata = &resource.metadata;
+
+    if let Some(name) = &metadata.name {
+        fields.push(("name".to_owned(), name.clone()));
+    }
+    if let Some(namespace) = &metadata.namespace {
+        fields.push(("namespace".to_owned(), namespace.clone()));
+    }
+    for (field, map) in [
+        ("labels", &metadata.labels),
+        ("annotations", &metadata.annotations),
+    ] {
+        for (key, value) in map.iter().flatten() {
+            fields.push((format!("{}.{}", field, key), format!("{}={}", key, value)));
+        }
+    }
+
+    if let Some(target_name) =
+        eso::external_secret_spec(resource).and_then(|spec| spec.target?.name)
+    {
+        fields.push(("target".to_owned(), target_name));
+    }
+    for store_ref in store_refs(resource) {
+        fields.push(("secretStoreRef".to_owned(), store_ref.name));
+    }
+    for remote_ref in remote_refs(resource) {
+        fields.push(("remoteRef".to_owned(), remote_ref.key));
+    }
+
+    fields
+}
+
+/// Returns the secret resources with at least one searchable field matching the pattern.
+pub fn search(pattern: &Regex, manifest_resources: Vec<ManifestResource>) -> Vec<SearchResult> {
+    manifest_resources
+        .into_iter()
+        .filter_map(|manifest_resource| {
+            let matches: Vec<SearchMatch> = searchable_fields(&manifest_resource.resource)
+                .into_iter()
+                .filter(|(_, value)| pattern.is_match(value))
+                .map(|(field, value)| SearchMatch { field, value })
+                .collect();
+            (!matches.is_empty()).then(|| SearchResult {
+                resource: manifest_resource.into(),
+                matches,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,118 @@
+use kube::core::{Expression, Selector, SelectorExt};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A Kubernetes label selector written as for `kubectl -l`, like
+/// `app=payments,tier!=db,env in (prod,staging),!legacy`.
+#[derive(Debug, Clone)]
+pub struct LabelSelector(Selector);
+
+/// Splits a selector into its requirements at the commas outside of value sets.
+fn requirements(value: &str) -> Vec<&str> {
+    let mut requirements = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (index, c) in value.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                requirements.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    requirements.push(&value[start..]);
+    requirements
+        .into_iter()
+        .map(str::trim)
+        .filter(|requirement| !requirement.is_empty())
+        .collect()
+}
+
+fn key(key: &str, requirement: &str) -> Result<String, String> {
+    let key = key.trim();
+    if key.is_empty() || key.contains(char::is_whitespace) {
+        return Err(format!(
+            "invalid label key in requirement `{}`",
+            requirement
+        ));
+    }
+    Ok(key.to_owned())
+}
+
+/// Parses a set-based requirement like `env in (prod,staging)` or `env notin (dev)`.
+fn set_expression(requirement: &str) -> Option<Result<Expression, String>> {
+    let (head, values) = requirement.strip_suffix(')')?.split_once('(')?;
+    let head = head.trim_end();
+    let (key_part, operator) = head.rsplit_once(char::is_whitespace)?;
+    let values: BTreeSet<String> = values
+        .split(',')
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+        .collect();
+    let expression = match operator {
+        "in" => key(key_part, requirement).map(|key| Expression::In(key, values)),
+        "notin" => key(key_part, requirement).map(|key| Expression::NotIn(key, values)),
+        _ => return None,
+    };
+    Some(expression)
+}
+
+fn expression(requirement: &str) -> Result<Expression, String> {
+    if let Some(expression) = set_expression(requirement) {
+        return expression;
+    }
+    if let Some((name, value)) = requirement.split_once("!=") {
+        return Ok(Expression::NotEqual(
+            key(name, requirement)?,
+            value.trim().to_owned(),
+        ));
+    }
+    if let Some((name, value)) = requirement
+        .split_once("==")
+        .or_else(|| requirement.split_once('='))
+    {
+        return Ok(Expression::Equal(
+            key(name, requirement)?,
+            value.trim().to_owned(),
+        ));
+    }
+    match requirement.strip_prefix('!') {
+        Some(name) => Ok(Expression::DoesNotExist(key(name, requirement)?)),
+        None => Ok(Expression::Exists(key(requirement, requirement)?)),
+    }
+}
+
+pub fn parse_label_selector(value: &str) -> Result<LabelSelector, String> {
+    let expressions = requirements(value)
+        .into_iter()
+        .map(expression)
+        .collect::<Result<Vec<_>, String>>()?;
+    if expressions.is_empty() {
+        return Err("expected at least one requirement, like app=payments".to_owned());
+    }
+    Ok(LabelSelector(Selector::from_iter(expressions)))
+}
+
+/// Parses a requirement on annotations, like `owner=team-x`, `owner!=team-x` or just `owner`, as
+/// a single requirement of a label selector. Commas don't separate requirements, as annotation
+/// values often hold lists.
+pub fn parse_annotation_selector(value: &str) -> Result<LabelSelector, String> {
+    let requirement = value.trim();
+    if requirement.is_empty() {
+        return Err("expected a requirement, like owner=team-x".to_owned());
+    }
+    Ok(LabelSelector(Selector::from_iter([expression(
+        requirement,
+    )?])))
+}
+
+impl LabelSelector {
+    pub fn matches(&self, labels: Option<&BTreeMap<String, String>>) -> bool {
+        match labels {
+            Some(labels) => self.0.matches(labels),
+            None => self.0.matches(&BTreeMap::new()),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::inventory;
+use crate::system_manifests::{FlatManifestResource, ManifestResource, Platform};
+
+/// A platform with its components, as listed on `/platforms`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlatformSummary {
+    name: String,
+    components: Vec<String>,
+    secrets: usize,
+}
+
+/// A secret resource, as listed on `/secrets`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Secret {
+    #[serde(flatten)]
+    resource: FlatManifestResource,
+}
+
+impl Secret {
+    fn matches(&self, parameter: &str, value: &str) -> bool {
+        let resource = &self.resource;
+        match parameter {
+            "platform" => resource.platform_name == value,
+            "component" => resource.component_name == value,
+            "kind" => resource.kind == value,
+            "namespace" => resource.resource_meta.namespace.as_deref() == Some(value),
+            "name" => resource.resource_meta.name.as_deref() == Some(value),
+            _ => true,
+        }
+    }
+}
+
+/// The secrets inventory the API answers from, built from the secret resources.
+#[derive(Debug, Default)]
+pub struct Index {
+    platforms: Vec<PlatformSummary>,
+    secrets: Vec<Secret>,
+}
+
+impl Index {
+    pub fn new(platforms: &[Rc<Platform>], manifest_resources: &[ManifestResource]) -> Self {
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for manifest_resource in manifest_resources {
+            *counts.entry(&manifest_resource.platform.name).or_default() += 1;
+        }
+        let platforms = platforms
+            .iter()
+            .map(|platform| PlatformSummary {
+                name: platform.name.clone(),
+                components: platform
+                    .components
+                    .iter()
+                    .map(|component| component.name.clone())
+                    .collect(),
+                secrets: counts
+                    .get(platform.name.as_str())
+                    .copied()
+                    .unwrap_or_default(),
+            })
+            .collect();
+        let secrets = manifest_resources
+            .iter()
+            .map(|manifest_resource| Secret {
+                resource: inventory::flatten(manifest_resource.clone(), true),
+            })
+            .collect();
+        Index { platforms, secrets }
+    }
+
+    pub fn platforms(&self) -> &[PlatformSummary] {
+        &self.platforms
+    }
+
+    /// Returns the secret resources matching all query parameters, like `platform=prod` or
+    /// `kind=ExternalSecret`. Unknown parameters are ignored.
+    pub fn secrets(&self, parameters: &[(String, String)]) -> Vec<&Secret> {
+        self.secrets
+            .iter()
+            .filter(|secret| {
+                parameters
+                    .iter()
+                    .all(|(parameter, value)| secret.matches(parameter, value))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use crate::findings::{Finding, Severity};
+use crate::system_manifests::ManifestResource;
+
+/// Escapes a Prometheus label value.
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Writes a gauge in the Prometheus text format, with a sample per set of label values.
+fn write_gauge<const N: usize>(
+    text: &mut String,
+    name: &str,
+    help: &str,
+    labels: [&str; N],
+    samples: &BTreeMap<[String; N], usize>,
+) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} gauge", name);
+    for (values, count) in samples {
+        let labels: Vec<String> = labels
+            .iter()
+            .zip(values)
+            .map(|(label, value)| format!("{}=\"{}\"", label, label_value(value)))
+            .collect();
+        let _ = writeln!(text, "{}{{{}}} {}", name, labels.join(","), count);
+    }
+}
+
+fn severity(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+    }
+}
+
+/// Renders the metrics of the secrets inventory in the Prometheus text format: the number of
+/// secret resources, of plain Secrets and of lint findings.
+pub fn metrics(
+    manifest_resources: &[ManifestResource],
+    plain_secrets: &[Finding],
+    lint_findings: &[Finding],
+) -> String {
+    let mut totals: BTreeMap<[String; 3], usize> = BTreeMap::new();
+    for manifest_resource in manifest_resources {
+        let Some(types) = &manifest_resource.resource.types else {
+            continue;
+        };
+        *totals
+            .entry([
+                manifest_resource.platform.name.clone(),
+                manifest_resource.component.name.clone(),
+                types.kind.clone(),
+            ])
+            .or_default() += 1;
+    }
+    let mut plaintext: BTreeMap<[String; 2], usize> = BTreeMap::new();
+    for finding in plain_secrets {
+        *plaintext
+            .entry([
+                finding.platform_name.clone(),
+                finding.component_name.clone(),
+            ])
+            .or_default() += 1;
+    }
+    let mut findings: BTreeMap<[String; 3], usize> = BTreeMap::new();
+    for finding in lint_findings {
+        *findings
+            .entry([
+                finding.platform_name.clone(),
+                finding.rule.clone(),
+                severity(finding.severity).to_owned(),
+            ])
+            .or_default() += 1;
+    }
+
+    let mut text = String::new();
+    write_gauge(
+        &mut text,
+        "dp_secrets_total",
+        "Number of secret resources in the system manifests.",
+        ["platform", "component", "kind"],
+        &totals,
+    );
+    write_gauge(
+        &mut text,
+        "dp_secrets_plaintext_total",
+        "Number of Secrets carrying inline data that aren't allowlisted.",
+        ["platform", "component"],
+        &plaintext,
+    );
+    write_gauge(
+        &mut text,
+        "dp_secrets_lint_findings",
+        "Number of lint findings.",
+        ["platform", "rule", "severity"],
+        &findings,
+    );
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let _ = writeln!(
+        text,
+        "# HELP dp_secrets_last_refresh_timestamp_seconds When the metrics were last refreshed."
+    );
+    let _ = writeln!(
+        text,
+        "# TYPE dp_secrets_last_refresh_timestamp_seconds gauge"
+    );
+    let _ = writeln!(text, "dp_secrets_last_refresh_timestamp_seconds {}", now);
+    text
+}
@@ -0,0 +1,129 @@
+use anyhow::Result;
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response};
+
+pub use api::Index;
+pub use metrics::metrics;
+
+mod api;
+mod metrics;
+
+/// What the server answers from, read anew every interval.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    pub index: Index,
+    /// Metrics in the Prometheus text format, if served.
+    pub metrics: Option<String>,
+}
+
+type Reply = Response<std::io::Cursor<Vec<u8>>>;
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name, value).expect("static header is valid")
+}
+
+fn json_error(status: u16, message: &str) -> Reply {
+    let body = serde_json::json!({ "error": message }).to_string();
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn json(value: &impl Serialize) -> Reply {
+    match serde_json::to_string(value) {
+        Ok(body) => {
+            Response::from_string(body).with_header(header("Content-Type", "application/json"))
+        }
+        Err(error) => json_error(500, &format!("Failed to serialize response: {}", error)),
+    }
+}
+
+/// Answers a request from the snapshot:
+///
+/// - `/platforms` lists the platforms with their components and number of secret resources.
+/// - `/secrets` lists secret resources, narrowed down by the `platform`, `component`, `kind`,
+///   `namespace` and `name` query parameters.
+/// - `/secrets/{namespace}/{name}` lists the secret resources with that namespace and name on
+///   every platform, narrowed down by the same query parameters.
+/// - `/metrics` has the Prometheus metrics, if served.
+fn respond(request: &Request, snapshot: &Snapshot) -> Reply {
+    if *request.method() != Method::Get {
+        return json_error(405, "Only GET requests are supported");
+    }
+    let (path, query) = request.url().split_once('?').unwrap_or((request.url(), ""));
+    let mut parameters: Vec<(String, String)> = form_urlencoded::parse(query.as_bytes())
+        .into_owned()
+        .collect();
+    let segments: Vec<String> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| percent_decode_str(segment).decode_utf8_lossy().into_owned())
+        .collect();
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    match segments.as_slice() {
+        ["platforms"] => json(&snapshot.index.platforms()),
+        ["secrets"] => json(&snapshot.index.secrets(&parameters)),
+        ["secrets", namespace, name] => {
+            parameters.push(("namespace".to_owned(), namespace.to_string()));
+            parameters.push(("name".to_owned(), name.to_string()));
+            let secrets = snapshot.index.secrets(&parameters);
+            if secrets.is_empty() {
+                json_error(404, &format!("No secret resource {}/{}", namespace, name))
+            } else {
+                json(&secrets)
+            }
+        }
+        ["metrics"] => match &snapshot.metrics {
+            Some(metrics) => Response::from_string(metrics.clone()).with_header(header(
+                "Content-Type",
+                "text/plain; version=0.0.4; charset=utf-8",
+            )),
+            None => json_error(404, "Metrics aren't served, pass --metrics"),
+        },
+        _ => json_error(404, "Not found"),
+    }
+}
+
+/// Serves the secrets inventory over HTTP, refreshing it every interval until interrupted.
+///
+/// Failing to refresh is reported without stopping, answering from the last snapshot until it
+/// refreshes again. The last refresh timestamp of the metrics tells stale metrics apart.
+pub fn serve(
+    listen: SocketAddr,
+    interval: Duration,
+    mut refresh: impl FnMut() -> Result<Snapshot>,
+) -> Result<()> {
+    let snapshot = Arc::new(Mutex::new(refresh()?));
+    let server = tiny_http::Server::http(listen)
+        .map_err(|error| anyhow::anyhow!("Failed to listen on {}: {}", listen, error))?;
+    eprintln!("Serving the secrets inventory on http://{}", listen);
+
+    let served_snapshot = Arc::clone(&snapshot);
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match served_snapshot.lock() {
+                Ok(snapshot) => respond(&request, &snapshot),
+                Err(_) => json_error(500, "The inventory is unavailable"),
+            };
+            if let Err(error) = request.respond(response) {
+                eprintln!("Failed to respond to request: {}", error);
+            }
+        }
+    });
+
+    loop {
+        std::thread::sleep(interval);
+        match refresh() {
+            Ok(refreshed) => {
+                if let Ok(mut snapshot) = snapshot.lock() {
+                    *snapshot = refreshed;
+                }
+            }
+            Err(error) => eprintln!("Error: Failed to refresh the inventory: {:?}", error),
+        }
+    }
+}
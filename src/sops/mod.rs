@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use kube::api::DynamicObject;
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process::Command;
+
+/// Fields of a SOPS master key that identify it, by the key group field listing such keys.
+const RECIPIENT_FIELDS: &[(&str, &str)] = &[
+    ("age", "recipient"),
+    ("kms", "arn"),
+    ("gcp_kms", "resource_id"),
+    ("azure_kv", "vault_url"),
+    ("hc_vault", "vault_address"),
+    ("pgp", "fp"),
+];
+
+/// The `sops` metadata block of an encrypted manifest, without the encrypted data keys.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SopsMetadata {
+    /// Keys the data is encrypted to, like `age:age1...` or `kms:arn:aws:kms:...`.
+    pub recipients: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+}
+
+/// Returns the identity of a master key, qualified by its type. Azure Key Vault and Vault keys
+/// are identified by their URL along with the key name, as one vault holds many keys.
+fn recipient(key_type: &str, id_field: &str, key: &Value) -> Option<String> {
+    let id = key.get(id_field)?.as_str()?;
+    let id = match key_type {
+        "azure_kv" => format!(
+            "{}/keys/{}",
+            id.trim_end_matches('/'),
+            key.get("name")?.as_str()?
+        ),
+        "hc_vault" => format!(
+            "{}/v1/{}/keys/{}",
+            id.trim_end_matches('/'),
+            key.get("engine_path")?.as_str()?,
+            key.get("key_name")?.as_str()?
+        ),
+        _ => id.to_owned(),
+    };
+    Some(format!("{}:{}", key_type, id))
+}
+
+fn group_recipients(group: &Value) -> impl Iterator<Item = String> + '_ {
+    RECIPIENT_FIELDS
+        .iter()
+        .flat_map(move |(key_type, id_field)| {
+            group
+                .get(key_type)
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(move |key| recipient(key_type, id_field, key))
+        })
+}
+
+/// Returns the SOPS metadata of a manifest encrypted with SOPS, collecting the recipients of
+/// all key groups.
+pub fn metadata(resource: &DynamicObject) -> Option<SopsMetadata> {
+    let sops = resource.data.get("sops").filter(|sops| sops.is_object())?;
+    let key_groups = sops
+        .get("key_groups")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten();
+    let mut recipients: Vec<String> = group_recipients(sops)
+        .chain(key_groups.flat_map(group_recipients))
+        .collect();
+    recipients.sort();
+    recipients.dedup();
+    Some(SopsMetadata {
+        recipients,
+        last_modified: sops
+            .get("lastmodified")
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+    })
+}
+
+/// Returns whether a manifest is encrypted with SOPS.
+pub fn is_encrypted(resource: &DynamicObject) -> bool {
+    resource.data.get("sops").is_some_and(Value::is_object)
+}
+
+/// Decrypts a SOPS-encrypted file with `sops --decrypt`, returning its plain contents without
+/// writing them to disk.
+pub fn decrypt(file: &Path) -> Result<String> {
+    let output = match Command::new("sops").arg("--decrypt").arg(file).output() {
+        Err(error) if error.kind() == ErrorKind::NotFound => anyhow::bail!(
+            "Decrypting SOPS-encrypted manifests requires the sops CLI to be installed"
+        ),
+        output => output.with_context(|| "Failed to run sops")?,
+    };
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to decrypt {}: {}",
+            file.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout)
+        .with_context(|| format!("Decrypted {} isn't valid UTF-8", file.display()))
+}
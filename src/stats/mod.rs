@@ -0,0 +1,87 @@
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::system_manifests::ManifestResource;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Total,
+    Platform,
+    Component,
+    Namespace,
+}
+
+/// Number of secret resources per kind within a scope.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SecretCounts {
+    pub scope: Scope,
+    pub name: String,
+    pub secret: usize,
+    pub external_secret: usize,
+    pub push_secret: usize,
+    pub sealed_secret: usize,
+    pub certificate: usize,
+    pub total: usize,
+}
+
+impl SecretCounts {
+    fn new(scope: Scope, name: String) -> Self {
+        SecretCounts {
+            scope,
+            name,
+            secret: 0,
+            external_secret: 0,
+            push_secret: 0,
+            sealed_secret: 0,
+            certificate: 0,
+            total: 0,
+        }
+    }
+
+    fn count(&mut self, kind: &str) {
+        match kind {
+            "Secret" => self.secret += 1,
+            "ExternalSecret" => self.external_secret += 1,
+            "PushSecret" => self.push_secret += 1,
+            "SealedSecret" => self.sealed_secret += 1,
+            "Certificate" => self.certificate += 1,
+            _ => return,
+        }
+        self.total += 1;
+    }
+}
+
+/// Counts secret resources in total, per platform, per platform/component and per
+/// platform/namespace.
+pub fn secret_counts(manifest_resources: &[ManifestResource]) -> Vec<SecretCounts> {
+    let mut counts: BTreeMap<(Scope, String), SecretCounts> = BTreeMap::new();
+    for manifest_resource in manifest_resources {
+        let Some(kind) = manifest_resource.resource.types.as_ref().map(|t| &t.kind) else {
+            continue;
+        };
+        let platform_name = &manifest_resource.platform.name;
+        let namespace = manifest_resource
+            .resource
+            .metadata
+            .namespace
+            .as_deref()
+            .unwrap_or("<none>");
+        for (scope, name) in [
+            (Scope::Total, "all".to_owned()),
+            (Scope::Platform, platform_name.clone()),
+            (
+                Scope::Component,
+                format!("{}/{}", platform_name, manifest_resource.component.name),
+            ),
+            (Scope::Namespace, format!("{}/{}", platform_name, namespace)),
+        ] {
+            counts
+                .entry((scope, name.clone()))
+                .or_insert_with(|| SecretCounts::new(scope, name))
+                .count(kind);
+        }
+    }
+    counts.into_values().collect()
+}
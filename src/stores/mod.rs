@@ -0,0 +1,152 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::findings::{Finding, Severity};
+use crate::inventory::{store_refs, StoreRef};
+use crate::system_manifests::ManifestResource;
+
+pub const RULE: &str = "check-stores";
+
+/// Kinds of resources that define a secret store.
+pub const STORE_KINDS: &[&str] = &["SecretStore", "ClusterSecretStore"];
+
+/// Identifies a SecretStore or ClusterSecretStore, only SecretStores have a namespace.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct StoreKey {
+    kind: String,
+    namespace: Option<String>,
+    name: String,
+}
+
+fn store_key(manifest_resource: &ManifestResource) -> Option<StoreKey> {
+    let resource = &manifest_resource.resource;
+    let kind = resource.types.as_ref()?.kind.as_str();
+    if !STORE_KINDS.contains(&kind) {
+        return None;
+    }
+    Some(StoreKey {
+        kind: kind.to_owned(),
+        namespace: resource
+            .metadata
+            .namespace
+            .clone()
+            .filter(|_| kind == "SecretStore"),
+        name: resource.metadata.name.clone()?,
+    })
+}
+
+/// Returns the provider type of a store, the single key of its `spec.provider`, along with the
+/// provider's configuration.
+pub fn provider(store: &ManifestResource) -> Option<(&str, &Value)> {
+    let (provider, configuration) = store
+        .resource
+        .data
+        .pointer("/spec/provider")?
+        .as_object()?
+        .iter()
+        .next()?;
+    Some((provider.as_str(), configuration))
+}
+
+/// The SecretStores and ClusterSecretStores declared on each platform.
+#[derive(Default)]
+pub struct SecretStores {
+    stores: HashMap<String, BTreeMap<StoreKey, ManifestResource>>,
+}
+
+impl SecretStores {
+    /// Adds the resource if it is a store, or else hands it back.
+    pub fn insert(&mut self, manifest_resource: ManifestResource) -> Option<ManifestResource> {
+        let Some(key) = store_key(&manifest_resource) else {
+            return Some(manifest_resource);
+        };
+        self.stores
+            .entry(manifest_resource.platform.name.clone())
+            .or_default()
+            .insert(key, manifest_resource);
+        None
+    }
+
+    /// Returns the store a reference from a resource in the given namespace resolves to, or a
+    /// description of why it doesn't.
+    pub fn resolve(
+        &self,
+        platform_name: &str,
+        store_ref: &StoreRef,
+        namespace: Option<&str>,
+    ) -> Result<&ManifestResource, String> {
+        let namespace = namespace.filter(|_| store_ref.kind == "SecretStore");
+        let same_name: Vec<(&StoreKey, &ManifestResource)> = self
+            .stores
+            .get(platform_name)
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| key.name == store_ref.name)
+            .collect();
+        if let Some((_, store)) = same_name
+            .iter()
+            .find(|(key, _)| key.kind == store_ref.kind && key.namespace.as_deref() == namespace)
+        {
+            return Ok(store);
+        }
+
+        if let Some((key, _)) = same_name.iter().find(|(key, _)| key.kind != store_ref.kind) {
+            Err(format!(
+                "{} does not exist, but a {} with that name does",
+                store_ref, key.kind
+            ))
+        } else if let Some((key, _)) = same_name.first() {
+            Err(format!(
+                "{} does not exist in namespace {}, but it does in namespace {}",
+                store_ref,
+                namespace.unwrap_or("<none>"),
+                key.namespace.as_deref().unwrap_or("<none>")
+            ))
+        } else {
+            Err(format!("{} does not exist", store_ref))
+        }
+    }
+}
+
+/// Splits resources into the declared stores and the resources referencing a store.
+pub fn collect(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+) -> Result<(SecretStores, Vec<ManifestResource>)> {
+    let mut stores = SecretStores::default();
+    let mut referencing = Vec::new();
+    for manifest_resource_result in resources {
+        if let Some(manifest_resource) = stores.insert(manifest_resource_result?) {
+            if !store_refs(&manifest_resource.resource).is_empty() {
+                referencing.push(manifest_resource);
+            }
+        }
+    }
+    Ok((stores, referencing))
+}
+
+/// Returns a finding for every store reference of an ExternalSecret or PushSecret that doesn't
+/// resolve to a SecretStore or ClusterSecretStore on the same platform.
+pub fn check_stores(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+) -> Result<Vec<Finding>> {
+    let (stores, referencing) = collect(resources)?;
+
+    let mut findings = Vec::new();
+    for manifest_resource in &referencing {
+        let namespace = manifest_resource.resource.metadata.namespace.as_deref();
+        for store_ref in store_refs(&manifest_resource.resource) {
+            if let Err(reason) =
+                stores.resolve(&manifest_resource.platform.name, &store_ref, namespace)
+            {
+                findings.push(Finding::new(
+                    RULE,
+                    Severity::Error,
+                    reason,
+                    manifest_resource,
+                ));
+            }
+        }
+    }
+    Ok(findings)
+}
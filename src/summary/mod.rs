@@ -0,0 +1,85 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::findings::{Finding, Severity};
+use crate::system_manifests::ManifestResource;
+
+/// Counts of secret resources and findings, written with `--summary` instead of listing them.
+#[derive(Debug, Default)]
+pub struct Summary {
+    /// Number of secret resources per kind and per platform, if the command read them.
+    resources: Option<(BTreeMap<String, usize>, BTreeMap<String, usize>)>,
+    /// Number of findings per severity, for findings commands.
+    findings: Option<BTreeMap<Severity, usize>>,
+}
+
+/// Joins counts like `ExternalSecret 9, Secret 6`.
+fn counts(counts: &BTreeMap<String, usize>) -> String {
+    counts
+        .iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn plural(count: usize, word: &str) -> String {
+    match count {
+        1 => format!("{} {}", count, word),
+        _ => format!("{} {}s", count, word),
+    }
+}
+
+impl Summary {
+    pub fn resources(manifest_resources: &[ManifestResource]) -> Self {
+        let mut kinds: BTreeMap<String, usize> = BTreeMap::new();
+        let mut platforms: BTreeMap<String, usize> = BTreeMap::new();
+        for manifest_resource in manifest_resources {
+            let kind = manifest_resource
+                .resource
+                .types
+                .as_ref()
+                .map(|t| t.kind.clone())
+                .unwrap_or_default();
+            *kinds.entry(kind).or_default() += 1;
+            *platforms
+                .entry(manifest_resource.platform.name.clone())
+                .or_default() += 1;
+        }
+        Summary {
+            resources: Some((kinds, platforms)),
+            findings: None,
+        }
+    }
+
+    pub fn with_findings(mut self, findings: &[Finding]) -> Self {
+        let mut severities: BTreeMap<Severity, usize> = BTreeMap::new();
+        for finding in findings {
+            *severities.entry(finding.severity).or_default() += 1;
+        }
+        self.findings = Some(severities);
+        self
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((kinds, platforms)) = &self.resources {
+            writeln!(f, "{}", plural(kinds.values().sum(), "secret resource"))?;
+            if !kinds.is_empty() {
+                writeln!(f, "  per kind: {}", counts(kinds))?;
+                writeln!(f, "  per platform: {}", counts(platforms))?;
+            }
+        }
+        if let Some(severities) = &self.findings {
+            let count = |severity| severities.get(&severity).copied().unwrap_or_default();
+            writeln!(
+                f,
+                "{} ({}, {})",
+                plural(severities.values().sum(), "finding"),
+                plural(count(Severity::Error), "error"),
+                plural(count(Severity::Warning), "warning")
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,194 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use kube::api::{Api, DynamicObject, ListParams};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::cluster::{api_resource_for, ClusterContexts};
+use crate::duration::parse_duration;
+use crate::eso::Spec;
+use crate::system_manifests::{ManifestResource, Platform, SystemManifests};
+
+/// The refresh interval External Secrets Operator applies when none is set.
+const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncState {
+    /// The ExternalSecret does not exist in the cluster.
+    NotFound,
+    /// The ExternalSecret's `Ready` condition is not `True`.
+    NotSynced,
+    /// The ExternalSecret has not refreshed for more than twice its refresh interval.
+    Stale,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SyncStatus {
+    pub file: PathBuf,
+    pub component_name: String,
+    pub namespace: String,
+    pub name: String,
+    pub state: SyncState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_time: Option<String>,
+}
+
+fn ready_condition(live: &DynamicObject) -> Option<&serde_json::Value> {
+    live.data
+        .pointer("/status/conditions")?
+        .as_array()?
+        .iter()
+        .find(|condition| condition.get("type").and_then(|t| t.as_str()) == Some("Ready"))
+}
+
+fn condition_field(condition: Option<&serde_json::Value>, field: &str) -> Option<String> {
+    condition?.get(field)?.as_str().map(str::to_owned)
+}
+
+fn sync_state(declared: &DynamicObject, live: &DynamicObject) -> Result<Option<SyncState>> {
+    let ready = ready_condition(live);
+    if condition_field(ready, "status").as_deref() != Some("True") {
+        return Ok(Some(SyncState::NotSynced));
+    }
+
+    let refresh_interval = match Spec::of(declared).as_ref().and_then(Spec::refresh_interval) {
+        Some(interval) => parse_duration(interval)?,
+        None => DEFAULT_REFRESH_INTERVAL,
+    };
+    if refresh_interval.is_zero() {
+        return Ok(None);
+    }
+    let Some(refresh_time) = live
+        .data
+        .pointer("/status/refreshTime")
+        .and_then(|time| time.as_str())
+    else {
+        return Ok(Some(SyncState::Stale));
+    };
+    let refresh_time: DateTime<Utc> = refresh_time
+        .parse()
+        .with_context(|| format!("Invalid refreshTime: {}", refresh_time))?;
+    let age = (Utc::now() - refresh_time).to_std().unwrap_or_default();
+    Ok((age > refresh_interval * 2).then_some(SyncState::Stale))
+}
+
+/// The ExternalSecrets with sync issues, grouped by platform name.
+pub type SyncIssues = BTreeMap<String, Vec<SyncStatus>>;
+
+async fn platform_sync_status(
+    platform: &Platform,
+    external_secrets: Vec<ManifestResource>,
+    contexts: &ClusterContexts,
+) -> Result<Vec<SyncStatus>> {
+    if external_secrets.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let client = contexts.client_for(platform).await?;
+    let mut live_by_name: HashMap<(String, String), DynamicObject> = HashMap::new();
+    let mut listed: BTreeSet<(String, String)> = BTreeSet::new();
+    let mut statuses = Vec::new();
+    for manifest_resource in external_secrets {
+        let api_resource = api_resource_for(&manifest_resource)?;
+        let metadata = &manifest_resource.resource.metadata;
+        let namespace = metadata
+            .namespace
+            .clone()
+            .unwrap_or_else(|| "default".to_owned());
+        let name = metadata.name.clone().unwrap_or_default();
+
+        if listed.insert((api_resource.version.clone(), namespace.clone())) {
+            let api: Api<DynamicObject> =
+                Api::namespaced_with(client.clone(), &namespace, &api_resource);
+            let live = api.list(&ListParams::default()).await.with_context(|| {
+                format!(
+                    "Failed to list ExternalSecrets in namespace {} on platform {}",
+                    namespace, platform.name
+                )
+            })?;
+            for item in live.items {
+                let item_name = item.metadata.name.clone().unwrap_or_default();
+                live_by_name.insert((namespace.clone(), item_name), item);
+            }
+        }
+
+        let live = live_by_name.get(&(namespace.clone(), name.clone()));
+        let state = match live {
+            None => Some(SyncState::NotFound),
+            Some(live) => sync_state(&manifest_resource.resource, live).with_context(|| {
+                format!(
+                    "Failed to determine sync state of ExternalSecret {}/{} on platform {}",
+                    namespace, name, platform.name
+                )
+            })?,
+        };
+        let Some(state) = state else {
+            continue;
+        };
+        let ready = live.and_then(ready_condition);
+        statuses.push(SyncStatus {
+            file: manifest_resource.file.clone(),
+            component_name: manifest_resource.component.name.clone(),
+            namespace,
+            name,
+            state,
+            reason: condition_field(ready, "reason"),
+            message: condition_field(ready, "message"),
+            refresh_time: live
+                .and_then(|live| live.data.pointer("/status/refreshTime"))
+                .and_then(|time| time.as_str())
+                .map(str::to_owned),
+        });
+    }
+
+    Ok(statuses)
+}
+
+/// Queries the live status of every ExternalSecret declared in the manifests and returns the
+/// ones that are missing, failing to sync or stale, grouped by platform name. Clusters are
+/// queried concurrently, and the names of the platforms whose cluster couldn't be queried are
+/// returned along with the issues found on the others.
+pub fn find_sync_issues(
+    system_manifests: &SystemManifests,
+    contexts: &ClusterContexts,
+) -> Result<(SyncIssues, Vec<String>)> {
+    let mut declared: HashMap<String, Vec<ManifestResource>> = HashMap::new();
+    for manifest_resource_result in system_manifests.resource_iter() {
+        let manifest_resource = manifest_resource_result?;
+        if manifest_resource
+            .resource
+            .types
+            .as_ref()
+            .is_some_and(|t| t.kind == "ExternalSecret")
+        {
+            declared
+                .entry(manifest_resource.platform.name.clone())
+                .or_default()
+                .push(manifest_resource);
+        }
+    }
+
+    let queries = system_manifests.platforms.iter().map(|platform| {
+        let external_secrets = declared.remove(&platform.name).unwrap_or_default();
+        let query = async move {
+            let statuses = platform_sync_status(platform, external_secrets, contexts).await?;
+            Ok((platform.name.clone(), statuses))
+        };
+        (platform.as_ref(), query.boxed_local())
+    });
+    let (statuses, failed_platforms) = contexts.query_all(queries)?;
+    let issues = statuses
+        .into_iter()
+        .filter(|(_, statuses)| !statuses.is_empty())
+        .collect();
+    Ok((issues, failed_platforms))
+}
@@ -0,0 +1,224 @@
+use globset::{GlobBuilder, GlobSetBuilder};
+use kube::api::DynamicObject;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::discovery::AppliedPath;
+use crate::git;
+
+/// Reduces a git URL to its host and path, so the HTTPS and SSH URLs of a repository compare
+/// equal.
+fn normalize_url(url: &str) -> String {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let (url, scp_like) = match url.split_once("://") {
+        Some((_, rest)) => (rest, false),
+        None => (url, true),
+    };
+    let (host, path) = match url.split_once('/') {
+        Some((host, path)) => (host, path),
+        None => (url, ""),
+    };
+    let host = host.rsplit_once('@').map_or(host, |(_, host)| host);
+    // `git@host:org/repo` puts the first path segment after a colon.
+    let (host, path) = match host.split_once(':') {
+        Some((host, first)) if scp_like => (host, format!("{}/{}", first, path)),
+        Some((host, _port)) => (host, path.to_owned()),
+        None => (host, path.to_owned()),
+    };
+    format!("{}/{}", host, path.trim_end_matches('/')).to_lowercase()
+}
+
+/// The URLs of the system manifests repository, to tell its sources from other repositories.
+pub struct Repositories {
+    urls: Vec<String>,
+}
+
+impl Repositories {
+    /// Reads the remotes of the repository. Outside of a git repository every source is taken to
+    /// be the system manifests repository.
+    pub fn of(repository: &Path) -> Self {
+        let urls = git::remote_urls(repository)
+            .unwrap_or_default()
+            .iter()
+            .map(|url| normalize_url(url))
+            .collect();
+        Repositories { urls }
+    }
+
+    fn contains(&self, url: Option<&str>) -> bool {
+        self.urls.is_empty() || url.is_some_and(|url| self.urls.contains(&normalize_url(url)))
+    }
+}
+
+/// Returns the paths of sources from the system manifests repository in an Application spec,
+/// leaving out Helm charts from chart repositories.
+fn source_paths<'a>(spec: &'a Value, repositories: &Repositories) -> Vec<&'a str> {
+    spec.get("source")
+        .into_iter()
+        .chain(
+            spec.get("sources")
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten(),
+        )
+        .filter(|source| source.get("chart").is_none())
+        .filter(|source| repositories.contains(source.get("repoURL").and_then(Value::as_str)))
+        .filter_map(|source| source.get("path").and_then(Value::as_str))
+        .collect()
+}
+
+fn relative(path: &str) -> PathBuf {
+    PathBuf::from(path.trim_start_matches("./").trim_end_matches('/'))
+}
+
+/// Returns the directories of the repository that the git directory generators of an
+/// ApplicationSet select.
+fn generated_directories(
+    spec: &Value,
+    repository: &Path,
+    repositories: &Repositories,
+) -> Vec<PathBuf> {
+    let mut includes = GlobSetBuilder::new();
+    let mut excludes = GlobSetBuilder::new();
+    let generators = spec
+        .get("generators")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|generator| generator.get("git"))
+        .filter(|git| repositories.contains(git.get("repoURL").and_then(Value::as_str)));
+    for git in generators {
+        for directory in git
+            .get("directories")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+        {
+            let Some(pattern) = directory.get("path").and_then(Value::as_str) else {
+                continue;
+            };
+            let Ok(glob) = GlobBuilder::new(&relative(pattern).to_string_lossy())
+                .literal_separator(true)
+                .build()
+            else {
+                continue;
+            };
+            if directory.get("exclude").and_then(Value::as_bool) == Some(true) {
+                excludes.add(glob);
+            } else {
+                includes.add(glob);
+            }
+        }
+    }
+    let (Ok(includes), Ok(excludes)) = (includes.build(), excludes.build()) else {
+        return Vec::new();
+    };
+    if includes.is_empty() {
+        return Vec::new();
+    }
+    WalkDir::new(repository)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_dir())
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(repository).ok()?.to_owned();
+            (includes.is_match(&path) && !excludes.is_match(&path)).then_some(path)
+        })
+        .collect()
+}
+
+fn basename(directory: &Path) -> String {
+    directory
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Fills in the path parameters of a git directory generator in a template, in the plain and in
+/// the Go template syntax. Returns None if other parameters remain.
+fn expand(template: &str, directory: &Path) -> Option<String> {
+    let path = directory.to_string_lossy();
+    let basename = basename(directory);
+    let mut expanded = template.to_owned();
+    for (parameters, value) in [
+        (["path.basename", ".path.basename"], basename.as_str()),
+        (["path", ".path.path"], path.as_ref()),
+    ] {
+        for parameter in parameters {
+            for placeholder in [
+                format!("{{{{{}}}}}", parameter),
+                format!("{{{{ {} }}}}", parameter),
+            ] {
+                expanded = expanded.replace(&placeholder, value);
+            }
+        }
+    }
+    (!expanded.contains("{{")).then_some(expanded)
+}
+
+/// Returns the paths an ArgoCD Application deploys from the repository, or that the Applications
+/// generated by an ApplicationSet do.
+///
+/// ApplicationSets with a templated source path are only followed for their git directory
+/// generators, naming a component after each directory like `{{path.basename}}` does.
+pub fn applied_paths(
+    resource: &DynamicObject,
+    repository: &Path,
+    repositories: &Repositories,
+) -> Vec<AppliedPath> {
+    let Some(types) = resource
+        .types
+        .as_ref()
+        .filter(|t| t.api_version.starts_with("argoproj.io/"))
+    else {
+        return Vec::new();
+    };
+    let name = resource.metadata.name.clone().unwrap_or_default();
+    match types.kind.as_str() {
+        "Application" => resource
+            .data
+            .get("spec")
+            .map(|spec| source_paths(spec, repositories))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|path| AppliedPath {
+                name: name.clone(),
+                path: relative(path),
+            })
+            .collect(),
+        "ApplicationSet" => {
+            let Some(spec) = resource.data.get("spec") else {
+                return Vec::new();
+            };
+            let template_paths = spec
+                .pointer("/template/spec")
+                .map(|template| source_paths(template, repositories))
+                .unwrap_or_default();
+            let mut applied_paths = Vec::new();
+            for template_path in template_paths {
+                if !template_path.contains("{{") {
+                    applied_paths.push(AppliedPath {
+                        name: name.clone(),
+                        path: relative(template_path),
+                    });
+                    continue;
+                }
+                for directory in generated_directories(spec, repository, repositories) {
+                    if let Some(path) = expand(template_path, &directory) {
+                        applied_paths.push(AppliedPath {
+                            name: basename(&directory),
+                            path: relative(&path),
+                        });
+                    }
+                }
+            }
+            applied_paths
+        }
+        _ => Vec::new(),
+    }
+}
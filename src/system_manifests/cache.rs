@@ -0,0 +1,171 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use super::Document;
+
+/// Name of the directory in the system manifests directory that holds the cache.
+pub const CACHE_DIRECTORY_NAME: &str = ".dp-secrets-cache";
+
+const CACHE_FILE_NAME: &str = "index.json";
+
+/// The documents read from a manifest file, along with what identifies its contents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    modified: u128,
+    size: u64,
+    hash: String,
+    documents: Vec<Document>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    version: String,
+    /// Entries by file path relative to the system manifests directory.
+    entries: HashMap<PathBuf, Entry>,
+}
+
+fn modified(metadata: &Metadata) -> u128 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|modified| modified.as_nanos())
+        .unwrap_or_default()
+}
+
+/// Returns the hash a file's contents are cached by.
+pub fn hash(contents: &str) -> String {
+    Sha256::digest(contents.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// The documents of manifest files as read on earlier runs, so only files that changed since are
+/// parsed again. Files are looked up by path, modification time and size, and by the hash of
+/// their contents if only their modification time changed, like after a checkout.
+///
+/// The cache is written back to `.dp-secrets-cache/index.json` when dropped, leaving out files
+/// that no longer exist.
+#[derive(Debug)]
+pub struct Cache {
+    directory: PathBuf,
+    index: Index,
+    changed: bool,
+}
+
+impl Cache {
+    /// Loads the cache of a system manifests directory, starting afresh if there is none or it was
+    /// written by another version.
+    pub fn load(directory: &Path) -> Result<Self> {
+        let file = directory.join(CACHE_DIRECTORY_NAME).join(CACHE_FILE_NAME);
+        let index = match std::fs::read(&file) {
+            Ok(contents) => serde_json::from_slice::<Index>(&contents)
+                .ok()
+                .filter(|index| index.version == env!("CARGO_PKG_VERSION"))
+                .unwrap_or_default(),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Index::default(),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("Failed to read cache: {}", file.display()))
+            }
+        };
+        Ok(Cache {
+            directory: directory.to_owned(),
+            index,
+            changed: false,
+        })
+    }
+
+    fn key<'a>(&self, file: &'a Path) -> &'a Path {
+        file.strip_prefix(&self.directory).unwrap_or(file)
+    }
+
+    /// Returns the cached documents of a file if its modification time and size are unchanged.
+    pub fn get(&self, file: &Path, metadata: &Metadata) -> Option<Vec<Document>> {
+        self.index
+            .entries
+            .get(self.key(file))
+            .filter(|entry| entry.modified == modified(metadata) && entry.size == metadata.len())
+            .map(|entry| entry.documents.clone())
+    }
+
+    /// Returns the hash of the contents of a file when it was cached.
+    pub fn hash_of(&self, file: &Path) -> Option<String> {
+        let entry = self.index.entries.get(self.key(file))?;
+        Some(entry.hash.clone())
+    }
+
+    /// Returns the cached documents of a file whose contents are unchanged, remembering its new
+    /// modification time.
+    pub fn touch(&mut self, file: &Path, metadata: &Metadata) -> Option<Vec<Document>> {
+        let key = self.key(file).to_owned();
+        let entry = self.index.entries.get_mut(&key)?;
+        entry.modified = modified(metadata);
+        entry.size = metadata.len();
+        self.changed = true;
+        Some(entry.documents.clone())
+    }
+
+    pub fn insert(
+        &mut self,
+        file: &Path,
+        metadata: &Metadata,
+        hash: String,
+        documents: Vec<Document>,
+    ) {
+        let entry = Entry {
+            modified: modified(metadata),
+            size: metadata.len(),
+            hash,
+            documents,
+        };
+        let key = self.key(file).to_owned();
+        self.index.entries.insert(key, entry);
+        self.changed = true;
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let directory = &self.directory;
+        self.index
+            .entries
+            .retain(|file, _| directory.join(file).is_file());
+        self.index.version = env!("CARGO_PKG_VERSION").to_owned();
+        let cache_directory = directory.join(CACHE_DIRECTORY_NAME);
+        std::fs::create_dir_all(&cache_directory).with_context(|| {
+            format!(
+                "Failed to create cache directory: {}",
+                cache_directory.display()
+            )
+        })?;
+        let file = cache_directory.join(CACHE_FILE_NAME);
+        // Write to a temporary file first, so runs at the same time don't read half a cache.
+        let temporary = tempfile::NamedTempFile::new_in(&cache_directory)
+            .with_context(|| "Failed to create a temporary cache file")?;
+        let mut writer = BufWriter::new(temporary);
+        serde_json::to_writer(&mut writer, &self.index).with_context(|| "Failed to write cache")?;
+        writer
+            .into_inner()
+            .with_context(|| "Failed to write cache")?
+            .persist(&file)
+            .with_context(|| format!("Failed to write cache: {}", file.display()))?;
+        Ok(())
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        if !self.changed {
+            return;
+        }
+        if let Err(error) = self.save() {
+            eprintln!("Warning: {:?}", error);
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use kube::api::DynamicObject;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::{argocd, flux, is_manifest_file, parse_file_documents, Component, Document};
+
+/// How the platforms' components are found.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Discovery {
+    /// Every directory in `manifests/<platform>` is a component.
+    #[default]
+    Directories,
+    /// Every path a Flux Kustomization applies is a component, starting from the Kustomizations
+    /// in `clusters/<platform>`.
+    Flux,
+    /// Every path an ArgoCD Application or ApplicationSet deploys from this repository is a
+    /// component, starting from the ones in `clusters/<platform>`.
+    Argocd,
+}
+
+/// A path of the repository that a deployment tool applies, as a component to be.
+pub struct AppliedPath {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Returns the resources in the manifest files of a directory. Documents that aren't valid
+/// resources are left for reading the manifests to report.
+fn read_resources(directory: &Path) -> Result<Vec<DynamicObject>> {
+    let mut resources = Vec::new();
+    for entry in WalkDir::new(directory)
+        .follow_links(true)
+        .sort_by_file_name()
+    {
+        let entry = entry.with_context(|| format!("Failed to read {}", directory.display()))?;
+        if !entry.file_type().is_file() || !is_manifest_file(entry.path()) {
+            continue;
+        }
+        let contents = std::fs::read_to_string(entry.path())
+            .with_context(|| format!("Failed to read {}", entry.path().display()))?;
+        for document in parse_file_documents(entry.path(), &contents) {
+            let Document::Resource { resource, .. } = document else {
+                break;
+            };
+            resources.push(*resource);
+        }
+    }
+    Ok(resources)
+}
+
+/// Follows the deployment resources of a cluster directory to the paths they apply, and the
+/// deployment resources found there in turn, returning a component per path named after the
+/// resource applying it.
+///
+/// Paths are taken relative to the repository. Paths within the cluster directory are left out,
+/// as they are its bootstrap component.
+pub fn discover_components(
+    repository: &Path,
+    cluster_directory: &Path,
+    discovery: Discovery,
+) -> Result<Vec<Component>> {
+    let repositories = match discovery {
+        Discovery::Argocd => Some(argocd::Repositories::of(repository)),
+        _ => None,
+    };
+    let applied_paths = |directory: &Path| -> Result<Vec<AppliedPath>> {
+        let resources = read_resources(directory)?;
+        Ok(match &repositories {
+            Some(repositories) => resources
+                .iter()
+                .flat_map(|resource| argocd::applied_paths(resource, repository, repositories))
+                .collect(),
+            None => resources.iter().filter_map(flux::applied_path).collect(),
+        })
+    };
+    let canonical = |path: &Path| {
+        std::fs::canonicalize(path).with_context(|| format!("Failed to read {}", path.display()))
+    };
+
+    let cluster_directory = canonical(cluster_directory)?;
+    let mut visited: HashSet<PathBuf> = HashSet::new();
+    let mut queue: VecDeque<AppliedPath> = applied_paths(&cluster_directory)?.into();
+    let mut components = Vec::new();
+    while let Some(applied_path) = queue.pop_front() {
+        let path = repository.join(&applied_path.path);
+        let manifests_directory = canonical(&path)
+            .with_context(|| format!("Failed to follow the path of {}", applied_path.name))?;
+        if manifests_directory.starts_with(&cluster_directory)
+            || !visited.insert(manifests_directory.clone())
+        {
+            continue;
+        }
+        queue.extend(applied_paths(&manifests_directory)?);
+        components.push(Component {
+            name: applied_path.name,
+            manifests_directory: path,
+        });
+    }
+    Ok(components)
+}
@@ -0,0 +1,23 @@
+use kube::api::DynamicObject;
+use std::path::PathBuf;
+
+use super::discovery::AppliedPath;
+
+/// Returns the path a Flux Kustomization applies, assuming the repository is its source.
+pub fn applied_path(resource: &DynamicObject) -> Option<AppliedPath> {
+    let is_flux_kustomization = resource.types.as_ref().is_some_and(|t| {
+        t.kind == "Kustomization" && t.api_version.starts_with("kustomize.toolkit.fluxcd.io/")
+    });
+    if !is_flux_kustomization {
+        return None;
+    }
+    let path = resource
+        .data
+        .pointer("/spec/path")
+        .and_then(|path| path.as_str())
+        .unwrap_or_default();
+    Some(AppliedPath {
+        name: resource.metadata.name.clone().unwrap_or_default(),
+        path: PathBuf::from(path.trim_start_matches("./")),
+    })
+}
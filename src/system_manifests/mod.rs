@@ -1,15 +1,115 @@
 use anyhow::{Context, Result};
+use globset::GlobSet;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use k8s_openapi::serde::{Deserialize, Serialize};
-use kube::api::{DynamicObject, ObjectMeta};
+use kube::api::DynamicObject;
+use schemars::JsonSchema;
 use serde_yaml::Deserializer;
-use std::{path::PathBuf, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+use tracing::{debug, trace};
+use walkdir::WalkDir;
 
-use crate::Cli;
+use crate::config::{self, Config};
+use crate::filter::Filter;
+use crate::inventory::{CertificateTarget, SealedSecretTarget, SECRET_KINDS};
+use crate::render::{self, helm, Render};
+use crate::selector::LabelSelector;
+use crate::sops::{self, SopsMetadata};
+use crate::{git, Cli};
+pub use discovery::Discovery;
+
+use cache::Cache;
+pub use cache::CACHE_DIRECTORY_NAME;
+
+/// Name of the file in the system manifests directory listing paths to leave out, in gitignore
+/// syntax.
+pub const IGNORE_FILE_NAME: &str = ".dpsecretsignore";
+
+mod argocd;
+mod cache;
+mod discovery;
+mod flux;
+mod pipeline;
 
 #[derive(Debug, Clone)]
 pub struct SystemManifests {
     pub directory: PathBuf,
+    /// How the platforms' components were found.
+    pub discovery: Discovery,
+    /// Whether platforms missing their environments or manifests directory failed reading.
+    pub strict: bool,
     pub platforms: Vec<Rc<Platform>>,
+    /// Canonical paths of the only files to read resources from, if restricted.
+    pub changed_files: Option<HashSet<PathBuf>>,
+    /// How deep to walk into component manifest directories, unlimited if None.
+    pub max_depth: Option<usize>,
+    /// Whether to also read manifests in the environments and clusters directories.
+    pub include_bootstrap: bool,
+    /// Whether to skip files and documents that aren't valid resources instead of failing.
+    pub skip_invalid: bool,
+    /// How to render component manifests, read as they are if empty.
+    pub render: Vec<Render>,
+    /// The invalid files and documents skipped so far.
+    pub invalid: RefCell<Vec<InvalidManifest>>,
+    /// Kinds of resources that make up the secrets inventory.
+    pub secret_kinds: Vec<String>,
+    /// Manifest files to leave out, matched relative to the directory.
+    pub exclude: GlobSet,
+    /// Paths the ignore file of the directory leaves out.
+    pub ignore: Gitignore,
+    /// The only platforms to read, if not empty.
+    pub platform_filter: Vec<String>,
+    /// The only components to read, if not empty.
+    pub component_filter: Vec<String>,
+    /// Expression secret resources must satisfy to be listed.
+    pub filter: Option<Rc<Filter>>,
+    /// Requirements on the annotations of the resources listed.
+    pub annotation_selectors: Vec<LabelSelector>,
+    /// How many manifests to read and parse at a time.
+    pub jobs: usize,
+    /// Whether to decrypt SOPS-encrypted manifest files.
+    pub decrypt_sops: bool,
+    /// Documents of manifest files read on earlier runs, if caching.
+    pub cache: Option<Rc<RefCell<Cache>>>,
+    /// Whether to show the progress of reading manifests on stderr.
+    pub show_progress: bool,
+    /// Repositories read along with the one in the directory, whose platforms are among the
+    /// platforms.
+    pub repositories: Vec<Repository>,
+}
+
+/// Directories of system manifests repositories, by repository name.
+pub type RepositoryDirectories = Vec<(String, PathBuf)>;
+
+/// A further system manifests repository read along with the first one.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    pub directory: PathBuf,
+    /// Paths the ignore file of the repository leaves out.
+    pub ignore: Gitignore,
+}
+
+/// Names a system manifests repository after the last segment of its path or URL, without a
+/// `.git` or archive extension.
+pub fn repository_name(location: &str) -> String {
+    let trimmed = location.trim_end_matches(['/', '\\']);
+    let last = trimmed.rsplit(['/', '\\', ':']).next().unwrap_or(trimmed);
+    let name = [".git", ".tar.gz", ".tgz", ".tar", ".zip"]
+        .iter()
+        .find_map(|extension| last.strip_suffix(extension))
+        .unwrap_or(last);
+    if !name.is_empty() && name != "." && name != ".." {
+        return name.to_owned();
+    }
+    std::fs::canonicalize(location)
+        .ok()
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| location.to_owned())
 }
 
 fn validate_directories_exist(directories: &[&PathBuf]) -> Result<()> {
@@ -25,6 +125,35 @@ fn validate_directories_exist(directories: &[&PathBuf]) -> Result<()> {
     Ok(())
 }
 
+/// Returns the names of the platforms in a clusters directory, leaving out those the ignore file
+/// matches.
+fn unignored_platform_names(
+    clusters_directory: &PathBuf,
+    ignore: &Gitignore,
+) -> Result<Vec<String>> {
+    Ok(
+        get_cluster_names_from_clusters_directories(clusters_directory)?
+            .into_iter()
+            .filter(|name| {
+                let ignored = ignore
+                    .matched(Path::new("clusters").join(name), true)
+                    .is_ignore();
+                if ignored {
+                    debug!(platform = %name, "Leaving out platform the ignore file matches");
+                }
+                !ignored
+            })
+            .collect(),
+    )
+}
+
+/// Returns the names of the platforms of a system manifests directory, the directories in
+/// `clusters` the ignore file doesn't match.
+pub fn platform_names(directory: &Path) -> Result<Vec<String>> {
+    let ignore = read_ignore_file(directory)?;
+    unignored_platform_names(&directory.join("clusters"), &ignore)
+}
+
 fn get_cluster_names_from_clusters_directories(
     clusters_directory: &PathBuf,
 ) -> Result<Vec<String>> {
@@ -44,8 +173,7 @@ fn get_cluster_names_from_clusters_directories(
         if path.is_dir() {
             platforms.push(
                 path.file_stem()
-                    .map(|os_string| os_string.to_str())
-                    .flatten()
+                    .and_then(|os_string| os_string.to_str())
                     .with_context(|| "Failed to read a platform directory name")?
                     .to_owned(),
             );
@@ -56,60 +184,347 @@ fn get_cluster_names_from_clusters_directories(
 }
 
 impl SystemManifests {
-    pub fn new(cli: &Cli) -> Result<Self> {
-        let directory: PathBuf = cli.system_manifests.clone().into();
+    /// Reads the platforms of the system manifests repositories in the given directories, by
+    /// name. Platforms are only told apart by their repository if there are several.
+    pub fn new(cli: &Cli, config: &Config, directories: RepositoryDirectories) -> Result<Self> {
+        let discovery = cli.discover.or(config.discover).unwrap_or_default();
+        let strict = cli.strict || config.strict.unwrap_or_default();
+        let mut directories = directories.into_iter();
+        let (name, directory) = directories
+            .next()
+            .with_context(|| "The system manifests directory is required")?;
+        let mut system_manifests = Self::from_directory(directory, discovery, strict)?;
+        let mut names = vec![name.clone()];
+        for (other_name, other_directory) in directories {
+            anyhow::ensure!(
+                !names.contains(&other_name),
+                "Several system manifests repositories are named {}, their platforms couldn't \
+                be told apart",
+                other_name
+            );
+            let other = Self::from_directory(other_directory, discovery, strict)
+                .with_context(|| format!("Failed to read repository {}", other_name))?;
+            system_manifests.add_repository(other_name.clone(), other);
+            names.push(other_name);
+        }
+        if !system_manifests.repositories.is_empty() {
+            system_manifests.set_platforms_repository(&name);
+        }
+        system_manifests.platform_filter = cli.platform.clone();
+        system_manifests.component_filter = cli.component.clone();
+        system_manifests.retain_filtered()?;
+        system_manifests.filter = cli
+            .filter
+            .as_deref()
+            .map(Filter::new)
+            .transpose()?
+            .map(Rc::new);
+        system_manifests.annotation_selectors = cli.annotation_selector.clone();
+        system_manifests.max_depth = cli.max_depth.or(config.max_depth);
+        system_manifests.include_bootstrap =
+            cli.include_bootstrap || config.include_bootstrap.unwrap_or_default();
+        system_manifests.skip_invalid = cli.skip_invalid || config.skip_invalid.unwrap_or_default();
+        system_manifests.render = if cli.render.is_empty() {
+            config.render.clone().unwrap_or_default()
+        } else {
+            cli.render.clone()
+        };
+        if let Some(kinds) = &config.kinds {
+            system_manifests.secret_kinds = kinds.clone();
+        }
+        system_manifests.exclude = config::exclude_set(config.exclude.iter().chain(&cli.exclude))?;
+        if let Some(jobs) = cli.jobs {
+            system_manifests.jobs = jobs;
+        }
+        system_manifests.decrypt_sops = cli.decrypt_sops;
+        if cli.cache || config.cache.unwrap_or_default() {
+            system_manifests.cache = Some(Rc::new(RefCell::new(Cache::load(
+                &system_manifests.directory,
+            )?)));
+        }
+        if let Some(reference) = &cli.changed_since {
+            system_manifests.changed_files = Some(
+                git::changed_files(&system_manifests.directory, reference)
+                    .with_context(|| format!("Failed to list files changed since {}", reference))?,
+            );
+        }
+        Ok(system_manifests)
+    }
+
+    /// Reads the platforms of a system manifests directory. Platforms missing their environments
+    /// or manifests directory are read without it, unless `strict`.
+    pub fn from_directory(directory: PathBuf, discovery: Discovery, strict: bool) -> Result<Self> {
         let clusters_directory = directory.join("clusters");
         validate_directories_exist(&[&clusters_directory])
             .with_context(|| "Failed to obtain clusters directory")?;
-        let platforms = get_cluster_names_from_clusters_directories(&clusters_directory)?
+        let ignore = read_ignore_file(&directory)?;
+        let platforms = unignored_platform_names(&clusters_directory, &ignore)?
             .into_iter()
-            .map(|name| Platform::new(name, directory.clone()).map(Rc::new))
+            .map(|name| Platform::new(name, directory.clone(), discovery, strict).map(Rc::new))
             .collect::<Result<_>>()?;
         Ok(SystemManifests {
             directory,
+            discovery,
+            strict,
             platforms,
+            changed_files: None,
+            max_depth: None,
+            include_bootstrap: false,
+            skip_invalid: false,
+            render: Vec::new(),
+            invalid: RefCell::new(Vec::new()),
+            secret_kinds: SECRET_KINDS.iter().map(|kind| kind.to_string()).collect(),
+            exclude: GlobSet::empty(),
+            ignore,
+            platform_filter: Vec::new(),
+            component_filter: Vec::new(),
+            filter: None,
+            annotation_selectors: Vec::new(),
+            jobs: std::thread::available_parallelism().map_or(1, usize::from),
+            decrypt_sops: false,
+            cache: None,
+            show_progress: false,
+            repositories: Vec::new(),
         })
     }
-}
 
-impl<'a> SystemManifests {
-    pub fn resource_iter(&'a self) -> SystemManifestsResourceIterator<'a> {
-        SystemManifestsResourceIterator::new(self)
+    /// Sets the repository of the platforms that don't have one yet.
+    fn set_platforms_repository(&mut self, name: &str) {
+        for platform in &mut self.platforms {
+            if platform.repo.is_none() {
+                let mut named = Platform::clone(platform);
+                named.repo = Some(name.to_owned());
+                *platform = Rc::new(named);
+            }
+        }
     }
-}
 
-pub struct SystemManifestsResourceIterator<'a> {
-    resource_iterator: Box<dyn Iterator<Item = anyhow::Result<ManifestResource>> + 'a>,
-}
+    /// Adds the platforms of another repository to the ones read.
+    pub fn add_repository(&mut self, name: String, mut other: SystemManifests) {
+        other.set_platforms_repository(&name);
+        self.platforms.extend(other.platforms);
+        self.repositories.push(Repository {
+            directory: other.directory,
+            ignore: other.ignore,
+        });
+    }
+
+    /// Returns a path relative to the directory of the repository it's in, along with that
+    /// repository's ignore file.
+    fn relative_path<'a>(&'a self, path: &'a Path) -> Option<(&'a Path, &'a Gitignore)> {
+        [(&self.directory, &self.ignore)]
+            .into_iter()
+            .chain(
+                self.repositories
+                    .iter()
+                    .map(|repository| (&repository.directory, &repository.ignore)),
+            )
+            .find_map(|(directory, ignore)| Some((path.strip_prefix(directory).ok()?, ignore)))
+    }
 
-impl<'a> SystemManifestsResourceIterator<'a> {
-    fn new(system_manifests: &'a SystemManifests) -> Self {
-        let resource_iterator = system_manifests
+    /// Returns the directory of the repository a path is in, the first one's if it's in none.
+    pub fn repository_directory(&self, path: &Path) -> &Path {
+        self.repositories
+            .iter()
+            .map(|repository| repository.directory.as_path())
+            .find(|directory| path.starts_with(directory))
+            .unwrap_or(&self.directory)
+    }
+
+    /// Drops the platforms and components left out by the filters, failing if a filter names a
+    /// platform or a component that doesn't exist.
+    fn retain_filtered(&mut self) -> Result<()> {
+        for name in &self.platform_filter {
+            if !self.platforms.iter().any(|platform| &platform.name == name) {
+                anyhow::bail!(
+                    "Unknown platform {}, expected one of: {}",
+                    name,
+                    self.platforms
+                        .iter()
+                        .map(|platform| platform.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+        let platform_filter = &self.platform_filter;
+        let component_filter = &self.component_filter;
+        self.platforms.retain(|platform| {
+            platform_filter.is_empty() || platform_filter.contains(&platform.name)
+        });
+        if component_filter.is_empty() {
+            return Ok(());
+        }
+        let component_names: BTreeSet<&str> = self
             .platforms
             .iter()
-            .flat_map(|p| p.resource_iter());
+            .flat_map(|platform| platform.components(true))
+            .map(|component| component.name.as_str())
+            .collect();
+        for name in component_filter {
+            if !component_names.contains(name.as_str()) {
+                anyhow::bail!(
+                    "Unknown component {}, expected one of: {}",
+                    name,
+                    component_names
+                        .iter()
+                        .copied()
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+        }
+        for platform in &mut self.platforms {
+            let mut filtered = Platform::clone(platform);
+            filtered
+                .components
+                .retain(|component| component_filter.contains(&component.name));
+            filtered
+                .bootstrap_components
+                .retain(|component| component_filter.contains(&component.name));
+            *platform = Rc::new(filtered);
+        }
+        Ok(())
+    }
 
-        SystemManifestsResourceIterator {
-            resource_iterator: Box::new(resource_iterator),
+    /// Reads manifests with the same options as `other`.
+    pub fn copy_options(&mut self, other: &SystemManifests) -> Result<()> {
+        self.max_depth = other.max_depth;
+        self.include_bootstrap = other.include_bootstrap;
+        self.skip_invalid = other.skip_invalid;
+        self.render = other.render.clone();
+        self.secret_kinds = other.secret_kinds.clone();
+        self.exclude = other.exclude.clone();
+        self.platform_filter = other.platform_filter.clone();
+        self.component_filter = other.component_filter.clone();
+        self.filter = other.filter.clone();
+        self.annotation_selectors = other.annotation_selectors.clone();
+        self.jobs = other.jobs;
+        self.decrypt_sops = other.decrypt_sops;
+        self.retain_filtered()
+    }
+
+    /// Returns whether the resources of a manifest file are among the changed ones, if restricted
+    /// to those. Those of a kustomization are if any file of its directory changed.
+    pub fn is_changed(&self, file: &Path) -> bool {
+        let changed_files = self.changed_files.as_ref();
+        match file.parent() {
+            Some(directory) if render::is_kustomization_file(file) => {
+                is_changed_directory(changed_files, directory)
+            }
+            _ => is_changed(changed_files, file),
         }
     }
-}
 
-impl<'a> Iterator for SystemManifestsResourceIterator<'a> {
-    type Item = Result<ManifestResource>;
+    /// Returns whether a resource is among the changed ones, if restricted to those, and
+    /// satisfies the annotation selectors and the filter expression, if there is one.
+    pub fn matches_filter(&self, manifest_resource: &ManifestResource) -> Result<bool> {
+        if !self.is_changed(&manifest_resource.file) {
+            return Ok(false);
+        }
+        let annotations = manifest_resource.resource.metadata.annotations.as_ref();
+        if !self
+            .annotation_selectors
+            .iter()
+            .all(|selector| selector.matches(annotations))
+        {
+            return Ok(false);
+        }
+        self.filter
+            .as_ref()
+            .map_or(Ok(true), |filter| filter.matches(manifest_resource))
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.resource_iterator.next()
+    /// Returns whether a manifest file matches one of the exclusion patterns, or the ignore file
+    /// leaves it or one of its directories out.
+    pub fn is_excluded(&self, file: &Path) -> bool {
+        self.relative_path(file).is_some_and(|(relative, ignore)| {
+            self.exclude.is_match(relative)
+                || ignore
+                    .matched_path_or_any_parents(relative, false)
+                    .is_ignore()
+        })
     }
+
+    /// Returns whether the ignore file leaves a directory out, so it isn't walked into.
+    fn is_ignored_directory(&self, directory: &Path) -> bool {
+        self.relative_path(directory)
+            .is_some_and(|(relative, ignore)| {
+                ignore
+                    .matched_path_or_any_parents(relative, true)
+                    .is_ignore()
+            })
+    }
+
+    /// Returns the warnings about how the platforms were read, which machine-readable outputs
+    /// carry along with the report.
+    pub fn platform_warnings(&self) -> Vec<PlatformWarning> {
+        self.platforms
+            .iter()
+            .flat_map(|platform| {
+                platform.missing_directories.iter().map(|directory| {
+                    let directory = self
+                        .relative_path(directory)
+                        .map_or(directory.as_path(), |(path, _)| path);
+                    PlatformWarning {
+                        platform_name: platform.name.clone(),
+                        repo: platform.repo.clone(),
+                        message: format!(
+                            "{} doesn't exist, the platform is read without it",
+                            directory.display()
+                        ),
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// Writes the directories platforms were read without, and the invalid files and documents
+    /// that were skipped, to stderr.
+    pub fn report_invalid(&self) {
+        for warning in self.platform_warnings() {
+            eprintln!("Platform {}: {}", warning.platform_name, warning.message);
+        }
+        let mut invalid = self.invalid.borrow().clone();
+        if invalid.is_empty() {
+            return;
+        }
+        invalid.sort();
+        invalid.dedup();
+        eprintln!("Skipped {} invalid manifest documents:", invalid.len());
+        for invalid_manifest in invalid {
+            eprintln!("  {}", invalid_manifest);
+        }
+    }
+}
+
+/// Something to know about how a platform was read, like a directory it was read without.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PlatformWarning {
+    pub platform_name: String,
+    /// The repository the platform is read from, if several are.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    pub message: String,
 }
 
 #[derive(Debug, Clone)]
 pub struct Platform {
     pub name: String,
+    /// The repository the platform is read from, if several are.
+    pub repo: Option<String>,
+    #[allow(dead_code)]
     pub environment_directory: PathBuf,
+    #[allow(dead_code)]
     pub cluster_directory: PathBuf,
+    #[allow(dead_code)]
     pub manifests_directory: PathBuf,
     pub components: Vec<Rc<Component>>,
+    /// The environments and clusters directories as components, for Secrets bootstrapped there.
+    pub bootstrap_components: Vec<Rc<Component>>,
+    /// The environments and manifests directories of the platform that don't exist, like for an
+    /// archived cluster, when not reading strictly.
+    pub missing_directories: Vec<PathBuf>,
 }
 
 fn get_component_names_from_manifest_directory(
@@ -131,8 +546,7 @@ fn get_component_names_from_manifest_directory(
         if path.is_dir() {
             components.push(
                 path.file_stem()
-                    .map(|os_string| os_string.to_str())
-                    .flatten()
+                    .and_then(|os_string| os_string.to_str())
                     .with_context(|| "Failed to read a component manifest directory")?
                     .to_owned(),
             );
@@ -143,7 +557,14 @@ fn get_component_names_from_manifest_directory(
 }
 
 impl Platform {
-    pub fn new(name: String, system_manifest_directory: PathBuf) -> Result<Self> {
+    /// Reads the components of a platform. With the directories layout, a platform missing its
+    /// environments or manifests directory fails if `strict`, and is read without it otherwise.
+    pub fn new(
+        name: String,
+        system_manifest_directory: PathBuf,
+        discovery: Discovery,
+        strict: bool,
+    ) -> Result<Self> {
         let environment_directory = system_manifest_directory
             .join("environments")
             .join(name.clone());
@@ -153,36 +574,102 @@ impl Platform {
         let manifests_directory = system_manifest_directory
             .join("manifests")
             .join(name.clone());
-        validate_directories_exist(&[
-            &environment_directory,
-            &cluster_directory,
-            &manifests_directory,
-        ])
-        .with_context(|| "Failed to obtain platform directories")?;
-        let components: Vec<Rc<Component>> =
-            get_component_names_from_manifest_directory(&manifests_directory)?
+        let missing_directories: Vec<PathBuf> = match discovery {
+            Discovery::Directories if !strict => [&environment_directory, &manifests_directory]
                 .into_iter()
-                .map(|name| {
-                    let component_manifests_directory = manifests_directory.join(name.clone());
-                    validate_directories_exist(&[&component_manifests_directory])
-                        .with_context(|| "Failed to obtain component manifest directory")?;
-                    Ok(Rc::new(Component {
-                        name,
-                        manifests_directory: component_manifests_directory,
-                    }))
-                })
-                .collect::<Result<_>>()?;
+                .filter(|directory| !directory.exists())
+                .cloned()
+                .collect(),
+            _ => Vec::new(),
+        };
+        let components: Vec<Rc<Component>> = match discovery {
+            Discovery::Directories => {
+                let directories: Vec<&PathBuf> = [
+                    &environment_directory,
+                    &cluster_directory,
+                    &manifests_directory,
+                ]
+                .into_iter()
+                .filter(|directory| !missing_directories.contains(directory))
+                .collect();
+                validate_directories_exist(&directories)
+                    .with_context(|| "Failed to obtain platform directories")?;
+                if missing_directories.contains(&manifests_directory) {
+                    Vec::new()
+                } else {
+                    get_component_names_from_manifest_directory(&manifests_directory)?
+                        .into_iter()
+                        .map(|name| {
+                            let component_manifests_directory =
+                                manifests_directory.join(name.clone());
+                            validate_directories_exist(&[&component_manifests_directory])
+                                .with_context(|| "Failed to obtain component manifest directory")?;
+                            Ok(Rc::new(Component {
+                                name,
+                                manifests_directory: component_manifests_directory,
+                            }))
+                        })
+                        .collect::<Result<_>>()?
+                }
+            }
+            discovery => discovery::discover_components(
+                &system_manifest_directory,
+                &cluster_directory,
+                discovery,
+            )
+            .with_context(|| format!("Failed to discover components of platform {}", name))?
+            .into_iter()
+            .map(Rc::new)
+            .collect(),
+        };
+        let bootstrap_components = [
+            ("environments", &environment_directory),
+            ("clusters", &cluster_directory),
+        ]
+        .into_iter()
+        // Without the fixed layout, or when read without it, a platform may have no environments
+        // directory.
+        .filter(|(_, directory)| directory.is_dir())
+        .map(|(name, directory)| {
+            Rc::new(Component {
+                name: name.to_owned(),
+                manifests_directory: directory.clone(),
+            })
+        })
+        .collect();
+        debug!(
+            platform = %name,
+            components = components.len(),
+            ?discovery,
+            "Discovered platform"
+        );
+        for component in &components {
+            debug!(
+                platform = %name,
+                component = %component.name,
+                directory = %component.manifests_directory.display(),
+                "Discovered component"
+            );
+        }
         Ok(Platform {
             name,
+            repo: None,
             environment_directory,
             cluster_directory,
             manifests_directory,
             components,
+            bootstrap_components,
+            missing_directories,
         })
     }
 
-    fn resource_iter(self: &Rc<Self>) -> PlatformResourceIterator {
-        PlatformResourceIterator::new(self)
+    /// Returns the components, followed by the bootstrap components if included.
+    pub fn components(&self, include_bootstrap: bool) -> impl Iterator<Item = &Rc<Component>> {
+        self.components.iter().chain(
+            self.bootstrap_components
+                .iter()
+                .filter(move |_| include_bootstrap),
+        )
     }
 }
 
@@ -195,113 +682,418 @@ pub struct Component {
 #[derive(Clone)]
 pub struct ManifestResource {
     pub file: PathBuf,
+    /// The line the resource's YAML document starts at, if known.
+    pub line: Option<usize>,
     pub component: Rc<Component>,
     pub platform: Rc<Platform>,
     pub resource: DynamicObject,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 pub struct FlatManifestResource {
     pub file: PathBuf,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
     pub component_name: String,
     pub platform_name: String,
+    /// The repository the platform is read from, if several are.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo: Option<String>,
+    pub api_version: String,
+    pub kind: String,
     pub resource_meta: kube::core::ObjectMeta,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keys: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sealed_secret: Option<SealedSecretTarget>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sops: Option<SopsMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certificate: Option<CertificateTarget>,
 }
 
 impl From<ManifestResource> for FlatManifestResource {
     fn from(value: ManifestResource) -> Self {
+        let types = value.resource.types.unwrap_or_default();
         FlatManifestResource {
             file: value.file.clone(),
+            line: value.line,
             component_name: value.component.name.clone(),
             platform_name: value.platform.name.clone(),
+            repo: value.platform.repo.clone(),
+            api_version: types.api_version,
+            kind: types.kind,
             resource_meta: value.resource.metadata,
+            keys: None,
+            sealed_secret: None,
+            sops: None,
+            certificate: None,
         }
     }
 }
 
-pub struct PlatformResourceIterator<'a> {
-    platform: Rc<Platform>,
-    resource_iterator: Box<dyn Iterator<Item = anyhow::Result<ManifestResource>> + 'a>,
+/// A manifest file, or a document in one, that can't be read as a Kubernetes resource.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InvalidManifest {
+    pub file: PathBuf,
+    /// Line and column of the YAML error.
+    pub position: Option<(usize, usize)>,
+    pub message: String,
 }
 
-impl<'a> PlatformResourceIterator<'a> {
-    fn new(platform: &'a Rc<Platform>) -> PlatformResourceIterator<'a> {
-        let platform_clone: Rc<Platform> = platform.clone();
+impl InvalidManifest {
+    fn new(file: PathBuf, position: Option<(usize, usize)>, error: &dyn std::fmt::Display) -> Self {
+        let mut message = error.to_string();
+        if let Some((line, column)) = position {
+            let suffix = format!(" at line {} column {}", line, column);
+            if let Some(stripped) = message.strip_suffix(&suffix) {
+                message = stripped.to_owned();
+            }
+        }
+        InvalidManifest {
+            file,
+            position,
+            message,
+        }
+    }
+
+    fn from_yaml(file: PathBuf, error: &serde_yaml::Error) -> Self {
+        let position = error
+            .location()
+            .map(|location| (location.line(), location.column()));
+        InvalidManifest::new(file, position, error)
+    }
 
-        let resource_iterator = platform
-            .components
-            .iter()
-            .flat_map(|c: &Rc<Component>| {
-                std::fs::read_dir(&c.manifests_directory)
-                    .into_iter()
-                    .flat_map(move |rd| {
-                        rd.into_iter()
-                            .filter(|dr| match dr {
-                                Ok(dir_entry) => {
-                                    dir_entry.path().is_file()
-                                        && dir_entry
-                                            .path()
-                                            .extension()
-                                            .map_or(false, |ext| ext == "yaml" || ext == "yml")
-                                }
-                                _ => true, // propagate errors
-                            })
-                            .map({
-                                let c = c.clone();
-                                move |dr| {
-                                    let c = c.clone();
-                                    dr.map(move |dir_entry: std::fs::DirEntry| (c, dir_entry))
-                                }
-                            })
-                    })
-            })
-            .flat_map(move |file_res| {
-                file_res
-                    .into_iter()
-                    .flat_map({
-                        let platform_clone = platform_clone.clone();
-                        move |(c, dir_entry)| {
-                            let c = c.clone();
-                            std::fs::File::open(dir_entry.path())
-                                .map(|file| std::io::BufReader::new(file))
-                                .map({
-                                    let platform_clone = platform_clone.clone();
-                                    move |reader: std::io::BufReader<std::fs::File>| {
-                                        Deserializer::from_reader(reader).into_iter().map(
-                                            move |doc| {
-                                                DynamicObject::deserialize(doc)
-                                                    .map_err(anyhow::Error::from)
-                                                    .map({
-                                                        let component = c.clone();
-                                                        let platform = platform_clone.clone();
-                                                        let file = dir_entry.path().to_owned();
-                                                        move |resource| ManifestResource {
-                                                            file,
-                                                            component,
-                                                            platform,
-                                                            resource,
-                                                        }
-                                                    })
-                                            },
-                                        )
-                                    }
-                                })
-                        }
-                    })
-                    .flatten()
-            });
+    fn from_json(file: PathBuf, error: &serde_json::Error) -> Self {
+        let position = (error.line() > 0).then(|| (error.line(), error.column()));
+        InvalidManifest::new(file, position, error)
+    }
+}
 
-        PlatformResourceIterator {
-            platform: platform.clone(),
-            resource_iterator: Box::new(resource_iterator),
+impl std::fmt::Display for InvalidManifest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.file.display())?;
+        if let Some((line, column)) = self.position {
+            write!(f, ":{}:{}", line, column)?;
         }
+        write!(f, ": {}", self.message)
     }
 }
 
-impl<'a> Iterator for PlatformResourceIterator<'a> {
-    type Item = Result<ManifestResource>;
+impl std::error::Error for InvalidManifest {}
 
-    fn next(&mut self) -> Option<Self::Item> {
-        self.resource_iterator.next()
+/// A YAML document or JSON value of a manifest, as a resource or why it isn't a valid resource.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Document {
+    Resource {
+        line: Option<usize>,
+        resource: Box<DynamicObject>,
+    },
+    Invalid {
+        position: Option<(usize, usize)>,
+        message: String,
+    },
+}
+
+impl Document {
+    /// Returns whether the document is a resource encrypted with SOPS.
+    fn is_encrypted(&self) -> bool {
+        matches!(self, Document::Resource { resource, .. } if sops::is_encrypted(resource))
     }
+
+    /// Returns the resource of the document, or why it isn't a valid resource.
+    fn read(
+        self,
+        file: &Path,
+    ) -> std::result::Result<(Option<usize>, DynamicObject), InvalidManifest> {
+        match self {
+            Document::Resource { line, resource } => Ok((line, *resource)),
+            Document::Invalid { position, message } => Err(InvalidManifest {
+                file: file.to_owned(),
+                position,
+                message,
+            }),
+        }
+    }
+}
+
+pub(crate) fn is_marker(line: &str, marker: &str) -> bool {
+    line.strip_prefix(marker)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(char::is_whitespace))
+}
+
+/// Returns the line each YAML document in a file starts at, starting at 1. That is the first line
+/// with content, or the `---` line of an empty document.
+fn document_lines(contents: &str) -> Vec<usize> {
+    // The start line of each document and whether it has content yet.
+    let mut documents: Vec<(usize, bool)> = Vec::new();
+    let mut ended = true;
+    for (index, line) in contents.lines().enumerate() {
+        let number = index + 1;
+        if is_marker(line, "---") {
+            let has_content = !line[3..].trim().is_empty();
+            documents.push((number, has_content));
+            ended = false;
+        } else if is_marker(line, "...") {
+            ended = true;
+        } else if !line.trim().is_empty()
+            && !line.trim_start().starts_with('#')
+            && !line.starts_with('%')
+        {
+            match documents.last_mut() {
+                Some(document) if !ended && !document.1 => *document = (number, true),
+                Some(_) if !ended => {}
+                _ => {
+                    documents.push((number, true));
+                    ended = false;
+                }
+            }
+        }
+    }
+    documents.into_iter().map(|(number, _)| number).collect()
+}
+
+/// Adds the resources rendered from the charts of HelmReleases after each HelmRelease, attributing
+/// them to the HelmRelease. Charts are looked up in the HelmRepositories among the resources.
+fn render_helm_releases<'a>(
+    resources: Vec<Result<ManifestResource>>,
+    directory: &'a Path,
+) -> impl Iterator<Item = Result<ManifestResource>> + 'a {
+    let repositories = helm::HelmRepositories::collect(resources.iter().flatten());
+    resources.into_iter().flat_map(
+        move |resource| -> Box<dyn Iterator<Item = Result<ManifestResource>>> {
+            let release = match resource {
+                Ok(release) if helm::is_helm_release(&release) => release,
+                resource => return Box::new(std::iter::once(resource)),
+            };
+            let rendered = match helm::helm_template(&release, &repositories, directory) {
+                Ok(contents) => {
+                    let line = release.line;
+                    let rendered = document_resources(
+                        release.file.clone(),
+                        parse_documents(&release.file, &contents, Vec::new()),
+                        release.component.clone(),
+                        release.platform.clone(),
+                    );
+                    Box::new(rendered.into_iter().map(move |resource| {
+                        resource.map(|resource| ManifestResource { line, ..resource })
+                    })) as Box<dyn Iterator<Item = _>>
+                }
+                Err(error) => Box::new(std::iter::once(Err(error))),
+            };
+            Box::new(std::iter::once(Ok(release)).chain(rendered))
+        },
+    )
+}
+
+/// Returns whether a file is a manifest by its extension: YAML, JSON, or JSON Lines with a
+/// resource per line.
+pub(crate) fn is_manifest_file(file: &Path) -> bool {
+    file.extension()
+        .is_some_and(|ext| ext == "yaml" || ext == "yml" || is_json_extension(ext))
+}
+
+fn is_json_extension(ext: &std::ffi::OsStr) -> bool {
+    ext == "json" || ext == "jsonl"
+}
+
+/// Parses the documents of a manifest file, as JSON values for JSON files and YAML documents
+/// otherwise.
+pub(crate) fn parse_file_documents(file: &Path, contents: &str) -> Vec<Document> {
+    if file.extension().is_some_and(is_json_extension) {
+        parse_json_documents(file, contents)
+    } else {
+        parse_documents(file, contents, document_lines(contents))
+    }
+}
+
+/// Parses the JSON values of a manifest, a single one or several following each other as in JSON
+/// Lines files, along with the line each starts at.
+fn parse_json_documents(file: &Path, contents: &str) -> Vec<Document> {
+    let mut documents = Vec::new();
+    let mut values = serde_json::Deserializer::from_str(contents).into_iter::<DynamicObject>();
+    loop {
+        let offset = values.byte_offset();
+        match values.next() {
+            Some(Ok(resource)) => {
+                let start = contents[offset..]
+                    .find(|c: char| !c.is_whitespace())
+                    .map_or(offset, |start| offset + start);
+                documents.push(Document::Resource {
+                    line: Some(contents[..start].matches('\n').count() + 1),
+                    resource: Box::new(resource),
+                });
+            }
+            Some(Err(error)) => {
+                // The values after a syntax error can't be told apart, so stop there.
+                let invalid = InvalidManifest::from_json(file.to_owned(), &error);
+                documents.push(Document::Invalid {
+                    position: invalid.position,
+                    message: invalid.message,
+                });
+                break;
+            }
+            None => break,
+        }
+    }
+    documents
+}
+
+/// Parses the YAML documents of a manifest, given the line each document starts at if known.
+fn parse_documents(file: &Path, contents: &str, lines: Vec<usize>) -> Vec<Document> {
+    let mut lines = lines.into_iter();
+    let mut last_invalid: Option<InvalidManifest> = None;
+    Deserializer::from_str(contents)
+        .map_while(|doc| {
+            let line = lines.next();
+            match DynamicObject::deserialize(doc) {
+                Ok(resource) => Some(Document::Resource {
+                    line,
+                    resource: Box::new(resource),
+                }),
+                Err(error) => {
+                    let invalid = InvalidManifest::from_yaml(file.to_owned(), &error);
+                    // A YAML syntax error is repeated for every following document, so stop there.
+                    if last_invalid.as_ref() == Some(&invalid) {
+                        return None;
+                    }
+                    last_invalid = Some(invalid.clone());
+                    Some(Document::Invalid {
+                        position: invalid.position,
+                        message: invalid.message,
+                    })
+                }
+            }
+        })
+        .collect()
+}
+
+/// Returns the resources of the parsed documents of a manifest, or why they aren't valid
+/// resources.
+fn document_resources(
+    file: PathBuf,
+    documents: Vec<Document>,
+    component: Rc<Component>,
+    platform: Rc<Platform>,
+) -> Vec<Result<ManifestResource>> {
+    documents
+        .into_iter()
+        .map(|document| {
+            let (line, resource) = document.read(&file)?;
+            trace!(
+                file = %file.display(),
+                line,
+                kind = resource.types.as_ref().map(|t| t.kind.as_str()),
+                name = resource.metadata.name.as_deref(),
+                "Read resource"
+            );
+            Ok(ManifestResource {
+                file: file.clone(),
+                line,
+                component: component.clone(),
+                platform: platform.clone(),
+                resource,
+            })
+        })
+        .collect()
+}
+
+/// Where resources of a component are read from.
+enum ManifestSource {
+    File(PathBuf),
+    /// A directory to build with kustomize, and its kustomization file.
+    Kustomization(PathBuf, PathBuf),
+}
+
+/// Reads the ignore file of a system manifests directory, matching nothing if there is none.
+fn read_ignore_file(directory: &Path) -> Result<Gitignore> {
+    let path = directory.join(IGNORE_FILE_NAME);
+    if !path.is_file() {
+        return Ok(Gitignore::empty());
+    }
+    debug!(file = %path.display(), "Reading ignore file");
+    let mut builder = GitignoreBuilder::new(directory);
+    if let Some(error) = builder.add(&path) {
+        return Err(error).with_context(|| format!("Failed to read {}", path.display()));
+    }
+    builder
+        .build()
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// Returns whether a file is among the changed files, if restricted to those.
+fn is_changed(changed_files: Option<&HashSet<PathBuf>>, file: &Path) -> bool {
+    changed_files.is_none_or(|changed_files| {
+        std::fs::canonicalize(file).is_ok_and(|path| changed_files.contains(&path))
+    })
+}
+
+/// Returns whether a file below a directory is among the changed files, if restricted to those.
+fn is_changed_directory(changed_files: Option<&HashSet<PathBuf>>, directory: &Path) -> bool {
+    changed_files.is_none_or(|changed_files| {
+        std::fs::canonicalize(directory).is_ok_and(|directory| {
+            changed_files
+                .iter()
+                .any(|file| file.starts_with(&directory))
+        })
+    })
+}
+
+/// Walks a component's manifests directory for manifest files, and for directories with a
+/// kustomization when rendering with kustomize. Files inside such directories are left to the
+/// kustomization, which is only built if one of them changed when restricted to `changed_only`
+/// files.
+fn manifest_sources<'a>(
+    component: &Component,
+    system_manifests: &'a SystemManifests,
+    changed_only: bool,
+) -> impl Iterator<Item = walkdir::Result<ManifestSource>> + 'a {
+    let changed_files = system_manifests
+        .changed_files
+        .as_ref()
+        .filter(|_| changed_only);
+    let mut kustomization_directory: Option<PathBuf> = None;
+    WalkDir::new(&component.manifests_directory)
+        .follow_links(true)
+        .max_depth(system_manifests.max_depth.unwrap_or(usize::MAX))
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|entry| {
+            let ignored =
+                entry.file_type().is_dir() && system_manifests.is_ignored_directory(entry.path());
+            if ignored {
+                debug!(directory = %entry.path().display(), "Leaving out ignored directory");
+            }
+            !ignored
+        })
+        .filter_map(move |entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(error) => return Some(Err(error)), // propagate errors
+            };
+            let path = entry.path();
+            if kustomization_directory
+                .as_ref()
+                .is_some_and(|directory| path.starts_with(directory))
+            {
+                return None;
+            }
+            if system_manifests.render.contains(&Render::Kustomize) && entry.file_type().is_dir() {
+                if let Some(file) = render::kustomization_file(path) {
+                    kustomization_directory = Some(path.to_owned());
+                    let changed = is_changed_directory(changed_files, path);
+                    return (changed && !system_manifests.is_excluded(&file))
+                        .then(|| Ok(ManifestSource::Kustomization(entry.into_path(), file)));
+                }
+            }
+            if !entry.file_type().is_file() || !is_manifest_file(path) {
+                return None;
+            }
+            if system_manifests.is_excluded(path) {
+                debug!(file = %path.display(), "Leaving out excluded manifest file");
+                return None;
+            }
+            is_changed(changed_files, path).then(|| Ok(ManifestSource::File(entry.into_path())))
+        })
 }
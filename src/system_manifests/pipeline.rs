@@ -0,0 +1,343 @@
+use anyhow::{Context, Result};
+use futures::future::{self, LocalBoxFuture};
+use futures::stream::{self, LocalBoxStream};
+use futures::{FutureExt, StreamExt};
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::Instant;
+use tokio::runtime::Runtime;
+use tokio::task::spawn_blocking;
+use tracing::debug;
+
+use super::cache::{self, Cache};
+use super::{
+    document_resources, manifest_sources, parse_documents, parse_file_documents,
+    render_helm_releases, Component, Document, InvalidManifest, ManifestResource, ManifestSource,
+    Platform, SystemManifests,
+};
+use crate::progress::Progress;
+use crate::render::{self, Render};
+use crate::sops;
+
+type Resources = Vec<Result<ManifestResource>>;
+
+/// What reading a manifest file in the background found.
+enum FileContents {
+    /// The file's contents hash to what its cached documents were parsed from.
+    Unchanged,
+    Parsed {
+        hash: Option<String>,
+        documents: Vec<Document>,
+    },
+}
+
+/// Runs blocking work on the runtime's blocking threads.
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> T + Send + 'static) -> Result<T> {
+    spawn_blocking(work)
+        .await
+        .with_context(|| "Failed to read manifests in the background")
+}
+
+/// Reads the documents of a manifest file, failing if the file can't be opened. Documents are
+/// taken from the cache if the file is unchanged, and cached otherwise.
+async fn read_documents(file: &Path, cache: Option<Rc<RefCell<Cache>>>) -> Result<Vec<Document>> {
+    let open_error = |error: std::io::Error| {
+        anyhow::Error::from(InvalidManifest {
+            file: file.to_owned(),
+            position: None,
+            message: error.to_string(),
+        })
+    };
+    let metadata = cache
+        .as_ref()
+        .map(|_| std::fs::metadata(file))
+        .transpose()
+        .map_err(open_error)?;
+    let mut cached_hash = None;
+    if let (Some(cache), Some(metadata)) = (&cache, &metadata) {
+        let cache = cache.borrow();
+        if let Some(documents) = cache.get(file, metadata) {
+            debug!(file = %file.display(), documents = documents.len(), "Read cached documents");
+            return Ok(documents);
+        }
+        cached_hash = cache.hash_of(file);
+    }
+
+    let caching = cache.is_some();
+    let path = file.to_owned();
+    let started = Instant::now();
+    let contents = blocking(move || -> std::io::Result<FileContents> {
+        let contents = std::fs::read_to_string(&path)?;
+        let hash = caching.then(|| cache::hash(&contents));
+        if hash.is_some() && hash == cached_hash {
+            return Ok(FileContents::Unchanged);
+        }
+        Ok(FileContents::Parsed {
+            hash,
+            documents: parse_file_documents(&path, &contents),
+        })
+    })
+    .await;
+    match contents?.map_err(open_error)? {
+        FileContents::Parsed { hash, documents } => {
+            debug!(
+                file = %file.display(),
+                documents = documents.len(),
+                elapsed = ?started.elapsed(),
+                "Parsed manifest file"
+            );
+            if let (Some(cache), Some(metadata), Some(hash)) = (&cache, &metadata, hash) {
+                cache
+                    .borrow_mut()
+                    .insert(file, metadata, hash, documents.clone());
+            }
+            Ok(documents)
+        }
+        FileContents::Unchanged => {
+            debug!(file = %file.display(), "Read cached documents of unchanged file");
+            let cached = cache
+                .as_ref()
+                .zip(metadata.as_ref())
+                .and_then(|(cache, metadata)| cache.borrow_mut().touch(file, metadata));
+            Ok(cached.unwrap_or_default())
+        }
+    }
+}
+
+/// Replaces the SOPS-encrypted resources of a file with the ones `sops --decrypt` returns,
+/// keeping their lines and SOPS metadata. The decrypted contents are only kept in memory.
+async fn decrypt_documents(file: &Path, documents: Vec<Document>) -> Result<Vec<Document>> {
+    let path = file.to_owned();
+    let decrypted = blocking(move || {
+        sops::decrypt(&path).map(|contents| parse_documents(&path, &contents, Vec::new()))
+    })
+    .await??;
+    let mut decrypted = decrypted.into_iter().filter_map(|document| match document {
+        Document::Resource { resource, .. } => Some(resource),
+        Document::Invalid { .. } => None,
+    });
+    documents
+        .into_iter()
+        .map(|document| {
+            let Document::Resource { line, resource } = document else {
+                return Ok(document);
+            };
+            let mut decrypted_resource = decrypted.next().with_context(|| {
+                format!(
+                    "Decrypted {} has fewer resources than the encrypted file",
+                    file.display()
+                )
+            })?;
+            let Some(sops_metadata) = resource.data.get("sops") else {
+                return Ok(Document::Resource { line, resource });
+            };
+            if let Some(data) = decrypted_resource.data.as_object_mut() {
+                data.insert("sops".to_owned(), sops_metadata.clone());
+            }
+            Ok(Document::Resource {
+                line,
+                resource: decrypted_resource,
+            })
+        })
+        .collect()
+}
+
+/// Reads the resources in a manifest file, yielding an error if the file can't be opened or
+/// decrypted and for every document that isn't a valid resource.
+async fn read_manifest_file(
+    file: PathBuf,
+    component: Rc<Component>,
+    platform: Rc<Platform>,
+    cache: Option<Rc<RefCell<Cache>>>,
+    decrypt_sops: bool,
+) -> Resources {
+    let documents = match read_documents(&file, cache).await {
+        Ok(documents) if decrypt_sops && documents.iter().any(Document::is_encrypted) => {
+            decrypt_documents(&file, documents).await
+        }
+        documents => documents,
+    };
+    match documents {
+        Ok(documents) => document_resources(file, documents, component, platform),
+        Err(error) => vec![Err(error)],
+    }
+}
+
+/// Reads the resources rendered from a kustomization, attributing them to its kustomization file
+/// without line numbers.
+async fn read_kustomization(
+    directory: PathBuf,
+    file: PathBuf,
+    component: Rc<Component>,
+    platform: Rc<Platform>,
+) -> Resources {
+    let path = file.clone();
+    let started = Instant::now();
+    let documents = blocking(move || {
+        render::kustomize_build(&directory, &path)
+            .map(|contents| parse_documents(&path, &contents, Vec::new()))
+    })
+    .await;
+    debug!(
+        kustomization = %file.display(),
+        elapsed = ?started.elapsed(),
+        "Built kustomization"
+    );
+    match documents {
+        Ok(Ok(documents)) => document_resources(file, documents, component, platform),
+        Ok(Err(error)) | Err(error) => vec![Err(error)],
+    }
+}
+
+/// Streams the resources of a platform's components. Manifests are read and parsed on up to
+/// `jobs` blocking threads at a time, yielding resources in the order of the files. The platform
+/// is numbered among all of them for the progress.
+fn platform_resources<'a>(
+    platform: &'a Rc<Platform>,
+    platform_number: usize,
+    system_manifests: &'a SystemManifests,
+    progress: Option<Progress>,
+    changed_only: bool,
+) -> LocalBoxStream<'a, Result<ManifestResource>> {
+    let sources = platform
+        .components(system_manifests.include_bootstrap)
+        .flat_map(move |component| {
+            manifest_sources(component, system_manifests, changed_only)
+                .map(move |source| (component.clone(), source))
+        });
+    let resources = stream::iter(sources)
+        .map(
+            move |(component, source)| -> LocalBoxFuture<'static, Resources> {
+                if let Some(progress) = &progress {
+                    progress.reading(
+                        &platform.name,
+                        platform_number,
+                        system_manifests.platforms.len(),
+                        &component.name,
+                    );
+                }
+                let progress = progress.clone();
+                let platform = platform.clone();
+                let resources = match source {
+                    Ok(ManifestSource::File(file)) => read_manifest_file(
+                        file,
+                        component,
+                        platform,
+                        system_manifests.cache.clone(),
+                        system_manifests.decrypt_sops,
+                    )
+                    .boxed_local(),
+                    Ok(ManifestSource::Kustomization(directory, file)) => {
+                        read_kustomization(directory, file, component, platform).boxed_local()
+                    }
+                    Err(error) => future::ready(vec![Err(InvalidManifest {
+                        file: error.path().map(PathBuf::from).unwrap_or_default(),
+                        position: None,
+                        message: error.to_string(),
+                    }
+                    .into())])
+                    .boxed_local(),
+                };
+                resources
+                    .inspect(move |_| {
+                        if let Some(progress) = &progress {
+                            progress.inc();
+                        }
+                    })
+                    .boxed_local()
+            },
+        )
+        .buffered(system_manifests.jobs.max(1))
+        .flat_map(stream::iter);
+
+    if system_manifests.render.contains(&Render::Helm) {
+        return resources
+            .collect::<Resources>()
+            .map(|resources| {
+                let rendered: Resources =
+                    render_helm_releases(resources, &system_manifests.directory).collect();
+                stream::iter(rendered)
+            })
+            .flatten_stream()
+            .boxed_local();
+    }
+    resources.boxed_local()
+}
+
+impl SystemManifests {
+    /// Streams the resources of all platforms, skipping invalid manifests if requested, and only
+    /// those of the changed files if restricted to those and `changed_only`. Must be polled
+    /// within a tokio runtime, which runs the reading of manifests.
+    pub fn resource_stream(
+        &self,
+        changed_only: bool,
+    ) -> LocalBoxStream<'_, Result<ManifestResource>> {
+        let progress = self.show_progress.then(Progress::files);
+        stream::iter(self.platforms.iter().enumerate())
+            .flat_map(move |(index, platform)| {
+                platform_resources(platform, index + 1, self, progress.clone(), changed_only)
+            })
+            .filter_map(|resource| {
+                future::ready(match resource {
+                    Err(error) if self.skip_invalid => match error.downcast::<InvalidManifest>() {
+                        Ok(invalid) => {
+                            self.invalid.borrow_mut().push(invalid);
+                            None
+                        }
+                        Err(error) => Some(Err(error)),
+                    },
+                    resource => Some(resource),
+                })
+            })
+            .boxed_local()
+    }
+
+    /// Iterates over the resources of all platforms, reading them on a runtime of its own.
+    pub fn resource_iter(&self) -> SystemManifestsResourceIterator<'_> {
+        self.iter(true)
+    }
+
+    /// Iterates over the resources of all platforms, including those of unchanged files when
+    /// restricted to changed files, for checking the changed resources against all the others.
+    pub fn all_resource_iter(&self) -> SystemManifestsResourceIterator<'_> {
+        self.iter(false)
+    }
+
+    fn iter(&self, changed_only: bool) -> SystemManifestsResourceIterator<'_> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .max_blocking_threads(self.jobs.max(1))
+            .build()
+            .with_context(|| "Failed to start async runtime");
+        match runtime {
+            Ok(runtime) => SystemManifestsResourceIterator {
+                stream: self.resource_stream(changed_only),
+                runtime: Some(runtime),
+                error: None,
+            },
+            Err(error) => SystemManifestsResourceIterator {
+                stream: stream::empty().boxed_local(),
+                runtime: None,
+                error: Some(error),
+            },
+        }
+    }
+}
+
+pub struct SystemManifestsResourceIterator<'a> {
+    // Dropped before the runtime that runs it.
+    stream: LocalBoxStream<'a, Result<ManifestResource>>,
+    runtime: Option<Runtime>,
+    error: Option<anyhow::Error>,
+}
+
+impl Iterator for SystemManifestsResourceIterator<'_> {
+    type Item = Result<ManifestResource>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(error) = self.error.take() {
+            return Some(Err(error));
+        }
+        self.runtime.as_ref()?.block_on(self.stream.next())
+    }
+}
@@ -0,0 +1,100 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::inventory;
+use crate::system_manifests::SystemManifests;
+
+/// A line in the tree along with the lines nested below it.
+struct Node {
+    label: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    fn new(label: impl Into<String>, children: Vec<Node>) -> Self {
+        Node {
+            label: label.into(),
+            children,
+        }
+    }
+}
+
+/// Builds the platform, component and secret resource levels, leaving out levels beyond
+/// `depth`.
+fn build(system_manifests: &SystemManifests, depth: usize) -> Result<Vec<Node>> {
+    let mut secrets: BTreeMap<(String, String), Vec<Node>> = BTreeMap::new();
+    if depth >= 3 {
+        for manifest_resource in inventory::secret_resources(system_manifests, &[])? {
+            let metadata = &manifest_resource.resource.metadata;
+            let label = format!(
+                "{} {}/{}",
+                manifest_resource
+                    .resource
+                    .types
+                    .as_ref()
+                    .map(|t| t.kind.as_str())
+                    .unwrap_or_default(),
+                metadata.namespace.as_deref().unwrap_or("<none>"),
+                metadata.name.as_deref().unwrap_or_default()
+            );
+            secrets
+                .entry((
+                    manifest_resource.platform.name.clone(),
+                    manifest_resource.component.name.clone(),
+                ))
+                .or_default()
+                .push(Node::new(label, Vec::new()));
+        }
+    }
+
+    let mut platforms: Vec<Node> = system_manifests
+        .platforms
+        .iter()
+        .map(|platform| {
+            let mut components: Vec<Node> = platform
+                .components(system_manifests.include_bootstrap)
+                .filter(|_| depth >= 2)
+                .map(|component| {
+                    let children = secrets
+                        .remove(&(platform.name.clone(), component.name.clone()))
+                        .unwrap_or_default();
+                    Node::new(component.name.clone(), children)
+                })
+                .collect();
+            components.sort_by(|a, b| a.label.cmp(&b.label));
+            Node::new(platform.name.clone(), components)
+        })
+        .collect();
+    platforms.sort_by(|a, b| a.label.cmp(&b.label));
+    Ok(platforms)
+}
+
+fn write_nodes(writer: &mut impl Write, nodes: &[Node], prefix: &str) -> Result<()> {
+    for (index, node) in nodes.iter().enumerate() {
+        let last = index + 1 == nodes.len();
+        let (branch, continuation) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        writeln!(writer, "{}{}{}", prefix, branch, node.label)?;
+        write_nodes(
+            writer,
+            &node.children,
+            &format!("{}{}", prefix, continuation),
+        )?;
+    }
+    Ok(())
+}
+
+/// Prints the platforms, their components and the secret resources of each component as an
+/// indented tree, up to `depth` levels deep.
+pub fn write_tree(system_manifests: &SystemManifests, depth: usize) -> Result<()> {
+    let stdout = std::io::stdout();
+    let mut writer = std::io::BufWriter::new(stdout.lock());
+
+    writeln!(writer, "{}", system_manifests.directory.display())?;
+    write_nodes(&mut writer, &build(system_manifests, depth)?, "")?;
+    Ok(())
+}
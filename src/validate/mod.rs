@@ -0,0 +1,411 @@
+use anyhow::{Context, Result};
+use base64::Engine;
+use futures::FutureExt;
+use jsonschema::Validator;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::api::{Api, ApiResource, DynamicObject, ListParams};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use serde_yaml::Deserializer;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+use tracing::debug;
+
+use crate::cluster::ClusterContexts;
+use crate::eso::Spec;
+use crate::findings::{Finding, Severity};
+use crate::scan::escape_pointer_segment;
+use crate::sops;
+use crate::system_manifests::{ManifestResource, Platform};
+
+/// Rule of findings for ExternalSecrets and PushSecrets whose spec can't be read.
+pub const SPEC_RULE: &str = "unreadable-spec";
+
+/// Rule of findings for documents that don't conform to the schema of their CRD.
+pub const SCHEMA_RULE: &str = "crd-schema";
+
+/// Rule of findings for Secrets whose data isn't what Kubernetes accepts or their type requires.
+pub const SECRET_DATA_RULE: &str = "secret-data";
+
+/// Keys Secrets of a type have to carry, any one of them or all of them.
+const REQUIRED_SECRET_KEYS: &[(&str, &[&str], bool)] = &[
+    ("kubernetes.io/tls", &["tls.crt", "tls.key"], true),
+    ("kubernetes.io/basic-auth", &["username", "password"], false),
+    ("kubernetes.io/ssh-auth", &["ssh-privatekey"], true),
+    (
+        "kubernetes.io/dockerconfigjson",
+        &[".dockerconfigjson"],
+        true,
+    ),
+    ("kubernetes.io/dockercfg", &[".dockercfg"], true),
+];
+
+/// CRDs of the External Secrets Operator, trimmed down to the fields manifests declare.
+const BUNDLED_CRDS: &str = include_str!("crds/external-secrets.yaml");
+
+/// Validators of the kinds CRD schemas are known for, keyed by API version and kind.
+pub struct Schemas {
+    validators: BTreeMap<(String, String), Validator>,
+    /// Kinds there's a schema for, in any version.
+    kinds: BTreeSet<String>,
+    /// The platform whose cluster the schemas were read from, if not bundled.
+    platform: Option<String>,
+}
+
+/// Converts the OpenAPI schema of a CRD to a JSON Schema. Objects with declared properties don't
+/// allow others unless they preserve unknown fields, as Kubernetes would prune or reject them.
+fn json_schema(openapi: &Value) -> Value {
+    let Value::Object(openapi) = openapi else {
+        return openapi.clone();
+    };
+    let mut schema = Map::new();
+    for (keyword, value) in openapi {
+        let value = match keyword.as_str() {
+            "properties" | "patternProperties" => Value::Object(
+                value
+                    .as_object()
+                    .into_iter()
+                    .flatten()
+                    .map(|(name, property)| (name.clone(), json_schema(property)))
+                    .collect(),
+            ),
+            "items" | "additionalProperties" | "not" => json_schema(value),
+            "allOf" | "anyOf" | "oneOf" => Value::Array(
+                value
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .map(json_schema)
+                    .collect(),
+            ),
+            _ => value.clone(),
+        };
+        schema.insert(keyword.clone(), value);
+    }
+
+    let flag = |name: &str| openapi.get(name).and_then(Value::as_bool) == Some(true);
+    if flag("x-kubernetes-int-or-string") {
+        schema.remove("type");
+        schema.insert(
+            "anyOf".to_owned(),
+            json!([{ "type": "integer" }, { "type": "string" }]),
+        );
+    }
+    if flag("nullable") {
+        if let Some(Value::String(schema_type)) = schema.get("type").cloned() {
+            schema.insert("type".to_owned(), json!([schema_type, "null"]));
+        }
+    }
+    if schema.contains_key("properties")
+        && !schema.contains_key("additionalProperties")
+        && !flag("x-kubernetes-preserve-unknown-fields")
+    {
+        schema.insert("additionalProperties".to_owned(), Value::Bool(false));
+    }
+    Value::Object(schema)
+}
+
+impl Schemas {
+    fn new(platform: Option<String>) -> Self {
+        Schemas {
+            validators: BTreeMap::new(),
+            kinds: BTreeSet::new(),
+            platform,
+        }
+    }
+
+    /// Reads the bundled CRDs, and those in the given directory on top of them so that they
+    /// replace the bundled schema of the same version of a kind.
+    pub fn load(schema_dir: Option<&Path>) -> Result<Self> {
+        let mut schemas = Schemas::new(None);
+        schemas
+            .add_crds(BUNDLED_CRDS)
+            .context("Failed to read the bundled CRDs")?;
+        if let Some(schema_dir) = schema_dir {
+            let mut files = fs::read_dir(schema_dir)
+                .with_context(|| format!("Failed to read {}", schema_dir.display()))?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()
+                .with_context(|| format!("Failed to read {}", schema_dir.display()))?;
+            files.retain(|file| {
+                file.extension().is_some_and(|extension| {
+                    ["yaml", "yml", "json"].iter().any(|e| extension == *e)
+                })
+            });
+            files.sort();
+            for file in files {
+                let contents = fs::read_to_string(&file)
+                    .with_context(|| format!("Failed to read {}", file.display()))?;
+                schemas
+                    .add_crds(&contents)
+                    .with_context(|| format!("Failed to read CRDs from {}", file.display()))?;
+            }
+        }
+        Ok(schemas)
+    }
+
+    /// Adds the schemas of every version of the CRDs in a YAML or JSON document stream, and in
+    /// lists in it, ignoring other kinds of documents.
+    fn add_crds(&mut self, contents: &str) -> Result<()> {
+        for document in Deserializer::from_str(contents) {
+            let object = DynamicObject::deserialize(document)?;
+            match object.types.as_ref().map(|t| t.kind.as_str()) {
+                Some("CustomResourceDefinition") => self.add_crd(&object)?,
+                // Like `kubectl get crds -o yaml` prints them.
+                Some("List" | "CustomResourceDefinitionList") => {
+                    for item in object.data["items"].as_array().into_iter().flatten() {
+                        let item: DynamicObject = serde_json::from_value(item.clone())?;
+                        if item.types.as_ref().map(|t| t.kind.as_str())
+                            == Some("CustomResourceDefinition")
+                        {
+                            self.add_crd(&item)?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the schemas of every version of a CRD.
+    fn add_crd(&mut self, crd: &DynamicObject) -> Result<()> {
+        let name = crd.metadata.name.as_deref().unwrap_or_default();
+        let spec = &crd.data["spec"];
+        let (Some(group), Some(kind)) = (
+            spec["group"].as_str(),
+            spec.pointer("/names/kind").and_then(Value::as_str),
+        ) else {
+            return Ok(());
+        };
+        for version in spec["versions"].as_array().into_iter().flatten() {
+            let (Some(version_name), Some(openapi)) = (
+                version["name"].as_str(),
+                version.pointer("/schema/openAPIV3Schema"),
+            ) else {
+                continue;
+            };
+            if version["served"].as_bool() == Some(false) {
+                continue;
+            }
+            let mut schema = json_schema(openapi);
+            // Every resource has these, whether the CRD declares them or not.
+            if let Some(Value::Object(properties)) = schema.get_mut("properties") {
+                for field in ["apiVersion", "kind", "metadata"] {
+                    properties.entry(field).or_insert_with(|| json!({}));
+                }
+            }
+            let validator = jsonschema::validator_for(&schema).map_err(|error| {
+                anyhow::anyhow!(
+                    "Invalid schema of version {} of CRD {}: {}",
+                    version_name,
+                    name,
+                    error
+                )
+            })?;
+            debug!(kind, version = version_name, "Read CRD schema");
+            self.validators.insert(
+                (format!("{}/{}", group, version_name), kind.to_owned()),
+                validator,
+            );
+            self.kinds.insert(kind.to_owned());
+        }
+        Ok(())
+    }
+
+    /// Returns whether there's a schema for some version of a kind.
+    pub fn covers(&self, kind: Option<&str>) -> bool {
+        kind.is_some_and(|kind| self.kinds.contains(kind))
+    }
+
+    /// Returns a finding for every way a resource doesn't conform to the schema of its CRD, or
+    /// a warning if there's no schema for its version although there is for its kind.
+    pub fn validate(&self, manifest_resource: &ManifestResource) -> Result<Vec<Finding>> {
+        let Some(types) = &manifest_resource.resource.types else {
+            return Ok(Vec::new());
+        };
+        let Some(validator) = self
+            .validators
+            .get(&(types.api_version.clone(), types.kind.clone()))
+        else {
+            if !self.covers(Some(&types.kind)) {
+                return Ok(Vec::new());
+            }
+            // A cluster rejects API versions it doesn't serve, while the bundled schemas may just
+            // not know them yet.
+            let (severity, message) = match &self.platform {
+                Some(platform) => (
+                    Severity::Error,
+                    format!(
+                        "API version {} of {} isn't served by the cluster of platform {}",
+                        types.api_version, types.kind, platform
+                    ),
+                ),
+                None => (
+                    Severity::Warning,
+                    format!(
+                        "No schema of {} for API version {}",
+                        types.kind, types.api_version
+                    ),
+                ),
+            };
+            return Ok(vec![Finding::new(
+                SCHEMA_RULE,
+                severity,
+                message,
+                manifest_resource,
+            )]);
+        };
+
+        let instance = serde_json::to_value(&manifest_resource.resource)?;
+        Ok(validator
+            .iter_errors(&instance)
+            .map(|error| {
+                let pointer = error.instance_path().as_str().to_owned();
+                let mut finding = Finding::new(
+                    SCHEMA_RULE,
+                    Severity::Error,
+                    match pointer.as_str() {
+                        "" => error.to_string(),
+                        _ => format!("{} at {}", error, pointer),
+                    },
+                    manifest_resource,
+                );
+                finding.pointer = Some(pointer).filter(|pointer| !pointer.is_empty());
+                finding
+            })
+            .collect())
+    }
+}
+
+/// Reads the schemas of the CRDs installed in a platform's cluster.
+async fn platform_schemas(
+    platform: &Platform,
+    contexts: &ClusterContexts,
+) -> Result<(String, Schemas)> {
+    let client = contexts.client_for(platform).await?;
+    let api: Api<DynamicObject> =
+        Api::all_with(client, &ApiResource::erase::<CustomResourceDefinition>(&()));
+    let crds = api.list(&ListParams::default()).await.with_context(|| {
+        format!(
+            "Failed to list CustomResourceDefinitions on platform {}",
+            platform.name
+        )
+    })?;
+    let mut schemas = Schemas::new(Some(platform.name.clone()));
+    for crd in &crds.items {
+        schemas
+            .add_crd(crd)
+            .with_context(|| format!("Failed to read CRDs on platform {}", platform.name))?;
+    }
+    Ok((platform.name.clone(), schemas))
+}
+
+/// Reads the schemas of the CRDs installed in the cluster of each platform, keyed by platform.
+/// Clusters are queried concurrently, and the names of the platforms whose cluster couldn't be
+/// queried are returned along with the schemas of the others.
+pub fn cluster_schemas<'a>(
+    platforms: impl IntoIterator<Item = &'a Rc<Platform>>,
+    contexts: &ClusterContexts,
+) -> Result<(HashMap<String, Schemas>, Vec<String>)> {
+    let queries = platforms.into_iter().map(|platform| {
+        (
+            platform.as_ref(),
+            platform_schemas(platform, contexts).boxed_local(),
+        )
+    });
+    let (schemas, failed_platforms) = contexts.query_all(queries)?;
+    Ok((schemas.into_iter().collect(), failed_platforms))
+}
+
+/// Returns a finding for an ExternalSecret or PushSecret whose spec doesn't have the shape the
+/// External Secrets Operator expects, like a list where an object belongs.
+pub fn check_spec(manifest_resource: &ManifestResource) -> Option<Finding> {
+    let resource = &manifest_resource.resource;
+    let kind = resource.types.as_ref()?.kind.as_str();
+    if !matches!(kind, "ExternalSecret" | "PushSecret")
+        || resource.data.get("spec").is_none()
+        || Spec::of(resource).is_some()
+    {
+        return None;
+    }
+    Some(Finding::new(
+        SPEC_RULE,
+        Severity::Error,
+        format!("The spec of the {} can't be read", kind),
+        manifest_resource,
+    ))
+}
+
+/// Returns findings for the `data` values of a Secret that aren't valid base64, keys set in both
+/// `data` and `stringData`, and keys its type requires that it lacks. SOPS-encrypted Secrets are
+/// skipped, as their values are ciphertexts.
+pub fn check_secret_data(manifest_resource: &ManifestResource) -> Vec<Finding> {
+    let resource = &manifest_resource.resource;
+    if resource.types.as_ref().map(|t| t.kind.as_str()) != Some("Secret")
+        || sops::is_encrypted(resource)
+    {
+        return Vec::new();
+    }
+    let empty = Map::new();
+    let data = resource.data["data"].as_object().unwrap_or(&empty);
+    let string_data = resource.data["stringData"].as_object().unwrap_or(&empty);
+    let finding = |severity, message, pointer: String| Finding {
+        pointer: Some(pointer),
+        ..Finding::new(SECRET_DATA_RULE, severity, message, manifest_resource)
+    };
+
+    let mut findings = Vec::new();
+    for (key, value) in data {
+        let valid = value.as_str().is_some_and(|value| {
+            base64::engine::general_purpose::STANDARD
+                .decode(value)
+                .is_ok()
+        });
+        if !valid {
+            findings.push(finding(
+                Severity::Error,
+                format!("Value of {} in data isn't valid base64", key),
+                format!("/data/{}", escape_pointer_segment(key)),
+            ));
+        }
+    }
+    for key in string_data.keys().filter(|key| data.contains_key(*key)) {
+        findings.push(finding(
+            Severity::Warning,
+            format!(
+                "{} is set in both data and stringData, whose value wins",
+                key
+            ),
+            format!("/stringData/{}", escape_pointer_segment(key)),
+        ));
+    }
+
+    let secret_type = resource.data["type"].as_str().unwrap_or("Opaque");
+    if let Some((_, keys, all)) = REQUIRED_SECRET_KEYS
+        .iter()
+        .find(|(required_type, _, _)| *required_type == secret_type)
+    {
+        let has = |key: &&str| data.contains_key(*key) || string_data.contains_key(*key);
+        let missing: Vec<&str> = keys.iter().filter(|key| !has(key)).copied().collect();
+        let lacking = match all {
+            true => !missing.is_empty(),
+            false => missing.len() == keys.len(),
+        };
+        if lacking {
+            findings.push(finding(
+                Severity::Error,
+                format!(
+                    "Secret of type {} {} {}",
+                    secret_type,
+                    if *all { "lacks" } else { "needs one of" },
+                    missing.join(", ")
+                ),
+                "/data".to_owned(),
+            ));
+        }
+    }
+    findings
+}
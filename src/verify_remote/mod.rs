@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Instant;
+use tracing::debug;
+
+use crate::eso;
+use crate::inventory::{remote_refs, store_refs, RemoteRef};
+use crate::progress::Progress;
+use crate::providers::{Registry, SecretBackend, SecretState, Store};
+use crate::stores;
+use crate::system_manifests::ManifestResource;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Problem {
+    /// No secret exists at the remote key.
+    Missing,
+    /// The secret exists, but lacks the referenced property.
+    MissingProperty,
+    /// The secret is scheduled for deletion.
+    PendingDeletion,
+    /// The secret, or the referenced version of it, is disabled.
+    Disabled,
+    /// The secret, or the referenced version of it, has expired.
+    Expired,
+    /// The secret exists, but none of its versions are enabled.
+    NoEnabledVersion,
+    /// No remote key matches a `dataFrom` find.
+    NoMatches,
+    /// The remote key a PushSecret writes to can't be written with the current credentials.
+    NotWritable,
+    /// Checking the remote key failed.
+    Failed,
+}
+
+/// A remote key that an ExternalSecret or PushSecret can't read or write.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadReference {
+    pub file: PathBuf,
+    pub component_name: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: String,
+    pub store: String,
+    pub remote_ref: String,
+    pub problem: Problem,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+fn check(
+    backend: &mut dyn SecretBackend,
+    store: &Store,
+    remote_ref: &RemoteRef,
+    push: bool,
+) -> Result<Option<Problem>> {
+    if push {
+        let writable = backend.writable(store, remote_ref)?;
+        return Ok((writable == Some(false)).then_some(Problem::NotWritable));
+    }
+    if !backend.exists(store, remote_ref)? {
+        return Ok(Some(Problem::Missing));
+    }
+
+    let metadata = backend.metadata(store, remote_ref)?;
+    let problem = match metadata.state {
+        SecretState::Enabled => None,
+        SecretState::Disabled => Some(Problem::Disabled),
+        SecretState::NoEnabledVersion => Some(Problem::NoEnabledVersion),
+        SecretState::PendingDeletion => Some(Problem::PendingDeletion),
+    };
+    if problem.is_some() {
+        return Ok(problem);
+    }
+    if metadata.expires.is_some_and(|expires| expires < Utc::now()) {
+        return Ok(Some(Problem::Expired));
+    }
+    if let Some(property) = &remote_ref.property {
+        if !metadata
+            .properties
+            .is_some_and(|properties| properties.contains(property))
+        {
+            return Ok(Some(Problem::MissingProperty));
+        }
+    }
+    Ok(None)
+}
+
+/// A `dataFrom` find of an ExternalSecret, matching remote keys by path and name.
+struct Find {
+    path: Option<String>,
+    name: Option<String>,
+}
+
+impl std::fmt::Display for Find {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "find {}", self.path.as_deref().unwrap_or_default())?;
+        if let Some(name) = &self.name {
+            write!(f, "/{}", name)?;
+        }
+        Ok(())
+    }
+}
+
+fn finds(manifest_resource: &ManifestResource) -> Vec<Find> {
+    eso::external_secret_spec(&manifest_resource.resource)
+        .into_iter()
+        .flat_map(|spec| spec.data_from)
+        .filter_map(|data_from| data_from.find)
+        .map(|find| Find {
+            path: find.path,
+            name: find.name.and_then(|name| name.regexp),
+        })
+        .collect()
+}
+
+fn check_find(
+    backend: &mut dyn SecretBackend,
+    store: &Store,
+    find: &Find,
+) -> Result<Option<Problem>> {
+    let name = find
+        .name
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .with_context(|| format!("Invalid name regexp in {}", find))?;
+    let keys = backend.list(store, find.path.as_deref())?;
+    let found = keys.iter().any(|key| {
+        let relative = find
+            .path
+            .as_deref()
+            .and_then(|path| key.strip_prefix(path))
+            .map(|relative| relative.trim_start_matches('/'))
+            .unwrap_or(key);
+        name.as_ref()
+            .is_none_or(|name| name.is_match(key) || name.is_match(relative))
+    });
+    Ok((!found).then_some(Problem::NoMatches))
+}
+
+/// Checks every remote key of ExternalSecrets and PushSecrets whose store has a backend in the
+/// registry, optionally only for the given provider types, returning the dead ones per platform.
+pub fn verify_remote(
+    resources: impl Iterator<Item = Result<ManifestResource>>,
+    providers: &[String],
+    registry: &mut Registry,
+) -> Result<BTreeMap<String, Vec<DeadReference>>> {
+    for provider in providers {
+        if registry.backend(provider).is_none() {
+            anyhow::bail!(
+                "Unknown provider {}, expected one of: {}",
+                provider,
+                registry.providers().collect::<Vec<_>>().join(", ")
+            );
+        }
+    }
+    let (secret_stores, referencing) = stores::collect(resources)?;
+
+    let progress = Progress::items(referencing.len(), "resources checked");
+    let mut dead_references: BTreeMap<String, Vec<DeadReference>> = BTreeMap::new();
+    for manifest_resource in &referencing {
+        progress.inc();
+        let resource = &manifest_resource.resource;
+        let platform_name = &manifest_resource.platform.name;
+        let kind = resource
+            .types
+            .as_ref()
+            .map(|t| t.kind.clone())
+            .unwrap_or_default();
+        let push = kind == "PushSecret";
+        for store_ref in store_refs(resource) {
+            let Ok(store_resource) = secret_stores.resolve(
+                platform_name,
+                &store_ref,
+                resource.metadata.namespace.as_deref(),
+            ) else {
+                continue;
+            };
+            let Some((provider, provider_spec)) = stores::provider(store_resource) else {
+                continue;
+            };
+            if !providers.is_empty() && !providers.iter().any(|p| p == provider) {
+                continue;
+            }
+            let Some(backend) = registry.backend(provider) else {
+                continue;
+            };
+            let store = Store {
+                platform_name,
+                provider: provider_spec,
+            };
+
+            let mut checks = Vec::new();
+            let log_check = |remote_ref: &str, started: Instant, failed: bool| {
+                debug!(
+                    provider,
+                    platform = platform_name,
+                    store = %store_ref,
+                    remote_ref,
+                    failed,
+                    elapsed = ?started.elapsed(),
+                    "Checked remote key"
+                )
+            };
+            for remote_ref in remote_refs(resource) {
+                let started = Instant::now();
+                let checked = check(backend.as_mut(), &store, &remote_ref, push);
+                log_check(&remote_ref.to_string(), started, checked.is_err());
+                checks.push((remote_ref.to_string(), checked));
+            }
+            for find in finds(manifest_resource) {
+                let started = Instant::now();
+                let checked = check_find(backend.as_mut(), &store, &find);
+                log_check(&find.to_string(), started, checked.is_err());
+                checks.push((find.to_string(), checked));
+            }
+
+            for (remote_ref, checked) in checks {
+                let (problem, message) = match checked {
+                    Ok(None) => continue,
+                    Ok(Some(problem)) => (problem, None),
+                    Err(error) => (Problem::Failed, Some(format!("{:#}", error))),
+                };
+                dead_references
+                    .entry(platform_name.clone())
+                    .or_default()
+                    .push(DeadReference {
+                        file: manifest_resource.file.clone(),
+                        component_name: manifest_resource.component.name.clone(),
+                        kind: kind.clone(),
+                        namespace: resource.metadata.namespace.clone(),
+                        name: resource.metadata.name.clone().unwrap_or_default(),
+                        store: store_ref.to_string(),
+                        remote_ref,
+                        problem,
+                        message,
+                    });
+            }
+        }
+    }
+    Ok(dead_references)
+}
@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use notify::{Event, RecursiveMode, Watcher};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Duration;
+
+use crate::system_manifests::CACHE_DIRECTORY_NAME;
+
+/// How long files have to stay unchanged before re-running, so saving several files or
+/// checking out a branch runs once.
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Returns whether an event changes files outside of `.git` directories and the cache. Reading
+/// the manifests causes access events and cache writes, which would otherwise re-run the command
+/// endlessly.
+fn is_change(event: &Event, directories: &[PathBuf]) -> bool {
+    (event.kind.is_create() || event.kind.is_modify() || event.kind.is_remove())
+        && event.paths.iter().any(|path| {
+            let relative = directories
+                .iter()
+                .find_map(|directory| path.strip_prefix(directory).ok())
+                .unwrap_or(path);
+            !relative.components().any(|component| {
+                component.as_os_str() == ".git" || component.as_os_str() == CACHE_DIRECTORY_NAME
+            })
+        })
+}
+
+/// Runs a command, and again whenever files in the directories change, until interrupted.
+pub fn watch(directories: &[PathBuf], mut run: impl FnMut()) -> Result<()> {
+    let directories = directories
+        .iter()
+        .map(|directory| {
+            std::fs::canonicalize(directory)
+                .with_context(|| format!("Failed to read {}", directory.display()))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher =
+        notify::recommended_watcher(sender).with_context(|| "Failed to watch for file changes")?;
+    for directory in &directories {
+        watcher
+            .watch(directory, RecursiveMode::Recursive)
+            .with_context(|| format!("Failed to watch {}", directory.display()))?;
+    }
+    let watched = directories
+        .iter()
+        .map(|directory| directory.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    loop {
+        run();
+        eprintln!("Watching {} for changes...", watched);
+        loop {
+            let event = receiver
+                .recv()?
+                .with_context(|| "Failed to watch for file changes")?;
+            if is_change(&event, &directories) {
+                break;
+            }
+        }
+        loop {
+            match receiver.recv_timeout(DEBOUNCE_TIMEOUT) {
+                Ok(event) => {
+                    event.with_context(|| "Failed to watch for file changes")?;
+                }
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+    }
+}